@@ -0,0 +1,76 @@
+/*
+ * Report fields
+ * HID packs fields back to back without alignment, least significant bit
+ * first, so e.g. two 12 bit coordinates share a byte. Offsets and sizes are
+ * in bits and fields are at most 32 bits wide. Fields reaching past the end
+ * of the report panic like slice indexing does.
+ */
+
+/* Stores the low `size` bits of value at the bit offset */
+pub fn write(data: &mut [u8], offset: usize, size: usize, value: u32) {
+    for bit in 0..size {
+        let (byte, shift) = ((offset + bit) / 8, (offset + bit) % 8);
+        if value >> bit & 1 != 0 {
+            data[byte] |= 1 << shift;
+        } else {
+            data[byte] &= !(1 << shift);
+        }
+    }
+}
+
+pub fn read(data: &[u8], offset: usize, size: usize) -> u32 {
+    (0..size).fold(0, |value, bit| {
+        let (byte, shift) = ((offset + bit) / 8, (offset + bit) % 8);
+        value | ((data[byte] >> shift & 1) as u32) << bit
+    })
+}
+
+/* Reads a two's complement field, e.g. relative axes */
+pub fn read_signed(data: &[u8], offset: usize, size: usize) -> i32 {
+    let value = read(data, offset, size);
+    if size > 0 && size < 32 && value >> (size - 1) & 1 != 0 {
+        (value | !0 << size) as i32
+    } else {
+        value as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_fields_across_bytes() {
+        let mut data = [0u8; 6];
+        write(&mut data, 4, 12, 0xabc);
+        assert_eq!(data[..2], [0xc0, 0xab]);
+        assert_eq!(read(&data, 4, 12), 0xabc);
+
+        write(&mut data, 13, 32, 0x8765_4321);
+        assert_eq!(read(&data, 13, 32), 0x8765_4321);
+        assert_eq!(read(&data, 4, 9), 0xbc);
+
+        /* the bits around a field are left alone */
+        let mut data = [0xff; 3];
+        write(&mut data, 3, 10, 0);
+        assert_eq!(data, [0x07, 0xe0, 0xff]);
+        write(&mut data, 3, 10, 0x3ff);
+        assert_eq!(data, [0xff; 3]);
+    }
+
+    #[test]
+    fn sign_extends_fields() {
+        let mut data = [0u8; 4];
+        write(&mut data, 6, 12, 0xfff);
+        assert_eq!(read_signed(&data, 6, 12), -1);
+        write(&mut data, 6, 12, 0x800);
+        assert_eq!(read_signed(&data, 6, 12), -2048);
+        write(&mut data, 6, 12, 0x7ff);
+        assert_eq!(read_signed(&data, 6, 12), 2047);
+
+        let data = [0x00, 0x00, 0x00, 0x80];
+        assert_eq!(read_signed(&data, 0, 32), i32::MIN);
+        assert_eq!(read_signed(&data, 31, 1), -1);
+        assert_eq!(read_signed(&data, 0, 0), 0);
+    }
+}
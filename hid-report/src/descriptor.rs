@@ -0,0 +1,311 @@
+/*
+ * Descriptor items
+ * Report descriptors are a sequence of short items: a prefix byte holding
+ * the tag, the type and the data size of 0, 1, 2 or 4 bytes, followed by the
+ * little endian data. Long items, prefix 0xfe, are reserved and skipped.
+ * Builder writes descriptors from items, so generated ones need no byte
+ * tables.
+ */
+
+pub const LONG_ITEM: u8 = 0xfe;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemType {
+    Main,
+    Global,
+    Local,
+    Reserved,
+}
+
+/* Tags of the items, by type */
+pub const INPUT: u8 = 0x8;
+pub const OUTPUT: u8 = 0x9;
+pub const COLLECTION: u8 = 0xa;
+pub const FEATURE: u8 = 0xb;
+pub const END_COLLECTION: u8 = 0xc;
+
+pub const USAGE_PAGE: u8 = 0x0;
+pub const LOGICAL_MINIMUM: u8 = 0x1;
+pub const LOGICAL_MAXIMUM: u8 = 0x2;
+pub const PHYSICAL_MINIMUM: u8 = 0x3;
+pub const PHYSICAL_MAXIMUM: u8 = 0x4;
+pub const UNIT_EXPONENT: u8 = 0x5;
+pub const UNIT: u8 = 0x6;
+pub const REPORT_SIZE: u8 = 0x7;
+pub const REPORT_ID: u8 = 0x8;
+pub const REPORT_COUNT: u8 = 0x9;
+pub const PUSH: u8 = 0xa;
+pub const POP: u8 = 0xb;
+
+pub const USAGE: u8 = 0x0;
+pub const USAGE_MINIMUM: u8 = 0x1;
+pub const USAGE_MAXIMUM: u8 = 0x2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Item {
+    pub item_type: ItemType,
+    pub tag: u8,
+    /* Bytes of data, 0, 1, 2 or 4 */
+    pub size: u8,
+    pub data: u32,
+}
+
+impl Item {
+    /* An item with the shortest data size holding the value */
+    pub fn new(item_type: ItemType, tag: u8, data: u32) -> Item {
+        let size = match data {
+            0 => 0,
+            1..=0xff => 1,
+            0x100..=0xffff => 2,
+            _ => 4,
+        };
+        Item { item_type, tag, size, data }
+    }
+
+    /* Like new() for signed data, e.g. logical minimum and maximum */
+    pub fn signed(item_type: ItemType, tag: u8, data: i32) -> Item {
+        let size = match data {
+            0 => 0,
+            -0x80..=0x7f => 1,
+            -0x8000..=0x7fff => 2,
+            _ => 4,
+        };
+        let mask = if size == 4 { !0 } else { (1u32 << (size * 8)) - 1 };
+        Item { item_type, tag, size, data: data as u32 & mask }
+    }
+
+    /* The data sign extended from its size */
+    pub fn signed_data(&self) -> i32 {
+        match self.size {
+            1 => self.data as u8 as i8 as i32,
+            2 => self.data as u16 as i16 as i32,
+            _ => self.data as i32,
+        }
+    }
+
+    /* Writes the item, returning its length or None if out is too short */
+    pub fn encode(&self, out: &mut [u8]) -> Option<usize> {
+        let length = 1 + self.size as usize;
+        if out.len() < length {
+            return None;
+        }
+        let item_type = match self.item_type {
+            ItemType::Main => 0,
+            ItemType::Global => 1,
+            ItemType::Local => 2,
+            ItemType::Reserved => 3,
+        };
+        let size_code = if self.size == 4 { 3 } else { self.size };
+        out[0] = self.tag << 4 | item_type << 2 | size_code;
+        for (index, byte) in out[1..length].iter_mut().enumerate() {
+            *byte = (self.data >> (index * 8)) as u8;
+        }
+        Some(length)
+    }
+}
+
+/* The descriptor ends within an item */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Truncated {
+    pub offset: usize,
+}
+
+/* Iterates over the items of a descriptor, with their offsets */
+pub struct Items<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+pub fn items(data: &[u8]) -> Items<'_> {
+    Items { data, offset: 0 }
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Result<(usize, Item), Truncated>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let offset = self.offset;
+            let rest = &self.data[offset..];
+            let prefix = *rest.first()?;
+
+            /* long items have their size and tag in two more bytes */
+            let (header, size) = match (prefix, rest.get(1)) {
+                (LONG_ITEM, Some(&size)) => (3, size as usize),
+                (LONG_ITEM, None) => (3, 0),
+                _ => (1, [0, 1, 2, 4][(prefix & 0x3) as usize]),
+            };
+            if rest.len() < header + size {
+                self.offset = self.data.len();
+                return Some(Err(Truncated { offset }));
+            }
+            self.offset += header + size;
+            if prefix == LONG_ITEM {
+                continue;
+            }
+
+            let item_type = match prefix >> 2 & 0x3 {
+                0 => ItemType::Main,
+                1 => ItemType::Global,
+                2 => ItemType::Local,
+                _ => ItemType::Reserved,
+            };
+            let data = rest[1..1 + size].iter().rev().fold(0u32, |data, &byte| data << 8 | byte as u32);
+            return Some(Ok((offset, Item { item_type, tag: prefix >> 4, size: size as u8, data })));
+        }
+    }
+}
+
+/* Collection types */
+pub const PHYSICAL: u32 = 0x00;
+pub const APPLICATION: u32 = 0x01;
+pub const LOGICAL: u32 = 0x02;
+
+/* Flags of input, output and feature items */
+pub const CONSTANT: u32 = 0x01;
+pub const VARIABLE: u32 = 0x02;
+pub const RELATIVE: u32 = 0x04;
+
+/*
+ * Writes a descriptor item by item into a buffer, e.g.
+ *
+ *   let mut builder = Builder::new(&mut buffer);
+ *   builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::MOUSE).collection(APPLICATION)
+ *       .usage_page(button::PAGE).usage_range(1, 3).logical(0, 1).report(1, 3).input(VARIABLE)
+ *       .end_collection();
+ *   let length = builder.finish()?;
+ *
+ * Items that do not fit are dropped and finish() returns None, so the calls
+ * can be chained without checking each. The usages are in usage.rs.
+ */
+pub struct Builder<'a> {
+    out: &'a mut [u8],
+    length: usize,
+    overflow: bool,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(out: &'a mut [u8]) -> Builder<'a> {
+        Builder { out, length: 0, overflow: false }
+    }
+
+    pub fn item(&mut self, item: Item) -> &mut Self {
+        match item.encode(&mut self.out[self.length..]) {
+            Some(length) if !self.overflow => self.length += length,
+            _ => self.overflow = true,
+        }
+        self
+    }
+
+    pub fn usage_page(&mut self, page: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Global, USAGE_PAGE, page))
+    }
+
+    pub fn usage(&mut self, usage: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Local, USAGE, usage))
+    }
+
+    pub fn usage_range(&mut self, minimum: u32, maximum: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Local, USAGE_MINIMUM, minimum))
+            .item(Item::new(ItemType::Local, USAGE_MAXIMUM, maximum))
+    }
+
+    pub fn collection(&mut self, kind: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Main, COLLECTION, kind))
+    }
+
+    pub fn end_collection(&mut self) -> &mut Self {
+        self.item(Item::new(ItemType::Main, END_COLLECTION, 0))
+    }
+
+    pub fn report_id(&mut self, id: u8) -> &mut Self {
+        self.item(Item::new(ItemType::Global, REPORT_ID, id as u32))
+    }
+
+    pub fn logical(&mut self, minimum: i32, maximum: i32) -> &mut Self {
+        self.item(Item::signed(ItemType::Global, LOGICAL_MINIMUM, minimum))
+            .item(Item::signed(ItemType::Global, LOGICAL_MAXIMUM, maximum))
+    }
+
+    /* Report size in bits and count of the fields that follow */
+    pub fn report(&mut self, size: u32, count: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Global, REPORT_SIZE, size))
+            .item(Item::new(ItemType::Global, REPORT_COUNT, count))
+    }
+
+    pub fn input(&mut self, flags: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Main, INPUT, flags))
+    }
+
+    pub fn output(&mut self, flags: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Main, OUTPUT, flags))
+    }
+
+    pub fn feature(&mut self, flags: u32) -> &mut Self {
+        self.item(Item::new(ItemType::Main, FEATURE, flags))
+    }
+
+    /* The length written, None if the buffer was too short */
+    pub fn finish(&self) -> Option<usize> {
+        if self.overflow { None } else { Some(self.length) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Encodes the item and parses it back */
+    fn round_trip(item: Item, encoded: &[u8]) {
+        let mut out = [0u8; 8];
+        let length = item.encode(&mut out).unwrap();
+        assert_eq!(&out[..length], encoded);
+        let mut items = items(encoded);
+        assert_eq!(items.next(), Some(Ok((0, item))));
+        assert_eq!(items.next(), None);
+    }
+
+    #[test]
+    fn encodes_and_parses_short_items() {
+        round_trip(Item::new(ItemType::Main, END_COLLECTION, 0), &[0xc0]);
+        round_trip(Item::new(ItemType::Global, USAGE_PAGE, 0x01), &[0x05, 0x01]);
+        round_trip(Item::new(ItemType::Global, LOGICAL_MAXIMUM, 0x7fff), &[0x26, 0xff, 0x7f]);
+        round_trip(Item::new(ItemType::Local, USAGE, 0x000d_0022), &[0x0b, 0x22, 0x00, 0x0d, 0x00]);
+        round_trip(Item::signed(ItemType::Global, LOGICAL_MINIMUM, -1), &[0x15, 0xff]);
+
+        let mut out = [0u8; 2];
+        assert_eq!(Item::new(ItemType::Global, UNIT, 0x1234).encode(&mut out), None);
+    }
+
+    #[test]
+    fn skips_long_items() {
+        let data = [0xfe, 0x02, 0x10, 0xaa, 0xbb, 0x05, 0x01];
+        let mut items = items(&data);
+        assert_eq!(items.next(), Some(Ok((5, Item::new(ItemType::Global, USAGE_PAGE, 0x01)))));
+        assert_eq!(items.next(), None);
+    }
+
+    #[test]
+    fn stops_at_truncated_items() {
+        let data = [0x05, 0x01, 0x27, 0xff, 0xff];
+        let mut parsed = items(&data);
+        assert!(parsed.next().unwrap().is_ok());
+        assert_eq!(parsed.next(), Some(Err(Truncated { offset: 2 })));
+        assert_eq!(parsed.next(), None);
+
+        assert_eq!(items(&[0xfe]).next(), Some(Err(Truncated { offset: 0 })));
+        assert_eq!(items(&[0xfe, 0x04, 0x10, 0xaa]).next(), Some(Err(Truncated { offset: 0 })));
+    }
+
+    #[test]
+    fn sign_extends_data() {
+        let sizes = [(0, 0), (-127, 1), (127, 1), (-128, 1), (-32768, 2), (32767, 2), (40000, 4), (i32::MIN, 4)];
+        for &(value, size) in sizes.iter() {
+            let item = Item::signed(ItemType::Global, LOGICAL_MINIMUM, value);
+            assert_eq!(item.size, size);
+            assert_eq!(item.signed_data(), value);
+        }
+        assert_eq!(Item::new(ItemType::Global, LOGICAL_MAXIMUM, 0xff).signed_data(), -1);
+        assert_eq!(Item::new(ItemType::Global, LOGICAL_MAXIMUM, 0x8000).signed_data(), -32768);
+    }
+}
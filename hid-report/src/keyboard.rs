@@ -0,0 +1,86 @@
+/*
+ * Boot keyboard
+ * Report 0x01 in the boot keyboard layout: modifier bits, a reserved byte
+ * and up to six pressed keys given as usages of the Keyboard/Keypad page.
+ * The host sets the LEDs with output report 0x01 carrying NumLock,
+ * CapsLock, ScrollLock, Compose and Kana bits.
+ */
+
+use usage;
+
+pub const MOD_LEFT_CTRL: u8 = 0x01;
+pub const MOD_LEFT_SHIFT: u8 = 0x02;
+pub const MOD_RIGHT_ALT: u8 = 0x40;
+
+pub const KEY_A: u8 = usage::keyboard::A as u8;
+pub const KEY_C: u8 = usage::keyboard::C as u8;
+pub const KEY_V: u8 = usage::keyboard::V as u8;
+pub const KEY_CAPS_LOCK: u8 = usage::keyboard::CAPS_LOCK as u8;
+pub const KEY_RIGHT: u8 = usage::keyboard::RIGHT as u8;
+pub const KEY_LEFT: u8 = usage::keyboard::LEFT as u8;
+pub const KEY_DOWN: u8 = usage::keyboard::DOWN as u8;
+pub const KEY_UP: u8 = usage::keyboard::UP as u8;
+pub const KEY_PAGE_UP: u8 = usage::keyboard::PAGE_UP as u8;
+pub const KEY_PAGE_DOWN: u8 = usage::keyboard::PAGE_DOWN as u8;
+
+pub const LED_NUM_LOCK: u8 = 0x01;
+pub const LED_CAPS_LOCK: u8 = 0x02;
+pub const LED_SCROLL_LOCK: u8 = 0x04;
+
+/* Report 0x01 of the boot keyboard, including its LED output report */
+pub const BOOT_KEYBOARD_RDESC: [u8; 65] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x06,	/* USAGE (Keyboard) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x01,		/* REPORT_ID (1) */
+    0x05, 0x07,		/* USAGE_PAGE (Keyboard) */
+    0x19, 0xe0,		/* USAGE_MINIMUM (Keyboard LeftControl) */
+    0x29, 0xe7,		/* USAGE_MAXIMUM (Keyboard Right GUI) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x95, 0x08,		/* REPORT_COUNT (8) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x81, 0x01,		/* INPUT (Cnst,Ary,Abs) */
+    0x05, 0x08,		/* USAGE_PAGE (LEDs) */
+    0x19, 0x01,		/* USAGE_MINIMUM (Num Lock) */
+    0x29, 0x05,		/* USAGE_MAXIMUM (Kana) */
+    0x95, 0x05,		/* REPORT_COUNT (5) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x91, 0x02,		/* OUTPUT (Data,Var,Abs) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x75, 0x03,		/* REPORT_SIZE (3) */
+    0x91, 0x01,		/* OUTPUT (Cnst,Ary,Abs) */
+    0x05, 0x07,		/* USAGE_PAGE (Keyboard) */
+    0x19, 0x00,		/* USAGE_MINIMUM (Reserved (no event indicated)) */
+    0x29, 0x65,		/* USAGE_MAXIMUM (Keyboard Application) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x25, 0x65,		/* LOGICAL_MAXIMUM (101) */
+    0x95, 0x06,		/* REPORT_COUNT (6) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x81, 0x00,		/* INPUT (Data,Ary,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+pub const REPORT_LEN: usize = 9;
+
+/* Builds report 0x01 for the given modifiers and at most six pressed keys */
+pub fn report(modifiers: u8, keys: &[u8]) -> [u8; REPORT_LEN] {
+    let mut data = [0u8; REPORT_LEN];
+    data[0] = 0x01;
+    data[1] = modifiers;
+    for (slot, key) in data[3..].iter_mut().zip(keys.iter()) {
+        *slot = *key;
+    }
+    data
+}
+
+/* LED state as sent by the host in output report 0x01 */
+pub fn leds(data: &[u8]) -> Option<u8> {
+    match data {
+        [0x01, leds] => Some(*leds),
+        _ => None,
+    }
+}
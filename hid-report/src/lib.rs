@@ -0,0 +1,14 @@
+/*
+ * HID report encoding
+ * The pure parts of describing a HID device: descriptor items, packing of
+ * report fields and the report definitions of standard devices. Nothing in
+ * here allocates or touches the OS, so firmware can share the definitions
+ * the virtual devices use and test against them on the host.
+ */
+
+#![no_std]
+
+pub mod bits;
+pub mod descriptor;
+pub mod keyboard;
+pub mod usage;
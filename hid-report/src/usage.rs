@@ -0,0 +1,360 @@
+/*
+ * HID usage tables
+ * Symbolic names for the usages of the HID Usage Tables (HUT) pages devices
+ * here use, so descriptors can be built and reports described without magic
+ * numbers, e.g.
+ *
+ *   builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::MOUSE)
+ *
+ * Each page is a module with its PAGE number, the usage ids as constants and
+ * NAMES, the ids with the names they are printed with, in order. The pages
+ * hold the usages of common devices rather than the whole of the HUT;
+ * usages missing here have no name() and are usually shown as page:id in
+ * hex. Keys are named by their US legend.
+ */
+
+macro_rules! page {
+    ($module:ident, $page:expr, $title:expr, $($name:ident = $id:expr, $text:expr;)*) => {
+        pub mod $module {
+            pub const PAGE: u32 = $page;
+            pub const TITLE: &str = $title;
+            $(pub const $name: u32 = $id;)*
+            pub const NAMES: &[(u32, &str)] = &[$(($id, $text)),*];
+        }
+    };
+}
+
+page! {
+    generic_desktop, 0x01, "Generic Desktop",
+    POINTER = 0x01, "Pointer";
+    MOUSE = 0x02, "Mouse";
+    JOYSTICK = 0x04, "Joystick";
+    GAME_PAD = 0x05, "Game Pad";
+    KEYBOARD = 0x06, "Keyboard";
+    KEYPAD = 0x07, "Keypad";
+    MULTI_AXIS_CONTROLLER = 0x08, "Multi-axis Controller";
+    X = 0x30, "X";
+    Y = 0x31, "Y";
+    Z = 0x32, "Z";
+    RX = 0x33, "Rx";
+    RY = 0x34, "Ry";
+    RZ = 0x35, "Rz";
+    SLIDER = 0x36, "Slider";
+    DIAL = 0x37, "Dial";
+    WHEEL = 0x38, "Wheel";
+    HAT_SWITCH = 0x39, "Hat switch";
+    COUNTED_BUFFER = 0x3a, "Counted Buffer";
+    BYTE_COUNT = 0x3b, "Byte Count";
+    MOTION_WAKEUP = 0x3c, "Motion Wakeup";
+    START = 0x3d, "Start";
+    SELECT = 0x3e, "Select";
+    VX = 0x40, "Vx";
+    VY = 0x41, "Vy";
+    VZ = 0x42, "Vz";
+    VBRX = 0x43, "Vbrx";
+    VBRY = 0x44, "Vbry";
+    VBRZ = 0x45, "Vbrz";
+    VNO = 0x46, "Vno";
+    FEATURE_NOTIFICATION = 0x47, "Feature Notification";
+    RESOLUTION_MULTIPLIER = 0x48, "Resolution Multiplier";
+    SYSTEM_CONTROL = 0x80, "System Control";
+    SYSTEM_POWER_DOWN = 0x81, "System Power Down";
+    SYSTEM_SLEEP = 0x82, "System Sleep";
+    SYSTEM_WAKE_UP = 0x83, "System Wake Up";
+    SYSTEM_CONTEXT_MENU = 0x84, "System Context Menu";
+    SYSTEM_MAIN_MENU = 0x85, "System Main Menu";
+    SYSTEM_APP_MENU = 0x86, "System App Menu";
+    DPAD_UP = 0x90, "D-pad Up";
+    DPAD_DOWN = 0x91, "D-pad Down";
+    DPAD_RIGHT = 0x92, "D-pad Right";
+    DPAD_LEFT = 0x93, "D-pad Left";
+}
+
+page! {
+    keyboard, 0x07, "Keyboard/Keypad",
+    A = 0x04, "A";
+    B = 0x05, "B";
+    C = 0x06, "C";
+    D = 0x07, "D";
+    E = 0x08, "E";
+    F = 0x09, "F";
+    G = 0x0a, "G";
+    H = 0x0b, "H";
+    I = 0x0c, "I";
+    J = 0x0d, "J";
+    K = 0x0e, "K";
+    L = 0x0f, "L";
+    M = 0x10, "M";
+    N = 0x11, "N";
+    O = 0x12, "O";
+    P = 0x13, "P";
+    Q = 0x14, "Q";
+    R = 0x15, "R";
+    S = 0x16, "S";
+    T = 0x17, "T";
+    U = 0x18, "U";
+    V = 0x19, "V";
+    W = 0x1a, "W";
+    X = 0x1b, "X";
+    Y = 0x1c, "Y";
+    Z = 0x1d, "Z";
+    DIGIT_1 = 0x1e, "1";
+    DIGIT_2 = 0x1f, "2";
+    DIGIT_3 = 0x20, "3";
+    DIGIT_4 = 0x21, "4";
+    DIGIT_5 = 0x22, "5";
+    DIGIT_6 = 0x23, "6";
+    DIGIT_7 = 0x24, "7";
+    DIGIT_8 = 0x25, "8";
+    DIGIT_9 = 0x26, "9";
+    DIGIT_0 = 0x27, "0";
+    ENTER = 0x28, "Enter";
+    ESCAPE = 0x29, "Escape";
+    BACKSPACE = 0x2a, "Backspace";
+    TAB = 0x2b, "Tab";
+    SPACE = 0x2c, "Space";
+    MINUS = 0x2d, "-";
+    EQUAL = 0x2e, "=";
+    LEFT_BRACKET = 0x2f, "[";
+    RIGHT_BRACKET = 0x30, "]";
+    BACKSLASH = 0x31, "\\";
+    NON_US_HASH = 0x32, "Non-US #";
+    SEMICOLON = 0x33, ";";
+    APOSTROPHE = 0x34, "'";
+    GRAVE = 0x35, "`";
+    COMMA = 0x36, ",";
+    PERIOD = 0x37, ".";
+    SLASH = 0x38, "/";
+    CAPS_LOCK = 0x39, "Caps Lock";
+    F1 = 0x3a, "F1";
+    F2 = 0x3b, "F2";
+    F3 = 0x3c, "F3";
+    F4 = 0x3d, "F4";
+    F5 = 0x3e, "F5";
+    F6 = 0x3f, "F6";
+    F7 = 0x40, "F7";
+    F8 = 0x41, "F8";
+    F9 = 0x42, "F9";
+    F10 = 0x43, "F10";
+    F11 = 0x44, "F11";
+    F12 = 0x45, "F12";
+    PRINT_SCREEN = 0x46, "Print Screen";
+    SCROLL_LOCK = 0x47, "Scroll Lock";
+    PAUSE = 0x48, "Pause";
+    INSERT = 0x49, "Insert";
+    HOME = 0x4a, "Home";
+    PAGE_UP = 0x4b, "Page Up";
+    DELETE = 0x4c, "Delete";
+    END = 0x4d, "End";
+    PAGE_DOWN = 0x4e, "Page Down";
+    RIGHT = 0x4f, "Right";
+    LEFT = 0x50, "Left";
+    DOWN = 0x51, "Down";
+    UP = 0x52, "Up";
+    NUM_LOCK = 0x53, "Num Lock";
+    KP_SLASH = 0x54, "Keypad /";
+    KP_ASTERISK = 0x55, "Keypad *";
+    KP_MINUS = 0x56, "Keypad -";
+    KP_PLUS = 0x57, "Keypad +";
+    KP_ENTER = 0x58, "Keypad Enter";
+    KP_1 = 0x59, "Keypad 1";
+    KP_2 = 0x5a, "Keypad 2";
+    KP_3 = 0x5b, "Keypad 3";
+    KP_4 = 0x5c, "Keypad 4";
+    KP_5 = 0x5d, "Keypad 5";
+    KP_6 = 0x5e, "Keypad 6";
+    KP_7 = 0x5f, "Keypad 7";
+    KP_8 = 0x60, "Keypad 8";
+    KP_9 = 0x61, "Keypad 9";
+    KP_0 = 0x62, "Keypad 0";
+    KP_PERIOD = 0x63, "Keypad .";
+    NON_US_BACKSLASH = 0x64, "Non-US \\";
+    APPLICATION = 0x65, "Application";
+    POWER = 0x66, "Power";
+    KP_EQUAL = 0x67, "Keypad =";
+    F13 = 0x68, "F13";
+    F14 = 0x69, "F14";
+    F15 = 0x6a, "F15";
+    F16 = 0x6b, "F16";
+    F17 = 0x6c, "F17";
+    F18 = 0x6d, "F18";
+    F19 = 0x6e, "F19";
+    F20 = 0x6f, "F20";
+    F21 = 0x70, "F21";
+    F22 = 0x71, "F22";
+    F23 = 0x72, "F23";
+    F24 = 0x73, "F24";
+    EXECUTE = 0x74, "Execute";
+    HELP = 0x75, "Help";
+    MENU = 0x76, "Menu";
+    SELECT = 0x77, "Select";
+    STOP = 0x78, "Stop";
+    AGAIN = 0x79, "Again";
+    UNDO = 0x7a, "Undo";
+    CUT = 0x7b, "Cut";
+    COPY = 0x7c, "Copy";
+    PASTE = 0x7d, "Paste";
+    FIND = 0x7e, "Find";
+    MUTE = 0x7f, "Mute";
+    VOLUME_UP = 0x80, "Volume Up";
+    VOLUME_DOWN = 0x81, "Volume Down";
+    KP_COMMA = 0x85, "Keypad ,";
+    INTERNATIONAL_1 = 0x87, "International1";
+    INTERNATIONAL_2 = 0x88, "International2";
+    INTERNATIONAL_3 = 0x89, "International3";
+    INTERNATIONAL_4 = 0x8a, "International4";
+    INTERNATIONAL_5 = 0x8b, "International5";
+    LANG_1 = 0x90, "LANG1";
+    LANG_2 = 0x91, "LANG2";
+    LEFT_CONTROL = 0xe0, "Left Control";
+    LEFT_SHIFT = 0xe1, "Left Shift";
+    LEFT_ALT = 0xe2, "Left Alt";
+    LEFT_GUI = 0xe3, "Left GUI";
+    RIGHT_CONTROL = 0xe4, "Right Control";
+    RIGHT_SHIFT = 0xe5, "Right Shift";
+    RIGHT_ALT = 0xe6, "Right Alt";
+    RIGHT_GUI = 0xe7, "Right GUI";
+}
+
+page! {
+    led, 0x08, "LED",
+    NUM_LOCK = 0x01, "Num Lock";
+    CAPS_LOCK = 0x02, "Caps Lock";
+    SCROLL_LOCK = 0x03, "Scroll Lock";
+    COMPOSE = 0x04, "Compose";
+    KANA = 0x05, "Kana";
+    POWER = 0x06, "Power";
+    SHIFT = 0x07, "Shift";
+    DO_NOT_DISTURB = 0x08, "Do Not Disturb";
+    MUTE = 0x09, "Mute";
+    OFF_HOOK = 0x17, "Off-Hook";
+    RING = 0x18, "Ring";
+    MESSAGE_WAITING = 0x19, "Message Waiting";
+    DATA_MODE = 0x1a, "Data Mode";
+    BATTERY_OPERATION = 0x1b, "Battery Operation";
+    BATTERY_OK = 0x1c, "Battery OK";
+    BATTERY_LOW = 0x1d, "Battery Low";
+    SPEAKER = 0x1e, "Speaker";
+    HEADSET = 0x1f, "Headset";
+    HOLD = 0x20, "Hold";
+    MICROPHONE = 0x21, "Microphone";
+    CAMERA_ON = 0x28, "Camera On";
+    CAMERA_OFF = 0x29, "Camera Off";
+    GENERIC_INDICATOR = 0x4b, "Generic Indicator";
+}
+
+page! {
+    button, 0x09, "Button",
+    NO_BUTTON = 0x00, "No Button";
+    PRIMARY = 0x01, "Button 1";
+    SECONDARY = 0x02, "Button 2";
+    TERTIARY = 0x03, "Button 3";
+}
+
+page! {
+    consumer, 0x0c, "Consumer",
+    CONSUMER_CONTROL = 0x01, "Consumer Control";
+    NUMERIC_KEY_PAD = 0x02, "Numeric Key Pad";
+    PROGRAMMABLE_BUTTONS = 0x03, "Programmable Buttons";
+    POWER = 0x30, "Power";
+    RESET = 0x31, "Reset";
+    SLEEP = 0x32, "Sleep";
+    MENU = 0x40, "Menu";
+    BRIGHTNESS_INCREMENT = 0x6f, "Display Brightness Increment";
+    BRIGHTNESS_DECREMENT = 0x70, "Display Brightness Decrement";
+    PLAY = 0xb0, "Play";
+    PAUSE = 0xb1, "Pause";
+    RECORD = 0xb2, "Record";
+    FAST_FORWARD = 0xb3, "Fast Forward";
+    REWIND = 0xb4, "Rewind";
+    SCAN_NEXT_TRACK = 0xb5, "Scan Next Track";
+    SCAN_PREVIOUS_TRACK = 0xb6, "Scan Previous Track";
+    STOP = 0xb7, "Stop";
+    EJECT = 0xb8, "Eject";
+    PLAY_PAUSE = 0xcd, "Play/Pause";
+    VOLUME = 0xe0, "Volume";
+    MUTE = 0xe2, "Mute";
+    BASS = 0xe3, "Bass";
+    VOLUME_INCREMENT = 0xe9, "Volume Increment";
+    VOLUME_DECREMENT = 0xea, "Volume Decrement";
+    AL_CONSUMER_CONTROL_CONFIGURATION = 0x183, "AL Consumer Control Configuration";
+    AL_EMAIL_READER = 0x18a, "AL Email Reader";
+    AL_CALCULATOR = 0x192, "AL Calculator";
+    AL_LOCAL_BROWSER = 0x194, "AL Local Machine Browser";
+    AC_SEARCH = 0x221, "AC Search";
+    AC_HOME = 0x223, "AC Home";
+    AC_BACK = 0x224, "AC Back";
+    AC_FORWARD = 0x225, "AC Forward";
+    AC_STOP = 0x226, "AC Stop";
+    AC_REFRESH = 0x227, "AC Refresh";
+    AC_BOOKMARKS = 0x22a, "AC Bookmarks";
+    AC_PAN = 0x238, "AC Pan";
+}
+
+page! {
+    digitizer, 0x0d, "Digitizer",
+    DIGITIZER = 0x01, "Digitizer";
+    PEN = 0x02, "Pen";
+    TOUCH_SCREEN = 0x04, "Touch Screen";
+    TOUCH_PAD = 0x05, "Touch Pad";
+    DEVICE_CONFIGURATION = 0x0e, "Device Configuration";
+    STYLUS = 0x20, "Stylus";
+    FINGER = 0x22, "Finger";
+    TIP_PRESSURE = 0x30, "Tip Pressure";
+    BARREL_PRESSURE = 0x31, "Barrel Pressure";
+    IN_RANGE = 0x32, "In Range";
+    TOUCH = 0x33, "Touch";
+    INVERT = 0x3c, "Invert";
+    X_TILT = 0x3d, "X Tilt";
+    Y_TILT = 0x3e, "Y Tilt";
+    AZIMUTH = 0x3f, "Azimuth";
+    ALTITUDE = 0x40, "Altitude";
+    TWIST = 0x41, "Twist";
+    TIP_SWITCH = 0x42, "Tip Switch";
+    SECONDARY_TIP_SWITCH = 0x43, "Secondary Tip Switch";
+    BARREL_SWITCH = 0x44, "Barrel Switch";
+    ERASER = 0x45, "Eraser";
+    CONFIDENCE = 0x47, "Confidence";
+    WIDTH = 0x48, "Width";
+    HEIGHT = 0x49, "Height";
+    CONTACT_IDENTIFIER = 0x51, "Contact Id";
+    DEVICE_MODE = 0x52, "Device Mode";
+    CONTACT_COUNT = 0x54, "Contact Count";
+    CONTACT_COUNT_MAXIMUM = 0x55, "Contact Count Maximum";
+    SCAN_TIME = 0x56, "Scan Time";
+    TRANSDUCER_SERIAL_NUMBER = 0x5b, "Transducer Serial Number";
+}
+
+/* Usage ids with their names, in order */
+type Names = &'static [(u32, &'static str)];
+
+const PAGES: &[(u32, &str, Names)] = &[
+    (generic_desktop::PAGE, generic_desktop::TITLE, generic_desktop::NAMES),
+    (keyboard::PAGE, keyboard::TITLE, keyboard::NAMES),
+    (led::PAGE, led::TITLE, led::NAMES),
+    (button::PAGE, button::TITLE, button::NAMES),
+    (consumer::PAGE, consumer::TITLE, consumer::NAMES),
+    (digitizer::PAGE, digitizer::TITLE, digitizer::NAMES),
+];
+
+/* Vendor defined pages, 0xff00 to 0xffff */
+pub const VENDOR_PAGES: u32 = 0xff00;
+
+/* A usage with its page in the upper 16 bits, as 4 byte usage items hold it */
+pub const fn extended(page: u32, id: u32) -> u32 {
+    page << 16 | id
+}
+
+pub fn page_name(page: u32) -> Option<&'static str> {
+    if (VENDOR_PAGES..=0xffff).contains(&page) {
+        return Some("Vendor Defined");
+    }
+    PAGES.iter().find(|&&(number, _, _)| number == page).map(|&(_, title, _)| title)
+}
+
+/* The name of an extended usage, if its page and id are in the tables */
+pub fn name(usage: u32) -> Option<&'static str> {
+    let (page, id) = (usage >> 16, usage & 0xffff);
+    let &(_, _, names) = PAGES.iter().find(|&&(number, _, _)| number == page)?;
+    names.binary_search_by_key(&id, |&(id, _)| id).ok().map(|index| names[index].1)
+}
@@ -0,0 +1,89 @@
+/*
+ * UHID Example C API
+ * Creates virtual HID devices through uhid, backed by libuhid_example. All
+ * functions but uhid_device_create() return 0 on success and a negative
+ * errno value on failure.
+ */
+
+#ifndef UHID_EXAMPLE_H
+#define UHID_EXAMPLE_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define UHID_DEVICE_DATA_MAX 4096
+
+struct uhid_device;
+
+struct uhid_device_event {
+	uint32_t type;		/* enum uhid_event_type from <linux/uhid.h> */
+	uint32_t id;		/* GET_REPORT and SET_REPORT request to reply to */
+	uint8_t rnum;		/* report number of GET_REPORT and SET_REPORT */
+	uint8_t rtype;		/* enum uhid_report_type */
+	uint16_t size;
+	uint8_t data[UHID_DEVICE_DATA_MAX];	/* OUTPUT and SET_REPORT data */
+};
+
+struct uhid_device_report_stats {
+	uint64_t answered;
+	uint64_t timed_out;	/* answered with ETIMEDOUT, see below */
+	uint64_t late;		/* replies refused after timing out */
+};
+
+/*
+ * Opens path, /dev/uhid when NULL, and creates a device with the given report
+ * descriptor. name may be NULL for the default name. Returns NULL and sets
+ * errno on failure.
+ */
+struct uhid_device *uhid_device_create(const char *path, const char *name,
+				       const uint8_t *rdesc, size_t rdesc_size,
+				       uint16_t bus, uint32_t vendor,
+				       uint32_t product, uint32_t version,
+				       uint32_t country);
+
+/* Destroys the device and frees it */
+void uhid_device_destroy(struct uhid_device *device);
+
+/* File descriptor to wait on in an existing poll loop */
+int uhid_device_fd(const struct uhid_device *device);
+
+/* Sends an input report, data[0] is the report-id for numbered reports */
+int uhid_device_send_input(struct uhid_device *device, const uint8_t *data,
+			   size_t size);
+
+/*
+ * Waits up to timeout_ms, or forever when negative, for the next event.
+ * Returns 1 with the event filled in, 0 on timeout. GET_REPORT requests not
+ * replied to within the report timeout are answered with ETIMEDOUT meanwhile,
+ * so readers of the report are not left blocked.
+ */
+int uhid_device_poll_event(struct uhid_device *device, int timeout_ms,
+			   struct uhid_device_event *event);
+
+/* Replies to GET_REPORT and SET_REPORT events, err is a positive errno or 0 */
+int uhid_device_get_report_reply(struct uhid_device *device, uint32_t id,
+				 uint16_t err, const uint8_t *data,
+				 size_t size);
+int uhid_device_set_report_reply(struct uhid_device *device, uint32_t id,
+				 uint16_t err);
+
+/*
+ * Sets how long GET_REPORT requests wait for a reply, 1000ms by default.
+ * A reply to a request that timed out returns -ETIMEDOUT and is not sent.
+ */
+int uhid_device_set_report_timeout(struct uhid_device *device,
+				   int timeout_ms);
+
+/* Counts of the GET_REPORT requests answered, timed out and replied late */
+int uhid_device_report_stats(const struct uhid_device *device,
+			     struct uhid_device_report_stats *stats);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* UHID_EXAMPLE_H */
@@ -0,0 +1,183 @@
+/*
+ * Authentication
+ * A pre-shared token connections from the network have to send before
+ * anything they send is taken as input, since whoever reaches the socket
+ * could otherwise type into the desktop. It is read from the file given with
+ * --token-file, or else the UHID_EXAMPLE_TOKEN environment variable, e.g. set
+ * with an EnvironmentFile= of the systemd unit; a file keeps it out of the
+ * process list. Surrounding whitespace is not part of it.
+ *
+ * The token is required of
+ *
+ *   connections to the control socket: a line with the token, then the keys
+ *   vsock-serve connections: an "auth <token>" frame, answered with "ok",
+ *     before any command
+ *
+ * and a control socket on TCP refuses to start without one, unless TLS
+ * verifies the certificates of its clients, see src/tls.rs. Connections
+ * sending anything else are closed. Every connection is logged to stderr
+ * with its peer, as it is accepted, authenticated or refused, the commands
+ * it sends and when it closes, for the journal to keep.
+ */
+
+use libc;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::mem;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+
+pub const TOKEN_VAR: &str = "UHID_EXAMPLE_TOKEN";
+
+/* Longest line a control connection can send the token in */
+pub const MAX_TOKEN: usize = 256;
+
+#[derive(Clone)]
+pub struct Auth {
+    token: Vec<u8>,
+}
+
+impl Auth {
+    /* The token of the file, or of the environment without one; None when
+     * neither is set */
+    pub fn load(path: Option<&Path>) -> io::Result<Option<Auth>> {
+        let token = match path {
+            Some(path) => fs::read_to_string(path)?,
+            None => match env::var(TOKEN_VAR) {
+                Ok(token) => token,
+                Err(_) => return Ok(None),
+            },
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The token is empty"));
+        }
+        if token.len() > MAX_TOKEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The token is longer than {} bytes", MAX_TOKEN)));
+        }
+        Ok(Some(Auth { token: token.as_bytes().to_vec() }))
+    }
+
+    /* Whether the token offered is the one, taking as long whatever it
+     * differs in so the time does not tell how much of it was right */
+    pub fn check(&self, offered: &[u8]) -> bool {
+        let mut difference = (self.token.len() != offered.len()) as u8;
+        for (index, &byte) in self.token.iter().enumerate() {
+            difference |= byte ^ offered.get(index).cloned().unwrap_or(0);
+        }
+        difference == 0
+    }
+}
+
+/* The address of a socket or its peer, as far as it is known */
+pub enum Peer {
+    Inet(Ipv4Addr, u16),
+    Inet6(Ipv6Addr, u16),
+    Vsock(u32, u32),
+    Unix,
+    Unknown,
+}
+
+impl Peer {
+    fn from(address: &libc::sockaddr_storage) -> Peer {
+        match address.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let address = unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+                Peer::Inet(Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)), u16::from_be(address.sin_port))
+            },
+            libc::AF_INET6 => {
+                let address = unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+                Peer::Inet6(Ipv6Addr::from(address.sin6_addr.s6_addr), u16::from_be(address.sin6_port))
+            },
+            libc::AF_VSOCK => {
+                let address = unsafe { &*(address as *const libc::sockaddr_storage as *const libc::sockaddr_vm) };
+                Peer::Vsock(address.svm_cid, address.svm_port)
+            },
+            libc::AF_UNIX => Peer::Unix,
+            _ => Peer::Unknown,
+        }
+    }
+
+    /* Whether it is reached over the network */
+    pub fn is_network(&self) -> bool {
+        matches!(*self, Peer::Inet(..) | Peer::Inet6(..))
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Peer::Inet(address, port) => write!(f, "{}:{}", address, port),
+            Peer::Inet6(address, port) => write!(f, "[{}]:{}", address, port),
+            Peer::Vsock(cid, port) => write!(f, "{}:{}", cid, port),
+            Peer::Unix => write!(f, "unix socket"),
+            Peer::Unknown => write!(f, "unknown peer"),
+        }
+    }
+}
+
+fn address(fd: RawFd, name: unsafe extern "C" fn(libc::c_int, *mut libc::sockaddr, *mut libc::socklen_t) -> libc::c_int) -> Peer {
+    let mut address: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let mut length = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    if unsafe { name(fd, &mut address as *mut libc::sockaddr_storage as *mut libc::sockaddr, &mut length) } < 0 {
+        return Peer::Unknown;
+    }
+    Peer::from(&address)
+}
+
+/* What a listening socket is bound to */
+pub fn local(fd: RawFd) -> Peer {
+    address(fd, libc::getsockname)
+}
+
+/* Who is at the other end of a connection */
+pub fn peer(fd: RawFd) -> Peer {
+    address(fd, libc::getpeername)
+}
+
+/* Logs what a connection did, to stderr like everything else */
+pub fn audit(connection: &str, what: fmt::Arguments) {
+    eprintln!("Audit: {}: {}", connection, what);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+
+    /* Loads the token from a file holding the text */
+    fn load(name: &str, text: &str) -> io::Result<Option<Auth>> {
+        let path = env::temp_dir().join(format!("uhid-example-token-{}-{}", process::id(), name));
+        fs::write(&path, text)?;
+        let auth = Auth::load(Some(&path));
+        fs::remove_file(&path)?;
+        auth
+    }
+
+    #[test]
+    fn checks_the_whole_token() {
+        let auth = load("check", "secret").unwrap().unwrap();
+        assert!(auth.check(b"secret"));
+        assert!(!auth.check(b"secre"));
+        assert!(!auth.check(b"secrets"));
+        assert!(!auth.check(b"secreT"));
+        assert!(!auth.check(b""));
+    }
+
+    #[test]
+    fn trims_the_token_file() {
+        let auth = load("trim", "  secret\n").unwrap().unwrap();
+        assert!(auth.check(b"secret"));
+        assert!(!auth.check(b"  secret\n"));
+    }
+
+    #[test]
+    fn refuses_empty_and_long_tokens() {
+        assert_eq!(load("empty", " \n").map(|_| ()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(load("longest", &"x".repeat(MAX_TOKEN)).unwrap().is_some());
+        assert_eq!(load("long", &"x".repeat(MAX_TOKEN + 1)).map(|_| ()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Autoclicker
+ * Clicks a button at a steady rate while running, each click a press held
+ * for the given time and its release. Clicks are timed from when clicking
+ * was started, so the rate holds on average even if the event loop wakes up
+ * a little late; clicks missed over longer stalls are skipped rather than
+ * made up for in a burst. Stopping while the button is down releases it.
+ */
+
+use devices::Action;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_CPS: u32 = 10;
+pub const DEFAULT_HOLD: Duration = Duration::from_millis(20);
+
+pub struct Autoclicker {
+    button: u8,
+    interval: Duration,
+    hold: Duration,
+    /* Start of the current run and clicks made since */
+    running: Option<(Instant, u32)>,
+    pressed: bool,
+}
+
+impl Autoclicker {
+    /* None if the button cannot be held that long at that rate */
+    pub fn new(button: u8, clicks_per_second: u32, hold: Duration) -> Option<Autoclicker> {
+        let interval = Duration::from_secs(1) / clicks_per_second.max(1);
+        if hold.is_zero() || hold >= interval {
+            return None;
+        }
+        Some(Autoclicker {
+            button,
+            interval,
+            hold,
+            running: None,
+            pressed: false,
+        })
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /* Starts or stops clicking, returning the release if the button is down */
+    pub fn toggle(&mut self, now: Instant) -> Vec<Action> {
+        if self.running.take().is_none() {
+            self.running = Some((now, 0));
+            return vec![];
+        }
+        if self.pressed {
+            self.pressed = false;
+            return vec![Action::ToggleButton(self.button)];
+        }
+        vec![]
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        let (start, clicks) = self.running?;
+        let press = start + self.interval * clicks;
+        Some(if self.pressed { press + self.hold } else { press })
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Vec<Action> {
+        let mut actions = Vec::new();
+        while let Some(due) = self.deadline() {
+            if due > now {
+                break;
+            }
+            if !self.pressed && due + self.interval <= now {
+                self.running = Some((now, 0));
+                continue;
+            }
+            if self.pressed {
+                if let Some((_, ref mut clicks)) = self.running {
+                    *clicks += 1;
+                }
+            }
+            self.pressed = !self.pressed;
+            actions.push(Action::ToggleButton(self.button));
+        }
+        actions
+    }
+}
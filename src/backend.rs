@@ -0,0 +1,298 @@
+/*
+ * Backends
+ * The transports devices are brought to the kernel with. A device only makes
+ * and takes reports, the backend creates it somewhere, sends its input
+ * reports and passes back the events for it: uhid creates a HID device in
+ * the kernel through the uhid-cdev, mock keeps the reports in memory, e.g. to
+ * run the devices in CI where there is no uhid, and dry-run prints them, to
+ * develop descriptors and scripts without root. Further transports, like
+ * uinput or the hidg device of a USB gadget, can be added by implementing
+ * Backend, without touching the devices.
+ */
+
+use decode::Reports;
+use libc;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use uhid::{self, Event, Identity};
+
+pub const BACKEND_NAMES: &[&str] = &["uhid", "mock", "dry-run"];
+
+pub trait Backend {
+    fn create(&mut self, name: &str, descriptor: &[u8], identity: &Identity) -> io::Result<()>;
+
+    fn destroy(&mut self) -> io::Result<()>;
+
+    /* Starts over with a fresh connection to where devices are created, like
+     * the uhid-cdev after it went away; the fd may change */
+    fn reopen(&mut self) -> io::Result<()>;
+
+    /* Sends one input report; data[0] is the report-id for numbered reports */
+    fn input(&mut self, report: &[u8]) -> io::Result<()>;
+
+    /* The next event for the device, None if there is none right now */
+    fn read_event(&mut self) -> io::Result<Option<Event>>;
+
+    fn get_report_reply(&mut self, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()>;
+
+    fn set_report_reply(&mut self, id: u32, result: io::Result<()>) -> io::Result<()>;
+
+    /* Readable when there are events, to poll along with other input */
+    fn fd(&self) -> RawFd;
+
+    /* The devices created, numbered from 0; more than one for split devices */
+    fn devices(&self) -> usize {
+        1
+    }
+
+    /* Sends the input reports to the device numbered so rather than the one
+     * declaring their report-id, until None is selected */
+    fn select(&mut self, device: Option<usize>) -> io::Result<()> {
+        match device {
+            None | Some(0) => Ok(()),
+            Some(device) => Err(no_device(device)),
+        }
+    }
+
+    /* The number of the device the last event came from */
+    fn current(&self) -> usize {
+        0
+    }
+}
+
+pub fn no_device(device: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("No device {}", device))
+}
+
+/* A device in the kernel, created through the uhid-cdev with UHID_CREATE2,
+ * or the legacy request on kernels before 3.15 */
+pub struct Uhid {
+    file: File,
+    /* whether CREATE2 was taken, the input then sent as UHID_INPUT2 */
+    created2: bool,
+    /* where the uhid-cdev is opened again from */
+    path: Option<PathBuf>,
+}
+
+impl Uhid {
+    /* Takes the opened uhid-cdev, non-blocking to be polled */
+    pub fn new(file: File) -> Uhid {
+        Uhid { file, created2: false, path: None }
+    }
+
+    /* Opens the uhid-cdev at the path again on reopen() */
+    pub fn reopens(mut self, path: &Path) -> Uhid {
+        self.path = Some(path.to_path_buf());
+        self
+    }
+}
+
+impl Backend for Uhid {
+    fn create(&mut self, name: &str, descriptor: &[u8], identity: &Identity) -> io::Result<()> {
+        self.created2 = uhid::create_any(&mut self.file, name, descriptor, identity)?;
+        Ok(())
+    }
+
+    fn destroy(&mut self) -> io::Result<()> {
+        uhid::destroy(&mut self.file)
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        if let Some(path) = self.path.as_ref() {
+            self.file = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC | libc::O_NONBLOCK).open(path)?;
+        }
+        Ok(())
+    }
+
+    fn input(&mut self, report: &[u8]) -> io::Result<()> {
+        uhid::send_input(&mut self.file, report, self.created2)
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        match uhid::read_event(&mut self.file) {
+            Ok(ev) => Ok(Some(Event::from_raw(&ev))),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_report_reply(&mut self, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()> {
+        uhid::get_report_reply(&mut self.file, id, result)
+    }
+
+    fn set_report_reply(&mut self, id: u32, result: io::Result<()>) -> io::Result<()> {
+        uhid::set_report_reply(&mut self.file, id, result)
+    }
+
+    fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/*
+ * Keeps the input reports instead of sending them anywhere. The device is
+ * started and opened right after it is created, as if something listened,
+ * and further events can be queued with push(). A pipe with a byte per
+ * queued event makes them pollable.
+ */
+pub struct Mock {
+    events: VecDeque<Event>,
+    reports: Vec<Vec<u8>>,
+    /* whether the input reports are kept, see without_reports() */
+    keeps_reports: bool,
+    replies: Vec<(u32, io::Result<Vec<u8>>)>,
+    created: bool,
+    reader: File,
+    writer: File,
+}
+
+impl Mock {
+    pub fn new() -> io::Result<Mock> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (reader, writer) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        Ok(Mock { events: VecDeque::new(), reports: vec![], replies: vec![], created: false, keeps_reports: true, reader, writer })
+    }
+
+    /* Checks the input reports and lets them go, for running indefinitely */
+    pub fn without_reports(mut self) -> Mock {
+        self.keeps_reports = false;
+        self
+    }
+
+    pub fn push(&mut self, event: Event) -> io::Result<()> {
+        self.events.push_back(event);
+        self.writer.write_all(&[0])
+    }
+
+    pub fn is_created(&self) -> bool {
+        self.created
+    }
+
+    /* The input reports sent so far */
+    pub fn reports(&self) -> &[Vec<u8>] {
+        &self.reports
+    }
+
+    /* The answers to GET_REPORT and SET_REPORT requests, by request id; set
+     * reports have no data */
+    pub fn replies(&self) -> &[(u32, io::Result<Vec<u8>>)] {
+        &self.replies
+    }
+}
+
+impl Backend for Mock {
+    fn create(&mut self, _name: &str, _descriptor: &[u8], _identity: &Identity) -> io::Result<()> {
+        if self.created {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Device already created"));
+        }
+        self.created = true;
+        self.push(Event::Start)?;
+        self.push(Event::Open)
+    }
+
+    fn destroy(&mut self) -> io::Result<()> {
+        self.created = false;
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn input(&mut self, report: &[u8]) -> io::Result<()> {
+        if !self.created {
+            return Err(io::Error::new(io::ErrorKind::NotConnected, "No device created"));
+        }
+        uhid::check_report(report)?;
+        if self.keeps_reports {
+            self.reports.push(report.to_vec());
+        }
+        Ok(())
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        let mut byte = [0u8; 1];
+        match self.reader.read(&mut byte) {
+            Ok(_) => Ok(self.events.pop_front()),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn get_report_reply(&mut self, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()> {
+        /* fails like uhid does for replies too large */
+        self.replies.push((id, result.and_then(|data| uhid::check_report(&data).map(|_| data))));
+        Ok(())
+    }
+
+    fn set_report_reply(&mut self, id: u32, result: io::Result<()>) -> io::Result<()> {
+        self.replies.push((id, result.map(|_| vec![])));
+        Ok(())
+    }
+
+    fn fd(&self) -> RawFd {
+        self.reader.as_raw_fd()
+    }
+}
+
+/*
+ * Creates no device either, printing each input report to stdout instead,
+ * in hex and decoded with the descriptor, see src/decode.rs. Otherwise it
+ * behaves like Mock, without keeping the reports printed.
+ */
+pub struct DryRun {
+    mock: Mock,
+    reports: Reports,
+}
+
+impl DryRun {
+    pub fn new() -> io::Result<DryRun> {
+        Ok(DryRun { mock: Mock::new()?.without_reports(), reports: Reports::parse(&[]) })
+    }
+}
+
+impl Backend for DryRun {
+    fn create(&mut self, name: &str, descriptor: &[u8], identity: &Identity) -> io::Result<()> {
+        self.reports = Reports::parse(descriptor);
+        eprintln!("Dry run, not creating {} with a {} byte descriptor", name, descriptor.len());
+        self.mock.create(name, descriptor, identity)
+    }
+
+    fn destroy(&mut self) -> io::Result<()> {
+        self.mock.destroy()
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        self.mock.reopen()
+    }
+
+    fn input(&mut self, report: &[u8]) -> io::Result<()> {
+        let hex: Vec<String> = report.iter().map(|byte| format!("{:02x}", byte)).collect();
+        println!("{}", format!("{}  {}", hex.join(" "), self.reports.decode(report)).trim_end());
+        self.mock.input(report)
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        self.mock.read_event()
+    }
+
+    fn get_report_reply(&mut self, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()> {
+        self.mock.get_report_reply(id, result)
+    }
+
+    fn set_report_reply(&mut self, id: u32, result: io::Result<()>) -> io::Result<()> {
+        self.mock.set_report_reply(id, result)
+    }
+
+    fn fd(&self) -> RawFd {
+        self.mock.fd()
+    }
+}
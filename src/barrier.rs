@@ -0,0 +1,407 @@
+/*
+ * Barrier client
+ * Acts as a client screen of a Barrier or Synergy 1.x server, so the mouse
+ * and keyboard shared by the server can control a machine without a display
+ * server, e.g. a headless box on the desk: the input the server sends while
+ * the pointer is on this screen is injected through a Screen device, see
+ * src/devices/screen.rs.
+ *
+ * The protocol runs over TCP, port 24800 unless given otherwise. Every
+ * message is its length as a 32 bit big endian number followed by a four
+ * letter code and the arguments, big endian integers and strings prefixed
+ * with their length. The server greets with "Barrier" or "Synergy" and its
+ * protocol version, the client answers with the name of its screen, which
+ * has to be in the configuration of the server. Only the plain protocol is
+ * spoken, TLS has to be turned off on the server.
+ *
+ * Keys arrive as the character or function they produce on the server plus
+ * the server's key button, which releases them again. Characters are mapped
+ * to the key that types them with the configured layout; the modifiers are
+ * keys of their own, so a shifted character is just its key. Key repeats are
+ * left to the host, which repeats held keys itself.
+ */
+
+use devices::layout::Layout;
+use devices::screen::Screen;
+use std::collections::HashMap;
+use std::io;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PORT: u16 = 24800;
+
+/* The protocol version spoken, that of Barrier 2 */
+const MAJOR: u16 = 1;
+const MINOR: u16 = 6;
+
+/* Clipboards can be large, anything beyond this is not a Barrier server */
+const MAX_MESSAGE_LEN: usize = 4 * 1024 * 1024;
+
+/* The server sends keep-alives this often unless configured otherwise, and
+ * drops clients missing three of them; the client does the same */
+const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(3);
+const MISSED_HEARTBEATS: u32 = 3;
+
+/* Wheel deltas are 120 per detent like on Windows */
+const WHEEL_DETENT: i32 = 120;
+
+/* Messages of the server that affect the screen, the others are answered or
+ * ignored by Client */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Enter { x: i16, y: i16 },
+    Leave,
+    MouseMove(i16, i16),
+    MouseRelative(i16, i16),
+    MouseDown(u8),
+    MouseUp(u8),
+    Wheel(i16, i16),
+    KeyDown { id: u16, button: u16 },
+    KeyUp { id: u16, button: u16 },
+}
+
+/* Arguments of a message, read in order */
+struct Arguments<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Arguments<'a> {
+    fn take(&mut self, size: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated message"));
+        }
+        let (taken, rest) = self.data.split_at(size);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> io::Result<i16> {
+        self.u16().map(|value| value as i16)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+fn write_message(output: &mut TcpStream, parts: &[&[u8]]) -> io::Result<()> {
+    let length: usize = parts.iter().map(|part| part.len()).sum();
+    let mut message = (length as u32).to_be_bytes().to_vec();
+    for part in parts {
+        message.extend_from_slice(part);
+    }
+    output.write_all(&message)
+}
+
+/* One message, blocking, for the handshake */
+fn read_message(input: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    input.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid message length {}", length)));
+    }
+    let mut message = vec![0; length];
+    input.read_exact(&mut message)?;
+    Ok(message)
+}
+
+/* Appends the default port to an address without one */
+pub fn with_port(address: &str) -> String {
+    if address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) && !address.ends_with(']') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    }
+}
+
+pub struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    size: (u16, u16),
+    position: (i16, i16),
+    heartbeat: Duration,
+    last_heard: Instant,
+}
+
+impl Client {
+    /* Connects and introduces the screen, then reads without blocking */
+    pub fn connect(address: &str, name: &str, size: (u16, u16)) -> io::Result<Client> {
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+
+        let hello = read_message(&mut stream)?;
+        let protocol = match hello.get(..7) {
+            Some(b"Barrier") => "Barrier",
+            Some(b"Synergy") => "Synergy",
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a Barrier server")),
+        };
+        let mut version = Arguments { data: &hello[7..] };
+        let (major, minor) = (version.u16()?, version.u16()?);
+        if major != MAJOR || minor < 3 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("Unsupported protocol version {}.{}", major, minor)));
+        }
+        write_message(&mut stream, &[protocol.as_bytes(), &MAJOR.to_be_bytes(), &minor.min(MINOR).to_be_bytes(),
+                                     &(name.len() as u32).to_be_bytes(), name.as_bytes()])?;
+
+        stream.set_nonblocking(true)?;
+        Ok(Client {
+            stream,
+            buffer: vec![],
+            size,
+            position: (size.0 as i16 / 2, size.1 as i16 / 2),
+            heartbeat: DEFAULT_HEARTBEAT,
+            last_heard: Instant::now(),
+        })
+    }
+
+    /* Reads what the server sent; false once the connection is closed */
+    pub fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(size) => {
+                    self.buffer.extend_from_slice(&chunk[..size]);
+                    self.last_heard = Instant::now();
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /* The next message affecting the screen, answering the others on the way */
+    pub fn next_message(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            if self.buffer.len() < 4 {
+                return Ok(None);
+            }
+            let length = u32::from_be_bytes([self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]]) as usize;
+            if !(4..=MAX_MESSAGE_LEN).contains(&length) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid message length {}", length)));
+            }
+            if self.buffer.len() < 4 + length {
+                return Ok(None);
+            }
+            let message: Vec<u8> = self.buffer.drain(..4 + length).skip(4).collect();
+            if let Some(message) = self.handle(&message[..4], Arguments { data: &message[4..] })? {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    fn handle(&mut self, code: &[u8], mut arguments: Arguments) -> io::Result<Option<Message>> {
+        let message = match code {
+            b"QINF" => {
+                let mut info = Vec::new();
+                for value in &[0, 0, self.size.0 as i16, self.size.1 as i16, 0, self.position.0, self.position.1] {
+                    info.extend_from_slice(&value.to_be_bytes());
+                }
+                write_message(&mut self.stream, &[b"DINF", &info])?;
+                return Ok(None);
+            },
+            b"CALV" => {
+                write_message(&mut self.stream, &[b"CALV"])?;
+                return Ok(None);
+            },
+            b"CROP" => {
+                self.heartbeat = DEFAULT_HEARTBEAT;
+                return Ok(None);
+            },
+            b"DSOP" => {
+                /* pairs of option and value */
+                let count = arguments.u32()? / 2;
+                for _ in 0..count {
+                    let (option, value) = (arguments.u32()?, arguments.u32()?);
+                    if &option.to_be_bytes() == b"HART" && value > 0 {
+                        self.heartbeat = Duration::from_millis(value as u64);
+                    }
+                }
+                return Ok(None);
+            },
+            b"CBYE" => return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "Disconnected by the server")),
+            b"EICV" => return Err(io::Error::new(io::ErrorKind::InvalidData, "Incompatible protocol version")),
+            b"EBSY" => return Err(io::Error::new(io::ErrorKind::AddrInUse, "Screen name already in use")),
+            b"EUNK" => return Err(io::Error::new(io::ErrorKind::NotFound, "Screen name unknown to the server")),
+            b"EBAD" => return Err(io::Error::new(io::ErrorKind::InvalidData, "Protocol error")),
+            b"CINN" => Message::Enter { x: arguments.i16()?, y: arguments.i16()? },
+            b"COUT" => Message::Leave,
+            b"DMMV" => Message::MouseMove(arguments.i16()?, arguments.i16()?),
+            b"DMRM" => Message::MouseRelative(arguments.i16()?, arguments.i16()?),
+            b"DMDN" => Message::MouseDown(arguments.u8()?),
+            b"DMUP" => Message::MouseUp(arguments.u8()?),
+            b"DMWM" => Message::Wheel(arguments.i16()?, arguments.i16()?),
+            b"DKDN" | b"DKUP" => {
+                let (id, _mask, button) = (arguments.u16()?, arguments.u16()?, arguments.u16()?);
+                if code == b"DKDN" { Message::KeyDown { id, button } } else { Message::KeyUp { id, button } }
+            },
+            /* key repeats, clipboards, screen savers, options acks, no-ops */
+            _ => return Ok(None),
+        };
+        if let Message::Enter { x, y } | Message::MouseMove(x, y) = message {
+            self.position = (x, y);
+        }
+        Ok(Some(message))
+    }
+
+    /* When the server counts as gone without a keep-alive */
+    pub fn deadline(&self) -> Instant {
+        self.last_heard + self.heartbeat * MISSED_HEARTBEATS
+    }
+}
+
+impl AsRawFd for Client {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+/* Keyboard/Keypad usage of the keys the server sends as functions */
+fn function_key(id: u16) -> Option<u8> {
+    let usage = match id {
+        0xef08 => 0x2a,				/* BackSpace */
+        0xef09 | 0xee20 => 0x2b,		/* Tab, Shift+Tab */
+        0xef0d => 0x28,				/* Return */
+        0xef13 => 0x48,				/* Pause */
+        0xef14 => 0x47,				/* Scroll_Lock */
+        0xef15 | 0xef61 => 0x46,		/* Sys_Req, Print */
+        0xef1b => 0x29,				/* Escape */
+        0xef50 => 0x4a,				/* Home */
+        0xef51 => 0x50,				/* Left */
+        0xef52 => 0x52,				/* Up */
+        0xef53 => 0x4f,				/* Right */
+        0xef54 => 0x51,				/* Down */
+        0xef55 => 0x4b,				/* Page_Up */
+        0xef56 => 0x4e,				/* Page_Down */
+        0xef57 => 0x4d,				/* End */
+        0xef63 => 0x49,				/* Insert */
+        0xef67 => 0x65,				/* Menu */
+        0xef7e => 0xe6,				/* AltGr */
+        0xef7f => 0x53,				/* Num_Lock */
+        0xef8d => 0x58,				/* KP_Enter */
+        0xef95 => 0x5f,				/* KP_Home */
+        0xef96 => 0x5c,				/* KP_Left */
+        0xef97 => 0x60,				/* KP_Up */
+        0xef98 => 0x5e,				/* KP_Right */
+        0xef99 => 0x5a,				/* KP_Down */
+        0xef9a => 0x61,				/* KP_Page_Up */
+        0xef9b => 0x5b,				/* KP_Page_Down */
+        0xef9c => 0x59,				/* KP_End */
+        0xef9d => 0x5d,				/* KP_Begin */
+        0xef9e => 0x62,				/* KP_Insert */
+        0xef9f => 0x63,				/* KP_Delete */
+        0xefaa => 0x55,				/* KP_Multiply */
+        0xefab => 0x57,				/* KP_Add */
+        0xefad => 0x56,				/* KP_Subtract */
+        0xefae => 0x63,				/* KP_Decimal */
+        0xefaf => 0x54,				/* KP_Divide */
+        0xefb0 => 0x62,				/* KP_0 */
+        0xefb1..=0xefb9 => 0x59 + (id - 0xefb1) as u8,	/* KP_1..KP_9 */
+        0xefbe..=0xefc9 => 0x3a + (id - 0xefbe) as u8,	/* F1..F12 */
+        0xefe1 => 0xe1,				/* Shift_L */
+        0xefe2 => 0xe5,				/* Shift_R */
+        0xefe3 => 0xe0,				/* Control_L */
+        0xefe4 => 0xe4,				/* Control_R */
+        0xefe5 => 0x39,				/* Caps_Lock */
+        0xefe7 | 0xefe9 => 0xe2,		/* Meta_L, Alt_L */
+        0xefe8 | 0xefea => 0xe6,		/* Meta_R, Alt_R */
+        0xefeb => 0xe3,				/* Super_L */
+        0xefec => 0xe7,				/* Super_R */
+        0xefff => 0x4c,				/* Delete */
+        _ => return None,
+    };
+    Some(usage)
+}
+
+/* Barrier numbers the buttons left, middle, right, HID left, right, middle */
+fn hid_button(button: u8) -> u8 {
+    match button {
+        2 => 3,
+        3 => 2,
+        _ => button,
+    }
+}
+
+/* Turns the messages of a client into reports of a screen */
+pub struct Barrier {
+    pub client: Client,
+    screen: Screen,
+    layout: &'static Layout,
+    /* usages of the keys held, by the server's key button */
+    pressed: HashMap<u16, u8>,
+    /* wheel movement short of a detent */
+    scroll: (i32, i32),
+}
+
+impl Barrier {
+    pub fn new(client: Client, screen: Screen, layout: &'static Layout) -> Barrier {
+        Barrier { client, screen, layout, pressed: HashMap::new(), scroll: (0, 0) }
+    }
+
+    /* The reports for everything the server sent so far, None once the
+     * connection is closed */
+    pub fn read(&mut self) -> io::Result<Option<Vec<Vec<u8>>>> {
+        let open = self.client.fill()?;
+        let mut reports = Vec::new();
+        while let Some(message) = self.client.next_message()? {
+            reports.extend(self.reports(message));
+        }
+        Ok(if open { Some(reports) } else { None })
+    }
+
+    fn reports(&mut self, message: Message) -> Vec<Vec<u8>> {
+        match message {
+            Message::Enter { x, y } | Message::MouseMove(x, y) => self.screen.move_to(x as i32, y as i32),
+            Message::MouseRelative(dx, dy) => self.screen.move_by(dx as i32, dy as i32),
+            Message::Leave => {
+                self.pressed.clear();
+                self.scroll = (0, 0);
+                self.screen.release_all()
+            },
+            Message::MouseDown(button) => self.screen.button(hid_button(button), true),
+            Message::MouseUp(button) => self.screen.button(hid_button(button), false),
+            Message::Wheel(x, y) => {
+                self.scroll = (self.scroll.0 + x as i32, self.scroll.1 + y as i32);
+                let detents = (self.scroll.0 / WHEEL_DETENT, self.scroll.1 / WHEEL_DETENT);
+                if detents == (0, 0) {
+                    return vec![];
+                }
+                self.scroll = (self.scroll.0 % WHEEL_DETENT, self.scroll.1 % WHEEL_DETENT);
+                let clamp = |detents: i32| detents.clamp(-127, 127) as i8;
+                self.screen.wheel(clamp(detents.1), clamp(detents.0))
+            },
+            Message::KeyDown { id, button } => {
+                let usage = function_key(id)
+                    .or_else(|| char::from_u32(id as u32).and_then(|c| self.layout.stroke(c)).map(|stroke| stroke.key));
+                match usage {
+                    Some(usage) => {
+                        self.pressed.insert(button, usage);
+                        self.screen.key(usage, true)
+                    },
+                    None => {
+                        eprintln!("Cannot type key {:#06x} with the {} layout", id, self.layout.name);
+                        vec![]
+                    },
+                }
+            },
+            Message::KeyUp { button, .. } => match self.pressed.remove(&button) {
+                Some(usage) => self.screen.key(usage, false),
+                None => vec![],
+            },
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Only sending changes
+ * With --only-changes, an input report the same as the last one sent with
+ * its report-id is left out, so devices whose state is generated on a timer,
+ * like scripts, patterns or schedules ticking faster than anything changes,
+ * don't flood the event nodes with reports telling nothing new. It is done
+ * for every report-id, or only for the ones given: the same relative report
+ * twice is two movements rather than a repetition, so a device mixing
+ * relative axes with state, like a mouse with a battery report, is better
+ * given the ids of its state reports. Unnumbered reports have report-id 0.
+ *
+ * The reports left out are counted in the metrics. Creating the device again
+ * forgets what was sent, the kernel starting over without any state.
+ */
+
+use std::collections::HashMap;
+
+/* The report-ids only sent on change */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Only {
+    All,
+    Ids(Vec<u8>),
+}
+
+impl Only {
+    /* all, or report-ids separated by commas, like 1,3 */
+    pub fn from_name(value: &str) -> Option<Only> {
+        if value == "all" {
+            return Some(Only::All);
+        }
+        value.split(',')
+            .map(|id| id.trim().parse().ok())
+            .collect::<Option<Vec<u8>>>()
+            .map(Only::Ids)
+    }
+
+    fn includes(&self, report_id: u8) -> bool {
+        match *self {
+            Only::All => true,
+            Only::Ids(ref ids) => ids.contains(&report_id),
+        }
+    }
+}
+
+pub struct Changes {
+    only: Only,
+    /* whether report[0] is the report-id */
+    numbered: bool,
+    /* the last report sent, by report-id */
+    last: HashMap<u8, Vec<u8>>,
+}
+
+impl Changes {
+    pub fn new(only: Only, numbered: bool) -> Changes {
+        Changes { only, numbered, last: HashMap::new() }
+    }
+
+    fn report_id(&self, report: &[u8]) -> u8 {
+        match report.first() {
+            Some(&report_id) if self.numbered => report_id,
+            _ => 0,
+        }
+    }
+
+    /* Whether the report is only sent on change and the same as the last one
+     * sent with its report-id */
+    pub fn is_unchanged(&self, report: &[u8]) -> bool {
+        let report_id = self.report_id(report);
+        self.only.includes(report_id) && self.last.get(&report_id).is_some_and(|last| last.as_slice() == report)
+    }
+
+    /* Remembers a report as the last one sent with its report-id */
+    pub fn sent(&mut self, report: &[u8]) {
+        let report_id = self.report_id(report);
+        if self.only.includes(report_id) {
+            self.last.insert(report_id, report.to_vec());
+        }
+    }
+
+    /* Forgets the reports sent, once the device is created again */
+    pub fn clear(&mut self) {
+        self.last.clear();
+    }
+}
@@ -0,0 +1,170 @@
+/*
+ * Shell completions
+ * Completion scripts for bash, zsh and fish, printed by completions <shell>
+ * to be sourced or installed with the others of the system, e.g.
+ *
+ *   uhid-example completions bash > /usr/share/bash-completion/completions/uhid-example
+ *
+ * The words are read out of the usage text rather than kept in a list of
+ * their own, so the two cannot drift apart: the options and subcommands,
+ * whether they take a value, and the values of those listing theirs, like
+ * --preset <mouse|keyboard|...>. Other values complete as file names.
+ */
+
+use std::fmt::Write as FmtWrite;
+
+pub const SHELL_NAMES: &[&str] = &["bash", "zsh", "fish"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    pub fn from_name(name: &str) -> Option<Shell> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+/* An option or subcommand of the usage text */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Word {
+    pub name: String,
+    pub takes_value: bool,
+    /* The values it lists, empty for any value */
+    pub choices: Vec<String>,
+}
+
+/* The literal alternatives of a value like <a|b|<other>>, without the
+ * placeholders */
+fn choices(value: &str) -> Vec<String> {
+    let value = value.trim_matches(|c| c == '[' || c == ']');
+    let value = value.strip_prefix('<').and_then(|value| value.strip_suffix('>')).unwrap_or(value);
+    if !value.contains('|') {
+        return vec![];
+    }
+    value.split('|').filter(|choice| !choice.contains('<') && !choice.contains('>')).map(String::from).collect()
+}
+
+/* The words of a usage segment like "replay [--format a|b] <file>" */
+fn segment_words(segment: &str, words: &mut Vec<Word>) {
+    let tokens: Vec<&str> = segment.split_whitespace().collect();
+    for (index, &token) in tokens.iter().enumerate() {
+        let name = token.trim_matches(|c| c == '[' || c == ']');
+        let is_option = name.starts_with("--");
+        /* a subcommand starts the segment */
+        let is_command = index == 0 && name.chars().all(|c| c.is_ascii_lowercase() || c == '-') && !name.is_empty();
+        if !(is_option || is_command) {
+            continue;
+        }
+        let value = tokens.get(index + 1).filter(|&&value| !token.ends_with(']') && !value.starts_with('[') && !value.starts_with('-'));
+        if words.iter().any(|word| word.name == name) {
+            continue;
+        }
+        words.push(Word {
+            name: name.to_string(),
+            takes_value: value.is_some(),
+            choices: value.map(|value| choices(value)).unwrap_or_default(),
+        });
+    }
+}
+
+/* The options and subcommands of a usage text: a "Usage:" line with the
+ * forms of the command in brackets, then "Options:" and one line each,
+ * alternatives separated by ", " */
+pub fn words(usage: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut lines = usage.lines();
+    if let Some(header) = lines.next() {
+        for group in header.split('[').skip(1).filter_map(|group| group.split(']').next()) {
+            for segment in group.split('|').filter(|segment| segment.starts_with(|c: char| c.is_ascii_lowercase()) && *segment != "options") {
+                segment_words(segment, &mut words);
+            }
+        }
+    }
+    for line in lines.filter(|line| line.starts_with("  ")) {
+        for segment in line.trim().split(", ") {
+            segment_words(segment, &mut words);
+        }
+    }
+    words
+}
+
+/* The words taking a value without listing any */
+fn any_value(words: &[Word]) -> Vec<&str> {
+    words.iter().filter(|word| word.takes_value && word.choices.is_empty()).map(|word| word.name.as_str()).collect()
+}
+
+fn bash(program: &str, words: &[Word]) -> String {
+    let function = format!("_{}", program.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let mut script = format!("{}() {{\n", function);
+    script.push_str("    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n");
+    script.push_str("    case \"$prev\" in\n");
+    for word in words.iter().filter(|word| !word.choices.is_empty()) {
+        let _ = writeln!(script, "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return;;", word.name, word.choices.join(" "));
+    }
+    /* no match falls back to file names */
+    let _ = writeln!(script, "        {}) return;;", any_value(words).join("|"));
+    script.push_str("    esac\n");
+    let names: Vec<&str> = words.iter().map(|word| word.name.as_str()).collect();
+    let _ = writeln!(script, "    COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", names.join(" "));
+    script.push_str("}\n");
+    let _ = writeln!(script, "complete -o default -F {} {}", function, program);
+    script
+}
+
+fn zsh(program: &str, words: &[Word]) -> String {
+    let function = format!("_{}", program.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    let mut script = format!("#compdef {}\n\n{}() {{\n", program, function);
+    script.push_str("    case \"${words[CURRENT-1]}\" in\n");
+    for word in words.iter().filter(|word| !word.choices.is_empty()) {
+        let _ = writeln!(script, "        {}) compadd -- {}; return;;", word.name, word.choices.join(" "));
+    }
+    let _ = writeln!(script, "        {}) _files; return;;", any_value(words).join("|"));
+    script.push_str("    esac\n");
+    let names: Vec<&str> = words.iter().map(|word| word.name.as_str()).collect();
+    let _ = writeln!(script, "    compadd -- {}", names.join(" "));
+    script.push_str("    _files\n}\n\n");
+    let _ = writeln!(script, "{} \"$@\"", function);
+    script
+}
+
+fn fish(program: &str, words: &[Word]) -> String {
+    let mut script = String::new();
+    for word in words {
+        let mut line = match word.name.strip_prefix("--") {
+            Some(option) => format!("complete -c {} -l {}", program, option),
+            None => format!("complete -c {} -n __fish_use_subcommand -a {}", program, word.name),
+        };
+        if word.takes_value {
+            if word.name.starts_with("--") {
+                line.push_str(if word.choices.is_empty() { " -r" } else { " -x" });
+            } else if !word.choices.is_empty() {
+                /* the values of a subcommand follow it */
+                let _ = write!(line, "\ncomplete -c {} -n \"__fish_seen_subcommand_from {}\" -x", program, word.name);
+            }
+            if !word.choices.is_empty() {
+                let _ = write!(line, " -a \"{}\"", word.choices.join(" "));
+            }
+        }
+        script.push_str(&line);
+        script.push('\n');
+    }
+    script
+}
+
+/* The completion script of the shell for the program */
+pub fn script(shell: Shell, program: &str, words: &[Word]) -> String {
+    match shell {
+        Shell::Bash => bash(program, words),
+        Shell::Zsh => zsh(program, words),
+        Shell::Fish => fish(program, words),
+    }
+}
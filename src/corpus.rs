@@ -0,0 +1,143 @@
+/*
+ * Descriptor corpus
+ * Generates valid report descriptors of varied shape, as seeds for
+ * fuzz-rdesc and for external fuzzers of HID parsers: one to three
+ * application collections of real device types, each with up to three
+ * reports, numbered when there is more than one. Reports hold inputs,
+ * outputs and features of buttons, axes, keys, consumer controls, digitizer
+ * values, LEDs or vendor usages, as variables or arrays, in fields of 1 to
+ * 32 bits, optionally inside nested physical and logical collections. Every
+ * report is padded to whole bytes.
+ *
+ * Generation follows from a seed, so a corpus can be made again instead of
+ * being kept.
+ */
+
+use fuzz::Rng;
+use hid_report::descriptor::{Builder, APPLICATION, CONSTANT, LOGICAL, PHYSICAL, RELATIVE, VARIABLE};
+use hid_report::usage::{button, consumer, digitizer, generic_desktop, keyboard, led, VENDOR_PAGES};
+
+/* Long enough for the largest descriptor generated */
+const MAX_DESCRIPTOR_LEN: usize = 4096;
+
+/* Usage page and usage of the application collections */
+const APPLICATIONS: &[(u32, u32)] = &[
+    (generic_desktop::PAGE, generic_desktop::MOUSE),
+    (generic_desktop::PAGE, generic_desktop::JOYSTICK),
+    (generic_desktop::PAGE, generic_desktop::GAME_PAD),
+    (generic_desktop::PAGE, generic_desktop::KEYBOARD),
+    (generic_desktop::PAGE, generic_desktop::SYSTEM_CONTROL),
+    (consumer::PAGE, consumer::CONSUMER_CONTROL),
+    (digitizer::PAGE, digitizer::PEN),
+    (digitizer::PAGE, digitizer::TOUCH_SCREEN),
+    (digitizer::PAGE, digitizer::TOUCH_PAD),
+];
+
+/* Usage page and the range of usages fields are made of, up to 16 buttons
+ * and 16 vendor usages */
+const USAGES: &[(u32, u32, u32)] = &[
+    (generic_desktop::PAGE, generic_desktop::X, generic_desktop::WHEEL),
+    (button::PAGE, button::PRIMARY, 0x10),
+    (keyboard::PAGE, keyboard::A, keyboard::APPLICATION),
+    (consumer::PAGE, consumer::VOLUME, consumer::VOLUME_DECREMENT),
+    (digitizer::PAGE, digitizer::TIP_PRESSURE, digitizer::TRANSDUCER_SERIAL_NUMBER),
+    (led::PAGE, led::NUM_LOCK, led::KANA),
+    (VENDOR_PAGES, 0x01, 0x10),
+];
+
+const SIZES: &[u32] = &[1, 2, 4, 8, 12, 16, 32];
+
+/* Range of a signed or unsigned field of the size, within what the logical
+ * items can hold */
+fn range(size: u32, signed: bool) -> (i32, i32) {
+    match (size, signed) {
+        (32, true) => (i32::MIN, i32::MAX),
+        (32, false) => (0, i32::MAX),
+        (_, true) if size > 1 => (-(1 << (size - 1)), (1 << (size - 1)) - 1),
+        _ => (0, (1 << size) - 1),
+    }
+}
+
+/* Adds a main item of the kind, 0 input, 1 output and 2 feature */
+fn main_item(builder: &mut Builder, kind: usize, flags: u32) {
+    match kind {
+        0 => builder.input(flags),
+        1 => builder.output(flags),
+        _ => builder.feature(flags),
+    };
+}
+
+/* Adds a field of random usages, returning the bits added to the report */
+fn field(builder: &mut Builder, rng: &mut Rng, kind: usize) -> u32 {
+    let (page, first, last) = USAGES[rng.below(USAGES.len())];
+    let count = 1 + rng.below(8) as u32;
+    builder.usage_page(page);
+
+    /* arrays report the index of the usage, all of them below 256 */
+    if rng.below(4) == 0 {
+        builder.usage_range(first, last).logical(first as i32, last as i32).report(8, count);
+        main_item(builder, kind, 0);
+        return 8 * count;
+    }
+
+    let minimum = first + rng.below((last - first + 1) as usize) as u32;
+    let size = SIZES[rng.below(SIZES.len())];
+    let (low, high) = range(size, rng.below(2) == 0);
+    let relative = if page == generic_desktop::PAGE && kind == 0 && low < 0 && rng.below(2) == 0 { RELATIVE } else { 0 };
+    builder.usage_range(minimum, (minimum + count - 1).min(last)).logical(low, high).report(size, count);
+    main_item(builder, kind, VARIABLE | relative);
+    size * count
+}
+
+/* Generates one descriptor */
+pub fn descriptor(rng: &mut Rng) -> Vec<u8> {
+    let mut buffer = [0u8; MAX_DESCRIPTOR_LEN];
+    let mut builder = Builder::new(&mut buffer);
+
+    let applications = 1 + rng.below(3);
+    let reports: Vec<usize> = (0..applications).map(|_| 1 + rng.below(3)).collect();
+    let numbered = reports.iter().sum::<usize>() > 1;
+    let mut id = 0;
+
+    for &count in reports.iter() {
+        let (page, usage) = APPLICATIONS[rng.below(APPLICATIONS.len())];
+        builder.usage_page(page).usage(usage).collection(APPLICATION);
+        for _ in 0..count {
+            id += 1;
+            if numbered {
+                builder.report_id(id);
+            }
+            let depth = rng.below(4);
+            for _ in 0..depth {
+                builder.collection(if rng.below(2) == 0 { PHYSICAL } else { LOGICAL });
+            }
+
+            /* bits of the input, output and feature report */
+            let mut bits = [0u32; 3];
+            for _ in 0..1 + rng.below(5) {
+                /* mostly inputs */
+                let kind = [0, 0, 0, 1, 2][rng.below(5)];
+                bits[kind] += field(&mut builder, rng, kind);
+            }
+            for (kind, &bits) in bits.iter().enumerate().filter(|&(_, &bits)| bits % 8 != 0) {
+                builder.report(8 - bits % 8, 1);
+                main_item(&mut builder, kind, CONSTANT);
+            }
+
+            for _ in 0..depth {
+                builder.end_collection();
+            }
+        }
+        builder.end_collection();
+    }
+
+    /* nine reports of five fields take nowhere near the buffer */
+    let length = builder.finish().unwrap();
+    buffer[..length].to_vec()
+}
+
+/* Generates `count` descriptors from the seed */
+pub fn generate(seed: u64, count: usize) -> Vec<Vec<u8>> {
+    let mut rng = Rng::new(seed);
+    (0..count).map(|_| descriptor(&mut rng)).collect()
+}
@@ -0,0 +1,557 @@
+/*
+ * Report decoding
+ * Reads the input fields out of a report descriptor and names their values
+ * in reports, so reports can be checked by eye without a device, e.g.
+ *
+ *   02 01 ff 3f ff 3f ff 00  Button 1 X=16383 Y=16383 Wheel=-1 AC Pan=0
+ *
+ * One bit flags like buttons are listed by name when set, other variable
+ * fields as name=value, array fields like the keys of a keyboard as the
+ * usages they hold. Usages are named by the tables of hid_report::usage,
+ * those without a name there are shown as page:usage in hex. Padding is
+ * skipped.
+ *
+ * Output and feature reports the host sends are decoded the same way, for
+ * the logs and --on-output, but list every value, set or not, e.g.
+ *
+ *   02 02  Num Lock=0 Caps Lock=1 Scroll Lock=0
+ *
+ * Code driving devices of descriptors it did not write, e.g. loaded ones,
+ * can build and read reports by usage instead of by bit offset:
+ *
+ *   let mut report = reports.report(Kind::Input, 2);
+ *   report.set_by_usage(generic_desktop::PAGE, generic_desktop::WHEEL, -1)?;
+ *
+ * Array fields take a usage as held with a value other than 0 and give
+ * back 1 for a usage they hold.
+ */
+
+use hid_report::bits;
+use hid_report::descriptor::{self, ItemType, CONSTANT, RELATIVE, VARIABLE};
+use hid_report::usage::{self, button, keyboard};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io;
+use uhid::uhid_report_type;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+    Input,
+    Output,
+    Feature,
+}
+
+impl Kind {
+    /* The kind of the reports of a uhid event */
+    pub fn from_rtype(rtype: u8) -> Option<Kind> {
+        match rtype {
+            rtype if rtype == uhid_report_type::UHID_INPUT_REPORT as u8 => Some(Kind::Input),
+            rtype if rtype == uhid_report_type::UHID_OUTPUT_REPORT as u8 => Some(Kind::Output),
+            rtype if rtype == uhid_report_type::UHID_FEATURE_REPORT as u8 => Some(Kind::Feature),
+            _ => None,
+        }
+    }
+}
+
+struct Field {
+    kind: Kind,
+    report_id: u8,
+    /* Bits from the start of the report, after the report-id */
+    offset: usize,
+    size: usize,
+    count: usize,
+    /* Usage of each value for variable fields, of each index for arrays */
+    usages: Vec<u32>,
+    variable: bool,
+    relative: bool,
+    signed: bool,
+    logical_minimum: i32,
+    logical_maximum: i32,
+}
+
+impl Field {
+    /* The usage an array value is the index of, none if out of range or
+     * usage 0 */
+    fn usage_at(&self, value: i32) -> Option<u32> {
+        let index = usize::try_from(value as i64 - self.logical_minimum as i64).ok()?;
+        self.usages.get(index).cloned().filter(|&usage| usage & 0xffff != 0)
+    }
+
+    /* The array value holding a usage, none if it is beyond the values the
+     * field can hold */
+    fn value_of(&self, usage: u32) -> Option<i32> {
+        let index = self.usages.iter().position(|&held| held == usage)?;
+        i32::try_from(self.logical_minimum as i64 + index as i64).ok()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Globals {
+    usage_page: u32,
+    logical_minimum: i32,
+    logical_maximum: i32,
+    report_size: usize,
+    report_count: usize,
+    report_id: u8,
+}
+
+pub struct Reports {
+    numbered: bool,
+    fields: Vec<Field>,
+    /* Bits of each report, padding included */
+    lengths: HashMap<(Kind, u8), usize>,
+}
+
+impl Reports {
+    /* Parses the fields of a descriptor, as far as it is well-formed */
+    pub fn parse(descriptor: &[u8]) -> Reports {
+        let mut fields = Vec::new();
+        let mut globals = Globals::default();
+        let mut stack = Vec::new();
+        let mut usages: Vec<u32> = Vec::new();
+        let mut minimum = None;
+        let mut offsets: HashMap<(Kind, u8), usize> = HashMap::new();
+        let mut numbered = false;
+
+        for (_, item) in descriptor::items(descriptor).map_while(Result::ok) {
+            /* usages of one or two bytes are on the current page */
+            let usage = if item.size == 4 { item.data } else { globals.usage_page << 16 | item.data };
+            match (item.item_type, item.tag) {
+                (ItemType::Global, descriptor::USAGE_PAGE) => globals.usage_page = item.data,
+                (ItemType::Global, descriptor::LOGICAL_MINIMUM) => globals.logical_minimum = item.signed_data(),
+                (ItemType::Global, descriptor::LOGICAL_MAXIMUM) => globals.logical_maximum = item.signed_data(),
+                (ItemType::Global, descriptor::REPORT_SIZE) => globals.report_size = item.data as usize,
+                (ItemType::Global, descriptor::REPORT_COUNT) => globals.report_count = item.data as usize,
+                (ItemType::Global, descriptor::REPORT_ID) => {
+                    globals.report_id = item.data as u8;
+                    numbered = true;
+                },
+                (ItemType::Global, descriptor::PUSH) => stack.push(globals.clone()),
+                (ItemType::Global, descriptor::POP) => globals = stack.pop().unwrap_or_default(),
+                (ItemType::Local, descriptor::USAGE) => usages.push(usage),
+                (ItemType::Local, descriptor::USAGE_MINIMUM) => minimum = Some(usage),
+                (ItemType::Local, descriptor::USAGE_MAXIMUM) => if let Some(minimum) = minimum.take() {
+                    /* a bogus range would take forever */
+                    usages.extend((minimum..=usage).take(0x10000));
+                },
+                (ItemType::Main, tag) => {
+                    let kind = match tag {
+                        descriptor::INPUT => Some(Kind::Input),
+                        descriptor::OUTPUT => Some(Kind::Output),
+                        descriptor::FEATURE => Some(Kind::Feature),
+                        _ => None,
+                    };
+                    if let Some(kind) = kind {
+                        let offset = offsets.entry((kind, globals.report_id)).or_insert(0);
+                        let size = globals.report_size * globals.report_count;
+                        if item.data & CONSTANT == 0 && globals.report_size > 0 && globals.report_size <= 32 {
+                            fields.push(Field {
+                                kind,
+                                report_id: globals.report_id,
+                                offset: *offset,
+                                size: globals.report_size,
+                                count: globals.report_count,
+                                usages: usages.clone(),
+                                variable: item.data & VARIABLE != 0,
+                                relative: item.data & RELATIVE != 0,
+                                signed: globals.logical_minimum < 0,
+                                logical_minimum: globals.logical_minimum,
+                                logical_maximum: globals.logical_maximum,
+                            });
+                        }
+                        *offset += size;
+                    }
+                    usages.clear();
+                    minimum = None;
+                },
+                _ => (),
+            }
+        }
+
+        Reports { numbered, fields, lengths: offsets }
+    }
+
+    /* Whether the reports start with their report-id */
+    pub fn is_numbered(&self) -> bool {
+        self.numbered
+    }
+
+    /* The values of a report of the kind, in the order of the fields;
+     * data[0] is the report-id if the reports are numbered */
+    fn read(&self, kind: Kind, report: &[u8]) -> Vec<Value> {
+        let (report_id, data) = match (self.numbered, report.split_first()) {
+            (true, Some((&report_id, data))) => (report_id, data),
+            _ => (0, report),
+        };
+        let mut values = Vec::new();
+        for field in self.fields.iter().filter(|field| field.kind == kind && field.report_id == report_id) {
+            for index in 0..field.count {
+                let offset = field.offset + index * field.size;
+                if offset + field.size > data.len() * 8 {
+                    values.push(Value::Short);
+                    return values;
+                }
+                let value = if field.signed {
+                    bits::read_signed(data, offset, field.size)
+                } else {
+                    bits::read(data, offset, field.size) as i32
+                };
+
+                if !field.variable {
+                    /* arrays hold the index of a usage */
+                    if let Some(usage) = field.usage_at(value) {
+                        values.push(Value::Usage(usage_name(usage)));
+                    }
+                    continue;
+                }
+                if let Some(&usage) = field.usages.get(index).or(field.usages.last()) {
+                    values.push(Value::Variable(usage_name(usage), value, field.size));
+                }
+            }
+        }
+        values
+    }
+
+    /* The values of an input report, named; data[0] is the report-id if
+     * the reports are numbered */
+    pub fn decode(&self, report: &[u8]) -> String {
+        let values: Vec<String> = self.read(Kind::Input, report).into_iter().filter_map(|value| match value {
+            Value::Variable(_, 0, 1) => None,
+            Value::Variable(name, _, 1) | Value::Usage(name) => Some(name),
+            Value::Variable(name, value, _) => Some(format!("{}={}", name, value)),
+            Value::Short => Some(String::from("(short)")),
+        }).collect();
+        values.join(" ")
+    }
+
+    /* Every value of a report, by name, the usages held by arrays as 1;
+     * empty for reports the descriptor does not declare */
+    pub fn values(&self, kind: Kind, report: &[u8]) -> Vec<(String, i32)> {
+        self.read(kind, report).into_iter().map_while(|value| match value {
+            Value::Variable(name, value, _) => Some((name, value)),
+            Value::Usage(name) => Some((name, 1)),
+            Value::Short => None,
+        }).collect()
+    }
+
+    /* The buttons and keys an input report holds, by name */
+    fn held(&self, report: &[u8]) -> Vec<String> {
+        self.read(Kind::Input, report).into_iter().filter_map(|value| match value {
+            Value::Variable(name, value, 1) if value != 0 => Some(name),
+            Value::Usage(name) => Some(name),
+            _ => None,
+        }).collect()
+    }
+
+    /* Whether an input report lets go of buttons or keys the last one sent
+     * with its report-id held, without holding any new */
+    pub fn releases(&self, last: &[u8], report: &[u8]) -> bool {
+        let (last, held) = (self.held(last), self.held(report));
+        held.len() < last.len() && held.iter().all(|name| last.contains(name))
+    }
+
+    /* An input report with everything it holds let go and no movement, the
+     * absolute values like positions kept */
+    pub fn released(&self, report: &[u8]) -> Vec<u8> {
+        let (report_id, start) = match (self.numbered, report.first()) {
+            (true, Some(&report_id)) => (report_id, 1),
+            _ => (0, 0),
+        };
+        let mut released = report.to_vec();
+        let data = &mut released[start..];
+        for field in self.fields.iter().filter(|field| field.kind == Kind::Input && field.report_id == report_id) {
+            if field.variable && field.size > 1 && !field.relative {
+                continue;
+            }
+            /* arrays hold the index of usage 0, or one out of their range */
+            let none = match field.usages.iter().position(|&usage| usage & 0xffff == 0) {
+                _ if field.variable => 0,
+                Some(index) => (field.logical_minimum as i64 + index as i64) as u32,
+                None if field.logical_minimum > 0 => 0,
+                None => (field.logical_maximum as i64 + 1) as u32,
+            };
+            for index in 0..field.count {
+                let offset = field.offset + index * field.size;
+                if offset + field.size <= data.len() * 8 {
+                    bits::write(data, offset, field.size, none);
+                }
+            }
+        }
+        released
+    }
+
+    /* The values as name=value pairs, for the log */
+    pub fn describe(&self, kind: Kind, report: &[u8]) -> String {
+        let values: Vec<String> = self.values(kind, report).iter().map(|&(ref name, value)| format!("{}={}", name, value)).collect();
+        values.join(" ")
+    }
+
+    /* A report of the kind with every field 0, sized for the report of
+     * the id, which goes first if the reports are numbered */
+    pub fn report(&self, kind: Kind, report_id: u8) -> Report<'_> {
+        let report_id = if self.numbered { report_id } else { 0 };
+        let bits = self.lengths.get(&(kind, report_id)).cloned().unwrap_or(0);
+        let mut data = vec![0; self.numbered as usize + bits.div_ceil(8)];
+        if self.numbered {
+            data[0] = report_id;
+        }
+        Report { reports: self, kind, data }
+    }
+
+    /* A report of the kind made elsewhere, e.g. sent by the kernel, to get
+     * its values by usage */
+    pub fn wrap(&self, kind: Kind, data: Vec<u8>) -> Report<'_> {
+        Report { reports: self, kind, data }
+    }
+
+    /* The field of the report holding the usage, with the index of the
+     * value for variable fields; usages of a variable field beyond its
+     * count have no value */
+    fn find(&self, kind: Kind, report_id: u8, usage: u32) -> Option<(&Field, usize)> {
+        self.fields.iter().filter(|field| field.kind == kind && field.report_id == report_id).find_map(|field| {
+            field.usages.iter().position(|&held| held == usage)
+                .filter(|&index| !field.variable || index < field.count)
+                .map(|index| (field, if field.variable { index } else { 0 }))
+        })
+    }
+}
+
+/* A report of a descriptor, its fields addressed by usage */
+pub struct Report<'a> {
+    reports: &'a Reports,
+    kind: Kind,
+    data: Vec<u8>,
+}
+
+impl<'a> Report<'a> {
+    fn split(&self) -> (u8, usize) {
+        match (self.reports.numbered, self.data.first()) {
+            (true, Some(&report_id)) => (report_id, 1),
+            _ => (0, 0),
+        }
+    }
+
+    fn field(&self, page: u32, id: u32) -> io::Result<(&'a Field, usize)> {
+        let (report_id, _) = self.split();
+        self.reports.find(self.kind, report_id, usage::extended(page, id)).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("The report has no field of {}", usage_name(usage::extended(page, id))))
+        })
+    }
+
+    /* Reads a value of a field, the report ending within it being none */
+    fn read(&self, field: &Field, index: usize) -> Option<i32> {
+        let (_, start) = self.split();
+        let offset = field.offset + index * field.size;
+        let data = &self.data[start..];
+        if offset + field.size > data.len() * 8 {
+            return None;
+        }
+        Some(if field.signed { bits::read_signed(data, offset, field.size) } else { bits::read(data, offset, field.size) as i32 })
+    }
+
+    /* Writes a value of a field, failing if the report ends within it */
+    fn write(&mut self, field: &Field, index: usize, value: i32) -> io::Result<()> {
+        let (_, start) = self.split();
+        let offset = field.offset + index * field.size;
+        let data = &mut self.data[start..];
+        if offset + field.size > data.len() * 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("The report of {} bytes ends within the field", self.data.len())));
+        }
+        bits::write(data, offset, field.size, value as u32);
+        Ok(())
+    }
+
+    /* Sets the usage on the page, of the report the id of which the report
+     * starts with if numbered; for arrays, holds it unless value is 0 */
+    pub fn set_by_usage(&mut self, page: u32, id: u32, value: i32) -> io::Result<()> {
+        let (field, index) = self.field(page, id)?;
+        if field.variable {
+            /* a maximum below the minimum is one meant unsigned */
+            if field.logical_minimum <= field.logical_maximum && (value < field.logical_minimum || value > field.logical_maximum) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("{} takes {}..{}", usage_name(usage::extended(page, id)), field.logical_minimum, field.logical_maximum)));
+            }
+            return self.write(field, index, value);
+        }
+
+        let usage = usage::extended(page, id);
+        let wanted = field.value_of(usage).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("The array cannot hold {}", usage_name(usage)))
+        })?;
+        let slots: Vec<Option<i32>> = (0..field.count).map(|slot| self.read(field, slot)).collect();
+        if value == 0 {
+            for slot in (0..field.count).filter(|&slot| slots[slot] == Some(wanted)) {
+                self.write(field, slot, 0)?;
+            }
+            return Ok(());
+        }
+        if slots.contains(&Some(wanted)) {
+            return Ok(());
+        }
+        /* a slot holding no usage is free */
+        let free = (0..field.count).find(|&slot| slots[slot].is_some_and(|held| field.usage_at(held).is_none()));
+        match free {
+            Some(slot) => self.write(field, slot, wanted),
+            None => Err(io::Error::other(format!("The array holding {} is full", usage_name(usage)))),
+        }
+    }
+
+    /* The value of the usage, of arrays 1 if they hold it and 0 if not;
+     * None if the report has no field of it */
+    pub fn get_by_usage(&self, page: u32, id: u32) -> Option<i32> {
+        let (field, index) = self.field(page, id).ok()?;
+        if field.variable {
+            return self.read(field, index);
+        }
+        let usage = usage::extended(page, id);
+        let wanted = field.value_of(usage)?;
+        Some((0..field.count).any(|slot| self.read(field, slot) == Some(wanted)) as i32)
+    }
+
+    /* The bytes of the report, the report-id first if numbered */
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+enum Value {
+    /* of a variable field, with its size in bits */
+    Variable(String, i32, usize),
+    /* held by an array field */
+    Usage(String),
+    /* the report ends within the field */
+    Short,
+}
+
+fn usage_name(usage: u32) -> String {
+    let (page, id) = (usage >> 16, usage & 0xffff);
+    match (page, usage::name(usage)) {
+        (button::PAGE, _) => format!("Button {}", id),
+        (keyboard::PAGE, Some(name)) => format!("Key {}", name),
+        (keyboard::PAGE, None) => format!("Key 0x{:02x}", id),
+        (_, Some(name)) => String::from(name),
+        (_, None) => format!("{:04x}:{:04x}", page, id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hid_report::usage::{generic_desktop, led};
+
+    /* A mouse as report 1, with five button usages for three buttons, and
+     * a keyboard of two key slots with three LEDs as report 2 */
+    const RDESC: [u8; 85] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01,
+        0x85, 0x01,
+        0x05, 0x09, 0x19, 0x01, 0x29, 0x05, 0x15, 0x00, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x81, 0x02,
+        0x95, 0x01, 0x75, 0x05, 0x81, 0x01,
+        0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95, 0x02, 0x81, 0x06,
+        0x85, 0x02,
+        0x05, 0x07, 0x19, 0x00, 0x29, 0x65, 0x15, 0x00, 0x25, 0x65, 0x75, 0x08, 0x95, 0x02, 0x81, 0x00,
+        0x05, 0x08, 0x19, 0x01, 0x29, 0x03, 0x25, 0x01, 0x75, 0x01, 0x95, 0x03, 0x91, 0x02,
+        0x95, 0x01, 0x75, 0x05, 0x91, 0x01,
+        0xc0,
+    ];
+
+    #[test]
+    fn names_the_fields_of_reports() {
+        let reports = Reports::parse(&RDESC);
+        assert!(reports.is_numbered());
+        assert_eq!(reports.decode(&[1, 0b101, 0xff, 0x05]), "Button 1 Button 3 X=-1 Y=5");
+        assert_eq!(reports.decode(&[1, 0b001]), "Button 1 (short)");
+        assert_eq!(reports.decode(&[2, 0x04, 0x00]), "Key A");
+        assert_eq!(reports.describe(Kind::Output, &[2, 0b010]), "Num Lock=0 Caps Lock=1 Scroll Lock=0");
+        assert!(reports.values(Kind::Feature, &[2, 0]).is_empty());
+        assert_eq!(Kind::from_rtype(uhid_report_type::UHID_OUTPUT_REPORT as u8), Some(Kind::Output));
+    }
+
+    #[test]
+    fn builds_and_reads_reports_by_usage() {
+        let reports = Reports::parse(&RDESC);
+        let mut report = reports.report(Kind::Input, 1);
+        report.set_by_usage(generic_desktop::PAGE, generic_desktop::X, -1).unwrap();
+        report.set_by_usage(button::PAGE, 2, 1).unwrap();
+        assert_eq!(report.data(), &[1, 0b010, 0xff, 0]);
+        assert_eq!(report.get_by_usage(generic_desktop::PAGE, generic_desktop::X), Some(-1));
+        assert_eq!(report.get_by_usage(button::PAGE, 1), Some(0));
+
+        let mut report = reports.report(Kind::Input, 2);
+        report.set_by_usage(keyboard::PAGE, keyboard::A, 1).unwrap();
+        report.set_by_usage(keyboard::PAGE, keyboard::B, 1).unwrap();
+        report.set_by_usage(keyboard::PAGE, keyboard::A, 1).unwrap();
+        assert_eq!(report.data(), &[2, 0x04, 0x05]);
+        assert_eq!(report.set_by_usage(keyboard::PAGE, keyboard::C, 1).unwrap_err().kind(), io::ErrorKind::Other);
+        report.set_by_usage(keyboard::PAGE, keyboard::A, 0).unwrap();
+        assert_eq!(report.get_by_usage(keyboard::PAGE, keyboard::A), Some(0));
+        assert_eq!(report.get_by_usage(keyboard::PAGE, keyboard::B), Some(1));
+
+        let report = reports.wrap(Kind::Output, vec![2, 0b100]);
+        assert_eq!(report.get_by_usage(led::PAGE, led::SCROLL_LOCK), Some(1));
+    }
+
+    #[test]
+    fn refuses_what_the_report_cannot_hold() {
+        let reports = Reports::parse(&RDESC);
+        let mut report = reports.report(Kind::Input, 1);
+        let kind = |result: io::Result<()>| result.unwrap_err().kind();
+        assert_eq!(kind(report.set_by_usage(generic_desktop::PAGE, generic_desktop::X, 200)), io::ErrorKind::InvalidInput);
+        assert_eq!(kind(report.set_by_usage(generic_desktop::PAGE, generic_desktop::WHEEL, 1)), io::ErrorKind::NotFound);
+        /* usages beyond the count of buttons */
+        assert_eq!(kind(report.set_by_usage(button::PAGE, 4, 1)), io::ErrorKind::NotFound);
+        assert_eq!(report.get_by_usage(button::PAGE, 4), None);
+
+        let mut short = reports.wrap(Kind::Input, vec![1, 0]);
+        assert_eq!(kind(short.set_by_usage(generic_desktop::PAGE, generic_desktop::Y, 1)), io::ErrorKind::InvalidInput);
+        assert_eq!(short.get_by_usage(generic_desktop::PAGE, generic_desktop::Y), None);
+        assert_eq!(short.into_bytes(), vec![1, 0]);
+    }
+
+    #[test]
+    fn takes_array_values_out_of_range_as_none() {
+        /* keys A and B as the lowest values of a 32-bit array */
+        let reports = Reports::parse(&[
+            0x05, 0x07, 0x19, 0x04, 0x29, 0x05,
+            0x17, 0x00, 0x00, 0x00, 0x80, 0x27, 0xff, 0xff, 0xff, 0x7f,
+            0x75, 0x20, 0x95, 0x01, 0x81, 0x00,
+        ]);
+        assert_eq!(reports.decode(&[0x00, 0x00, 0x00, 0x80]), "Key A");
+        assert_eq!(reports.decode(&[0x01, 0x00, 0x00, 0x80]), "Key B");
+        assert_eq!(reports.decode(&[0xff, 0xff, 0xff, 0x7f]), "");
+        assert_eq!(reports.decode(&[0x00, 0x00, 0x00, 0x00]), "");
+    }
+
+    #[test]
+    fn refuses_usages_beyond_the_values_of_an_array() {
+        /* keys A to D from a minimum of i32::MAX - 1, C and D out of reach */
+        let reports = Reports::parse(&[
+            0x05, 0x07, 0x19, 0x04, 0x29, 0x07,
+            0x17, 0xfe, 0xff, 0xff, 0x7f, 0x27, 0xff, 0xff, 0xff, 0x7f,
+            0x75, 0x20, 0x95, 0x02, 0x81, 0x00,
+        ]);
+        let mut report = reports.report(Kind::Input, 0);
+        report.set_by_usage(keyboard::PAGE, keyboard::B, 1).unwrap();
+        assert_eq!(report.data(), &[0xff, 0xff, 0xff, 0x7f, 0, 0, 0, 0]);
+        assert_eq!(report.get_by_usage(keyboard::PAGE, keyboard::B), Some(1));
+        assert_eq!(report.set_by_usage(keyboard::PAGE, keyboard::C, 1).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(report.get_by_usage(keyboard::PAGE, keyboard::D), None);
+    }
+
+    #[test]
+    fn tells_reports_letting_go() {
+        let reports = Reports::parse(&RDESC);
+        assert!(reports.releases(&[1, 0b011, 0, 0], &[1, 0b001, 5, 0]));
+        assert!(!reports.releases(&[1, 0b011, 0, 0], &[1, 0b101, 0, 0]));
+        assert!(!reports.releases(&[1, 0b000, 0, 0], &[1, 0b000, 0, 0]));
+        assert!(reports.releases(&[2, 0x04, 0x05], &[2, 0x00, 0x05]));
+        assert!(!reports.releases(&[2, 0x04, 0x00], &[2, 0x06, 0x00]));
+
+        assert_eq!(reports.released(&[1, 0b111, 0x10, 0xf0]), vec![1, 0, 0, 0]);
+        assert_eq!(reports.released(&[2, 0x04, 0x05]), vec![2, 0, 0]);
+    }
+}
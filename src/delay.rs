@@ -0,0 +1,93 @@
+/*
+ * Delayed reports
+ * Holds back forwarded or replayed input reports by a fixed latency plus a
+ * random jitter, like a congested bus, a Bluetooth link or a remote desktop
+ * would, to test how applications smooth and predict input arriving late and
+ * unevenly.
+ *
+ * The jitter is added on top of the latency, drawn from one of:
+ *   uniform      anywhere between 0 and the jitter
+ *   normal       the jitter is the standard deviation, folded to be positive
+ *   exponential  the jitter is the mean, mostly short with a long tail
+ * Reports keep their order, as on a real transport: one drawn to be sent
+ * before an earlier one waits for it, so reports bunch up instead.
+ */
+
+use devices::path::Rng;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const DISTRIBUTION_NAMES: &[&str] = &["uniform", "normal", "exponential"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Normal,
+    Exponential,
+}
+
+impl Distribution {
+    pub fn from_name(name: &str) -> Option<Distribution> {
+        match name {
+            "uniform" => Some(Distribution::Uniform),
+            "normal" => Some(Distribution::Normal),
+            "exponential" => Some(Distribution::Exponential),
+            _ => None,
+        }
+    }
+}
+
+pub struct Delay {
+    latency: Duration,
+    jitter: Duration,
+    distribution: Distribution,
+    rng: Rng,
+    /* Reports with the time they are due, in order */
+    queue: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl Delay {
+    pub fn new(latency: Duration, jitter: Duration, distribution: Distribution, seed: u64) -> Delay {
+        Delay { latency, jitter, distribution, rng: Rng::new(seed), queue: VecDeque::new() }
+    }
+
+    /* A random share of the jitter, by the distribution */
+    fn jitter(&mut self) -> Duration {
+        let scale = match self.distribution {
+            Distribution::Uniform => self.rng.range(0.0, 1.0),
+            Distribution::Normal => {
+                /* Box-Muller, 1 - u keeps the logarithm finite */
+                let radius = (-2.0 * (1.0 - self.rng.range(0.0, 1.0)).ln()).sqrt();
+                (radius * (2.0 * std::f32::consts::PI * self.rng.range(0.0, 1.0)).cos()).abs()
+            },
+            Distribution::Exponential => -(1.0 - self.rng.range(0.0, 1.0)).ln(),
+        };
+        self.jitter.mul_f32(scale)
+    }
+
+    pub fn push(&mut self, report: Vec<u8>, now: Instant) {
+        let mut due = now + self.latency + self.jitter();
+        if let Some(&(last, _)) = self.queue.back() {
+            due = due.max(last);
+        }
+        self.queue.push_back((due, report));
+    }
+
+    /* When the next report is due */
+    pub fn deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|&(due, _)| due)
+    }
+
+    /* Takes off the reports due by `now`, to send */
+    pub fn due(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        while self.deadline().is_some_and(|due| due <= now) {
+            reports.push(self.queue.pop_front().unwrap().1);
+        }
+        reports
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Report callbacks
+ * Wraps a device so the embedding program answers GET_REPORT and SET_REPORT
+ * requests itself, for reports whose contents are computed when the host
+ * asks for them, e.g. sensor readings or authenticator responses, instead of
+ * being kept in the device.
+ *
+ * Requests are passed the report number and the uhid_report_type. Without a
+ * callback registered they go to the wrapped device as usual; everything
+ * else always does.
+ */
+
+use super::{Action, Device, Identity};
+use std::io;
+use std::time::{Duration, Instant};
+
+pub type GetReport = Box<dyn FnMut(u8, u8) -> io::Result<Vec<u8>>>;
+pub type SetReport = Box<dyn FnMut(u8, u8, &[u8]) -> io::Result<()>>;
+
+pub struct Callbacks<D: Device> {
+    device: D,
+    get_report: Option<GetReport>,
+    set_report: Option<SetReport>,
+}
+
+impl<D: Device> Callbacks<D> {
+    pub fn new(device: D) -> Callbacks<D> {
+        Callbacks {
+            device,
+            get_report: None,
+            set_report: None,
+        }
+    }
+
+    /* The callback returns the report including its report-id if numbered */
+    pub fn on_get_report<F>(mut self, callback: F) -> Self
+        where F: FnMut(u8, u8) -> io::Result<Vec<u8>> + 'static
+    {
+        self.get_report = Some(Box::new(callback));
+        self
+    }
+
+    pub fn on_set_report<F>(mut self, callback: F) -> Self
+        where F: FnMut(u8, u8, &[u8]) -> io::Result<()> + 'static
+    {
+        self.set_report = Some(Box::new(callback));
+        self
+    }
+
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    pub fn device_mut(&mut self) -> &mut D {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+impl<D: Device> Device for Callbacks<D> {
+    fn descriptor(&self) -> &[u8] {
+        self.device.descriptor()
+    }
+
+    fn identity(&self) -> Identity {
+        self.device.identity()
+    }
+
+    fn is_keyboard(&self) -> bool {
+        self.device.is_keyboard()
+    }
+
+    fn leds(&self) -> Option<u8> {
+        self.device.leds()
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        self.device.action(action)
+    }
+
+    fn scroll(&mut self, pixels: i32, duration: Duration) -> Option<Vec<(Duration, Vec<u8>)>> {
+        self.device.scroll(pixels, duration)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.device.deadline()
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        self.device.tick(now)
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        self.device.handle_output(rtype, data)
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        self.device.decode_output(rtype, data)
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        match self.get_report {
+            Some(ref mut callback) => callback(rnum, rtype),
+            None => self.device.get_report(rnum, rtype),
+        }
+    }
+
+    fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+        match self.set_report {
+            Some(ref mut callback) => callback(rnum, rtype, data),
+            None => self.device.set_report(rnum, rtype, data),
+        }
+    }
+}
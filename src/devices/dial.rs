@@ -0,0 +1,112 @@
+/*
+ * Surface Dial
+ * A radial controller as defined by the System Multi-Axis Controller
+ * application collection: a puck with a single button and a relative dial
+ * reported in tenths of a degree. The identity is that of the Bluetooth
+ * Surface Dial (045e:091b); the kernel maps the dial to REL_DIAL, which
+ * compositors handle through their rarely exercised dial input path.
+ *
+ * Report 0x01 (3 bytes):
+ *   byte 1, 2     bit 0 is the button, bits 1..15 the signed rotation in
+ *                 tenths of a degree since the last report
+ *
+ * Keys map onto the dial as follows:
+ *   1: Press/release the dial
+ *   a/d: Rotate counter-clockwise/clockwise in coarse steps
+ *   r/f: Rotate clockwise/counter-clockwise in fine steps
+ */
+
+use super::{Action, Device, Identity};
+use uhid::BUS_BLUETOOTH;
+
+const RDESC: [u8; 54] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x0e,	/* USAGE (System Multi-Axis Controller) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x01,		/* REPORT_ID (1) */
+    0x05, 0x0d,		/* USAGE_PAGE (Digitizers) */
+    0x09, 0x21,		/* USAGE (Puck) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x05, 0x09,			/* USAGE_PAGE (Button) */
+    0x09, 0x01,			/* USAGE (Button 1) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x37,			/* USAGE (Dial) */
+    0x55, 0x0f,			/* UNIT_EXPONENT (-1) */
+    0x65, 0x14,			/* UNIT (Degrees) */
+    0x36, 0xf0, 0xf1,		/* PHYSICAL_MINIMUM (-3600) */
+    0x46, 0x10, 0x0e,		/* PHYSICAL_MAXIMUM (3600) */
+    0x16, 0xf0, 0xf1,		/* LOGICAL_MINIMUM (-3600) */
+    0x26, 0x10, 0x0e,		/* LOGICAL_MAXIMUM (3600) */
+    0x75, 0x0f,			/* REPORT_SIZE (15) */
+    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
+    0xc0,			/* END_COLLECTION */
+    0xc0,		/* END_COLLECTION */
+];
+
+/* A single report can carry at most one full turn */
+const MAX_ROTATION: i32 = 3600;
+
+const COARSE_STEP: f32 = 5.0;
+const FINE_STEP: f32 = 1.0;
+
+#[derive(Default)]
+pub struct Dial {
+    pressed: bool,
+}
+
+impl Dial {
+    fn report(&self, rotation: i32) -> Vec<u8> {
+        let value = (rotation as u16 & 0x7fff) << 1 | self.pressed as u16;
+        let mut data = vec![0x01];
+        data.extend_from_slice(&value.to_le_bytes());
+        data
+    }
+
+    /* Report a rotation by the given number of degrees, clockwise is positive */
+    pub fn rotate(&mut self, degrees: f32) -> Vec<u8> {
+        let tenths = (degrees * 10.0).round() as i32;
+        self.report(tenths.clamp(-MAX_ROTATION, MAX_ROTATION))
+    }
+
+    pub fn press(&mut self) -> Vec<u8> {
+        self.pressed = true;
+        self.report(0)
+    }
+
+    pub fn release(&mut self) -> Vec<u8> {
+        self.pressed = false;
+        self.report(0)
+    }
+}
+
+impl Device for Dial {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_BLUETOOTH as u16,
+            vendor: 0x045e,
+            product: 0x091b,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) if self.pressed => vec![self.release()],
+            Action::ToggleButton(1) => vec![self.press()],
+            Action::ToggleButton(_) => vec![],
+            Action::Move(0, _) => vec![],
+            Action::Move(dx, _) => vec![self.rotate(dx.signum() as f32 * COARSE_STEP)],
+            Action::Wheel(delta) => vec![self.rotate(delta as f32 * FINE_STEP)],
+        }
+    }
+}
@@ -0,0 +1,331 @@
+/*
+ * DualShock 4 controller
+ * This preset announces itself as a first generation DualShock 4 on USB
+ * (054c:05c4) and follows the layout hid-sony and hid-playstation expect, so
+ * both drivers bind and create the gamepad, touchpad and motion sensor
+ * devices.
+ *
+ * Input report 0x01 (64 bytes):
+ *   bytes 1..4    left X/Y, right X/Y sticks, 0x80 is centered
+ *   byte 5        hat in the low nibble (8 is released), square, cross,
+ *                 circle, triangle
+ *   byte 6        L1, R1, L2, R2, share, options, L3, R3
+ *   byte 7        PS, touchpad click, 6 bit report counter
+ *   byte 8, 9     L2 and R2 triggers
+ *   bytes 10..12  sensor timestamp (5.33us units) and temperature
+ *   bytes 13..24  gyro X/Y/Z and accelerometer X/Y/Z, signed 16 bit
+ *   byte 30       battery level in the low nibble, bit 4 set while cabled
+ *   byte 33       number of touch reports that follow
+ *   bytes 34..60  three touch reports, each a timestamp and two contacts:
+ *                 bit 7 set when the contact is lifted, 7 bit tracking id,
+ *                 then 12 bit X and 12 bit Y packed into three bytes
+ *
+ * Output report 0x05 sets rumble and the lightbar:
+ *   byte 1        valid flags, 0x01 motors, 0x02 lightbar, 0x04 blinking
+ *   byte 4, 5     weak (right) and strong (left) motor
+ *   bytes 6..8    lightbar red, green, blue
+ *   byte 9, 10    blink on/off duration
+ *
+ * The drivers read calibration (0x02), pairing info (0x12, 0x81) and firmware
+ * info (0xa3) feature reports while probing; these are answered with
+ * plausible fixed values.
+ *
+ * Keys map onto the controller as follows:
+ *   1/2: Toggle cross/circle
+ *   3: Place or lift a finger on the touchpad
+ *   a/d/w/s: Move the finger while touching, the left stick otherwise
+ *   r/f: Press/release the R2 trigger
+ */
+
+use super::{Action, Device, Identity};
+use hid_report::bits;
+use std::io;
+use uhid::{uhid_report_type, BUS_USB};
+
+const RDESC: [u8; 140] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x05,	/* USAGE (Game Pad) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x01,		/* REPORT_ID (1) */
+    0x09, 0x30,		/* USAGE (X) */
+    0x09, 0x31,		/* USAGE (Y) */
+    0x09, 0x32,		/* USAGE (Z) */
+    0x09, 0x35,		/* USAGE (Rz) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x95, 0x04,		/* REPORT_COUNT (4) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x09, 0x39,		/* USAGE (Hat switch) */
+    0x25, 0x07,		/* LOGICAL_MAXIMUM (7) */
+    0x35, 0x00,		/* PHYSICAL_MINIMUM (0) */
+    0x46, 0x3b, 0x01,	/* PHYSICAL_MAXIMUM (315) */
+    0x65, 0x14,		/* UNIT (Degrees) */
+    0x75, 0x04,		/* REPORT_SIZE (4) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x81, 0x42,		/* INPUT (Data,Var,Abs,Null) */
+    0x65, 0x00,		/* UNIT (None) */
+    0x45, 0x00,		/* PHYSICAL_MAXIMUM (0) */
+    0x05, 0x09,		/* USAGE_PAGE (Button) */
+    0x19, 0x01,		/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x0e,		/* USAGE_MAXIMUM (Button 14) */
+    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x95, 0x0e,		/* REPORT_COUNT (14) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x06, 0x00, 0xff,	/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x20,		/* USAGE (Vendor Usage 0x20) */
+    0x25, 0x3f,		/* LOGICAL_MAXIMUM (63) */
+    0x75, 0x06,		/* REPORT_SIZE (6) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x05, 0x01,		/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x33,		/* USAGE (Rx) */
+    0x09, 0x34,		/* USAGE (Ry) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x95, 0x02,		/* REPORT_COUNT (2) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x06, 0x00, 0xff,	/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x21,		/* USAGE (Vendor Usage 0x21) */
+    0x95, 0x36,		/* REPORT_COUNT (54) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x85, 0x05,		/* REPORT_ID (5) */
+    0x09, 0x22,		/* USAGE (Vendor Usage 0x22) */
+    0x95, 0x1f,		/* REPORT_COUNT (31) */
+    0x91, 0x02,		/* OUTPUT (Data,Var,Abs) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x09, 0x24,		/* USAGE (Vendor Usage 0x24) */
+    0x95, 0x24,		/* REPORT_COUNT (36) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0x85, 0x12,		/* REPORT_ID (18) */
+    0x09, 0x25,		/* USAGE (Vendor Usage 0x25) */
+    0x95, 0x0f,		/* REPORT_COUNT (15) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0x85, 0x81,		/* REPORT_ID (129) */
+    0x09, 0x26,		/* USAGE (Vendor Usage 0x26) */
+    0x95, 0x06,		/* REPORT_COUNT (6) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0x85, 0xa3,		/* REPORT_ID (163) */
+    0x09, 0x27,		/* USAGE (Vendor Usage 0x27) */
+    0x95, 0x30,		/* REPORT_COUNT (48) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+const BTN_CROSS: u8 = 0x20;
+const BTN_CIRCLE: u8 = 0x40;
+const HAT_RELEASED: u8 = 0x08;
+
+const TOUCHPAD_WIDTH: i32 = 1920;
+const TOUCHPAD_HEIGHT: i32 = 942;
+
+/* Keyboard steps scaled onto the stick and touchpad ranges */
+const STICK_SCALE: i32 = 3;
+const TOUCH_SCALE: i32 = 5;
+const TRIGGER_STEP: i32 = 64;
+
+/* Sensor timestamp advance per report, roughly 1ms in 5.33us units */
+const TIMESTAMP_STEP: u16 = 188;
+
+/* The accelerometer reports 1g as 8192 */
+const ACCEL_1G: i16 = 8192;
+
+const MAC_ADDRESS: [u8; 6] = [0x02, 0x00, 0x00, 0xd5, 0x4d, 0x01];
+
+pub struct DualShock4 {
+    buttons: [u8; 3],
+    left_stick: (u8, u8),
+    r2: u8,
+    counter: u8,
+    timestamp: u16,
+    touching: bool,
+    touch_id: u8,
+    touch: (i32, i32),
+}
+
+impl Default for DualShock4 {
+    fn default() -> DualShock4 {
+        DualShock4 {
+            buttons: [HAT_RELEASED, 0, 0],
+            left_stick: (0x80, 0x80),
+            r2: 0,
+            counter: 0,
+            timestamp: 0,
+            touching: false,
+            touch_id: 0,
+            touch: (TOUCHPAD_WIDTH / 2, TOUCHPAD_HEIGHT / 2),
+        }
+    }
+}
+
+fn push_axis(value: u8, delta: i32) -> u8 {
+    (value as i32 + delta).clamp(0, 255) as u8
+}
+
+impl DualShock4 {
+    fn toggle_touch(&mut self) {
+        self.touching = !self.touching;
+        if self.touching {
+            /* every new contact gets a new tracking id */
+            self.touch_id = (self.touch_id + 1) & 0x7f;
+        }
+    }
+
+    fn report(&mut self) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+
+        self.counter = (self.counter + 1) & 0x3f;
+        self.timestamp = self.timestamp.wrapping_add(TIMESTAMP_STEP);
+
+        data[0] = 0x01;
+        data[1] = self.left_stick.0;
+        data[2] = self.left_stick.1;
+        data[3] = 0x80;
+        data[4] = 0x80;
+        data[5] = self.buttons[0];
+        data[6] = self.buttons[1];
+        data[7] = self.buttons[2] | (self.counter << 2);
+        data[9] = self.r2;
+        data[10..12].copy_from_slice(&self.timestamp.to_le_bytes());
+        /* gravity pulls along the Y axis of a controller lying flat */
+        data[21..23].copy_from_slice(&ACCEL_1G.to_le_bytes());
+        data[30] = 0x1b;
+
+        data[33] = 1;
+        data[34] = self.counter;
+        let (x, y) = (self.touch.0 as u16, self.touch.1 as u16);
+        data[35] = self.touch_id | if self.touching { 0x00 } else { 0x80 };
+        bits::write(&mut data[36..39], 0, 12, x as u32);
+        bits::write(&mut data[36..39], 12, 12, y as u32);
+        /* second contact is never down */
+        data[39] = 0x80;
+        data
+    }
+}
+
+fn calibration() -> Vec<u8> {
+    let values: [i16; 18] = [
+        0, 0, 0,		/* gyro pitch, yaw, roll bias */
+        8704, -8704,		/* gyro pitch plus/minus */
+        8704, -8704,		/* gyro yaw plus/minus */
+        8704, -8704,		/* gyro roll plus/minus */
+        540, 540,		/* gyro speed plus/minus */
+        ACCEL_1G, -ACCEL_1G,	/* accel X plus/minus */
+        ACCEL_1G, -ACCEL_1G,	/* accel Y plus/minus */
+        ACCEL_1G, -ACCEL_1G,	/* accel Z plus/minus */
+        0,			/* padding */
+    ];
+    let mut data = vec![0x02];
+    for value in values.iter() {
+        data.extend_from_slice(&value.to_le_bytes());
+    }
+    data
+}
+
+fn firmware_info() -> Vec<u8> {
+    let mut data = vec![0u8; 49];
+    data[0] = 0xa3;
+    data[1..12].copy_from_slice(b"Sep 21 2018");
+    data[16..24].copy_from_slice(b"04:50:51");
+    /* hardware and firmware version */
+    data[35..37].copy_from_slice(&0x0100u16.to_le_bytes());
+    data[41..43].copy_from_slice(&0x8001u16.to_le_bytes());
+    data
+}
+
+fn pairing_info(id: u8, size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    data[0] = id;
+    /* the address is stored least significant byte first */
+    for (i, byte) in MAC_ADDRESS.iter().rev().enumerate() {
+        data[1 + i] = *byte;
+    }
+    data
+}
+
+impl Device for DualShock4 {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x054c,
+            product: 0x05c4,
+            version: 0x0100,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => self.buttons[0] ^= BTN_CROSS,
+            Action::ToggleButton(2) => self.buttons[0] ^= BTN_CIRCLE,
+            Action::ToggleButton(3) => self.toggle_touch(),
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, dy) if self.touching => {
+                self.touch.0 = (self.touch.0 + dx as i32 * TOUCH_SCALE).clamp(0, TOUCHPAD_WIDTH - 1);
+                self.touch.1 = (self.touch.1 + dy as i32 * TOUCH_SCALE).clamp(0, TOUCHPAD_HEIGHT - 1);
+            },
+            Action::Move(dx, dy) => {
+                self.left_stick.0 = push_axis(self.left_stick.0, dx as i32 * STICK_SCALE);
+                self.left_stick.1 = push_axis(self.left_stick.1, dy as i32 * STICK_SCALE);
+            },
+            Action::Wheel(delta) => self.r2 = push_axis(self.r2, delta as i32 * TRIGGER_STEP),
+        }
+
+        vec![self.report()]
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 || data.len() < 11 || data[0] != 0x05 {
+            return;
+        }
+
+        let flags = data[1];
+        if flags & 0x01 != 0 {
+            eprintln!("Rumble weak motor {} strong motor {}", data[4], data[5]);
+        }
+        if flags & 0x02 != 0 {
+            eprintln!("Lightbar color #{:02x}{:02x}{:02x}", data[6], data[7], data[8]);
+        }
+        if flags & 0x04 != 0 {
+            eprintln!("Lightbar blink on {} off {}", data[9], data[10]);
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 || data.len() < 11 || data[0] != 0x05 {
+            return vec![];
+        }
+
+        /* only what the flags say is set */
+        let mut values = Vec::new();
+        if data[1] & 0x01 != 0 {
+            values.extend_from_slice(&[("rumble_weak", data[4] as i64), ("rumble_strong", data[5] as i64)]);
+        }
+        if data[1] & 0x02 != 0 {
+            values.extend_from_slice(&[("lightbar_red", data[6] as i64), ("lightbar_green", data[7] as i64), ("lightbar_blue", data[8] as i64)]);
+        }
+        if data[1] & 0x04 != 0 {
+            values.extend_from_slice(&[("blink_on", data[9] as i64), ("blink_off", data[10] as i64)]);
+        }
+        values
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"));
+        }
+
+        match rnum {
+            0x02 => Ok(calibration()),
+            0x12 => Ok(pairing_info(0x12, 16)),
+            0x81 => Ok(pairing_info(0x81, 7)),
+            0xa3 => Ok(firmware_info()),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report")),
+        }
+    }
+}
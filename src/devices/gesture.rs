@@ -0,0 +1,133 @@
+/*
+ * Touch gestures
+ * Generators turning high-level gestures into the frame sequence a person
+ * would produce on the touchscreen, so compositor gesture recognition can be
+ * scripted. Each frame comes with the delay to wait after the previous one;
+ * frames are spaced at the 120Hz rate of common touchscreens and play()
+ * sends them with that timing, which also makes the scan time in the
+ * reports advance correctly.
+ *
+ * Gestures use contact identifiers starting at 0, so they are meant to be
+ * played while no other contact is touching the screen.
+ */
+
+use super::touchscreen::{TouchFrame, Touchscreen, MAX_CONTACTS, MAX_X, MAX_Y};
+use std::f32::consts::PI;
+use std::io;
+use std::thread;
+use std::time::Duration;
+
+pub type Gesture = Vec<(Duration, TouchFrame)>;
+
+const FRAME_INTERVAL_US: u64 = 8333;
+
+/* Time a finger rests on the screen during a tap */
+const TAP_HOLD_MS: u64 = 60;
+
+/* Distance between neighbouring fingers in multi-finger gestures */
+const FINGER_SPACING: f32 = 250.0;
+
+fn frame_interval() -> Duration {
+    Duration::from_micros(FRAME_INTERVAL_US)
+}
+
+fn steps(duration: Duration) -> u32 {
+    let micros = duration.as_secs() * 1_000_000 + duration.subsec_micros() as u64;
+    ((micros / FRAME_INTERVAL_US) as u32).max(1)
+}
+
+fn clamp_point(x: f32, y: f32) -> (u16, u16) {
+    (x.round().clamp(0.0, MAX_X as f32) as u16, y.round().clamp(0.0, MAX_Y as f32) as u16)
+}
+
+/*
+ * Builds a gesture from the finger positions at a given progress: every
+ * finger goes down at progress 0, moves through the intermediate steps and
+ * goes up after progress 1.
+ */
+fn track<F>(fingers: u8, duration: Duration, position: F) -> Gesture
+    where F: Fn(u8, f32) -> (f32, f32)
+{
+    let steps = steps(duration);
+    let fingers = fingers.clamp(1, MAX_CONTACTS as u8);
+    let mut gesture = Vec::new();
+
+    let down = (0..fingers).fold(TouchFrame::new(), |frame, finger| {
+        let (x, y) = position(finger, 0.0);
+        let (x, y) = clamp_point(x, y);
+        frame.down(finger, x, y)
+    });
+    gesture.push((Duration::from_secs(0), down));
+
+    for step in 1..=steps {
+        let progress = step as f32 / steps as f32;
+        let frame = (0..fingers).fold(TouchFrame::new(), |frame, finger| {
+            let (x, y) = position(finger, progress);
+            let (x, y) = clamp_point(x, y);
+            frame.move_to(finger, x, y)
+        });
+        gesture.push((frame_interval(), frame));
+    }
+
+    let up = (0..fingers).fold(TouchFrame::new(), |frame, finger| frame.up(finger));
+    gesture.push((frame_interval(), up));
+    gesture
+}
+
+/* Offset of a finger within a row of fingers centered on the gesture */
+fn row_offset(finger: u8, fingers: u8) -> f32 {
+    (finger as f32 - (fingers - 1) as f32 / 2.0) * FINGER_SPACING
+}
+
+/* Two fingers moving apart (zoom in) or together (zoom out) horizontally */
+pub fn pinch(center: (u16, u16), from_dist: u16, to_dist: u16, duration: Duration) -> Gesture {
+    track(2, duration, |finger, progress| {
+        let distance = from_dist as f32 + (to_dist as f32 - from_dist as f32) * progress;
+        let direction = if finger == 0 { -1.0 } else { 1.0 };
+        (center.0 as f32 + direction * distance / 2.0, center.1 as f32)
+    })
+}
+
+/* A row of fingers moving in a straight line */
+pub fn swipe(from: (u16, u16), to: (u16, u16), fingers: u8, duration: Duration) -> Gesture {
+    let fingers = fingers.clamp(1, MAX_CONTACTS as u8);
+    track(fingers, duration, |finger, progress| {
+        let x = from.0 as f32 + (to.0 as f32 - from.0 as f32) * progress;
+        let y = from.1 as f32 + (to.1 as f32 - from.1 as f32) * progress;
+        (x + row_offset(finger, fingers), y)
+    })
+}
+
+/* Two fingers on opposite sides of a circle turning by the given angle, clockwise is positive */
+pub fn rotate(center: (u16, u16), radius: u16, degrees: f32, duration: Duration) -> Gesture {
+    track(2, duration, |finger, progress| {
+        let angle = (degrees * progress).to_radians() + finger as f32 * PI;
+        (center.0 as f32 + radius as f32 * angle.cos(), center.1 as f32 + radius as f32 * angle.sin())
+    })
+}
+
+/* A row of fingers touching and lifting again without moving */
+pub fn tap(at: (u16, u16), fingers: u8) -> Gesture {
+    let fingers = fingers.clamp(1, MAX_CONTACTS as u8);
+    let mut gesture = track(fingers, frame_interval(), |finger, _| {
+        (at.0 as f32 + row_offset(finger, fingers), at.1 as f32)
+    });
+
+    /* a tap has no motion, rest for the hold time instead */
+    let up = gesture.pop().unwrap().1;
+    gesture.truncate(1);
+    gesture.push((Duration::from_millis(TAP_HOLD_MS), up));
+    gesture
+}
+
+/* Plays a gesture in real time, handing each report to `send` */
+pub fn play<F>(touchscreen: &mut Touchscreen, gesture: &[(Duration, TouchFrame)], mut send: F) -> io::Result<()>
+    where F: FnMut(&[u8]) -> io::Result<()>
+{
+    for (delay, frame) in gesture.iter() {
+        thread::sleep(*delay);
+        let report = touchscreen.frame(frame)?;
+        send(&report)?;
+    }
+    Ok(())
+}
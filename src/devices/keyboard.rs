@@ -0,0 +1,325 @@
+/*
+ * Keyboard
+ * Helpers shared by the keyboard devices, plus a plain keyboard preset. They
+ * all use the boot keyboard layout as report 0x01: modifier bits, a reserved
+ * byte and up to six pressed keys given as usages of the Keyboard/Keypad
+ * page. The host sets the LEDs with output report 0x01 carrying NumLock,
+ * CapsLock, ScrollLock, Compose and Kana bits.
+ *
+ * Text is typed as one press/release report pair per character, see layout.rs
+ * for how characters map onto keys.
+ *
+ * The keyboard preset keeps the LED state the host sets, so text is typed
+ * with the right case while Caps Lock is on. With sync_host_leds() it starts
+ * out with the lock state of the host's other keyboards instead of all off,
+ * as the host may only send the state once the device is opened.
+ *
+ * Keyboards normally leave auto-repeat to the host. Some firmware repeats
+ * held keys itself instead, by releasing and pressing them again at the
+ * typematic rate; the keyboard preset can do the same when configured with a
+ * Repeat. The kernel's own soft-repeat should be disabled (e.g. with kbdrate
+ * or the compositor settings) to see only the device's repeats.
+ *
+ * Keys map onto the keyboard preset as follows:
+ *   1: Hold/release Left Shift
+ *   2: Hold/release A
+ *   3: Tap Caps Lock
+ *   a/d/w/s: Tap the arrow keys
+ *   r/f: Tap Page Up/Page Down
+ */
+
+use super::layout::Layout;
+use super::{Action, Device, Identity};
+use std::fs;
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+use uhid::{uhid_report_type, BUS_USB};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/* The report definitions are shared through hid-report */
+pub use hid_report::keyboard::{leds, BOOT_KEYBOARD_RDESC, KEY_A, KEY_C, KEY_CAPS_LOCK, KEY_DOWN, KEY_LEFT,
+                               KEY_PAGE_DOWN, KEY_PAGE_UP, KEY_RIGHT, KEY_UP, KEY_V, LED_CAPS_LOCK,
+                               LED_NUM_LOCK, LED_SCROLL_LOCK, MOD_LEFT_CTRL, MOD_LEFT_SHIFT, MOD_RIGHT_ALT};
+
+/* The LED bits of an output report, by name, for decode_output() */
+pub fn led_values(leds: u8) -> Vec<(&'static str, i64)> {
+    vec![
+        ("num_lock", (leds & LED_NUM_LOCK != 0) as i64),
+        ("caps_lock", (leds & LED_CAPS_LOCK != 0) as i64),
+        ("scroll_lock", (leds & LED_SCROLL_LOCK != 0) as i64),
+    ]
+}
+
+/* Pause between two typed characters unless configured otherwise */
+pub const DEFAULT_KEY_DELAY_MS: u64 = 10;
+
+const SYSFS_LEDS: &str = "/sys/class/leds";
+
+/* Builds report 0x01 for the given modifiers and at most six pressed keys */
+pub fn report(modifiers: u8, keys: &[u8]) -> Vec<u8> {
+    hid_report::keyboard::report(modifiers, keys).to_vec()
+}
+
+/* Press and release of a single key, optionally with modifiers held */
+pub fn tap(modifiers: u8, key: u8) -> Vec<Vec<u8>> {
+    vec![report(modifiers, &[key]), report(0, &[])]
+}
+
+/*
+ * Lock state shown by the keyboards attached to the host, combined from the
+ * LED class devices the input core registers for every keyboard LED.
+ */
+pub fn host_leds() -> io::Result<u8> {
+    let mut leds = 0;
+
+    for entry in fs::read_dir(SYSFS_LEDS)? {
+        let path = entry?.path();
+        let led = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.ends_with("::numlock") => LED_NUM_LOCK,
+            Some(name) if name.ends_with("::capslock") => LED_CAPS_LOCK,
+            Some(name) if name.ends_with("::scrolllock") => LED_SCROLL_LOCK,
+            _ => continue,
+        };
+
+        let brightness = fs::read_to_string(path.join("brightness"))?;
+        if brightness.trim() != "0" {
+            leds |= led;
+        }
+    }
+    Ok(leds)
+}
+
+/* A key press with the modifiers needed to produce one character */
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyStroke {
+    pub modifiers: u8,
+    pub key: u8,
+}
+
+impl KeyStroke {
+    pub fn reports(&self) -> Vec<Vec<u8>> {
+        tap(self.modifiers, self.key)
+    }
+}
+
+/*
+ * Sends the press/release pair of every stroke, keeping at least `delay`
+ * between two strokes and, if a rate is set, no more than `rate` strokes per
+ * second on average.
+ */
+pub struct Typist {
+    delay: Duration,
+    rate: Option<u32>,
+    start: Option<Instant>,
+    typed: u32,
+}
+
+impl Typist {
+    pub fn new(delay: Duration) -> Typist {
+        Typist {
+            delay,
+            rate: None,
+            start: None,
+            typed: 0,
+        }
+    }
+
+    pub fn rate(mut self, strokes_per_second: u32) -> Typist {
+        self.rate = Some(strokes_per_second.max(1));
+        self
+    }
+
+    /* Restarts the rate limit, e.g. after typing was paused */
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.typed = 0;
+    }
+
+    fn wait(&mut self) {
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some(Instant::now());
+                return;
+            }
+        };
+
+        thread::sleep(self.delay);
+        if let Some(rate) = self.rate {
+            let due = start + Duration::from_secs(1) * self.typed / rate;
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+    }
+
+    pub fn type_stroke<F>(&mut self, stroke: &KeyStroke, mut send: F) -> io::Result<()>
+        where F: FnMut(&[u8]) -> io::Result<()>
+    {
+        self.wait();
+        for report in stroke.reports() {
+            send(&report)?;
+        }
+        self.typed += 1;
+        Ok(())
+    }
+
+    pub fn type_strokes<F>(&mut self, strokes: &[KeyStroke], mut send: F) -> io::Result<()>
+        where F: FnMut(&[u8]) -> io::Result<()>
+    {
+        for stroke in strokes.iter() {
+            self.type_stroke(stroke, &mut send)?;
+        }
+        Ok(())
+    }
+
+    /* Types a text; nothing is sent if the layout cannot produce all of it */
+    pub fn type_text<F>(&mut self, text: &str, layout: &Layout, send: F) -> io::Result<()>
+        where F: FnMut(&[u8]) -> io::Result<()>
+    {
+        let strokes = layout.strokes(text)?;
+        self.type_strokes(&strokes, send)
+    }
+}
+
+/* Typematic delay before the first repeat and interval between repeats */
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Repeat {
+    pub delay: Duration,
+    pub interval: Duration,
+}
+
+#[derive(Default)]
+pub struct Keyboard {
+    modifiers: u8,
+    keys: Vec<u8>,
+    repeat: Option<Repeat>,
+    /* The most recently pressed key repeats, like on real keyboards */
+    repeating: Option<(u8, Instant)>,
+    leds: u8,
+}
+
+impl Keyboard {
+    pub fn set_repeat(&mut self, repeat: Option<Repeat>) {
+        self.repeat = repeat;
+        self.repeating = None;
+    }
+
+    /* Starts out with the lock state the host's keyboards currently show */
+    pub fn sync_host_leds(&mut self) -> io::Result<()> {
+        self.leds = host_leds()?;
+        Ok(())
+    }
+
+    fn toggle_key(&mut self, key: u8) {
+        match self.keys.iter().position(|held| *held == key) {
+            Some(index) => {
+                self.keys.remove(index);
+                if self.repeating.map(|(repeating, _)| repeating) == Some(key) {
+                    self.repeating = None;
+                }
+            },
+            None => {
+                self.keys.push(key);
+                if let Some(repeat) = self.repeat {
+                    self.repeating = Some((key, Instant::now() + repeat.delay));
+                }
+            },
+        }
+    }
+
+    fn report(&self) -> Vec<u8> {
+        report(self.modifiers, &self.keys)
+    }
+
+    /* Taps a key while keeping held keys pressed */
+    fn tap_held(&mut self, key: u8) -> Vec<Vec<u8>> {
+        self.keys.push(key);
+        let press = self.report();
+        self.keys.pop();
+        vec![press, self.report()]
+    }
+}
+
+impl Device for Keyboard {
+    fn descriptor(&self) -> &[u8] {
+        &BOOT_KEYBOARD_RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x15d9,
+            product: 0x0a3a,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn is_keyboard(&self) -> bool {
+        true
+    }
+
+    fn leds(&self) -> Option<u8> {
+        Some(self.leds)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.repeating.map(|(_, due)| due)
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let (key, due) = match (self.repeating, self.repeat) {
+            (Some(repeating), Some(repeat)) if repeating.1 <= now => (repeating.0, repeating.1 + repeat.interval),
+            _ => return vec![],
+        };
+
+        self.repeating = Some((key, due.max(now)));
+        let others: Vec<u8> = self.keys.iter().cloned().filter(|held| *held != key).collect();
+        vec![report(self.modifiers, &others), self.report()]
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => self.modifiers ^= MOD_LEFT_SHIFT,
+            Action::ToggleButton(2) => self.toggle_key(KEY_A),
+            Action::ToggleButton(3) => return self.tap_held(KEY_CAPS_LOCK),
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, _) if dx < 0 => return self.tap_held(KEY_LEFT),
+            Action::Move(dx, _) if dx > 0 => return self.tap_held(KEY_RIGHT),
+            Action::Move(_, dy) if dy < 0 => return self.tap_held(KEY_UP),
+            Action::Move(_, dy) if dy > 0 => return self.tap_held(KEY_DOWN),
+            Action::Move(..) => return vec![],
+            Action::Wheel(delta) if delta > 0 => return self.tap_held(KEY_PAGE_UP),
+            Action::Wheel(_) => return self.tap_held(KEY_PAGE_DOWN),
+        }
+
+        vec![self.report()]
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
+            return;
+        }
+
+        if let Some(leds) = leds(data) {
+            self.leds = leds;
+            eprintln!("Keyboard LEDs: NumLock {} CapsLock {} ScrollLock {}",
+                      leds & LED_NUM_LOCK != 0,
+                      leds & LED_CAPS_LOCK != 0,
+                      leds & LED_SCROLL_LOCK != 0);
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        match leds(data) {
+            Some(leds) if rtype == uhid_report_type::UHID_OUTPUT_REPORT as u8 => led_values(leds),
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/*
+ * Large report test device
+ * A vendor defined device with input, output and feature reports of 256 bytes
+ * each, past the 64 bytes of a full speed USB interrupt transfer that most
+ * code around HID silently assumes, to check reports that large make it
+ * through uhid, hidraw and userspace in one piece.
+ *
+ * Report 0x01 (input), 0x02 (output), 0x03 (feature), 257 bytes each:
+ *   byte 1..256   payload
+ *
+ * Input reports count up from a sequence number, so a report cut short or
+ * mixed up shows as a byte out of order. Output reports are checked for the
+ * same pattern and logged. The feature report holds what was last set, and
+ * starts out counting up from 0, so a set followed by a get must round-trip.
+ *
+ * Keys map onto the device as follows:
+ *   d: Send the next input report
+ */
+
+use hid_report::descriptor::{Builder, APPLICATION, VARIABLE};
+use libc;
+use std::io;
+use super::{Action, Device, Identity};
+use uhid::{uhid_report_type, BUS_VIRTUAL};
+
+const REPORT_INPUT: u8 = 0x01;
+const REPORT_OUTPUT: u8 = 0x02;
+const REPORT_FEATURE: u8 = 0x03;
+
+/* Bytes after the report-id of each report */
+pub const PAYLOAD_SIZE: usize = 256;
+
+/* Three main items with their globals, well below this */
+const MAX_DESCRIPTOR_LEN: usize = 64;
+
+pub struct LargeReport {
+    descriptor: Vec<u8>,
+    /* Input reports sent so far */
+    sent: usize,
+    feature: Vec<u8>,
+}
+
+impl LargeReport {
+    pub fn new() -> LargeReport {
+        LargeReport {
+            descriptor: descriptor(),
+            sent: 0,
+            feature: report(REPORT_FEATURE, 0),
+        }
+    }
+}
+
+impl Default for LargeReport {
+    fn default() -> LargeReport {
+        LargeReport::new()
+    }
+}
+
+fn descriptor() -> Vec<u8> {
+    let mut buffer = [0u8; MAX_DESCRIPTOR_LEN];
+    let mut builder = Builder::new(&mut buffer);
+    builder.usage_page(0xff00).usage(0x01).collection(APPLICATION)
+        .logical(0, 0xff).report(8, PAYLOAD_SIZE as u32);
+    for &(id, usage) in &[(REPORT_INPUT, 0x01), (REPORT_OUTPUT, 0x02), (REPORT_FEATURE, 0x03)] {
+        builder.report_id(id).usage(usage);
+        match id {
+            REPORT_INPUT => builder.input(VARIABLE),
+            REPORT_OUTPUT => builder.output(VARIABLE),
+            _ => builder.feature(VARIABLE),
+        };
+    }
+    builder.end_collection();
+    let length = builder.finish().unwrap();
+    buffer[..length].to_vec()
+}
+
+/* A report with the payload counting up from `first` */
+fn report(id: u8, first: u8) -> Vec<u8> {
+    let mut data = vec![id];
+    data.extend((0..PAYLOAD_SIZE).map(|index| first.wrapping_add(index as u8)));
+    data
+}
+
+/* Whether the payload counts up, from whatever it starts with */
+fn is_counting(data: &[u8]) -> bool {
+    data[1..].windows(2).all(|pair| pair[1] == pair[0].wrapping_add(1))
+}
+
+fn unknown_report() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "Unknown report")
+}
+
+impl Device for LargeReport {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_VIRTUAL as u16,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::Move(dx, _) if dx > 0 => {
+                let data = report(REPORT_INPUT, self.sent as u8);
+                self.sent += 1;
+                vec![data]
+            },
+            _ => vec![],
+        }
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 || data.first() != Some(&REPORT_OUTPUT) {
+            return;
+        }
+        if data.len() == PAYLOAD_SIZE + 1 && is_counting(data) {
+            eprintln!("Output report of {} bytes received intact", data.len());
+        } else {
+            eprintln!("Output report of {} bytes received, expected {} counting up", data.len(), PAYLOAD_SIZE + 1);
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 || data.first() != Some(&REPORT_OUTPUT) {
+            return vec![];
+        }
+        vec![("length", data.len() as i64), ("intact", (data.len() == PAYLOAD_SIZE + 1 && is_counting(data)) as i64)]
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 || rnum != REPORT_FEATURE {
+            return Err(unknown_report());
+        }
+        Ok(self.feature.clone())
+    }
+
+    fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 || rnum != REPORT_FEATURE {
+            return Err(unknown_report());
+        }
+        if data.len() != PAYLOAD_SIZE + 1 || data[0] != REPORT_FEATURE {
+            eprintln!("Feature report of {} bytes rejected, expected {}", data.len(), PAYLOAD_SIZE + 1);
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+        self.feature = data.to_vec();
+        eprintln!("Feature report set by the host");
+        Ok(())
+    }
+}
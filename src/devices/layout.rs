@@ -0,0 +1,495 @@
+/*
+ * Keyboard layouts
+ * Tables mapping characters to the key that produces them on a given host
+ * keyboard layout, i.e. a usage of the Keyboard/Keypad page plus the
+ * modifiers to hold. The layout has to match the one configured on the host,
+ * the device itself only ever reports key positions.
+ *
+ * Accented letters without a key of their own are typed through the dead
+ * keys of the layout, the accent and then the letter, like ê as ^ and e on
+ * de; the accent itself, where it has no key either, as the dead key and
+ * space. With substitution, letters the layout cannot type at all are typed
+ * without their accent instead of failing, like ñ as n on de. With Unicode
+ * input, any other character is typed as Ctrl+Shift+U, its code point in hex
+ * and Enter, which IBus and GTK applications take as that character; others
+ * see the keys. Unicode input goes before substitution.
+ *
+ * Other layouts can be loaded from a file, one character per line with the
+ * key producing it, as an XKB key name or a usage, and the modifiers:
+ *   # French AZERTY, from us with the keys that differ
+ *   include us
+ *   a AD01
+ *   A AD01 shift
+ *   € AE02 altgr
+ *   U+0023 0x20 altgr
+ *   dead circumflex AD11
+ * Characters are given as they are or as U+ and their code point, which
+ * spaces and # need. dead lines give the dead keys, for a grave, acute,
+ * circumflex, diaeresis or tilde. include starts from a built-in layout,
+ * lines after it override what it maps.
+ */
+
+use super::keyboard::{KeyStroke, LED_CAPS_LOCK, MOD_LEFT_CTRL, MOD_LEFT_SHIFT, MOD_RIGHT_ALT};
+use std::borrow::Cow;
+use std::char;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/* The modifiers and the key for a character */
+type Lookup = fn(char) -> Option<(u8, u8)>;
+
+#[derive(Clone)]
+enum Keys {
+    Builtin(Lookup),
+    /* Loaded, falling back to a built-in layout if included */
+    Table(HashMap<char, (u8, u8)>, Option<Lookup>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Accent {
+    Grave,
+    Acute,
+    Circumflex,
+    Diaeresis,
+    Tilde,
+}
+
+/* Each accent with its name, the character it stands for by itself and the
+ * letters carrying it, after the letters without */
+const ACCENTS: &[(Accent, &str, char, &str, &str)] = &[
+    (Accent::Grave, "grave", '`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    (Accent::Acute, "acute", '´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    (Accent::Circumflex, "circumflex", '^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    (Accent::Diaeresis, "diaeresis", '¨', "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+    (Accent::Tilde, "tilde", '~', "anoANO", "ãñõÃÑÕ"),
+];
+
+/* The letter without its accent and the accent, of accented letters */
+fn decompose(c: char) -> Option<(char, Accent)> {
+    ACCENTS.iter().find_map(|&(accent, _, _, bases, accented)| {
+        accented.chars().position(|letter| letter == c).and_then(|index| bases.chars().nth(index)).map(|base| (base, accent))
+    })
+}
+
+#[derive(Clone)]
+pub struct Layout {
+    pub name: Cow<'static, str>,
+    keys: Keys,
+    /* Accents with the modifiers and the key of their dead key */
+    dead: Cow<'static, [(Accent, u8, u8)]>,
+    /* Type letters without their accent rather than fail on them */
+    substitute: bool,
+    /* Type other characters by their code point, through the input method */
+    unicode: bool,
+}
+
+pub const LAYOUTS: &[Layout] = &[
+    Layout { name: Cow::Borrowed("us"), keys: Keys::Builtin(us), dead: Cow::Borrowed(&[]), substitute: false, unicode: false },
+    Layout { name: Cow::Borrowed("de"), keys: Keys::Builtin(de), dead: Cow::Borrowed(DE_DEAD), substitute: false, unicode: false },
+    Layout { name: Cow::Borrowed("fr"), keys: Keys::Builtin(fr), dead: Cow::Borrowed(FR_DEAD), substitute: false, unicode: false },
+    Layout { name: Cow::Borrowed("dvorak"), keys: Keys::Builtin(dvorak), dead: Cow::Borrowed(&[]), substitute: false, unicode: false },
+];
+
+pub const DEFAULT_LAYOUT: &str = "us";
+
+pub fn layout(name: &str) -> Option<&'static Layout> {
+    LAYOUTS.iter().find(|layout| layout.name == name)
+}
+
+/* XKB names of the keys of the alphanumeric block, with their usages */
+const KEY_NAMES: &[(&str, u8)] = &[
+    ("TLDE", 0x35), ("AE01", 0x1e), ("AE02", 0x1f), ("AE03", 0x20), ("AE04", 0x21), ("AE05", 0x22), ("AE06", 0x23),
+    ("AE07", 0x24), ("AE08", 0x25), ("AE09", 0x26), ("AE10", 0x27), ("AE11", 0x2d), ("AE12", 0x2e),
+    ("AD01", 0x14), ("AD02", 0x1a), ("AD03", 0x08), ("AD04", 0x15), ("AD05", 0x17), ("AD06", 0x1c),
+    ("AD07", 0x18), ("AD08", 0x0c), ("AD09", 0x12), ("AD10", 0x13), ("AD11", 0x2f), ("AD12", 0x30),
+    ("AC01", 0x04), ("AC02", 0x16), ("AC03", 0x07), ("AC04", 0x09), ("AC05", 0x0a), ("AC06", 0x0b),
+    ("AC07", 0x0d), ("AC08", 0x0e), ("AC09", 0x0f), ("AC10", 0x33), ("AC11", 0x34), ("BKSL", 0x31),
+    ("LSGT", 0x64), ("AB01", 0x1d), ("AB02", 0x1b), ("AB03", 0x06), ("AB04", 0x19), ("AB05", 0x05),
+    ("AB06", 0x11), ("AB07", 0x10), ("AB08", 0x36), ("AB09", 0x37), ("AB10", 0x38),
+    ("SPCE", 0x2c), ("RTRN", 0x28), ("TAB", 0x2b),
+];
+
+fn parse_char(field: &str) -> Option<char> {
+    if let Some(code) = field.strip_prefix("U+") {
+        return u32::from_str_radix(code, 16).ok().and_then(char::from_u32);
+    }
+    let mut chars = field.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+fn parse_key(field: &str) -> Option<u8> {
+    if let Some(&(_, key)) = KEY_NAMES.iter().find(|&&(name, _)| name == field) {
+        return Some(key);
+    }
+    match field.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok(),
+        None => field.parse().ok(),
+    }
+}
+
+const STROKE_EXPECTED: &str = "Expected an XKB key name or a usage, then shift or altgr";
+
+/* A key and its modifiers, as the modifiers and the key */
+fn parse_stroke<'a, I: Iterator<Item = &'a str>>(mut fields: I) -> Option<(u8, u8)> {
+    let key = fields.next().and_then(parse_key)?;
+    let mut modifiers = 0;
+    for modifier in fields {
+        modifiers |= match modifier {
+            "shift" => SHIFT,
+            "altgr" => ALTGR,
+            _ => return None,
+        };
+    }
+    Some((modifiers, key))
+}
+
+impl Layout {
+    /* Reads a layout file, named after the file */
+    pub fn load(path: &Path) -> io::Result<Layout> {
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        Layout::parse(name, &fs::read_to_string(path)?)
+    }
+
+    pub fn parse(name: String, text: &str) -> io::Result<Layout> {
+        let mut table = HashMap::new();
+        let mut include = None;
+        let mut dead = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number + 1, message));
+            let mut fields = line.split_whitespace();
+            let first = match fields.next() {
+                Some(field) if !field.starts_with('#') => field,
+                _ => continue,
+            };
+            if first == "include" {
+                let included = fields.next().and_then(layout).ok_or_else(|| invalid("Unknown layout to include"))?;
+                include = match included.keys {
+                    Keys::Builtin(lookup) => Some(lookup),
+                    Keys::Table(..) => None,
+                };
+                dead = included.dead.to_vec();
+                continue;
+            }
+            if first == "dead" {
+                let name = fields.next();
+                let accent = ACCENTS.iter().find(|accent| Some(accent.1) == name).ok_or_else(|| invalid("Unknown accent"))?.0;
+                let (modifiers, key) = parse_stroke(fields).ok_or_else(|| invalid(STROKE_EXPECTED))?;
+                dead.retain(|&(other, _, _)| other != accent);
+                dead.push((accent, modifiers, key));
+                continue;
+            }
+            let c = parse_char(first).ok_or_else(|| invalid("Expected a character or U+ and its code point"))?;
+            table.insert(c, parse_stroke(fields).ok_or_else(|| invalid(STROKE_EXPECTED))?);
+        }
+        Ok(Layout { name: Cow::Owned(name), keys: Keys::Table(table, include), dead: Cow::Owned(dead), substitute: false, unicode: false })
+    }
+
+    pub fn set_substitute(&mut self, substitute: bool) {
+        self.substitute = substitute;
+    }
+
+    pub fn set_unicode(&mut self, unicode: bool) {
+        self.unicode = unicode;
+    }
+
+    fn lookup(&self, c: char) -> Option<(u8, u8)> {
+        match self.keys {
+            Keys::Builtin(lookup) => lookup(c),
+            Keys::Table(ref table, include) => table.get(&c).cloned().or_else(|| include.and_then(|lookup| lookup(c))),
+        }
+    }
+
+    pub fn stroke(&self, c: char) -> Option<KeyStroke> {
+        self.lookup(c).map(|(modifiers, key)| KeyStroke { modifiers, key })
+    }
+
+    /* Maps a whole text, failing on the first character the layout lacks */
+    pub fn strokes(&self, text: &str) -> io::Result<Vec<KeyStroke>> {
+        self.strokes_with_leds(text, 0)
+    }
+
+    /*
+     * Like strokes(), for a host whose keyboard LEDs are in the given state:
+     * with Caps Lock on, letters are typed with the opposite Shift state.
+     */
+    pub fn strokes_with_leds(&self, text: &str, leds: u8) -> io::Result<Vec<KeyStroke>> {
+        let caps_lock = leds & LED_CAPS_LOCK != 0;
+
+        let mut strokes = Vec::new();
+        for c in text.chars() {
+            let typed = self.sequence(c, caps_lock)
+                .or_else(|| self.code_point(c, caps_lock))
+                .or_else(|| decompose(c).filter(|_| self.substitute).and_then(|(base, _)| self.sequence(base, caps_lock)));
+            match typed {
+                Some(sequence) => strokes.extend(sequence),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                  format!("Cannot type {:?} with the {} layout", c, self.name))),
+            }
+        }
+        Ok(strokes)
+    }
+
+    /* The strokes typing a character, through a dead key if need be */
+    fn sequence(&self, c: char, caps_lock: bool) -> Option<Vec<KeyStroke>> {
+        if let Some(stroke) = self.adjusted(c, caps_lock) {
+            return Some(vec![stroke]);
+        }
+        let (base, accent) = decompose(c)
+            .or_else(|| ACCENTS.iter().find(|accent| accent.2 == c).map(|accent| (' ', accent.0)))?;
+        let dead = self.dead.iter().find(|dead| dead.0 == accent).map(|&(_, modifiers, key)| KeyStroke { modifiers, key })?;
+        Some(vec![dead, self.adjusted(base, caps_lock)?])
+    }
+
+    /* The strokes entering a character by its code point, with Unicode input */
+    fn code_point(&self, c: char, caps_lock: bool) -> Option<Vec<KeyStroke>> {
+        if !self.unicode {
+            return None;
+        }
+        let u = self.stroke('u')?;
+        let mut strokes = vec![KeyStroke { modifiers: MOD_LEFT_CTRL | SHIFT, key: u.key }];
+        for digit in format!("{:x}", c as u32).chars() {
+            strokes.push(self.adjusted(digit, caps_lock)?);
+        }
+        strokes.push(self.stroke('\n')?);
+        Some(strokes)
+    }
+
+    /* The stroke of a character with a key of its own, as Caps Lock has it */
+    fn adjusted(&self, c: char, caps_lock: bool) -> Option<KeyStroke> {
+        match self.stroke(c) {
+            Some(mut stroke) if caps_lock && has_case(c) && stroke.modifiers & ALTGR == 0 => {
+                stroke.modifiers ^= SHIFT;
+                Some(stroke)
+            },
+            stroke => stroke,
+        }
+    }
+}
+
+/* Letters with a single character upper and lower case, which Caps Lock affects */
+fn has_case(c: char) -> bool {
+    let mut upper = c.to_uppercase();
+    let mut lower = c.to_lowercase();
+    upper.len() == 1 && lower.len() == 1 && upper.next() != lower.next()
+}
+
+const SHIFT: u8 = MOD_LEFT_SHIFT;
+const ALTGR: u8 = MOD_RIGHT_ALT;
+
+fn letter(c: char) -> u8 {
+    0x04 + (c.to_ascii_lowercase() as u8 - b'a')
+}
+
+/* Keys shared by most latin layouts */
+fn common(c: char) -> Option<(u8, u8)> {
+    match c {
+        'a'..='z' => Some((0, letter(c))),
+        'A'..='Z' => Some((SHIFT, letter(c))),
+        '1'..='9' => Some((0, 0x1e + (c as u8 - b'1'))),
+        '0' => Some((0, 0x27)),
+        '\n' => Some((0, 0x28)),
+        '\t' => Some((0, 0x2b)),
+        ' ' => Some((0, 0x2c)),
+        _ => None,
+    }
+}
+
+fn us(c: char) -> Option<(u8, u8)> {
+    let key = match c {
+        '!' => (SHIFT, 0x1e),
+        '@' => (SHIFT, 0x1f),
+        '#' => (SHIFT, 0x20),
+        '$' => (SHIFT, 0x21),
+        '%' => (SHIFT, 0x22),
+        '^' => (SHIFT, 0x23),
+        '&' => (SHIFT, 0x24),
+        '*' => (SHIFT, 0x25),
+        '(' => (SHIFT, 0x26),
+        ')' => (SHIFT, 0x27),
+        '-' => (0, 0x2d),
+        '_' => (SHIFT, 0x2d),
+        '=' => (0, 0x2e),
+        '+' => (SHIFT, 0x2e),
+        '[' => (0, 0x2f),
+        '{' => (SHIFT, 0x2f),
+        ']' => (0, 0x30),
+        '}' => (SHIFT, 0x30),
+        '\\' => (0, 0x31),
+        '|' => (SHIFT, 0x31),
+        ';' => (0, 0x33),
+        ':' => (SHIFT, 0x33),
+        '\'' => (0, 0x34),
+        '"' => (SHIFT, 0x34),
+        '`' => (0, 0x35),
+        '~' => (SHIFT, 0x35),
+        ',' => (0, 0x36),
+        '<' => (SHIFT, 0x36),
+        '.' => (0, 0x37),
+        '>' => (SHIFT, 0x37),
+        '/' => (0, 0x38),
+        '?' => (SHIFT, 0x38),
+        _ => return common(c),
+    };
+    Some(key)
+}
+
+/* ^ left of 1, ´ and ` right of ß, ¨ with AltGr on ü */
+const DE_DEAD: &[(Accent, u8, u8)] = &[
+    (Accent::Circumflex, 0, 0x35),
+    (Accent::Acute, 0, 0x2e),
+    (Accent::Grave, SHIFT, 0x2e),
+    (Accent::Diaeresis, ALTGR, 0x2f),
+];
+
+/* German QWERTZ, Y and Z swap places */
+fn de(c: char) -> Option<(u8, u8)> {
+    let key = match c {
+        'y' => (0, 0x1d),
+        'Y' => (SHIFT, 0x1d),
+        'z' => (0, 0x1c),
+        'Z' => (SHIFT, 0x1c),
+        '!' => (SHIFT, 0x1e),
+        '"' => (SHIFT, 0x1f),
+        '§' => (SHIFT, 0x20),
+        '$' => (SHIFT, 0x21),
+        '%' => (SHIFT, 0x22),
+        '&' => (SHIFT, 0x23),
+        '/' => (SHIFT, 0x24),
+        '(' => (SHIFT, 0x25),
+        ')' => (SHIFT, 0x26),
+        '=' => (SHIFT, 0x27),
+        '{' => (ALTGR, 0x24),
+        '[' => (ALTGR, 0x25),
+        ']' => (ALTGR, 0x26),
+        '}' => (ALTGR, 0x27),
+        'ß' => (0, 0x2d),
+        '?' => (SHIFT, 0x2d),
+        '\\' => (ALTGR, 0x2d),
+        'ü' => (0, 0x2f),
+        'Ü' => (SHIFT, 0x2f),
+        '+' => (0, 0x30),
+        '*' => (SHIFT, 0x30),
+        '~' => (ALTGR, 0x30),
+        '#' => (0, 0x32),
+        '\'' => (SHIFT, 0x32),
+        'ö' => (0, 0x33),
+        'Ö' => (SHIFT, 0x33),
+        'ä' => (0, 0x34),
+        'Ä' => (SHIFT, 0x34),
+        ',' => (0, 0x36),
+        ';' => (SHIFT, 0x36),
+        '.' => (0, 0x37),
+        ':' => (SHIFT, 0x37),
+        '-' => (0, 0x38),
+        '_' => (SHIFT, 0x38),
+        '<' => (0, 0x64),
+        '>' => (SHIFT, 0x64),
+        '|' => (ALTGR, 0x64),
+        '@' => (ALTGR, 0x14),
+        '€' => (ALTGR, 0x08),
+        '°' => (SHIFT, 0x35),
+        _ => return common(c),
+    };
+    Some(key)
+}
+
+/* French AZERTY: A and Q, Z and W swap places, M moves next to L and the
+ * digits are shifted */
+fn fr(c: char) -> Option<(u8, u8)> {
+    let key = match c {
+        'a' => (0, 0x14),
+        'A' => (SHIFT, 0x14),
+        'q' => (0, 0x04),
+        'Q' => (SHIFT, 0x04),
+        'z' => (0, 0x1a),
+        'Z' => (SHIFT, 0x1a),
+        'w' => (0, 0x1d),
+        'W' => (SHIFT, 0x1d),
+        'm' => (0, 0x33),
+        'M' => (SHIFT, 0x33),
+        '1'..='9' => (SHIFT, 0x1e + (c as u8 - b'1')),
+        '0' => (SHIFT, 0x27),
+        '²' => (0, 0x35),
+        '&' => (0, 0x1e),
+        'é' => (0, 0x1f),
+        '~' => (ALTGR, 0x1f),
+        '"' => (0, 0x20),
+        '#' => (ALTGR, 0x20),
+        '\'' => (0, 0x21),
+        '{' => (ALTGR, 0x21),
+        '(' => (0, 0x22),
+        '[' => (ALTGR, 0x22),
+        '-' => (0, 0x23),
+        '|' => (ALTGR, 0x23),
+        'è' => (0, 0x24),
+        '`' => (ALTGR, 0x24),
+        '_' => (0, 0x25),
+        '\\' => (ALTGR, 0x25),
+        'ç' => (0, 0x26),
+        '^' => (ALTGR, 0x26),
+        'à' => (0, 0x27),
+        '@' => (ALTGR, 0x27),
+        ')' => (0, 0x2d),
+        '°' => (SHIFT, 0x2d),
+        ']' => (ALTGR, 0x2d),
+        '=' => (0, 0x2e),
+        '+' => (SHIFT, 0x2e),
+        '}' => (ALTGR, 0x2e),
+        '$' => (0, 0x30),
+        '£' => (SHIFT, 0x30),
+        '¤' => (ALTGR, 0x30),
+        'ù' => (0, 0x34),
+        '%' => (SHIFT, 0x34),
+        '*' => (0, 0x32),
+        'µ' => (SHIFT, 0x32),
+        '<' => (0, 0x64),
+        '>' => (SHIFT, 0x64),
+        ',' => (0, 0x10),
+        '?' => (SHIFT, 0x10),
+        ';' => (0, 0x36),
+        '.' => (SHIFT, 0x36),
+        ':' => (0, 0x37),
+        '/' => (SHIFT, 0x37),
+        '!' => (0, 0x38),
+        '§' => (SHIFT, 0x38),
+        '€' => (ALTGR, 0x08),
+        _ => return common(c),
+    };
+    Some(key)
+}
+
+/* ^ and ¨ right of P */
+const FR_DEAD: &[(Accent, u8, u8)] = &[
+    (Accent::Circumflex, 0, 0x2f),
+    (Accent::Diaeresis, SHIFT, 0x2f),
+];
+
+/* US Dvorak, by key position: each key with what it types plain and shifted */
+const DVORAK: &[(char, char, u8)] = &[
+    ('`', '~', 0x35), ('[', '{', 0x2d), (']', '}', 0x2e),
+    ('\'', '"', 0x14), (',', '<', 0x1a), ('.', '>', 0x08), ('p', 'P', 0x15), ('y', 'Y', 0x17), ('f', 'F', 0x1c),
+    ('g', 'G', 0x18), ('c', 'C', 0x0c), ('r', 'R', 0x12), ('l', 'L', 0x13), ('/', '?', 0x2f), ('=', '+', 0x30),
+    ('\\', '|', 0x31),
+    ('a', 'A', 0x04), ('o', 'O', 0x16), ('e', 'E', 0x07), ('u', 'U', 0x09), ('i', 'I', 0x0a), ('d', 'D', 0x0b),
+    ('h', 'H', 0x0d), ('t', 'T', 0x0e), ('n', 'N', 0x0f), ('s', 'S', 0x33), ('-', '_', 0x34),
+    (';', ':', 0x1d), ('q', 'Q', 0x1b), ('j', 'J', 0x06), ('k', 'K', 0x19), ('x', 'X', 0x05), ('b', 'B', 0x11),
+    ('m', 'M', 0x10), ('w', 'W', 0x36), ('v', 'V', 0x37), ('z', 'Z', 0x38),
+];
+
+fn dvorak(c: char) -> Option<(u8, u8)> {
+    let key = DVORAK.iter().find_map(|&(plain, shifted, key)| match c {
+        _ if c == plain => Some((0, key)),
+        _ if c == shifted => Some((SHIFT, key)),
+        _ => None,
+    });
+    match key {
+        Some(key) => Some(key),
+        /* the number row is that of us */
+        None => us(c),
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Programmable macro keyboard
+ * A boot keyboard with twelve additional "G-keys" in a Consumer Programmable
+ * Buttons collection, which recent kernels map to KEY_MACRO1 and up. Each
+ * G-key can be bound to a sequence of keyboard reports that is played back
+ * right after the G-key itself was reported, turning the device into a
+ * virtual macro pad.
+ *
+ * Report 0x02 (3 bytes):
+ *   byte 1, 2     G1..G12 as bits 0..11
+ *
+ * The default bindings are:
+ *   G1: Ctrl+C
+ *   G2: Ctrl+V
+ *   G3: Types "uhid"
+ *
+ * Keys map onto the keyboard as follows:
+ *   1/2/3: Tap G1/G2/G3 and play back their binding
+ */
+
+use super::keyboard;
+use super::{Action, Device, Identity};
+use std::io;
+use uhid::{uhid_report_type, BUS_USB};
+
+const GKEYS_RDESC: [u8; 29] = [
+    0x05, 0x0c,	/* USAGE_PAGE (Consumer Devices) */
+    0x09, 0x03,	/* USAGE (Programmable Buttons) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x05, 0x09,		/* USAGE_PAGE (Button) */
+    0x19, 0x01,		/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x0c,		/* USAGE_MAXIMUM (Button 12) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x95, 0x0c,		/* REPORT_COUNT (12) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x95, 0x04,		/* REPORT_COUNT (4) */
+    0x81, 0x01,		/* INPUT (Cnst,Ary,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+pub const GKEY_COUNT: usize = 12;
+
+pub struct MacroKeyboard {
+    descriptor: Vec<u8>,
+    bindings: Vec<Vec<Vec<u8>>>,
+}
+
+impl Default for MacroKeyboard {
+    fn default() -> MacroKeyboard {
+        let mut bindings = vec![Vec::new(); GKEY_COUNT];
+        bindings[0] = keyboard::tap(keyboard::MOD_LEFT_CTRL, keyboard::KEY_C);
+        bindings[1] = keyboard::tap(keyboard::MOD_LEFT_CTRL, keyboard::KEY_V);
+        bindings[2] = b"uhid".iter()
+            .flat_map(|c| keyboard::tap(0, keyboard::KEY_A + (c - b'a')))
+            .collect();
+        MacroKeyboard {
+            descriptor: [&keyboard::BOOT_KEYBOARD_RDESC[..], &GKEYS_RDESC[..]].concat(),
+            bindings,
+        }
+    }
+}
+
+impl MacroKeyboard {
+    /* Binds G-key `gkey` (1-based) to a sequence of keyboard reports,
+     * failing for a G-key the device does not have */
+    pub fn bind(&mut self, gkey: usize, reports: Vec<Vec<u8>>) -> io::Result<()> {
+        let binding = gkey.checked_sub(1).and_then(|index| self.bindings.get_mut(index)).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("There is no G{}, only G1 to G{}", gkey, GKEY_COUNT))
+        })?;
+        *binding = reports;
+        Ok(())
+    }
+
+    fn gkey_report(pressed: u16) -> Vec<u8> {
+        let mut data = vec![0x02];
+        data.extend_from_slice(&pressed.to_le_bytes());
+        data
+    }
+
+    /* Reports press and release of a G-key followed by its binding; None
+     * for a G-key the device does not have */
+    pub fn tap(&self, gkey: usize) -> Option<Vec<Vec<u8>>> {
+        let binding = self.bindings.get(gkey.checked_sub(1)?)?;
+        let mut reports = vec![
+            MacroKeyboard::gkey_report(1 << (gkey - 1)),
+            MacroKeyboard::gkey_report(0),
+        ];
+        reports.extend(binding.iter().cloned());
+        Some(reports)
+    }
+}
+
+impl Device for MacroKeyboard {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x15d9,
+            product: 0x0a38,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn is_keyboard(&self) -> bool {
+        true
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(n) => self.tap(n as usize).unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
+            return;
+        }
+
+        if let Some(leds) = keyboard::leds(data) {
+            eprintln!("Keyboard LEDs: NumLock {} CapsLock {} ScrollLock {}",
+                      leds & keyboard::LED_NUM_LOCK != 0,
+                      leds & keyboard::LED_CAPS_LOCK != 0,
+                      leds & keyboard::LED_SCROLL_LOCK != 0);
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        match keyboard::leds(data) {
+            Some(leds) if rtype == uhid_report_type::UHID_OUTPUT_REPORT as u8 => keyboard::led_values(leds),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_gkeys_it_does_not_have() {
+        let mut device = MacroKeyboard::default();
+        for &gkey in [0, GKEY_COUNT + 1].iter() {
+            assert_eq!(device.bind(gkey, vec![]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+            assert_eq!(device.tap(gkey), None);
+        }
+        assert!(device.action(Action::ToggleButton(0)).is_empty());
+
+        device.bind(GKEY_COUNT, vec![vec![0x01, 0, 0, 0x04, 0, 0, 0, 0, 0]]).unwrap();
+        let reports = device.tap(GKEY_COUNT).unwrap();
+        assert_eq!(reports[0], vec![0x02, 0x00, 0x08]);
+        assert_eq!(reports.len(), 3);
+    }
+}
@@ -0,0 +1,221 @@
+/*
+ * Apple Magic Trackpad 2
+ * This preset reproduces the USB identity and the multitouch report format of
+ * the Magic Trackpad 2 so that hid-magicmouse and the libinput quirks for
+ * 05ac:0265 can be exercised without the hardware.
+ *
+ * The descriptor is not the one of the real device, only as much of it as
+ * hid-magicmouse relies on: report 0x02 starts like a plain mouse report
+ * (button and relative X/Y) so hid-generic has something to bind to,
+ * followed by the vendor payload that hid-magicmouse parses itself. Like the
+ * real device, reports are as long as the contacts they carry, 12 + 9 bytes
+ * per contact, the driver counting the contacts by the length; the
+ * descriptor declares the longest, with the 15 contacts the driver takes at
+ * most, and hid-core pads shorter ones.
+ *
+ *   byte 0        report-id (0x02)
+ *   byte 1        clicks, bit 0 is the physical click of the clickpad
+ *   bytes 2..11   rest of the 12 byte header, unused here
+ *   9 bytes per contact:
+ *     0, 1[0:4]   X, 13 bit signed
+ *     1[5:7], 2, 3[0:1]
+ *                 Y, 13 bit signed, inverted
+ *     3[6:7]      touch state, 0x80 while the finger is down
+ *     4, 5        touch major / minor
+ *     6           size
+ *     7           pressure, rises when the pad is clicked
+ *     8[0:3]      tracking id
+ *     8[5:7]      orientation + 4
+ *
+ * The kernel switches the device into multitouch mode with feature report
+ * 0x02 {0x02, 0x01}; we acknowledge it but always report in multitouch format.
+ *
+ * Keys map onto the pad as follows:
+ *   1: Toggle the physical click
+ *   2: Place or lift the first finger
+ *   3: Place or lift a second finger beside it
+ *   a/d/w/s: Move all touching fingers
+ *   r/f: Move all touching fingers vertically (two-finger scroll)
+ */
+
+use super::{Action, Device, Identity};
+use std::io;
+use uhid::{uhid_report_type, BUS_USB};
+
+const RDESC: [u8; 74] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x02,	/* USAGE (Mouse) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x09, 0x01,		/* USAGE (Pointer) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x05, 0x09,			/* USAGE_PAGE (Button) */
+    0x19, 0x01,			/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x01,			/* USAGE_MAXIMUM (Button 1) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x75, 0x07,			/* REPORT_SIZE (7) */
+    0x81, 0x01,			/* INPUT (Cnst,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x15, 0x81,			/* LOGICAL_MINIMUM (-127) */
+    0x25, 0x7f,			/* LOGICAL_MAXIMUM (127) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x02,			/* REPORT_COUNT (2) */
+    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
+    0x06, 0x00, 0xff,		/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x01,			/* USAGE (Vendor Usage 1) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x00,		/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x8f,			/* REPORT_COUNT (143) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x09, 0x02,			/* USAGE (Vendor Usage 2) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0xb1, 0x02,			/* FEATURE (Data,Var,Abs) */
+    0xc0,			/* END_COLLECTION */
+    0xc0,		/* END_COLLECTION */
+];
+
+/* 12 byte header and up to 15 contacts, as declared in RDESC */
+const HEADER_SIZE: usize = 12;
+const CONTACT_SIZE: usize = 9;
+const MAX_CONTACTS: usize = 15;
+
+/* Trackpad coordinates range from -3678..3934 (X) and -2478..2587 (Y) */
+const MIN_X: i16 = -3678;
+const MAX_X: i16 = 3934;
+const MIN_Y: i16 = -2478;
+const MAX_Y: i16 = 2587;
+
+/* Keyboard steps are tiny compared to the trackpad resolution */
+const MOVE_SCALE: i16 = 10;
+
+const PRESSURE_TOUCH: u8 = 0x20;
+const PRESSURE_CLICK: u8 = 0x90;
+
+#[derive(Clone, Copy, Default)]
+struct Contact {
+    down: bool,
+    x: i16,
+    y: i16,
+}
+
+impl Contact {
+    fn encode(&self, id: u8, clicked: bool, data: &mut [u8]) {
+        let x = self.x as u16 & 0x1fff;
+        let y = (-self.y) as u16 & 0x1fff;
+
+        data[0] = x as u8;
+        data[1] = (x >> 8) as u8 | ((y & 0x7) << 5) as u8;
+        data[2] = (y >> 3) as u8;
+        data[3] = (y >> 11) as u8 | 0x80;
+        data[4] = 0x30;
+        data[5] = 0x28;
+        data[6] = 0x20;
+        data[7] = if clicked { PRESSURE_CLICK } else { PRESSURE_TOUCH };
+        data[8] = (id & 0xf) | (4 << 5);
+    }
+}
+
+#[derive(Default)]
+pub struct MagicTrackpad2 {
+    clicked: bool,
+    contacts: [Contact; 2],
+}
+
+impl MagicTrackpad2 {
+    fn toggle_contact(&mut self, index: usize) {
+        let contact = &mut self.contacts[index];
+        contact.down = !contact.down;
+        if contact.down {
+            contact.x = index as i16 * 600;
+            contact.y = 0;
+        }
+    }
+
+    fn move_contacts(&mut self, dx: i16, dy: i16) {
+        for contact in self.contacts.iter_mut().filter(|c| c.down) {
+            contact.x = (contact.x + dx * MOVE_SCALE).clamp(MIN_X, MAX_X);
+            contact.y = (contact.y + dy * MOVE_SCALE).clamp(MIN_Y, MAX_Y);
+        }
+    }
+
+    fn report(&self) -> Vec<u8> {
+        let down = self.contacts.iter().filter(|c| c.down).count().min(MAX_CONTACTS);
+        let mut data = vec![0u8; HEADER_SIZE + down * CONTACT_SIZE];
+
+        data[0] = 0x02;
+        data[1] = self.clicked as u8;
+
+        let contacts = self.contacts.iter().enumerate().filter(|&(_, c)| c.down).take(MAX_CONTACTS);
+        for (slot, (id, contact)) in contacts.enumerate() {
+            let offset = HEADER_SIZE + slot * CONTACT_SIZE;
+            contact.encode(id as u8, self.clicked, &mut data[offset..offset + CONTACT_SIZE]);
+        }
+
+        data
+    }
+}
+
+impl Device for MagicTrackpad2 {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x05ac,
+            product: 0x0265,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => self.clicked = !self.clicked,
+            Action::ToggleButton(2) => self.toggle_contact(0),
+            Action::ToggleButton(3) => self.toggle_contact(1),
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, dy) => self.move_contacts(dx as i16, dy as i16),
+            Action::Wheel(delta) => self.move_contacts(0, -(delta as i16) * 20),
+        }
+
+        vec![self.report()]
+    }
+
+    fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+        if rtype == uhid_report_type::UHID_FEATURE_REPORT as u8 && rnum == 0x02 {
+            if data.get(1) == Some(&0x01) {
+                eprintln!("Magic Trackpad 2 switched to multitouch mode");
+            }
+            return Ok(());
+        }
+
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decode::{Kind, Reports};
+
+    #[test]
+    fn sends_reports_as_long_as_their_contacts() {
+        let declared = Reports::parse(&RDESC).report(Kind::Input, 0x02).into_bytes().len();
+        assert_eq!(declared, HEADER_SIZE + MAX_CONTACTS * CONTACT_SIZE);
+
+        let mut device = MagicTrackpad2::default();
+        assert_eq!(device.action(Action::ToggleButton(1))[0].len(), HEADER_SIZE);
+        assert_eq!(device.action(Action::ToggleButton(2))[0].len(), HEADER_SIZE + CONTACT_SIZE);
+        assert_eq!(device.action(Action::ToggleButton(3))[0].len(), HEADER_SIZE + 2 * CONTACT_SIZE);
+    }
+}
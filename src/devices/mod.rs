@@ -0,0 +1,247 @@
+/*
+ * Virtual devices
+ * Every device type provides a report descriptor plus the values used in the
+ * create request, and turns the generic actions bound to the keyboard into
+ * input reports in its own format.
+ */
+
+use libc;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::{Duration, Instant};
+
+pub use uhid::Identity;
+use uhid;
+
+pub mod callbacks;
+pub mod dial;
+pub mod dualshock4;
+pub mod gesture;
+pub mod keyboard;
+pub mod large_report;
+pub mod layout;
+pub mod macro_keyboard;
+pub mod magic_trackpad;
+pub mod mouse;
+pub mod multi_report;
+pub mod path;
+pub mod pattern;
+pub mod pen_tablet;
+pub mod replay;
+pub mod screen;
+pub mod template;
+pub mod touchscreen;
+pub mod xbox360;
+
+/* Generic actions triggered from the interactive keyboard */
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Action {
+    ToggleButton(u8),
+    Move(i8, i8),
+    Wheel(i8),
+}
+
+/* Splits a wheel movement into the deltas of consecutive reports, none over
+ * max counts, as a wheel field of a report only holds so much */
+pub fn wheel_deltas(counts: i32, max: i8) -> Vec<i8> {
+    let max = max.max(1) as i32;
+    let mut deltas = vec![(max * counts.signum()) as i8; (counts.abs() / max) as usize];
+    if counts % max != 0 {
+        deltas.push((counts % max) as i8);
+    }
+    deltas
+}
+
+/* Button number for left, right, middle or a number from 1 */
+pub fn button_from_name(name: &str) -> Option<u8> {
+    match name {
+        "left" => Some(1),
+        "right" => Some(2),
+        "middle" => Some(3),
+        _ => name.parse().ok().filter(|&button| button > 0),
+    }
+}
+
+pub trait Device {
+    fn descriptor(&self) -> &[u8];
+
+    fn identity(&self) -> Identity;
+
+    /* Whether report 0x01 is a boot keyboard report text can be typed with */
+    fn is_keyboard(&self) -> bool {
+        false
+    }
+
+    /* Keyboard LED state last set by the host, for keyboards tracking it */
+    fn leds(&self) -> Option<u8> {
+        None
+    }
+
+    /* Returns the input reports to send in order, each including the report-id
+     * if numbered */
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>>;
+
+    /* Timed reports scrolling smoothly by `pixels`, up if positive, over
+     * `duration`; None for devices without a hi-res wheel */
+    fn scroll(&mut self, _pixels: i32, _duration: Duration) -> Option<Vec<(Duration, Vec<u8>)>> {
+        None
+    }
+
+    /* Next time the device wants tick() to be called, for reports it
+     * generates on its own */
+    fn deadline(&self) -> Option<Instant> {
+        None
+    }
+
+    /* Returns the reports due by `now`, like action() */
+    fn tick(&mut self, _now: Instant) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    /* Raw output reports sent by the kernel; rtype is a uhid_report_type */
+    fn handle_output(&mut self, _rtype: u8, _data: &[u8]) {}
+
+    /* What an output report asks of the device, by name, e.g. the state of
+     * an LED or the speed of a rumble motor; empty if it is not understood */
+    fn decode_output(&self, _rtype: u8, _data: &[u8]) -> Vec<(&'static str, i64)> {
+        vec![]
+    }
+
+    fn get_report(&mut self, _rnum: u8, _rtype: u8) -> io::Result<Vec<u8>> {
+        Err(io::Error::from_raw_os_error(libc::EIO))
+    }
+
+    fn set_report(&mut self, _rnum: u8, _rtype: u8, _data: &[u8]) -> io::Result<()> {
+        Err(io::Error::from_raw_os_error(libc::EIO))
+    }
+}
+
+pub const PRESETS: &[&str] = &["mouse", "magic-trackpad2", "pen-tablet", "xbox360", "dualshock4", "dial", "keyboard", "macro-keyboard", "touchscreen", "multi-report", "large-report"];
+
+/* Settings given on the command line, applied to presets supporting them */
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Options {
+    pub repeat: Option<keyboard::Repeat>,
+    pub sync_leds: bool,
+    pub dpi: Option<u32>,
+    pub dpi_report: bool,
+    pub humanize: bool,
+    pub absolute: bool,
+    pub hi_res_wheel: bool,
+    /* Replace the values the preset identifies itself with */
+    pub name: Option<String>,
+    pub bus: Option<u16>,
+    pub vendor: Option<u32>,
+    pub product: Option<u32>,
+    pub version: Option<u32>,
+    pub country: Option<u32>,
+    /* Order of the reports of multi-report */
+    pub interleave: Option<multi_report::Interleave>,
+    /* Rendered instead of the preset, see template.rs */
+    pub template: Option<template::Template>,
+}
+
+impl Options {
+    pub fn name(&self) -> &str {
+        self.name.as_ref().map_or(uhid::DEFAULT_NAME, |name| name.as_str())
+    }
+
+    /* The identity to create a device with, after applying the overrides */
+    pub fn identity(&self, identity: Identity) -> Identity {
+        Identity {
+            bus: self.bus.unwrap_or(identity.bus),
+            vendor: self.vendor.unwrap_or(identity.vendor),
+            product: self.product.unwrap_or(identity.product),
+            version: self.version.unwrap_or(identity.version),
+            country: self.country.unwrap_or(identity.country),
+        }
+    }
+}
+
+/* Creates a device of a type registered by name, applying the settings it supports */
+pub type Constructor = fn(&Options) -> Box<dyn Device>;
+
+/*
+ * Device types selectable by name: the presets plus the ones registered by
+ * other crates, e.g. plugins, so they can ship devices without changing this
+ * crate. Registered types take precedence over presets of the same name.
+ */
+#[derive(Default)]
+pub struct Registry {
+    devices: Vec<(String, Constructor)>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    pub fn register(&mut self, name: &str, constructor: Constructor) {
+        self.devices.retain(|(registered, _)| registered != name);
+        self.devices.push((name.to_string(), constructor));
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        let registered = self.devices.iter().map(|(name, _)| name.as_str());
+        PRESETS.iter().cloned().filter(|preset| !self.devices.iter().any(|(name, _)| name == preset))
+            .chain(registered)
+            .collect()
+    }
+
+    pub fn create(&self, name: &str, options: &Options) -> Option<Box<dyn Device>> {
+        match self.devices.iter().find(|&(registered, _)| registered == name) {
+            Some(&(_, constructor)) => Some(constructor(options)),
+            None => from_preset(name, options),
+        }
+    }
+}
+
+pub fn from_preset(name: &str, options: &Options) -> Option<Box<dyn Device>> {
+    match name {
+        "mouse" => {
+            let mut mouse = mouse::Mouse::default();
+            if let Some(dpi) = options.dpi {
+                mouse.set_dpi(dpi);
+            }
+            if options.dpi_report {
+                mouse.expose_dpi();
+            }
+            if options.absolute {
+                mouse.add_absolute();
+            }
+            if options.hi_res_wheel {
+                mouse.add_hi_res_wheel();
+            }
+            if options.humanize {
+                mouse.set_humanize(Some(path::Humanize::default()));
+            }
+            Some(Box::new(mouse))
+        },
+        "magic-trackpad2" => Some(Box::new(magic_trackpad::MagicTrackpad2::default())),
+        "pen-tablet" => Some(Box::new(pen_tablet::PenTablet::default())),
+        "xbox360" => Some(Box::new(xbox360::Xbox360Pad::default())),
+        "dualshock4" => Some(Box::new(dualshock4::DualShock4::default())),
+        "dial" => Some(Box::new(dial::Dial::default())),
+        "keyboard" => {
+            let mut keyboard = keyboard::Keyboard::default();
+            keyboard.set_repeat(options.repeat);
+            if options.sync_leds {
+                if let Err(err) = keyboard.sync_host_leds() {
+                    eprintln!("Cannot read the host keyboard LEDs: {}", err);
+                }
+            }
+            Some(Box::new(keyboard))
+        },
+        "macro-keyboard" => Some(Box::new(macro_keyboard::MacroKeyboard::default())),
+        "touchscreen" => Some(Box::new(touchscreen::Touchscreen::default())),
+        "multi-report" => Some(Box::new(match options.interleave {
+            Some(ref interleave) => multi_report::MultiReport::new(interleave.clone()),
+            None => multi_report::MultiReport::default(),
+        })),
+        "large-report" => Some(Box::new(large_report::LargeReport::default())),
+        _ => None,
+    }
+}
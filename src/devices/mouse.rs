@@ -0,0 +1,602 @@
+use super::path::{self, Easing, Humanize, Point, Rng};
+use super::{wheel_deltas, Action, Device, Identity};
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+use uhid::{uhid_report_type, BUS_USB};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/*
+ * HID Report Desciptor
+ * We emulate a basic 3 button mouse with wheel and 3 keyboard LEDs. This is
+ * the report-descriptor as the kernel will parse it:
+ *
+ * INPUT(1)[INPUT]
+ *   Field(0)
+ *     Physical(GenericDesktop.Pointer)
+ *     Application(GenericDesktop.Mouse)
+ *     Usage(3)
+ *       Button.0001
+ *       Button.0002
+ *       Button.0003
+ *     Logical Minimum(0)
+ *     Logical Maximum(1)
+ *     Report Size(1)
+ *     Report Count(3)
+ *     Report Offset(0)
+ *     Flags( Variable Absolute )
+ *   Field(1)
+ *     Physical(GenericDesktop.Pointer)
+ *     Application(GenericDesktop.Mouse)
+ *     Usage(3)
+ *       GenericDesktop.X
+ *       GenericDesktop.Y
+ *       GenericDesktop.Wheel
+ *     Logical Minimum(-128)
+ *     Logical Maximum(127)
+ *     Report Size(8)
+ *     Report Count(3)
+ *     Report Offset(8)
+ *     Flags( Variable Relative )
+ * OUTPUT(2)[OUTPUT]
+ *   Field(0)
+ *     Application(GenericDesktop.Keyboard)
+ *     Usage(3)
+ *       LED.NumLock
+ *       LED.CapsLock
+ *       LED.ScrollLock
+ *     Logical Minimum(0)
+ *     Logical Maximum(1)
+ *     Report Size(1)
+ *     Report Count(3)
+ *     Report Offset(0)
+ *     Flags( Variable Absolute )
+ *
+ * This is the mapping that we expect:
+ *   Button.0001 ---> Key.LeftBtn
+ *   Button.0002 ---> Key.RightBtn
+ *   Button.0003 ---> Key.MiddleBtn
+ *   GenericDesktop.X ---> Relative.X
+ *   GenericDesktop.Y ---> Relative.Y
+ *   GenericDesktop.Wheel ---> Relative.Wheel
+ *   LED.NumLock ---> LED.NumLock
+ *   LED.CapsLock ---> LED.CapsLock
+ *   LED.ScrollLock ---> LED.ScrollLock
+ *
+ * This information can be verified by reading /sys/kernel/debug/hid/<dev>/rdesc
+ * This file should print the same information as showed above.
+ */
+
+/*
+ * Resolution
+ * The mouse simulates a sensor resolution: the keyboard moves are taken as
+ * distances measured at BASE_DPI and scaled to the current resolution, so
+ * pointer acceleration and speed settings can be tested against different
+ * DPI. Key 4 acts as the DPI button found on gaming mice and cycles through
+ * DPI_LEVELS.
+ *
+ * With humanized movement the keyboard moves are not reported as a single
+ * jump but spread over a short humanized path, see path.rs.
+ *
+ * Optionally the resolution is exposed in a vendor defined collection as
+ * feature report 3, a 16 bit DPI value, which can be read and written the way
+ * ratbag-style configuration tools talk to real mice.
+ */
+
+/*
+ * Absolute pointer
+ * Optionally a second pointer collection reports absolute positions, like
+ * the tablet mode of virtual machine mice, as report 4:
+ *   byte 1        buttons 1..3
+ *   byte 2..5     X and Y in 0..32767
+ * Key 5 switches between reporting movement and buttons through the relative
+ * and the absolute pointer, so both paths of the compositor can be tested in
+ * one session. The wheel is always reported relative.
+ */
+
+/*
+ * Hi-res wheel
+ * Optionally the pointer collection gets a Resolution Multiplier, feature
+ * report 5, which the kernel sets when binding the device. From then on the
+ * wheel reports eighths of a detent, which hid-input passes on as
+ * REL_WHEEL_HI_RES, 15 of its 120 units a count, and REL_WHEEL once a whole
+ * detent has been scrolled. A detent of the keyboard or the wheel command
+ * still scrolls a whole detent.
+ *
+ * A smooth scroll, the scroll command, spreads a distance over a duration as
+ * fractional-detent reports at the polling rate, fast at first and slowing
+ * down like a fling, so kinetic scrolling sees the same input every run. The
+ * distance is in pixels taken as REL_WHEEL_HI_RES units, 120 to a detent.
+ * Until the multiplier is set, e.g. with --dry-run, it scrolls in detents.
+ */
+
+const RDESC: [u8; 85] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x02,	/* USAGE (Mouse) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x09, 0x01,		/* USAGE (Pointer) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x85, 0x01,			/* REPORT_ID (1) */
+    0x05, 0x09,			/* USAGE_PAGE (Button) */
+    0x19, 0x01,			/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x03,			/* USAGE_MAXIMUM (Button 3) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x95, 0x03,			/* REPORT_COUNT (3) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x75, 0x05,			/* REPORT_SIZE (5) */
+    0x81, 0x01,			/* INPUT (Cnst,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x09, 0x38,			/* USAGE (WHEEL) */
+    0x15, 0x81,			/* LOGICAL_MINIMUM (-127) */
+    0x25, 0x7f,			/* LOGICAL_MAXIMUM (127) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x03,			/* REPORT_COUNT (3) */
+    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
+    0xc0,			/* END_COLLECTION */
+    0xc0,		/* END_COLLECTION */
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x06,	/* USAGE (Keyboard) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x05, 0x08,		/* USAGE_PAGE (Led) */
+    0x19, 0x01,		/* USAGE_MINIMUM (1) */
+    0x29, 0x03,		/* USAGE_MAXIMUM (3) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
+    0x95, 0x03,		/* REPORT_COUNT (3) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x91, 0x02,		/* Output (Data,Var,Abs) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x75, 0x05,		/* REPORT_SIZE (5) */
+    0x91, 0x01,		/* Output (Cnst,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+const DPI_RDESC: [u8; 25] = [
+    0x06, 0x00, 0xff,	/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x01,	/* USAGE (Vendor Usage 1) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x03,		/* REPORT_ID (3) */
+    0x09, 0x02,		/* USAGE (Vendor Usage 2) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x27, 0xff, 0xff, 0x00, 0x00,	/* LOGICAL_MAXIMUM (65535) */
+    0x75, 0x10,		/* REPORT_SIZE (16) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+pub const BASE_DPI: u32 = 800;
+
+/* Duration of a humanized keyboard move, before speed variation */
+const HUMANIZED_MOVE_MS: u64 = 120;
+pub const DPI_LEVELS: [u32; 4] = [400, 800, 1600, 3200];
+
+const ABSOLUTE_RDESC: [u8; 53] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x02,	/* USAGE (Mouse) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x04,		/* REPORT_ID (4) */
+    0x09, 0x01,		/* USAGE (Pointer) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x05, 0x09,			/* USAGE_PAGE (Button) */
+    0x19, 0x01,			/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x03,			/* USAGE_MAXIMUM (Button 3) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x95, 0x03,			/* REPORT_COUNT (3) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x75, 0x05,			/* REPORT_SIZE (5) */
+    0x81, 0x01,			/* INPUT (Cnst,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x7f,		/* LOGICAL_MAXIMUM (32767) */
+    0x75, 0x10,			/* REPORT_SIZE (16) */
+    0x95, 0x02,			/* REPORT_COUNT (2) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0xc0,			/* END_COLLECTION */
+    0xc0,		/* END_COLLECTION */
+];
+
+/* Inserted at the end of the pointer collection, so it applies to the wheel */
+const HI_RES_RDESC: [u8; 26] = [
+    0x85, 0x05,			/* REPORT_ID (5) */
+    0x09, 0x48,			/* USAGE (Resolution Multiplier) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x35, 0x01,			/* PHYSICAL_MINIMUM (1) */
+    0x45, 0x08,			/* PHYSICAL_MAXIMUM (8) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0xb1, 0x02,			/* FEATURE (Data,Var,Abs) */
+    0x75, 0x07,			/* REPORT_SIZE (7) */
+    0xb1, 0x01,			/* FEATURE (Cnst,Var,Abs) */
+    0x35, 0x00,			/* PHYSICAL_MINIMUM (0) */
+    0x45, 0x00,			/* PHYSICAL_MAXIMUM (0) */
+];
+
+/* Where the pointer collection of RDESC ends */
+const POINTER_END: usize = 52;
+
+/* Wheel counts per detent with the multiplier set, its physical maximum */
+pub const RESOLUTION_MULTIPLIER: i32 = 8;
+
+/* Pixels of a smooth scroll per detent, as REL_WHEEL_HI_RES counts them */
+pub const PIXELS_PER_DETENT: i32 = 120;
+
+pub const ABSOLUTE_MAX: i32 = 32767;
+
+/* Absolute units per relative count */
+const ABSOLUTE_SCALE: i32 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PointerMode {
+    Relative,
+    Absolute,
+}
+
+#[derive(Clone, Copy, Default)]
+struct DeviceState {
+    btn1_down: bool,
+    btn2_down: bool,
+    btn3_down: bool,
+}
+
+impl DeviceState {
+    fn toggle_btn1(&mut self) {
+        self.btn1_down = !self.btn1_down;
+    }
+    fn toggle_btn2(&mut self) {
+        self.btn2_down = !self.btn2_down;
+    }
+    fn toggle_btn3(&mut self) {
+        self.btn3_down = !self.btn3_down;
+    }
+}
+
+
+#[derive(Clone, Copy)]
+struct InputEvent {
+    btn1_down: bool,
+    btn2_down: bool,
+    btn3_down: bool,
+    abs_hor: i8,
+    abs_ver: i8,
+    wheel: i8,
+}
+
+impl InputEvent {
+    fn from_state(state: &DeviceState) -> InputEvent {
+        InputEvent {
+            btn1_down: state.btn1_down,
+            btn2_down: state.btn2_down,
+            btn3_down: state.btn3_down,
+            abs_hor: 0,
+            abs_ver: 0,
+            wheel: 0,
+        }
+    }
+
+    fn report(&self) -> Vec<u8> {
+        let mut data = vec![0x1, 0, 0, 0, 0];
+        if self.btn1_down {
+            data[1] |= 0x1;
+        }
+        if self.btn2_down {
+            data[1] |= 0x2;
+        }
+        if self.btn3_down {
+            data[1] |= 0x4;
+        }
+        data[2] = self.abs_hor as u8;
+        data[3] = self.abs_ver as u8;
+        data[4] = self.wheel as u8;
+        data
+    }
+}
+
+pub struct Mouse {
+    descriptor: Vec<u8>,
+    state: DeviceState,
+    dpi_report: bool,
+    absolute: bool,
+    hi_res: bool,
+    /* Wheel counts per detent, RESOLUTION_MULTIPLIER once the host set it */
+    multiplier: i32,
+    mode: PointerMode,
+    position: (i32, i32),
+    dpi: u32,
+    /* Motion below one count at the current resolution, in 1/BASE_DPI counts */
+    remainder: (i32, i32),
+    humanize: Option<(Humanize, Rng)>,
+    /* Reports of humanized moves still to be sent */
+    pending: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl Default for Mouse {
+    fn default() -> Mouse {
+        Mouse {
+            descriptor: RDESC.to_vec(),
+            state: DeviceState::default(),
+            dpi_report: false,
+            absolute: false,
+            hi_res: false,
+            multiplier: 1,
+            mode: PointerMode::Relative,
+            position: (ABSOLUTE_MAX / 2, ABSOLUTE_MAX / 2),
+            dpi: BASE_DPI,
+            remainder: (0, 0),
+            humanize: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl Mouse {
+    pub fn set_dpi(&mut self, dpi: u32) {
+        self.dpi = dpi.clamp(1, u16::MAX as u32);
+        self.remainder = (0, 0);
+    }
+
+    pub fn set_humanize(&mut self, humanize: Option<Humanize>) {
+        self.humanize = humanize.map(|humanize| (humanize, Rng::from_time()));
+    }
+
+    /* Adds the DPI feature report, must be called before the device is created */
+    pub fn expose_dpi(&mut self) {
+        if !self.dpi_report {
+            self.dpi_report = true;
+            self.descriptor.extend_from_slice(&DPI_RDESC);
+        }
+    }
+
+    /* Adds the absolute pointer, must be called before the device is created */
+    pub fn add_absolute(&mut self) {
+        if !self.absolute {
+            self.absolute = true;
+            self.descriptor.extend_from_slice(&ABSOLUTE_RDESC);
+        }
+    }
+
+    /* Adds the resolution multiplier, must be called before the device is created */
+    pub fn add_hi_res_wheel(&mut self) {
+        if !self.hi_res {
+            self.hi_res = true;
+            self.descriptor.splice(POINTER_END..POINTER_END, HI_RES_RDESC.iter().cloned());
+        }
+    }
+
+    /* Selects the pointer reporting movement; absolute needs add_absolute() */
+    pub fn set_mode(&mut self, mode: PointerMode) {
+        if mode == PointerMode::Absolute && !self.absolute {
+            eprintln!("No absolute pointer, staying in relative mode");
+            return;
+        }
+        self.mode = mode;
+        eprintln!("Pointer mode: {:?}", mode);
+    }
+
+    fn absolute_report(&self) -> Vec<u8> {
+        let mut data = InputEvent::from_state(&self.state).report();
+        data[0] = 0x04;
+        data.truncate(2);
+        data.extend_from_slice(&(self.position.0 as u16).to_le_bytes());
+        data.extend_from_slice(&(self.position.1 as u16).to_le_bytes());
+        data
+    }
+
+    /* Button state through the pointer of the current mode */
+    fn button_report(&self) -> Vec<u8> {
+        match self.mode {
+            PointerMode::Relative => InputEvent::from_state(&self.state).report(),
+            PointerMode::Absolute => self.absolute_report(),
+        }
+    }
+
+    fn cycle_dpi(&mut self) {
+        let next = DPI_LEVELS.iter().cloned().find(|level| *level > self.dpi).unwrap_or(DPI_LEVELS[0]);
+        self.set_dpi(next);
+        eprintln!("DPI set to {}", self.dpi);
+    }
+
+    fn scale(&mut self, dx: i8, dy: i8) -> (i32, i32) {
+        let x = dx as i32 * self.dpi as i32 + self.remainder.0;
+        let y = dy as i32 * self.dpi as i32 + self.remainder.1;
+        let base = BASE_DPI as i32;
+        self.remainder = (x % base, y % base);
+        (x / base, y / base)
+    }
+
+    fn move_reports(&mut self, dx: i8, dy: i8) -> Vec<Vec<u8>> {
+        let (x, y) = self.scale(dx, dy);
+        let steps = match self.humanize {
+            Some((ref humanize, ref mut rng)) => {
+                let duration = Duration::from_millis(HUMANIZED_MOVE_MS);
+                path::humanized_path((0.0, 0.0), (x as f32, y as f32), duration, humanize, rng)
+            },
+            None => return self.split_move(x, y),
+        };
+
+        /* queue behind moves still in progress */
+        let mut due = self.pending.back().map_or_else(Instant::now, |pending| pending.0);
+        for (delay, report) in self.follow(&steps) {
+            due += delay;
+            self.pending.push_back((due, report));
+        }
+        vec![]
+    }
+
+    /* Splits a move into as many reports as the 8 bit axes need */
+    fn split_move(&mut self, mut x: i32, mut y: i32) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+
+        if self.mode == PointerMode::Absolute {
+            self.position.0 = (self.position.0 + x * ABSOLUTE_SCALE).clamp(0, ABSOLUTE_MAX);
+            self.position.1 = (self.position.1 + y * ABSOLUTE_SCALE).clamp(0, ABSOLUTE_MAX);
+            if x != 0 || y != 0 {
+                reports.push(self.absolute_report());
+            }
+            return reports;
+        }
+
+        while x != 0 || y != 0 {
+            let mut input = InputEvent::from_state(&self.state);
+            input.abs_hor = x.clamp(-127, 127) as i8;
+            input.abs_ver = y.clamp(-127, 127) as i8;
+            x -= input.abs_hor as i32;
+            y -= input.abs_ver as i32;
+            reports.push(input.report());
+        }
+        reports
+    }
+
+    /* Wheel counts in as many reports as the 8 bit wheel needs */
+    fn wheel_reports(&self, counts: i32) -> Vec<Vec<u8>> {
+        wheel_deltas(counts, i8::MAX).into_iter().map(|delta| {
+            let mut input = InputEvent::from_state(&self.state);
+            input.wheel = delta;
+            input.report()
+        }).collect()
+    }
+
+    /*
+     * Timed reports following relative steps in pointer counts, see path.rs;
+     * held buttons stay pressed, so this also drags.
+     */
+    pub fn follow(&mut self, steps: &[(Duration, (i32, i32))]) -> Vec<(Duration, Vec<u8>)> {
+        let mut reports = Vec::new();
+        for &(delay, (x, y)) in steps.iter() {
+            for (i, report) in self.split_move(x, y).into_iter().enumerate() {
+                reports.push((if i == 0 { delay } else { Duration::from_secs(0) }, report));
+            }
+        }
+        reports
+    }
+
+    pub fn move_path(&mut self, from: Point, to: Point, duration: Duration, easing: Easing) -> Vec<(Duration, Vec<u8>)> {
+        self.follow(&path::move_path(from, to, duration, easing))
+    }
+}
+
+impl Device for Mouse {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x15d9,
+            product: 0x0a37,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => {
+                self.state.toggle_btn1();
+                vec![self.button_report()]
+            },
+            Action::ToggleButton(2) => {
+                self.state.toggle_btn2();
+                vec![self.button_report()]
+            },
+            Action::ToggleButton(3) => {
+                self.state.toggle_btn3();
+                vec![self.button_report()]
+            },
+            Action::ToggleButton(4) => {
+                self.cycle_dpi();
+                vec![]
+            },
+            Action::ToggleButton(5) => {
+                let mode = match self.mode {
+                    PointerMode::Relative => PointerMode::Absolute,
+                    PointerMode::Absolute => PointerMode::Relative,
+                };
+                self.set_mode(mode);
+                vec![]
+            },
+            Action::ToggleButton(_) => vec![],
+            Action::Move(dx, dy) => self.move_reports(dx, dy),
+            Action::Wheel(delta) => self.wheel_reports(delta as i32 * self.multiplier),
+        }
+    }
+
+    fn scroll(&mut self, pixels: i32, duration: Duration) -> Option<Vec<(Duration, Vec<u8>)>> {
+        if !self.hi_res {
+            return None;
+        }
+        let counts = pixels as f32 * self.multiplier as f32 / PIXELS_PER_DETENT as f32;
+        let mut reports = Vec::new();
+        for (delay, (counts, _)) in path::move_path((0.0, 0.0), (counts, 0.0), duration, Easing::EaseOut) {
+            for (i, report) in self.wheel_reports(counts).into_iter().enumerate() {
+                reports.push((if i == 0 { delay } else { Duration::from_secs(0) }, report));
+            }
+        }
+        Some(reports)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.pending.front().map(|pending| pending.0)
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        while self.pending.front().is_some_and(|pending| pending.0 <= now) {
+            reports.push(self.pending.pop_front().unwrap().1);
+        }
+        reports
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"));
+        }
+
+        match rnum {
+            0x03 if self.dpi_report => {
+                let mut data = vec![0x03];
+                data.extend_from_slice(&(self.dpi as u16).to_le_bytes());
+                Ok(data)
+            },
+            0x05 if self.hi_res => Ok(vec![0x05, (self.multiplier > 1) as u8]),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report")),
+        }
+    }
+
+    fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"));
+        }
+
+        match (rnum, data) {
+            (0x03, [0x03, low, high]) if self.dpi_report && (*low, *high) != (0, 0) => {
+                self.set_dpi(u16::from_le_bytes([*low, *high]) as u32);
+                eprintln!("DPI set to {} by the host", self.dpi);
+                Ok(())
+            },
+            (0x03, _) if self.dpi_report => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid DPI report")),
+            (0x05, [0x05, multiplier]) if self.hi_res => {
+                self.multiplier = if multiplier & 0x01 != 0 { RESOLUTION_MULTIPLIER } else { 1 };
+                eprintln!("Wheel resolution set to {} counts per detent by the host", self.multiplier);
+                Ok(())
+            },
+            (0x05, _) if self.hi_res => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid resolution multiplier report")),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report")),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+/*
+ * Multi-report test device
+ * A vendor defined device with several input reports of different sizes,
+ * sent interleaved, to exercise the report-ID demuxing of hid-core, hidraw
+ * readers and userspace descriptor parsers. It creates no input device,
+ * only a hidraw node.
+ *
+ * Report 0x01 (2 bytes), 0x02 (4 bytes), 0x03 (9 bytes), 0x04 (33 bytes):
+ *   byte 1        sequence number, counting all reports sent
+ *   byte 2..      counting up from the sequence number
+ *
+ * so a parser taking a report for another one or cutting it short shows up
+ * as a gap in the sequence or a byte out of order.
+ *
+ * The order of the reports is set with --interleave: round-robin cycles
+ * through the ids, burst sends each id four times in a row, random picks
+ * one at random each time, and a list like 1,1,4,2 is repeated as given.
+ *
+ * Keys map onto the device as follows:
+ *   1: Start/stop sending a report every interval, 8ms to begin with
+ *   d: Send the next report
+ *   r/f: Halve/double the interval
+ */
+
+use super::path::Rng;
+use super::{Action, Device, Identity};
+use std::io;
+use std::time::{Duration, Instant};
+use uhid::BUS_VIRTUAL;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const RDESC: [u8; 47] = [
+    0x06, 0x00, 0xff,	/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x01,	/* USAGE (Vendor Usage 1) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x85, 0x01,		/* REPORT_ID (1) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x09, 0x01,		/* USAGE (Vendor Usage 1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x95, 0x03,		/* REPORT_COUNT (3) */
+    0x09, 0x01,		/* USAGE (Vendor Usage 1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x85, 0x03,		/* REPORT_ID (3) */
+    0x95, 0x08,		/* REPORT_COUNT (8) */
+    0x09, 0x01,		/* USAGE (Vendor Usage 1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x85, 0x04,		/* REPORT_ID (4) */
+    0x95, 0x20,		/* REPORT_COUNT (32) */
+    0x09, 0x01,		/* USAGE (Vendor Usage 1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+/* Bytes after the report-id of reports 1..4 */
+const PAYLOAD_SIZES: [usize; 4] = [1, 3, 8, 32];
+
+const BURST: usize = 4;
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(8);
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Interleave {
+    RoundRobin,
+    Burst,
+    Random,
+    /* Report ids repeated in this order */
+    Sequence(Vec<u8>),
+}
+
+impl Interleave {
+    pub fn from_name(name: &str) -> io::Result<Interleave> {
+        match name {
+            "round-robin" => return Ok(Interleave::RoundRobin),
+            "burst" => return Ok(Interleave::Burst),
+            "random" => return Ok(Interleave::Random),
+            _ => (),
+        }
+        let ids: Option<Vec<u8>> = name.split(',')
+            .map(|id| id.trim().parse().ok().filter(|id| (1..=PAYLOAD_SIZES.len() as u8).contains(id)))
+            .collect();
+        ids.map(Interleave::Sequence).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           format!("Unknown interleaving {}, expected round-robin, burst, random or report ids 1..{} like 1,1,4,2", name, PAYLOAD_SIZES.len()))
+        })
+    }
+}
+
+pub struct MultiReport {
+    interleave: Interleave,
+    rng: Rng,
+    /* Reports sent so far */
+    sent: usize,
+    interval: Duration,
+    next: Option<Instant>,
+}
+
+impl MultiReport {
+    pub fn new(interleave: Interleave) -> MultiReport {
+        MultiReport {
+            interleave,
+            rng: Rng::from_time(),
+            sent: 0,
+            interval: DEFAULT_INTERVAL,
+            next: None,
+        }
+    }
+
+    fn next_id(&mut self) -> u8 {
+        let count = PAYLOAD_SIZES.len();
+        match self.interleave {
+            Interleave::RoundRobin => (self.sent % count) as u8 + 1,
+            Interleave::Burst => (self.sent / BURST % count) as u8 + 1,
+            Interleave::Random => (self.rng.range(0.0, count as f32) as usize).min(count - 1) as u8 + 1,
+            Interleave::Sequence(ref ids) => ids[self.sent % ids.len()],
+        }
+    }
+
+    /* The next report of the pattern */
+    pub fn next_report(&mut self) -> Vec<u8> {
+        let id = self.next_id();
+        let sequence = self.sent as u8;
+        self.sent += 1;
+
+        let mut data = vec![id];
+        data.extend((0..PAYLOAD_SIZES[id as usize - 1]).map(|index| sequence.wrapping_add(index as u8)));
+        data
+    }
+}
+
+impl Default for MultiReport {
+    fn default() -> MultiReport {
+        MultiReport::new(Interleave::RoundRobin)
+    }
+}
+
+impl Device for MultiReport {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_VIRTUAL as u16,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => {
+                self.next = match self.next {
+                    Some(_) => None,
+                    None => Some(Instant::now()),
+                };
+                vec![]
+            },
+            Action::Move(dx, _) if dx > 0 => vec![self.next_report()],
+            Action::Wheel(delta) if delta > 0 => {
+                self.interval = (self.interval / 2).max(MIN_INTERVAL);
+                vec![]
+            },
+            Action::Wheel(delta) if delta < 0 => {
+                self.interval = (self.interval * 2).min(MAX_INTERVAL);
+                vec![]
+            },
+            _ => vec![],
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.next
+    }
+
+    /* One report per interval; intervals missed while stalled are skipped */
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        match self.next {
+            Some(next) if next <= now => {
+                self.next = Some((next + self.interval).max(now));
+                vec![self.next_report()]
+            },
+            _ => vec![],
+        }
+    }
+}
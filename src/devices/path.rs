@@ -0,0 +1,212 @@
+/*
+ * Pointer paths
+ * Splits a pointer movement into the small relative steps a real mouse
+ * reports while being moved, instead of a single jump. The path is a line or
+ * a cubic Bezier curve in pointer counts, traversed over the given duration
+ * with an easing function shaping the speed. Steps are spaced at the 125Hz
+ * polling rate of common USB mice, each with the delay to wait after the
+ * previous step.
+ *
+ * Humanized paths add what distinguishes a hand from a script: the path bows
+ * slightly instead of being straight, the speed varies from move to move,
+ * the position wobbles by a few counts and long moves overshoot the target
+ * and correct back. All of it is bounded and the path always ends exactly on
+ * the target.
+ */
+
+use std::io;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub type Point = (f32, f32);
+
+pub const STEP_INTERVAL_MS: u64 = 8;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    /* Maps the elapsed fraction of the duration onto the travelled fraction */
+    pub fn apply(&self, t: f32) -> f32 {
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => -1.0 + (4.0 - 2.0 * t) * t,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Curve {
+    Line(Point, Point),
+    /* Start, two control points and end */
+    Bezier(Point, Point, Point, Point),
+}
+
+impl Curve {
+    pub fn at(&self, t: f32) -> Point {
+        match *self {
+            Curve::Line(from, to) => (from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t),
+            Curve::Bezier(p0, p1, p2, p3) => {
+                let u = 1.0 - t;
+                let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+                (a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0, a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1)
+            },
+        }
+    }
+}
+
+fn round(point: Point) -> (i32, i32) {
+    (point.0.round() as i32, point.1.round() as i32)
+}
+
+fn step_count(duration: Duration) -> u32 {
+    let millis = duration.as_secs() * 1000 + duration.subsec_millis() as u64;
+    (millis / STEP_INTERVAL_MS).max(1) as u32
+}
+
+/* Relative steps between absolute positions; steps that would not move are merged */
+fn to_steps(start: Point, points: &[Point]) -> Vec<(Duration, (i32, i32))> {
+    let interval = Duration::from_millis(STEP_INTERVAL_MS);
+    let mut position = round(start);
+    let mut delay = Duration::from_secs(0);
+    let mut steps = Vec::new();
+
+    for point in points.iter() {
+        let next = round(*point);
+        delay += interval;
+        if next != position {
+            steps.push((delay, (next.0 - position.0, next.1 - position.1)));
+            delay = Duration::from_secs(0);
+            position = next;
+        }
+    }
+    steps
+}
+
+fn sample(curve: &Curve, duration: Duration, easing: Easing) -> Vec<Point> {
+    let count = step_count(duration);
+    (1..=count).map(|step| curve.at(easing.apply(step as f32 / count as f32))).collect()
+}
+
+/* Relative steps along the curve */
+pub fn steps(curve: &Curve, duration: Duration, easing: Easing) -> Vec<(Duration, (i32, i32))> {
+    to_steps(curve.at(0.0), &sample(curve, duration, easing))
+}
+
+pub fn move_path(from: Point, to: Point, duration: Duration, easing: Easing) -> Vec<(Duration, (i32, i32))> {
+    steps(&Curve::Line(from, to), duration, easing)
+}
+
+/* Small xorshift generator, enough to vary humanized paths */
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed | 1)
+    }
+
+    pub fn from_time() -> Rng {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Rng::new(now.as_secs() ^ ((now.subsec_nanos() as u64) << 32))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /* Uniformly distributed in [low, high) */
+    pub fn range(&mut self, low: f32, high: f32) -> f32 {
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        low + (high - low) * unit
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Humanize {
+    /* Largest wobble of the position, in counts */
+    pub jitter: f32,
+    /* Largest deviation of the duration, as a fraction of it */
+    pub speed_variation: f32,
+    /* Largest sideways bow of the path, as a fraction of its length */
+    pub curvature: f32,
+    /* Largest overshoot past the target, as a fraction of the distance */
+    pub overshoot: f32,
+}
+
+impl Default for Humanize {
+    fn default() -> Humanize {
+        Humanize {
+            jitter: 1.5,
+            speed_variation: 0.3,
+            curvature: 0.15,
+            overshoot: 0.08,
+        }
+    }
+}
+
+/* Moves shorter than this hit their target without overshooting */
+const OVERSHOOT_MIN_DISTANCE: f32 = 50.0;
+
+fn bowed(from: Point, to: Point, bow: f32) -> Curve {
+    /* control points at a third and two thirds, pushed sideways */
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let (nx, ny) = (-dy * bow, dx * bow);
+    Curve::Bezier(from,
+                  (from.0 + dx / 3.0 + nx, from.1 + dy / 3.0 + ny),
+                  (from.0 + dx * 2.0 / 3.0 + nx, from.1 + dy * 2.0 / 3.0 + ny),
+                  to)
+}
+
+pub fn humanized_path(from: Point, to: Point, duration: Duration, humanize: &Humanize, rng: &mut Rng) -> Vec<(Duration, (i32, i32))> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let distance = (dx * dx + dy * dy).sqrt();
+    let speed = 1.0 + rng.range(-humanize.speed_variation, humanize.speed_variation);
+    let duration = duration.mul_f32(speed.max(0.1));
+
+    let mut points = Vec::new();
+    if distance >= OVERSHOOT_MIN_DISTANCE && humanize.overshoot > 0.0 {
+        let overshoot = rng.range(0.3, 1.0) * humanize.overshoot;
+        let past = (to.0 + dx * overshoot, to.1 + dy * overshoot);
+        let bow = rng.range(-humanize.curvature, humanize.curvature);
+        points.extend(sample(&bowed(from, past, bow), duration.mul_f32(0.8), Easing::EaseInOut));
+        points.extend(sample(&Curve::Line(past, to), duration.mul_f32(0.2), Easing::EaseOut));
+    } else {
+        let bow = rng.range(-humanize.curvature, humanize.curvature);
+        points.extend(sample(&bowed(from, to, bow), duration, Easing::EaseInOut));
+    }
+
+    /* wobble every position but the last, so the move still ends on target */
+    let last = points.len() - 1;
+    for point in points[..last].iter_mut() {
+        point.0 += rng.range(-humanize.jitter, humanize.jitter);
+        point.1 += rng.range(-humanize.jitter, humanize.jitter);
+    }
+    to_steps(from, &points)
+}
+
+/* Sends timed reports in real time, handing each one to `send` */
+pub fn play<F>(reports: &[(Duration, Vec<u8>)], mut send: F) -> io::Result<()>
+    where F: FnMut(&[u8]) -> io::Result<()>
+{
+    for (delay, report) in reports.iter() {
+        thread::sleep(*delay);
+        send(report)?;
+    }
+    Ok(())
+}
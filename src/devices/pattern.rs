@@ -0,0 +1,184 @@
+/*
+ * Motion patterns
+ * Wraps a pointing device so it keeps moving along a closed shape, to check
+ * pointer motion for smoothness by eye or to keep an input pipeline busy for
+ * burn-in tests. Movement is sent as relative steps at the 125Hz polling rate
+ * of common USB mice, repeating the shape every period until quit:
+ *
+ *   circle     of the given radius
+ *   spiral     winding out to the radius and back in again
+ *   lissajous  3:2 figure filling a square of twice the radius
+ *   square     with sides of twice the radius, at constant speed
+ *
+ * Shapes start where the pointer is. Positions are kept in fractions of a
+ * count and rounded per step, so the pointer ends every period exactly
+ * where it started. The keyboard controls still work alongside.
+ */
+
+use super::path::STEP_INTERVAL_MS;
+use super::{Action, Device, Identity};
+use std::f64::consts::PI;
+use std::io;
+use std::time::{Duration, Instant};
+
+/* Turns of the spiral on its way out, and again on the way in */
+const SPIRAL_TURNS: f64 = 4.0;
+
+pub const DEFAULT_RADIUS: u32 = 100;
+pub const DEFAULT_PERIOD: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    Circle,
+    Spiral,
+    Lissajous,
+    Square,
+}
+
+pub const SHAPES: &[(&str, Shape)] = &[
+    ("circle", Shape::Circle),
+    ("spiral", Shape::Spiral),
+    ("lissajous", Shape::Lissajous),
+    ("square", Shape::Square),
+];
+
+impl Shape {
+    pub fn from_name(name: &str) -> io::Result<Shape> {
+        SHAPES.iter().find(|shape| shape.0 == name).map(|shape| shape.1).ok_or_else(|| {
+            let names: Vec<&str> = SHAPES.iter().map(|shape| shape.0).collect();
+            io::Error::new(io::ErrorKind::InvalidInput,
+                           format!("Unknown pattern {}, expected one of: {}", name, names.join(", ")))
+        })
+    }
+
+    /* Position at fraction t of the period, on a shape of radius 1 */
+    fn point(&self, t: f64) -> (f64, f64) {
+        let angle = 2.0 * PI * t;
+        match *self {
+            Shape::Circle => (angle.cos(), angle.sin()),
+            Shape::Spiral => {
+                let radius = 1.0 - (2.0 * t - 1.0).abs();
+                let angle = angle * SPIRAL_TURNS;
+                (radius * angle.cos(), radius * angle.sin())
+            },
+            Shape::Lissajous => ((3.0 * angle).sin(), (2.0 * angle).sin()),
+            Shape::Square => {
+                /* clockwise around the corners, starting at the top left */
+                let side = (t * 4.0).floor().min(3.0);
+                let along = t * 4.0 - side;
+                match side as u8 {
+                    0 => (-1.0 + 2.0 * along, -1.0),
+                    1 => (1.0, -1.0 + 2.0 * along),
+                    2 => (1.0 - 2.0 * along, 1.0),
+                    _ => (-1.0, 1.0 - 2.0 * along),
+                }
+            },
+        }
+    }
+}
+
+pub struct Pattern {
+    device: Box<dyn Device>,
+    shape: Shape,
+    radius: f64,
+    period: Duration,
+    start: Option<Instant>,
+    next: Instant,
+    /* Position already sent, relative to the start */
+    sent: (i64, i64),
+}
+
+impl Pattern {
+    pub fn new(device: Box<dyn Device>, shape: Shape, radius: u32, period: Duration) -> Pattern {
+        Pattern {
+            device,
+            shape,
+            radius: radius as f64,
+            period: period.max(Duration::from_millis(STEP_INTERVAL_MS)),
+            start: None,
+            next: Instant::now(),
+            sent: (0, 0),
+        }
+    }
+
+    /* Position at the given time since the start, in counts */
+    fn position(&self, elapsed: Duration) -> (i64, i64) {
+        let t = (elapsed.as_secs_f64() / self.period.as_secs_f64()).fract();
+        let (x, y) = self.shape.point(t);
+        let (x0, y0) = self.shape.point(0.0);
+        (((x - x0) * self.radius).round() as i64, ((y - y0) * self.radius).round() as i64)
+    }
+
+    /* Moves by the given counts, in steps within the range of a report */
+    fn move_by(&mut self, mut dx: i64, mut dy: i64) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        while dx != 0 || dy != 0 {
+            let step_x = dx.clamp(i8::MIN as i64, i8::MAX as i64);
+            let step_y = dy.clamp(i8::MIN as i64, i8::MAX as i64);
+            reports.extend(self.device.action(Action::Move(step_x as i8, step_y as i8)));
+            dx -= step_x;
+            dy -= step_y;
+        }
+        reports
+    }
+}
+
+impl Device for Pattern {
+    fn descriptor(&self) -> &[u8] {
+        self.device.descriptor()
+    }
+
+    fn identity(&self) -> Identity {
+        self.device.identity()
+    }
+
+    fn is_keyboard(&self) -> bool {
+        self.device.is_keyboard()
+    }
+
+    fn leds(&self) -> Option<u8> {
+        self.device.leds()
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        self.device.action(action)
+    }
+
+    fn scroll(&mut self, pixels: i32, duration: Duration) -> Option<Vec<(Duration, Vec<u8>)>> {
+        self.device.scroll(pixels, duration)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        Some(self.device.deadline().map_or(self.next, |deadline| deadline.min(self.next)))
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut reports = self.device.tick(now);
+        if now < self.next {
+            return reports;
+        }
+
+        let start = *self.start.get_or_insert(now);
+        let (x, y) = self.position(now - start);
+        reports.extend(self.move_by(x - self.sent.0, y - self.sent.1));
+        self.sent = (x, y);
+        self.next = (self.next + Duration::from_millis(STEP_INTERVAL_MS)).max(now);
+        reports
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        self.device.handle_output(rtype, data)
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        self.device.decode_output(rtype, data)
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        self.device.get_report(rnum, rtype)
+    }
+
+    fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+        self.device.set_report(rnum, rtype, data)
+    }
+}
@@ -0,0 +1,309 @@
+/*
+ * Wacom-style pen tablet
+ * This preset is modeled on the pen interface of common Wacom tablets: a
+ * single stylus with tip, two barrel buttons and an eraser end reporting
+ * absolute X/Y, tip pressure and X/Y tilt. The product id is not in the wacom
+ * driver's device table, so the kernel takes the descriptor-driven generic
+ * pen path the DIGImend and wacom drivers share.
+ *
+ * As with real pens, In Range is reported while the pen hovers above the
+ * surface and Tip Switch only while it touches it. Turning the pen around
+ * makes it leave proximity first and then come back with Invert set; the
+ * eraser end touching the surface reports Eraser instead of Tip Switch and
+ * the barrel buttons are not reported while inverted.
+ *
+ * Feature report 3 carries the transducer serial number and vendor id which
+ * the drivers query to tell styluses apart; they are answered with fixed stub
+ * values.
+ *
+ * Keys map onto the pen as follows:
+ *   1: Toggle the tip (or the eraser, when flipped) touching the surface
+ *   2: Toggle the barrel button
+ *   3: Flip the pen between tip and eraser end
+ *   4: Toggle the secondary barrel button
+ *   5: Bring the pen into or out of proximity
+ *   a/d/w/s: Move the pen, tilting it into the direction of the stroke
+ *   r/f: Increase/decrease the pressure
+ */
+
+use super::{Action, Device, Identity};
+use std::io;
+use uhid::{uhid_report_type, BUS_USB};
+
+const RDESC: [u8; 141] = [
+    0x05, 0x0d,	/* USAGE_PAGE (Digitizers) */
+    0x09, 0x02,	/* USAGE (Pen) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x09, 0x20,		/* USAGE (Stylus) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x09, 0x42,			/* USAGE (Tip Switch) */
+    0x09, 0x44,			/* USAGE (Barrel Switch) */
+    0x09, 0x3c,			/* USAGE (Invert) */
+    0x09, 0x45,			/* USAGE (Eraser) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x95, 0x04,			/* REPORT_COUNT (4) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x09, 0x5a,			/* USAGE (Secondary Barrel Switch) */
+    0x09, 0x32,			/* USAGE (In Range) */
+    0x95, 0x02,			/* REPORT_COUNT (2) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x81, 0x03,			/* INPUT (Cnst,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x55, 0x0d,			/* UNIT_EXPONENT (-3) */
+    0x65, 0x11,			/* UNIT (Centimeter) */
+    0x35, 0x00,			/* PHYSICAL_MINIMUM (0) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x26, 0x60, 0x54,		/* LOGICAL_MAXIMUM (21600) */
+    0x46, 0x60, 0x54,		/* PHYSICAL_MAXIMUM (21600) */
+    0x75, 0x10,			/* REPORT_SIZE (16) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x26, 0xbc, 0x34,		/* LOGICAL_MAXIMUM (13500) */
+    0x46, 0xbc, 0x34,		/* PHYSICAL_MAXIMUM (13500) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x05, 0x0d,			/* USAGE_PAGE (Digitizers) */
+    0x55, 0x00,			/* UNIT_EXPONENT (0) */
+    0x65, 0x00,			/* UNIT (None) */
+    0x45, 0x00,			/* PHYSICAL_MAXIMUM (0) */
+    0x09, 0x30,			/* USAGE (Tip Pressure) */
+    0x26, 0xff, 0x1f,		/* LOGICAL_MAXIMUM (8191) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x09, 0x3d,			/* USAGE (X Tilt) */
+    0x09, 0x3e,			/* USAGE (Y Tilt) */
+    0x15, 0xc0,			/* LOGICAL_MINIMUM (-64) */
+    0x25, 0x3f,			/* LOGICAL_MAXIMUM (63) */
+    0x35, 0xc0,			/* PHYSICAL_MINIMUM (-64) */
+    0x45, 0x3f,			/* PHYSICAL_MAXIMUM (63) */
+    0x65, 0x14,			/* UNIT (Degrees) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x02,			/* REPORT_COUNT (2) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0xc0,			/* END_COLLECTION */
+    0x85, 0x03,		/* REPORT_ID (3) */
+    0x65, 0x00,		/* UNIT (None) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x35, 0x00,		/* PHYSICAL_MINIMUM (0) */
+    0x45, 0x00,		/* PHYSICAL_MAXIMUM (0) */
+    0x09, 0x5b,		/* USAGE (Transducer Serial Number) */
+    0x27, 0xff, 0xff, 0xff, 0x7f,	/* LOGICAL_MAXIMUM (2147483647) */
+    0x75, 0x20,		/* REPORT_SIZE (32) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0x09, 0x91,		/* USAGE (Transducer Vendor ID) */
+    0x26, 0xff, 0x0f,	/* LOGICAL_MAXIMUM (4095) */
+    0x75, 0x10,		/* REPORT_SIZE (16) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+const MAX_X: i32 = 21600;
+const MAX_Y: i32 = 13500;
+const MAX_PRESSURE: i32 = 8191;
+
+/* Keyboard steps are tiny compared to the tablet resolution */
+const MOVE_SCALE: i32 = 50;
+const PRESSURE_STEP: i32 = 512;
+const TILT: i8 = 30;
+
+const SERIAL_NUMBER: u32 = 0x0bad_cafe;
+const TRANSDUCER_VENDOR: u16 = 0x0001;
+
+pub struct PenTablet {
+    in_range: bool,
+    tip_down: bool,
+    barrel_down: bool,
+    secondary_barrel_down: bool,
+    inverted: bool,
+    x: i32,
+    y: i32,
+    pressure: i32,
+    tilt_x: i8,
+    tilt_y: i8,
+}
+
+impl Default for PenTablet {
+    fn default() -> PenTablet {
+        PenTablet {
+            in_range: false,
+            tip_down: false,
+            barrel_down: false,
+            secondary_barrel_down: false,
+            inverted: false,
+            x: MAX_X / 2,
+            y: MAX_Y / 2,
+            pressure: MAX_PRESSURE / 2,
+            tilt_x: 0,
+            tilt_y: 0,
+        }
+    }
+}
+
+impl PenTablet {
+    fn report(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 10];
+        let pressure = if self.tip_down { self.pressure } else { 0 };
+
+        data[0] = 0x02;
+        if self.in_range {
+            if self.tip_down && !self.inverted {
+                data[1] |= 0x01;
+            }
+            if self.barrel_down && !self.inverted {
+                data[1] |= 0x02;
+            }
+            if self.inverted {
+                data[1] |= 0x04;
+            }
+            if self.tip_down && self.inverted {
+                data[1] |= 0x08;
+            }
+            if self.secondary_barrel_down && !self.inverted {
+                data[1] |= 0x10;
+            }
+            data[1] |= 0x20;
+        }
+        data[2..4].copy_from_slice(&(self.x as u16).to_le_bytes());
+        data[4..6].copy_from_slice(&(self.y as u16).to_le_bytes());
+        data[6..8].copy_from_slice(&(pressure as u16).to_le_bytes());
+        data[8] = self.tilt_x as u8;
+        data[9] = self.tilt_y as u8;
+        data
+    }
+
+    fn lift(&mut self, reports: &mut Vec<Vec<u8>>) {
+        if self.tip_down {
+            self.tip_down = false;
+            reports.push(self.report());
+        }
+    }
+
+    /* Moves the pen above the surface, lifting it first if it touches */
+    pub fn hover_at(&mut self, x: i32, y: i32) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        self.lift(&mut reports);
+        self.in_range = true;
+        self.x = x.clamp(0, MAX_X);
+        self.y = y.clamp(0, MAX_Y);
+        reports.push(self.report());
+        reports
+    }
+
+    pub fn leave_proximity(&mut self) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        self.lift(&mut reports);
+        if self.in_range {
+            self.in_range = false;
+            reports.push(self.report());
+        }
+        reports
+    }
+
+    /* Turns the pen around; a pen in range leaves proximity while doing so */
+    pub fn set_inverted(&mut self, inverted: bool) -> Vec<Vec<u8>> {
+        if self.inverted == inverted {
+            return Vec::new();
+        }
+        if !self.in_range {
+            self.inverted = inverted;
+            return Vec::new();
+        }
+
+        let mut reports = self.leave_proximity();
+        self.inverted = inverted;
+        self.in_range = true;
+        reports.push(self.report());
+        reports
+    }
+
+    fn draw(&mut self, inverted: bool, points: &[(i32, i32)]) -> Vec<Vec<u8>> {
+        let (first, rest) = match points.split_first() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+
+        let mut reports = self.set_inverted(inverted);
+        reports.extend(self.hover_at(first.0, first.1));
+        self.tip_down = true;
+        reports.push(self.report());
+        for &(x, y) in rest {
+            self.x = x.clamp(0, MAX_X);
+            self.y = y.clamp(0, MAX_Y);
+            reports.push(self.report());
+        }
+        let mut lifted = Vec::new();
+        self.lift(&mut lifted);
+        reports.extend(lifted);
+        reports
+    }
+
+    /* Draws through the given points with the tip, ending up hovering */
+    pub fn stroke(&mut self, points: &[(i32, i32)]) -> Vec<Vec<u8>> {
+        self.draw(false, points)
+    }
+
+    /* Erases along the given points with the eraser end, ending up hovering */
+    pub fn erase_stroke(&mut self, points: &[(i32, i32)]) -> Vec<Vec<u8>> {
+        self.draw(true, points)
+    }
+}
+
+impl Device for PenTablet {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x056a,
+            product: 0x8000,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => {
+                self.tip_down = !self.tip_down;
+                self.in_range = true;
+            },
+            Action::ToggleButton(2) => self.barrel_down = !self.barrel_down,
+            Action::ToggleButton(3) => {
+                let inverted = !self.inverted;
+                return self.set_inverted(inverted);
+            },
+            Action::ToggleButton(4) => self.secondary_barrel_down = !self.secondary_barrel_down,
+            Action::ToggleButton(5) if self.in_range => return self.leave_proximity(),
+            Action::ToggleButton(5) => self.in_range = true,
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, dy) => {
+                self.x = (self.x + dx as i32 * MOVE_SCALE).clamp(0, MAX_X);
+                self.y = (self.y + dy as i32 * MOVE_SCALE).clamp(0, MAX_Y);
+                self.tilt_x = dx.signum() * TILT;
+                self.tilt_y = dy.signum() * TILT;
+                self.in_range = true;
+            },
+            Action::Wheel(delta) => {
+                self.pressure = (self.pressure + delta as i32 * PRESSURE_STEP).clamp(1, MAX_PRESSURE);
+            },
+        }
+
+        vec![self.report()]
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        if rtype == uhid_report_type::UHID_FEATURE_REPORT as u8 && rnum == 0x03 {
+            let mut data = vec![0x03];
+            data.extend_from_slice(&SERIAL_NUMBER.to_le_bytes());
+            data.extend_from_slice(&TRANSDUCER_VENDOR.to_le_bytes());
+            return Ok(data);
+        }
+
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"))
+    }
+}
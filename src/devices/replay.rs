@@ -0,0 +1,65 @@
+/*
+ * Replay
+ * Reproduces a recorded device, e.g. from a hid-recorder capture attached to
+ * a bug report: the descriptor and identity are the recorded ones and the
+ * recorded input reports are sent again with their original timing.
+ *
+ * Timing starts with the first tick(), so a replay should begin once the
+ * host opened the device, or the first reports are lost. The interactive
+ * controls do nothing.
+ */
+
+use super::{Action, Device, Identity};
+use recording::Capture;
+use std::time::Instant;
+
+pub struct Replay {
+    capture: Capture,
+    next: usize,
+    start: Option<Instant>,
+}
+
+impl Replay {
+    pub fn new(capture: Capture) -> Replay {
+        Replay {
+            capture,
+            next: 0,
+            start: None,
+        }
+    }
+}
+
+impl Device for Replay {
+    fn descriptor(&self) -> &[u8] {
+        &self.capture.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        self.capture.identity
+    }
+
+    fn action(&mut self, _action: Action) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        match self.start {
+            Some(start) => self.capture.events.get(self.next).map(|&(time, _)| start + time),
+            None => Some(Instant::now()),
+        }
+    }
+
+    fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let start = *self.start.get_or_insert(now);
+
+        let mut reports = Vec::new();
+        while let Some(&(time, ref report)) = self.capture.events.get(self.next) {
+            if start + time > now {
+                break;
+            }
+            reports.push(report.clone());
+            self.next += 1;
+        }
+        reports
+    }
+}
@@ -0,0 +1,247 @@
+/*
+ * Screen
+ * A keyboard and an absolute pointer together, the input of a whole screen
+ * as a KVM switch or a Barrier client injects it. Positions are given in
+ * pixels of a screen of the configured size and scaled to the range of the
+ * pointer, so they land where the sending side means them to as long as the
+ * compositor maps the pointer onto the whole screen.
+ *
+ * Report 0x01 is the boot keyboard, see keyboard.rs, with the LED output
+ * report. Report 0x02 (8 bytes) is the pointer:
+ *   byte 1        buttons 1..5
+ *   byte 2..5     X and Y in 0..32767
+ *   byte 6        wheel, relative
+ *   byte 7        horizontal wheel (AC Pan), relative
+ *
+ * Clones share the state, so what the Barrier client injects, see
+ * src/barrier.rs, and the keys of the terminal act on the same pointer and
+ * keys. Keys map onto the screen like onto the mouse, moving the pointer by
+ * as many pixels as the mouse would move counts.
+ */
+
+use super::keyboard::{led_values, leds, report, BOOT_KEYBOARD_RDESC, LED_CAPS_LOCK, LED_NUM_LOCK, LED_SCROLL_LOCK};
+use super::{Action, Device, Identity};
+use std::cell::RefCell;
+use std::rc::Rc;
+use uhid::{uhid_report_type, BUS_VIRTUAL};
+
+const POINTER_RDESC: [u8; 74] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x02,	/* USAGE (Mouse) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x09, 0x01,		/* USAGE (Pointer) */
+    0xa1, 0x00,		/* COLLECTION (Physical) */
+    0x05, 0x09,			/* USAGE_PAGE (Button) */
+    0x19, 0x01,			/* USAGE_MINIMUM (Button 1) */
+    0x29, 0x05,			/* USAGE_MAXIMUM (Button 5) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x95, 0x05,			/* REPORT_COUNT (5) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x75, 0x03,			/* REPORT_SIZE (3) */
+    0x81, 0x01,			/* INPUT (Cnst,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x7f,		/* LOGICAL_MAXIMUM (32767) */
+    0x75, 0x10,			/* REPORT_SIZE (16) */
+    0x95, 0x02,			/* REPORT_COUNT (2) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x09, 0x38,			/* USAGE (Wheel) */
+    0x15, 0x81,			/* LOGICAL_MINIMUM (-127) */
+    0x25, 0x7f,			/* LOGICAL_MAXIMUM (127) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
+    0x05, 0x0c,			/* USAGE_PAGE (Consumer Devices) */
+    0x0a, 0x38, 0x02,		/* USAGE (AC Pan) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
+    0xc0,			/* END_COLLECTION */
+    0xc0,		/* END_COLLECTION */
+];
+
+const POINTER_MAX: i32 = 32767;
+
+/* Modifier keys are usages 0xe0..0xe7 and reported as bits instead */
+const FIRST_MODIFIER: u8 = 0xe0;
+const LAST_MODIFIER: u8 = 0xe7;
+
+#[derive(Default)]
+struct State {
+    modifiers: u8,
+    keys: Vec<u8>,
+    buttons: u8,
+    position: (i32, i32),
+    leds: u8,
+}
+
+#[derive(Clone)]
+pub struct Screen {
+    descriptor: Vec<u8>,
+    width: u16,
+    height: u16,
+    state: Rc<RefCell<State>>,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Screen {
+        let (width, height) = (width.max(1), height.max(1));
+        Screen {
+            descriptor: [&BOOT_KEYBOARD_RDESC[..], &POINTER_RDESC[..]].concat(),
+            width,
+            height,
+            state: Rc::new(RefCell::new(State {
+                position: (width as i32 / 2, height as i32 / 2),
+                ..State::default()
+            })),
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /* The pointer position in pixels */
+    pub fn position(&self) -> (i32, i32) {
+        self.state.borrow().position
+    }
+
+    fn pointer_report(&self, wheel: i8, pan: i8) -> Vec<u8> {
+        let state = self.state.borrow();
+        let scale = |value: i32, size: u16| (value * POINTER_MAX / (size as i32 - 1).max(1)) as u16;
+        let mut data = vec![0x02, state.buttons];
+        data.extend_from_slice(&scale(state.position.0, self.width).to_le_bytes());
+        data.extend_from_slice(&scale(state.position.1, self.height).to_le_bytes());
+        data.extend_from_slice(&[wheel as u8, pan as u8]);
+        data
+    }
+
+    /* Presses or releases a key given as a Keyboard/Keypad usage */
+    pub fn key(&mut self, usage: u8, pressed: bool) -> Vec<Vec<u8>> {
+        let mut state = self.state.borrow_mut();
+        if (FIRST_MODIFIER..=LAST_MODIFIER).contains(&usage) {
+            let bit = 1 << (usage - FIRST_MODIFIER);
+            if pressed { state.modifiers |= bit } else { state.modifiers &= !bit }
+        } else if pressed && !state.keys.contains(&usage) {
+            /* the boot report has room for six, the oldest gives way */
+            if state.keys.len() == 6 {
+                state.keys.remove(0);
+            }
+            state.keys.push(usage);
+        } else if !pressed {
+            state.keys.retain(|&held| held != usage);
+        }
+        vec![report(state.modifiers, &state.keys)]
+    }
+
+    /* Moves the pointer to a position in pixels, clamped to the screen */
+    pub fn move_to(&mut self, x: i32, y: i32) -> Vec<Vec<u8>> {
+        self.state.borrow_mut().position = (x.clamp(0, self.width as i32 - 1), y.clamp(0, self.height as i32 - 1));
+        vec![self.pointer_report(0, 0)]
+    }
+
+    pub fn move_by(&mut self, dx: i32, dy: i32) -> Vec<Vec<u8>> {
+        let (x, y) = self.position();
+        self.move_to(x + dx, y + dy)
+    }
+
+    /* Presses or releases button 1..5 */
+    pub fn button(&mut self, button: u8, pressed: bool) -> Vec<Vec<u8>> {
+        if !(1..=5).contains(&button) {
+            return vec![];
+        }
+        let bit = 1 << (button - 1);
+        {
+            let mut state = self.state.borrow_mut();
+            if pressed { state.buttons |= bit } else { state.buttons &= !bit }
+        }
+        vec![self.pointer_report(0, 0)]
+    }
+
+    /* Scrolls by detents, positive up and right */
+    pub fn wheel(&mut self, vertical: i8, horizontal: i8) -> Vec<Vec<u8>> {
+        vec![self.pointer_report(vertical, horizontal)]
+    }
+
+    /* Releases every key and button, e.g. when input moves elsewhere */
+    pub fn release_all(&mut self) -> Vec<Vec<u8>> {
+        let mut reports = vec![];
+        let (keys, buttons) = {
+            let mut state = self.state.borrow_mut();
+            let keys = state.modifiers != 0 || !state.keys.is_empty();
+            let buttons = state.buttons != 0;
+            state.modifiers = 0;
+            state.keys.clear();
+            state.buttons = 0;
+            (keys, buttons)
+        };
+        if keys {
+            reports.push(report(0, &[]));
+        }
+        if buttons {
+            reports.push(self.pointer_report(0, 0));
+        }
+        reports
+    }
+}
+
+impl Device for Screen {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_VIRTUAL as u16,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn is_keyboard(&self) -> bool {
+        true
+    }
+
+    fn leds(&self) -> Option<u8> {
+        Some(self.state.borrow().leds)
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(button) => {
+                let pressed = (1..=5).contains(&button) && self.state.borrow().buttons & (1 << (button - 1)) == 0;
+                self.button(button, pressed)
+            },
+            Action::Move(dx, dy) => self.move_by(dx as i32, dy as i32),
+            Action::Wheel(delta) => self.wheel(delta, 0),
+        }
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
+            return;
+        }
+
+        if let Some(leds) = leds(data) {
+            self.state.borrow_mut().leds = leds;
+            eprintln!("Keyboard LEDs: NumLock {} CapsLock {} ScrollLock {}",
+                      leds & LED_NUM_LOCK != 0,
+                      leds & LED_CAPS_LOCK != 0,
+                      leds & LED_SCROLL_LOCK != 0);
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        match leds(data) {
+            Some(leds) if rtype == uhid_report_type::UHID_OUTPUT_REPORT as u8 => led_values(leds),
+            _ => vec![],
+        }
+    }
+}
@@ -0,0 +1,709 @@
+/*
+ * Descriptor templates
+ * A library of devices rendered from a template and a few variables instead
+ * of a fixed descriptor, so families of related devices, e.g. mice with any
+ * number of buttons, need no descriptor written by hand. Given with
+ * --template <name>[:<variable>=<value>,...]:
+ *
+ *   --template rel-mouse:buttons=8,resolution=16,minimum=-32767,maximum=32767
+ *
+ *   rel-mouse     buttons, X, Y and a wheel as relative axes; mouse for short
+ *   abs-mouse     buttons, X and Y as absolute axes, like the tablet of a VM
+ *   kbd-6kro      modifiers and an array of the keys held, like boot keyboards
+ *   kbd-nkro      modifiers and a bit for each key, any number of them held
+ *   gamepad       buttons, X, Y, Z, Rx, Ry and Rz, as many as axes
+ *   touchscreen   contacts of a tip switch, contact id, X and Y, and a count
+ *   tablet        a pen with tip, barrel and eraser switches and pressure
+ *   consumer      an array of consumer controls, media keys and the like
+ *   vendor-raw    vendor defined input and output reports of size bytes
+ *
+ * templates list prints them and templates show <name> the variables of one,
+ * with their ranges and defaults, and the descriptor it renders by default.
+ * Every template takes report-id, 1..255 to number its report, 0 to leave it
+ * unnumbered as by default. The fields of the report follow the order of the
+ * descriptor, padded to whole bytes, and absolute axes rest at the middle of
+ * their logical range.
+ *
+ * Keys map onto the devices like onto the presets: the number keys toggle
+ * the buttons, the switches of the pen, the first contact of the
+ * touchscreen, keys from a on and media keys, moving moves the axes or taps
+ * the arrow keys and skips tracks, and the wheel keys turn the wheel, tap
+ * page up and down or change the volume.
+ */
+
+use super::{Action, Device, Identity};
+use hid_report::bits;
+use hid_report::descriptor::{self, Builder, ItemType, APPLICATION, CONSTANT, LOGICAL, PHYSICAL, RELATIVE, VARIABLE};
+use hid_report::usage::{self, button, consumer, digitizer, generic_desktop, keyboard, led, VENDOR_PAGES};
+use std::fmt::Write as FmtWrite;
+use std::io;
+use uhid::BUS_VIRTUAL;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/* Long enough for the largest descriptor rendered, a touchscreen of ten
+ * contacts */
+const MAX_DESCRIPTOR_LEN: usize = 1024;
+
+const AXES: &[u32] = &[
+    generic_desktop::X, generic_desktop::Y, generic_desktop::Z,
+    generic_desktop::RX, generic_desktop::RY, generic_desktop::RZ,
+];
+
+/* The switches of the pen, in the order of the buttons */
+const PEN_SWITCHES: &[u32] = &[digitizer::TIP_SWITCH, digitizer::BARREL_SWITCH, digitizer::ERASER];
+
+/* What the number keys press on consumer controls */
+const MEDIA_KEYS: &[u32] = &[
+    consumer::PLAY_PAUSE, consumer::SCAN_NEXT_TRACK, consumer::SCAN_PREVIOUS_TRACK, consumer::MUTE,
+    consumer::VOLUME_INCREMENT, consumer::VOLUME_DECREMENT, consumer::STOP,
+];
+
+/* Highest usage of the arrays of keyboards and consumer controls */
+const MAX_KEY_USAGE: u32 = 0xff;
+const MAX_CONSUMER_USAGE: u32 = 0x3ff;
+
+/* What keyboards report every key as when more are held than the array
+ * holds */
+const ERROR_ROLL_OVER: i64 = 0x01;
+
+pub struct Variable {
+    pub name: &'static str,
+    pub minimum: i64,
+    pub maximum: i64,
+    pub default: i64,
+    pub about: &'static str,
+}
+
+const fn variable(name: &'static str, minimum: i64, maximum: i64, default: i64, about: &'static str) -> Variable {
+    Variable { name, minimum, maximum, default, about }
+}
+
+const REPORT_ID: Variable = variable("report-id", 0, 255, 0, "the id numbering the report, 0 for none");
+const MINIMUM: i64 = i32::MIN as i64;
+const MAXIMUM: i64 = i32::MAX as i64;
+
+const REL_MOUSE: &[Variable] = &[
+    variable("buttons", 1, 32, 3, "buttons"),
+    variable("resolution", 2, 32, 8, "bits of each axis"),
+    variable("minimum", MINIMUM, MAXIMUM, -127, "logical minimum of the axes"),
+    variable("maximum", MINIMUM, MAXIMUM, 127, "logical maximum of the axes"),
+    REPORT_ID,
+];
+const ABS_MOUSE: &[Variable] = &[
+    variable("buttons", 1, 32, 3, "buttons"),
+    variable("resolution", 2, 32, 16, "bits of each axis"),
+    variable("minimum", MINIMUM, MAXIMUM, 0, "logical minimum of the axes"),
+    variable("maximum", MINIMUM, MAXIMUM, 32767, "logical maximum of the axes"),
+    REPORT_ID,
+];
+const KBD_6KRO: &[Variable] = &[
+    variable("keys", 1, 32, 6, "keys held at once besides the modifiers"),
+    REPORT_ID,
+];
+const KBD_NKRO: &[Variable] = &[
+    variable("keys", 8, 232, 104, "keys with a bit, usages 0 up to keys - 1"),
+    REPORT_ID,
+];
+const GAMEPAD: &[Variable] = &[
+    variable("buttons", 1, 32, 8, "buttons"),
+    variable("axes", 1, AXES.len() as i64, 2, "axes of X, Y, Z, Rx, Ry and Rz"),
+    variable("resolution", 2, 32, 8, "bits of each axis"),
+    variable("minimum", MINIMUM, MAXIMUM, -127, "logical minimum of the axes"),
+    variable("maximum", MINIMUM, MAXIMUM, 127, "logical maximum of the axes"),
+    REPORT_ID,
+];
+const TOUCHSCREEN: &[Variable] = &[
+    variable("contacts", 1, 10, 2, "contacts reported at once"),
+    variable("resolution", 2, 16, 12, "bits of X and Y"),
+    variable("maximum", 1, 65535, 4095, "logical maximum of X and Y"),
+    REPORT_ID,
+];
+const TABLET: &[Variable] = &[
+    variable("buttons", 1, PEN_SWITCHES.len() as i64, 2, "switches of the tip, barrel and eraser"),
+    variable("resolution", 2, 16, 16, "bits of X and Y"),
+    variable("maximum", 1, 65535, 32767, "logical maximum of X and Y"),
+    variable("pressure", 1, 65535, 4095, "logical maximum of the tip pressure"),
+    REPORT_ID,
+];
+const CONSUMER: &[Variable] = &[
+    variable("keys", 1, 4, 1, "controls pressed at once"),
+    REPORT_ID,
+];
+const VENDOR_RAW: &[Variable] = &[
+    variable("size", 1, 4095, 64, "bytes of the input and output report"),
+    REPORT_ID,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Kind {
+    RelMouse,
+    AbsMouse,
+    Kbd6kro,
+    KbdNkro,
+    Gamepad,
+    Touchscreen,
+    Tablet,
+    Consumer,
+    VendorRaw,
+}
+
+pub const KINDS: &[Kind] = &[
+    Kind::RelMouse, Kind::AbsMouse, Kind::Kbd6kro, Kind::KbdNkro, Kind::Gamepad,
+    Kind::Touchscreen, Kind::Tablet, Kind::Consumer, Kind::VendorRaw,
+];
+
+/* The names of the kinds, in the same order */
+pub const TEMPLATES: &[&str] = &[
+    "rel-mouse", "abs-mouse", "kbd-6kro", "kbd-nkro", "gamepad", "touchscreen", "tablet", "consumer", "vendor-raw",
+];
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Kind> {
+        match name {
+            "mouse" => Some(Kind::RelMouse),
+            _ => KINDS.iter().cloned().find(|kind| kind.name() == name),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        TEMPLATES[KINDS.iter().position(|&kind| kind == self).unwrap_or(0)]
+    }
+
+    pub fn about(self) -> &'static str {
+        match self {
+            Kind::RelMouse => "a mouse with buttons, X, Y and a wheel as relative axes",
+            Kind::AbsMouse => "a pointer with buttons, X and Y as absolute axes",
+            Kind::Kbd6kro => "a keyboard reporting the modifiers and an array of the keys held",
+            Kind::KbdNkro => "a keyboard reporting the modifiers and a bit for each key",
+            Kind::Gamepad => "a gamepad with buttons and up to six absolute axes",
+            Kind::Touchscreen => "a multitouch screen, a tip switch, contact id, X and Y for each contact",
+            Kind::Tablet => "a pen tablet with tip, barrel and eraser switches and tip pressure",
+            Kind::Consumer => "consumer controls, media keys and the like, as an array",
+            Kind::VendorRaw => "vendor defined input and output reports, for raw hidraw clients",
+        }
+    }
+
+    pub fn variables(self) -> &'static [Variable] {
+        match self {
+            Kind::RelMouse => REL_MOUSE,
+            Kind::AbsMouse => ABS_MOUSE,
+            Kind::Kbd6kro => KBD_6KRO,
+            Kind::KbdNkro => KBD_NKRO,
+            Kind::Gamepad => GAMEPAD,
+            Kind::Touchscreen => TOUCHSCREEN,
+            Kind::Tablet => TABLET,
+            Kind::Consumer => CONSUMER,
+            Kind::VendorRaw => VENDOR_RAW,
+        }
+    }
+}
+
+/* A template with its variables, checked to render a valid descriptor */
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Template {
+    pub kind: Kind,
+    /* in the order of the variables of the kind */
+    pub values: Vec<i64>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Template {
+    /* The template with the defaults of its variables */
+    pub fn new(kind: Kind) -> Template {
+        Template { kind, values: kind.variables().iter().map(|variable| variable.default).collect() }
+    }
+
+    /* The template of <name>[:<variable>=<value>,...] */
+    pub fn from_spec(spec: &str) -> io::Result<Template> {
+        let mut parts = spec.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let mut template = match Kind::from_name(name) {
+            Some(kind) => Template::new(kind),
+            None => return Err(invalid(format!("Unknown template {}, expected one of: {}", name, TEMPLATES.join(", ")))),
+        };
+        for assignment in parts.next().into_iter().flat_map(|variables| variables.split(',')) {
+            let mut assignment = assignment.splitn(2, '=');
+            let name = assignment.next().unwrap_or("").trim();
+            let value: i64 = match assignment.next().map(|value| value.trim().parse()) {
+                Some(Ok(value)) => value,
+                _ => return Err(invalid(format!("Expected <variable>=<number> for {}", name))),
+            };
+            let variables = template.kind.variables();
+            let index = match variables.iter().position(|variable| variable.name == name) {
+                Some(index) => index,
+                None => return Err(invalid(format!("The {} template has no variable {}", template.kind.name(), name))),
+            };
+            let variable = &variables[index];
+            if value < variable.minimum || value > variable.maximum {
+                return Err(invalid(format!("{} must be within {}..{}", name, variable.minimum, variable.maximum)));
+            }
+            template.values[index] = value;
+        }
+        template.check()?;
+        Ok(template)
+    }
+
+    /* The value of a variable of the kind, 0 for one it does not have */
+    pub fn value(&self, name: &str) -> i64 {
+        self.kind.variables().iter().position(|variable| variable.name == name).map_or(0, |index| self.values[index])
+    }
+
+    /* The logical range of the axes, from 0 for templates without a minimum */
+    fn range(&self) -> (i64, i64) {
+        (self.value("minimum"), self.value("maximum"))
+    }
+
+    /* Whether the logical range is one the axes can hold */
+    fn check(&self) -> io::Result<()> {
+        let bits = self.value("resolution");
+        if bits == 0 {
+            return Ok(());
+        }
+        let (minimum, maximum) = self.range();
+        if minimum >= maximum {
+            return Err(invalid(format!("The minimum {} is not below the maximum {}", minimum, maximum)));
+        }
+        let (lowest, highest) = if minimum < 0 { (-(1 << (bits - 1)), (1 << (bits - 1)) - 1) } else { (0, (1 << bits) - 1) };
+        if minimum < lowest || maximum > highest {
+            return Err(invalid(format!("{}..{} does not fit axes of {} bits, {}..{} does", minimum, maximum, bits, lowest, highest)));
+        }
+        if self.kind == Kind::RelMouse && minimum >= 0 {
+            return Err(invalid("The mouse moves both ways, the minimum has to be negative".to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn device(&self) -> Templated {
+        Templated::new(self.clone())
+    }
+}
+
+/* Offset, size and count of the fields of the input report taking values */
+type Fields = Vec<(usize, usize, usize)>;
+
+/* Builds the descriptor and lays out the input report along with it */
+struct Render<'a> {
+    builder: Builder<'a>,
+    fields: Fields,
+    bits: usize,
+}
+
+impl<'a> Render<'a> {
+    fn input(&mut self, size: u32, count: u32, flags: u32) {
+        self.builder.report(size, count).input(flags);
+        if flags & CONSTANT == 0 {
+            self.fields.push((self.bits, size as usize, count as usize));
+        }
+        self.bits += (size * count) as usize;
+    }
+
+    /* Pads the report to whole bytes */
+    fn pad(&mut self) {
+        let padding = self.bits % 8;
+        if padding != 0 {
+            self.input(8 - padding as u32, 1, CONSTANT);
+        }
+    }
+
+    fn buttons(&mut self, count: u32) {
+        self.builder.usage_page(button::PAGE).usage_range(button::PRIMARY, count).logical(0, 1);
+        self.input(1, count, VARIABLE);
+        self.pad();
+    }
+
+    fn modifiers(&mut self) {
+        self.builder.usage_page(keyboard::PAGE).usage_range(keyboard::LEFT_CONTROL, keyboard::RIGHT_GUI).logical(0, 1);
+        self.input(1, 8, VARIABLE);
+    }
+
+    /* The output report of the keyboard LEDs, num lock to kana */
+    fn leds(&mut self) {
+        self.builder.usage_page(led::PAGE).usage_range(led::NUM_LOCK, 5)
+            .report(1, 5).output(VARIABLE)
+            .report(3, 1).output(CONSTANT);
+    }
+}
+
+/* The descriptor into out, returning its length, the fields and the bytes
+ * of the input report without the report-id */
+fn render(template: &Template, out: &mut [u8]) -> Option<(usize, Fields, usize)> {
+    let value = |name: &str| template.value(name) as u32;
+    let mut render = Render { builder: Builder::new(out), fields: Vec::new(), bits: 0 };
+    let (page, application) = match template.kind {
+        Kind::RelMouse | Kind::AbsMouse => (generic_desktop::PAGE, generic_desktop::MOUSE),
+        Kind::Kbd6kro | Kind::KbdNkro => (generic_desktop::PAGE, generic_desktop::KEYBOARD),
+        Kind::Gamepad => (generic_desktop::PAGE, generic_desktop::GAME_PAD),
+        Kind::Touchscreen => (digitizer::PAGE, digitizer::TOUCH_SCREEN),
+        Kind::Tablet => (digitizer::PAGE, digitizer::PEN),
+        Kind::Consumer => (consumer::PAGE, consumer::CONSUMER_CONTROL),
+        Kind::VendorRaw => (VENDOR_PAGES, 0x01),
+    };
+    render.builder.usage_page(page).usage(application).collection(APPLICATION);
+    if value("report-id") != 0 {
+        render.builder.report_id(value("report-id") as u8);
+    }
+    let (minimum, maximum) = template.range();
+
+    match template.kind {
+        Kind::RelMouse | Kind::AbsMouse | Kind::Gamepad => {
+            let mouse = template.kind != Kind::Gamepad;
+            if mouse {
+                render.builder.usage(generic_desktop::POINTER).collection(PHYSICAL);
+            }
+            render.buttons(value("buttons"));
+            render.builder.usage_page(generic_desktop::PAGE);
+            let axes: &[u32] = match template.kind {
+                Kind::RelMouse => &[generic_desktop::X, generic_desktop::Y, generic_desktop::WHEEL],
+                Kind::AbsMouse => &AXES[..2],
+                _ => &AXES[..value("axes") as usize],
+            };
+            for &axis in axes {
+                render.builder.usage(axis);
+            }
+            render.builder.logical(minimum as i32, maximum as i32);
+            let flags = if template.kind == Kind::RelMouse { VARIABLE | RELATIVE } else { VARIABLE };
+            render.input(value("resolution"), axes.len() as u32, flags);
+            render.pad();
+            if mouse {
+                render.builder.end_collection();
+            }
+        },
+        Kind::Kbd6kro => {
+            render.modifiers();
+            render.input(8, 1, CONSTANT);
+            render.builder.usage_range(0, MAX_KEY_USAGE).logical(0, MAX_KEY_USAGE as i32);
+            render.input(8, value("keys"), 0);
+            render.leds();
+        },
+        Kind::KbdNkro => {
+            render.modifiers();
+            render.builder.usage_range(0, value("keys") - 1);
+            render.input(1, value("keys"), VARIABLE);
+            render.pad();
+            render.leds();
+        },
+        Kind::Touchscreen => {
+            let contacts = value("contacts");
+            for _ in 0..contacts {
+                render.builder.usage(digitizer::FINGER).collection(LOGICAL)
+                    .usage(digitizer::TIP_SWITCH).logical(0, 1);
+                render.input(1, 1, VARIABLE);
+                render.pad();
+                render.builder.usage(digitizer::CONTACT_IDENTIFIER).logical(0, contacts as i32 - 1);
+                render.input(8, 1, VARIABLE);
+                render.builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::X).usage(generic_desktop::Y)
+                    .logical(0, maximum as i32);
+                render.input(value("resolution"), 2, VARIABLE);
+                render.pad();
+                render.builder.usage_page(digitizer::PAGE).end_collection();
+            }
+            render.builder.usage(digitizer::CONTACT_COUNT).logical(0, contacts as i32);
+            render.input(8, 1, VARIABLE);
+        },
+        Kind::Tablet => {
+            render.builder.usage(digitizer::STYLUS).collection(PHYSICAL).usage(digitizer::IN_RANGE);
+            let switches = &PEN_SWITCHES[..value("buttons") as usize];
+            for &switch in switches {
+                render.builder.usage(switch);
+            }
+            render.builder.logical(0, 1);
+            render.input(1, 1 + switches.len() as u32, VARIABLE);
+            render.pad();
+            render.builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::X).usage(generic_desktop::Y)
+                .logical(0, maximum as i32);
+            render.input(value("resolution"), 2, VARIABLE);
+            render.pad();
+            render.builder.usage_page(digitizer::PAGE).usage(digitizer::TIP_PRESSURE).logical(0, value("pressure") as i32);
+            render.input(16, 1, VARIABLE);
+            render.builder.end_collection();
+        },
+        Kind::Consumer => {
+            render.builder.usage_range(0, MAX_CONSUMER_USAGE).logical(0, MAX_CONSUMER_USAGE as i32);
+            render.input(16, value("keys"), 0);
+        },
+        Kind::VendorRaw => {
+            render.builder.usage(0x02).logical(0, 0xff);
+            render.input(8, value("size"), VARIABLE);
+            render.builder.usage(0x03).report(8, value("size")).output(VARIABLE);
+        },
+    }
+    render.builder.end_collection();
+    let length = render.builder.finish()?;
+    Some((length, render.fields, render.bits.div_ceil(8)))
+}
+
+/* A device of a rendered template */
+pub struct Templated {
+    template: Template,
+    descriptor: Vec<u8>,
+    fields: Fields,
+    /* bytes of the input report, without the report-id */
+    length: usize,
+    /* buttons or switches, a bit each */
+    pressed: u32,
+    /* usages of the keys or controls held, in the order pressed */
+    held: Vec<u32>,
+    /* where the absolute axes are */
+    positions: Vec<i64>,
+}
+
+impl Templated {
+    fn new(template: Template) -> Templated {
+        let mut out = [0u8; MAX_DESCRIPTOR_LEN];
+        let (size, fields, length) = render(&template, &mut out).expect("template descriptor too long");
+        let (minimum, maximum) = template.range();
+        let axes = match template.kind {
+            Kind::Gamepad => template.value("axes") as usize,
+            Kind::AbsMouse | Kind::Touchscreen | Kind::Tablet => 2,
+            _ => 0,
+        };
+        Templated {
+            descriptor: out[..size].to_vec(),
+            fields,
+            length,
+            pressed: 0,
+            held: Vec::new(),
+            positions: vec![(minimum + maximum) / 2; axes],
+            template,
+        }
+    }
+
+    /* The report of the values of each field, in order */
+    fn report(&self, values: &[Vec<i64>]) -> Vec<u8> {
+        let report_id = self.template.value("report-id") as u8;
+        let numbered = (report_id != 0) as usize;
+        let mut data = vec![0; numbered + self.length];
+        if numbered != 0 {
+            data[0] = report_id;
+        }
+        let report = &mut data[numbered..];
+        for (&(offset, size, count), values) in self.fields.iter().zip(values) {
+            for (index, &value) in values.iter().take(count).enumerate() {
+                bits::write(report, offset + index * size, size, value as u32);
+            }
+        }
+        data
+    }
+
+    fn pressed(&self, count: usize) -> Vec<i64> {
+        (0..count).map(|bit| (self.pressed >> bit & 1) as i64).collect()
+    }
+
+    /* The values of the fields, the relative axes moved by motion */
+    fn values(&self, motion: &[i64]) -> Vec<Vec<i64>> {
+        let value = |name: &str| self.template.value(name) as usize;
+        match self.template.kind {
+            Kind::RelMouse => vec![self.pressed(value("buttons")), motion.to_vec()],
+            Kind::AbsMouse | Kind::Gamepad => vec![self.pressed(value("buttons")), self.positions.clone()],
+            Kind::Kbd6kro | Kind::KbdNkro => {
+                let modifiers = (keyboard::LEFT_CONTROL..=keyboard::RIGHT_GUI).map(|usage| self.held.contains(&usage) as i64).collect();
+                let keys: Vec<u32> = self.held.iter().cloned().filter(|&usage| usage < keyboard::LEFT_CONTROL).collect();
+                let keys = if self.template.kind == Kind::KbdNkro {
+                    (0..value("keys") as u32).map(|usage| keys.contains(&usage) as i64).collect()
+                } else if keys.len() > value("keys") {
+                    vec![ERROR_ROLL_OVER; value("keys")]
+                } else {
+                    keys.iter().map(|&usage| usage as i64).collect()
+                };
+                vec![modifiers, keys]
+            },
+            Kind::Touchscreen => {
+                /* only the first contact touches */
+                let touching = (self.pressed & 1) as i64;
+                let mut values = vec![vec![touching], vec![0], self.positions.clone()];
+                for contact in 1..value("contacts") {
+                    values.extend(vec![vec![0], vec![contact as i64], vec![0, 0]]);
+                }
+                values.push(vec![touching]);
+                values
+            },
+            Kind::Tablet => {
+                let mut switches = vec![1];
+                switches.extend(self.pressed(value("buttons")));
+                let pressure = if self.pressed & 1 != 0 { self.template.value("pressure") / 2 } else { 0 };
+                vec![switches, self.positions.clone(), vec![pressure]]
+            },
+            Kind::Consumer => vec![self.held.iter().map(|&usage| usage as i64).collect()],
+            Kind::VendorRaw => vec![],
+        }
+    }
+
+    fn state(&self) -> Vec<u8> {
+        self.report(&self.values(&[0, 0, 0]))
+    }
+
+    /* Whether the report has room for the key or control */
+    fn holds(&self, usage: u32) -> bool {
+        match self.template.kind {
+            Kind::KbdNkro => usage >= keyboard::LEFT_CONTROL || usage < self.template.value("keys") as u32,
+            Kind::Consumer => self.held.len() < self.template.value("keys") as usize,
+            _ => true,
+        }
+    }
+
+    fn toggle_key(&mut self, usage: u32) -> Vec<Vec<u8>> {
+        if self.held.contains(&usage) {
+            self.held.retain(|&held| held != usage);
+        } else if self.holds(usage) {
+            self.held.push(usage);
+        } else {
+            return vec![];
+        }
+        vec![self.state()]
+    }
+
+    /* Presses and releases a key or control */
+    fn tap(&mut self, usage: u32) -> Vec<Vec<u8>> {
+        if self.held.contains(&usage) {
+            return vec![];
+        }
+        let mut reports = self.toggle_key(usage);
+        if !reports.is_empty() {
+            reports.extend(self.toggle_key(usage));
+        }
+        reports
+    }
+
+    fn toggle_button(&mut self, button: u8) -> Vec<Vec<u8>> {
+        let buttons = match self.template.kind {
+            Kind::Touchscreen => 1,
+            _ => self.template.value("buttons"),
+        };
+        if button as i64 > buttons {
+            return vec![];
+        }
+        self.pressed ^= 1 << (button - 1);
+        vec![self.state()]
+    }
+
+    fn clamp(&self, value: i64) -> i64 {
+        let (minimum, maximum) = self.template.range();
+        value.clamp(minimum, maximum)
+    }
+
+    fn move_axes(&mut self, dx: i8, dy: i8) -> Vec<Vec<u8>> {
+        /* a step moves as far on any resolution */
+        let (minimum, maximum) = self.template.range();
+        let scale = (maximum - minimum) / 256 + 1;
+        for (index, &delta) in [dx, dy].iter().enumerate().take(self.positions.len()) {
+            self.positions[index] = self.clamp(self.positions[index] + delta as i64 * scale);
+        }
+        vec![self.state()]
+    }
+}
+
+impl Device for Templated {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_VIRTUAL as u16,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        let typing = self.template.kind == Kind::Kbd6kro || self.template.kind == Kind::KbdNkro;
+        match (self.template.kind, action) {
+            (Kind::VendorRaw, Action::ToggleButton(button)) => {
+                let values = vec![vec![button as i64; self.template.value("size") as usize]];
+                vec![self.report(&values)]
+            },
+            (_, Action::ToggleButton(0)) => vec![],
+            (_, Action::ToggleButton(button)) if typing => self.toggle_key(keyboard::A + button as u32 - 1),
+            (Kind::Consumer, Action::ToggleButton(button)) => match MEDIA_KEYS.get(button as usize - 1) {
+                Some(&usage) => self.toggle_key(usage),
+                None => vec![],
+            },
+            (_, Action::ToggleButton(button)) => self.toggle_button(button),
+            (Kind::RelMouse, Action::Move(dx, dy)) => vec![self.report(&self.values(&[self.clamp(dx as i64), self.clamp(dy as i64), 0]))],
+            (Kind::RelMouse, Action::Wheel(delta)) => vec![self.report(&self.values(&[0, 0, self.clamp(delta as i64)]))],
+            (_, Action::Move(dx, dy)) if typing => {
+                let mut reports = Vec::new();
+                if dx != 0 {
+                    reports.extend(self.tap(if dx > 0 { keyboard::RIGHT } else { keyboard::LEFT }));
+                }
+                if dy != 0 {
+                    reports.extend(self.tap(if dy > 0 { keyboard::DOWN } else { keyboard::UP }));
+                }
+                reports
+            },
+            (_, Action::Wheel(0)) => vec![],
+            (_, Action::Wheel(delta)) if typing => self.tap(if delta > 0 { keyboard::PAGE_UP } else { keyboard::PAGE_DOWN }),
+            (Kind::Consumer, Action::Move(0, _)) => vec![],
+            (Kind::Consumer, Action::Move(dx, _)) => self.tap(if dx > 0 { consumer::SCAN_NEXT_TRACK } else { consumer::SCAN_PREVIOUS_TRACK }),
+            (Kind::Consumer, Action::Wheel(delta)) => self.tap(if delta > 0 { consumer::VOLUME_INCREMENT } else { consumer::VOLUME_DECREMENT }),
+            (Kind::VendorRaw, _) => vec![],
+            (_, Action::Move(dx, dy)) => self.move_axes(dx, dy),
+            (_, Action::Wheel(_)) => vec![],
+        }
+    }
+}
+
+fn item_name(item_type: ItemType, tag: u8) -> &'static str {
+    match (item_type, tag) {
+        (ItemType::Main, descriptor::INPUT) => "Input",
+        (ItemType::Main, descriptor::OUTPUT) => "Output",
+        (ItemType::Main, descriptor::FEATURE) => "Feature",
+        (ItemType::Main, descriptor::COLLECTION) => "Collection",
+        (ItemType::Main, descriptor::END_COLLECTION) => "End Collection",
+        (ItemType::Global, descriptor::USAGE_PAGE) => "Usage Page",
+        (ItemType::Global, descriptor::LOGICAL_MINIMUM) => "Logical Minimum",
+        (ItemType::Global, descriptor::LOGICAL_MAXIMUM) => "Logical Maximum",
+        (ItemType::Global, descriptor::REPORT_SIZE) => "Report Size",
+        (ItemType::Global, descriptor::REPORT_ID) => "Report ID",
+        (ItemType::Global, descriptor::REPORT_COUNT) => "Report Count",
+        (ItemType::Local, descriptor::USAGE) => "Usage",
+        (ItemType::Local, descriptor::USAGE_MINIMUM) => "Usage Minimum",
+        (ItemType::Local, descriptor::USAGE_MAXIMUM) => "Usage Maximum",
+        _ => "Item",
+    }
+}
+
+/* What templates show prints: the variables of the template, and the
+ * descriptor of their defaults an item a line */
+pub fn describe(kind: Kind) -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "{}: {}", kind.name(), kind.about());
+    let _ = writeln!(text, "Variables, their range and default:");
+    for variable in kind.variables() {
+        let range = if variable.minimum == MINIMUM { String::from("any") } else { format!("{}..{}", variable.minimum, variable.maximum) };
+        let _ = writeln!(text, "  {:<11} {:<9} {:>6}  {}", variable.name, range, variable.default, variable.about);
+    }
+
+    let device = Template::new(kind).device();
+    let _ = writeln!(text, "Descriptor, {} bytes:", device.descriptor.len());
+    let mut page = 0;
+    for (offset, item) in descriptor::items(&device.descriptor).map_while(Result::ok) {
+        let bytes: Vec<String> = device.descriptor[offset..offset + 1 + item.size as usize].iter().map(|byte| format!("{:02x}", byte)).collect();
+        let data = match (item.item_type, item.tag) {
+            (ItemType::Global, descriptor::USAGE_PAGE) => {
+                page = item.data;
+                usage::page_name(page).map_or_else(|| format!("0x{:02x}", page), String::from)
+            },
+            (ItemType::Local, _) => usage::name(usage::extended(page, item.data)).map_or_else(|| format!("0x{:02x}", item.data), String::from),
+            (ItemType::Global, descriptor::LOGICAL_MINIMUM) | (ItemType::Global, descriptor::LOGICAL_MAXIMUM) => item.signed_data().to_string(),
+            (ItemType::Main, descriptor::END_COLLECTION) => String::new(),
+            (ItemType::Main, _) => format!("0x{:02x}", item.data),
+            _ => item.data.to_string(),
+        };
+        let name = item_name(item.item_type, item.tag);
+        if data.is_empty() {
+            let _ = writeln!(text, "  {:<16} {}", bytes.join(" "), name);
+        } else {
+            let _ = writeln!(text, "  {:<16} {} ({})", bytes.join(" "), name, data);
+        }
+    }
+    text
+}
@@ -0,0 +1,360 @@
+/*
+ * Ten finger touchscreen
+ * A multitouch digitizer in the format hid-multitouch and Windows expect:
+ * one logical Finger collection per contact slot, followed by Scan Time and
+ * Contact Count. Every frame reports all contacts touching the surface plus
+ * those lifted since the previous frame, the latter with Tip Switch cleared.
+ *
+ * Report 0x01 (64 bytes):
+ *   bytes 1..60   ten contacts of 6 bytes each: Tip Switch in bit 0, Contact
+ *                 Identifier, 16 bit X and Y in 0..4095
+ *   byte 61, 62   scan time in 100us units, wrapping
+ *   byte 63       number of valid contacts in this report
+ *
+ * Feature report 0x02 carries Contact Count Maximum (10), which
+ * hid-multitouch reads while probing.
+ *
+ * Frames are built with TouchFrame, which checks that every contact goes
+ * through down, move and up in order before anything is reported.
+ *
+ * Keys map onto the touchscreen as follows:
+ *   1..5: Place or lift fingers one to five, side by side
+ *   a/d/w/s: Drag all fingers touching the screen
+ *   r/f: Spread the fingers apart or pinch them together
+ */
+
+use super::{Action, Device, Identity};
+use std::io;
+use std::time::Instant;
+use uhid::{uhid_report_type, BUS_USB};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+const HEADER_RDESC: [u8; 8] = [
+    0x05, 0x0d,	/* USAGE_PAGE (Digitizers) */
+    0x09, 0x04,	/* USAGE (Touch Screen) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x85, 0x01,		/* REPORT_ID (1) */
+];
+
+/* Repeated once per contact slot */
+const FINGER_RDESC: [u8; 60] = [
+    0x05, 0x0d,		/* USAGE_PAGE (Digitizers) */
+    0x09, 0x22,		/* USAGE (Finger) */
+    0xa1, 0x02,		/* COLLECTION (Logical) */
+    0x09, 0x42,			/* USAGE (Tip Switch) */
+    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
+    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,			/* REPORT_SIZE (1) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x95, 0x07,			/* REPORT_COUNT (7) */
+    0x81, 0x03,			/* INPUT (Cnst,Var,Abs) */
+    0x09, 0x51,			/* USAGE (Contact Identifier) */
+    0x25, 0x7f,			/* LOGICAL_MAXIMUM (127) */
+    0x75, 0x08,			/* REPORT_SIZE (8) */
+    0x95, 0x01,			/* REPORT_COUNT (1) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
+    0x26, 0xff, 0x0f,		/* LOGICAL_MAXIMUM (4095) */
+    0x75, 0x10,			/* REPORT_SIZE (16) */
+    0x55, 0x0e,			/* UNIT_EXPONENT (-2) */
+    0x65, 0x11,			/* UNIT (Centimeter) */
+    0x35, 0x00,			/* PHYSICAL_MINIMUM (0) */
+    0x46, 0xa2, 0x08,		/* PHYSICAL_MAXIMUM (2210) */
+    0x09, 0x30,			/* USAGE (X) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0x46, 0xd8, 0x04,		/* PHYSICAL_MAXIMUM (1240) */
+    0x09, 0x31,			/* USAGE (Y) */
+    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+const TRAILER_RDESC: [u8; 43] = [
+    0x05, 0x0d,		/* USAGE_PAGE (Digitizers) */
+    0x45, 0x00,		/* PHYSICAL_MAXIMUM (0) */
+    0x55, 0x0c,		/* UNIT_EXPONENT (-4) */
+    0x66, 0x01, 0x10,	/* UNIT (Seconds) */
+    0x09, 0x56,		/* USAGE (Scan Time) */
+    0x27, 0xff, 0xff, 0x00, 0x00,	/* LOGICAL_MAXIMUM (65535) */
+    0x75, 0x10,		/* REPORT_SIZE (16) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x55, 0x00,		/* UNIT_EXPONENT (0) */
+    0x65, 0x00,		/* UNIT (None) */
+    0x09, 0x54,		/* USAGE (Contact Count) */
+    0x25, 0x7f,		/* LOGICAL_MAXIMUM (127) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x85, 0x02,		/* REPORT_ID (2) */
+    0x09, 0x55,		/* USAGE (Contact Count Maximum) */
+    0x25, 0x0a,		/* LOGICAL_MAXIMUM (10) */
+    0xb1, 0x02,		/* FEATURE (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+pub const MAX_CONTACTS: usize = 10;
+pub const MAX_X: u16 = 4095;
+pub const MAX_Y: u16 = 4095;
+
+const CONTACT_SIZE: usize = 6;
+const REPORT_SIZE: usize = 1 + MAX_CONTACTS * CONTACT_SIZE + 3;
+
+/* Keyboard steps scaled onto the touchscreen range */
+const MOVE_SCALE: i32 = 10;
+const SPREAD_STEP: i32 = 40;
+const FINGERS: u8 = 5;
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+enum Change {
+    Down(u8, u16, u16),
+    Move(u8, u16, u16),
+    Up(u8),
+}
+
+impl Change {
+    fn id(&self) -> u8 {
+        match *self {
+            Change::Down(id, _, _) | Change::Move(id, _, _) | Change::Up(id) => id,
+        }
+    }
+}
+
+/*
+ * The contacts that changed between two reports. Contacts touching the screen
+ * that are not mentioned keep their position.
+ */
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TouchFrame {
+    changes: Vec<Change>,
+}
+
+impl TouchFrame {
+    pub fn new() -> TouchFrame {
+        TouchFrame::default()
+    }
+
+    pub fn down(mut self, id: u8, x: u16, y: u16) -> TouchFrame {
+        self.changes.push(Change::Down(id, x, y));
+        self
+    }
+
+    pub fn move_to(mut self, id: u8, x: u16, y: u16) -> TouchFrame {
+        self.changes.push(Change::Move(id, x, y));
+        self
+    }
+
+    pub fn up(mut self, id: u8) -> TouchFrame {
+        self.changes.push(Change::Up(id));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Contact {
+    id: u8,
+    x: u16,
+    y: u16,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+pub struct Touchscreen {
+    descriptor: Vec<u8>,
+    start: Instant,
+    contacts: Vec<Contact>,
+    /* Interactive state: where the fingers are placed and how far apart */
+    cursor: (i32, i32),
+    spread: i32,
+}
+
+impl Default for Touchscreen {
+    fn default() -> Touchscreen {
+        let mut descriptor = HEADER_RDESC.to_vec();
+        for _ in 0..MAX_CONTACTS {
+            descriptor.extend_from_slice(&FINGER_RDESC);
+        }
+        descriptor.extend_from_slice(&TRAILER_RDESC);
+
+        Touchscreen {
+            descriptor,
+            start: Instant::now(),
+            contacts: Vec::new(),
+            cursor: (MAX_X as i32 / 2, MAX_Y as i32 / 2),
+            spread: 200,
+        }
+    }
+}
+
+impl Touchscreen {
+    /* Scan time relative to device creation, in 100us units */
+    fn scan_time(&self) -> u16 {
+        let elapsed = self.start.elapsed();
+        let ticks = elapsed.as_secs() * 10_000 + elapsed.subsec_micros() as u64 / 100;
+        ticks as u16
+    }
+
+    fn contact(&self, id: u8) -> Option<usize> {
+        self.contacts.iter().position(|contact| contact.id == id)
+    }
+
+    /* Checks the frame against the contacts currently down */
+    fn validate(&self, frame: &TouchFrame) -> io::Result<()> {
+        let mut seen = Vec::new();
+        let mut down = self.contacts.len();
+
+        for change in frame.changes.iter() {
+            let id = change.id();
+            if id > 0x7f {
+                return Err(invalid(format!("Contact {} exceeds the identifier range", id)));
+            }
+            if seen.contains(&id) {
+                return Err(invalid(format!("Contact {} changes twice in one frame", id)));
+            }
+            seen.push(id);
+
+            match *change {
+                Change::Down(_, x, y) | Change::Move(_, x, y) if x > MAX_X || y > MAX_Y => {
+                    return Err(invalid(format!("Contact {} is outside the screen", id)));
+                },
+                Change::Down(..) if self.contact(id).is_some() => {
+                    return Err(invalid(format!("Contact {} is already down", id)));
+                },
+                Change::Down(..) => {
+                    down += 1;
+                    if down > MAX_CONTACTS {
+                        return Err(invalid(format!("More than {} contacts down", MAX_CONTACTS)));
+                    }
+                },
+                Change::Move(..) | Change::Up(_) if self.contact(id).is_none() => {
+                    return Err(invalid(format!("Contact {} is not down", id)));
+                },
+                Change::Move(..) | Change::Up(_) => {},
+            }
+        }
+        Ok(())
+    }
+
+    /* Applies a frame and returns the report describing it */
+    pub fn frame(&mut self, frame: &TouchFrame) -> io::Result<Vec<u8>> {
+        self.validate(frame)?;
+
+        let mut lifted = Vec::new();
+        for change in frame.changes.iter() {
+            match *change {
+                Change::Down(id, x, y) => self.contacts.push(Contact { id, x, y }),
+                Change::Move(id, x, y) => {
+                    let index = self.contact(id).unwrap();
+                    self.contacts[index].x = x;
+                    self.contacts[index].y = y;
+                },
+                Change::Up(id) => {
+                    let index = self.contact(id).unwrap();
+                    lifted.push(self.contacts.remove(index));
+                },
+            }
+        }
+
+        let mut data = vec![0u8; REPORT_SIZE];
+        data[0] = 0x01;
+
+        let slots = self.contacts.iter().map(|contact| (contact, true))
+            .chain(lifted.iter().map(|contact| (contact, false)));
+        let mut count = 0;
+        for (slot, (contact, touching)) in data[1..].chunks_mut(CONTACT_SIZE).zip(slots) {
+            slot[0] = touching as u8;
+            slot[1] = contact.id;
+            slot[2..4].copy_from_slice(&contact.x.to_le_bytes());
+            slot[4..6].copy_from_slice(&contact.y.to_le_bytes());
+            count += 1;
+        }
+
+        let trailer = 1 + MAX_CONTACTS * CONTACT_SIZE;
+        data[trailer..trailer + 2].copy_from_slice(&self.scan_time().to_le_bytes());
+        data[trailer + 2] = count;
+        Ok(data)
+    }
+
+    fn finger_position(&self, finger: u8) -> (u16, u16) {
+        let offset = (finger as i32 - (FINGERS as i32 - 1) / 2) * self.spread;
+        let x = (self.cursor.0 + offset).clamp(0, MAX_X as i32);
+        (x as u16, self.cursor.1 as u16)
+    }
+
+    /* Moves every finger touching the screen to its current position */
+    fn follow_fingers(&self) -> TouchFrame {
+        (0..FINGERS)
+            .filter(|finger| self.contact(*finger).is_some())
+            .fold(TouchFrame::new(), |frame, finger| {
+                let (x, y) = self.finger_position(finger);
+                frame.move_to(finger, x, y)
+            })
+    }
+}
+
+impl Device for Touchscreen {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x15d9,
+            product: 0x0a39,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        let frame = match action {
+            Action::ToggleButton(n) if (1..=FINGERS).contains(&n) => {
+                let finger = n - 1;
+                if self.contact(finger).is_some() {
+                    TouchFrame::new().up(finger)
+                } else {
+                    let (x, y) = self.finger_position(finger);
+                    TouchFrame::new().down(finger, x, y)
+                }
+            },
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, dy) => {
+                self.cursor.0 = (self.cursor.0 + dx as i32 * MOVE_SCALE).clamp(0, MAX_X as i32);
+                self.cursor.1 = (self.cursor.1 + dy as i32 * MOVE_SCALE).clamp(0, MAX_Y as i32);
+                self.follow_fingers()
+            },
+            Action::Wheel(delta) => {
+                self.spread = (self.spread + delta as i32 * SPREAD_STEP).clamp(0, MAX_X as i32 / 4);
+                self.follow_fingers()
+            },
+        };
+
+        if frame.is_empty() {
+            return vec![];
+        }
+        match self.frame(&frame) {
+            Ok(report) => vec![report],
+            Err(err) => {
+                eprintln!("Invalid touch frame: {}", err);
+                vec![]
+            },
+        }
+    }
+
+    fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+        if rtype != uhid_report_type::UHID_FEATURE_REPORT as u8 || rnum != 0x02 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Unknown report"));
+        }
+
+        Ok(vec![0x02, MAX_CONTACTS as u8])
+    }
+}
@@ -0,0 +1,212 @@
+/*
+ * Xbox 360 controller
+ * This preset announces itself as the wired 360 pad (045e:028e) and uses the
+ * 20 byte input packet of the real controller as its unnumbered input report,
+ * so userspace matching on the ids and tools reading the packet layout can be
+ * tested. The real pad is driven by xpad at the USB level, here hid-generic
+ * binds to the descriptor below instead.
+ *
+ *   byte 0, 1     message type (0x00) and length (0x14)
+ *   byte 2        d-pad up, down, left, right, start, back, left/right thumb
+ *   byte 3        LB, RB, guide, -, A, B, X, Y
+ *   byte 4, 5     left and right trigger, 0..255
+ *   bytes 6..13   left X/Y and right X/Y sticks, signed 16 bit, Y up is positive
+ *   bytes 14..19  unused
+ *
+ * The host drives rumble and the ring LEDs with the 8 byte output packets
+ *   00 08 00 <large motor> <small motor> 00 00 00
+ *   01 03 <led pattern>
+ * Rumble requests are handed to the callback installed with set_rumble,
+ * which by default prints the motor speeds.
+ *
+ * Keys map onto the pad as follows:
+ *   1/2/3: Toggle A/B/X
+ *   a/d/w/s: Push the left stick
+ *   r/f: Press/release the right trigger
+ */
+
+use super::{Action, Device, Identity};
+use uhid::{uhid_report_type, BUS_USB};
+
+const RDESC: [u8; 125] = [
+    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x05,	/* USAGE (Game Pad) */
+    0xa1, 0x01,	/* COLLECTION (Application) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x95, 0x02,		/* REPORT_COUNT (2) */
+    0x81, 0x01,		/* INPUT (Cnst,Ary,Abs) */
+    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
+    0x75, 0x01,		/* REPORT_SIZE (1) */
+    0x95, 0x04,		/* REPORT_COUNT (4) */
+    0x09, 0x90,		/* USAGE (D-pad Up) */
+    0x09, 0x91,		/* USAGE (D-pad Down) */
+    0x09, 0x93,		/* USAGE (D-pad Left) */
+    0x09, 0x92,		/* USAGE (D-pad Right) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x05, 0x09,		/* USAGE_PAGE (Button) */
+    0x09, 0x0c,		/* USAGE (Button 12, start) */
+    0x09, 0x0b,		/* USAGE (Button 11, back) */
+    0x09, 0x0e,		/* USAGE (Button 14, left thumb) */
+    0x09, 0x0f,		/* USAGE (Button 15, right thumb) */
+    0x09, 0x07,		/* USAGE (Button 7, LB) */
+    0x09, 0x08,		/* USAGE (Button 8, RB) */
+    0x09, 0x0d,		/* USAGE (Button 13, guide) */
+    0x95, 0x07,		/* REPORT_COUNT (7) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x95, 0x01,		/* REPORT_COUNT (1) */
+    0x81, 0x01,		/* INPUT (Cnst,Ary,Abs) */
+    0x09, 0x01,		/* USAGE (Button 1, A) */
+    0x09, 0x02,		/* USAGE (Button 2, B) */
+    0x09, 0x04,		/* USAGE (Button 4, X) */
+    0x09, 0x05,		/* USAGE (Button 5, Y) */
+    0x95, 0x04,		/* REPORT_COUNT (4) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x05, 0x01,		/* USAGE_PAGE (Generic Desktop) */
+    0x09, 0x32,		/* USAGE (Z) */
+    0x09, 0x35,		/* USAGE (Rz) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x95, 0x02,		/* REPORT_COUNT (2) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x09, 0x30,		/* USAGE (X) */
+    0x09, 0x31,		/* USAGE (Y) */
+    0x09, 0x33,		/* USAGE (Rx) */
+    0x09, 0x34,		/* USAGE (Ry) */
+    0x16, 0x00, 0x80,	/* LOGICAL_MINIMUM (-32768) */
+    0x26, 0xff, 0x7f,	/* LOGICAL_MAXIMUM (32767) */
+    0x75, 0x10,		/* REPORT_SIZE (16) */
+    0x95, 0x04,		/* REPORT_COUNT (4) */
+    0x81, 0x02,		/* INPUT (Data,Var,Abs) */
+    0x75, 0x08,		/* REPORT_SIZE (8) */
+    0x95, 0x06,		/* REPORT_COUNT (6) */
+    0x81, 0x01,		/* INPUT (Cnst,Ary,Abs) */
+    0x06, 0x00, 0xff,	/* USAGE_PAGE (Vendor Defined 0xff00) */
+    0x09, 0x01,		/* USAGE (Vendor Usage 1) */
+    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
+    0x26, 0xff, 0x00,	/* LOGICAL_MAXIMUM (255) */
+    0x95, 0x08,		/* REPORT_COUNT (8) */
+    0x91, 0x02,		/* OUTPUT (Data,Var,Abs) */
+    0xc0,		/* END_COLLECTION */
+];
+
+const BTN_A: u16 = 1 << 12;
+const BTN_B: u16 = 1 << 13;
+const BTN_X: u16 = 1 << 14;
+
+const STICK_STEP: i32 = 1600;
+const TRIGGER_STEP: i32 = 64;
+
+pub struct Xbox360Pad {
+    buttons: u16,
+    left_trigger: u8,
+    right_trigger: u8,
+    left_stick: (i16, i16),
+    right_stick: (i16, i16),
+    /* Force feedback callback, called with the large and small motor speed */
+    rumble: Box<dyn FnMut(u8, u8)>,
+}
+
+impl Default for Xbox360Pad {
+    fn default() -> Xbox360Pad {
+        Xbox360Pad {
+            buttons: 0,
+            left_trigger: 0,
+            right_trigger: 0,
+            left_stick: (0, 0),
+            right_stick: (0, 0),
+            rumble: Box::new(|large, small| eprintln!("Rumble large motor {} small motor {}", large, small)),
+        }
+    }
+}
+
+impl Xbox360Pad {
+    /* Installs the force feedback callback, called with the large and small
+     * motor speed of every rumble request */
+    pub fn set_rumble<F: FnMut(u8, u8) + 'static>(&mut self, f: F) {
+        self.rumble = Box::new(f);
+    }
+
+    fn report(&self) -> Vec<u8> {
+        let mut data = vec![0u8; 20];
+
+        data[1] = 0x14;
+        data[2..4].copy_from_slice(&self.buttons.to_le_bytes());
+        data[4] = self.left_trigger;
+        data[5] = self.right_trigger;
+        data[6..8].copy_from_slice(&self.left_stick.0.to_le_bytes());
+        data[8..10].copy_from_slice(&self.left_stick.1.to_le_bytes());
+        data[10..12].copy_from_slice(&self.right_stick.0.to_le_bytes());
+        data[12..14].copy_from_slice(&self.right_stick.1.to_le_bytes());
+        data
+    }
+}
+
+fn push_axis(value: i16, delta: i32) -> i16 {
+    (value as i32 + delta).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+impl Device for Xbox360Pad {
+    fn descriptor(&self) -> &[u8] {
+        &RDESC
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: 0x045e,
+            product: 0x028e,
+            version: 0x0114,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+        match action {
+            Action::ToggleButton(1) => self.buttons ^= BTN_A,
+            Action::ToggleButton(2) => self.buttons ^= BTN_B,
+            Action::ToggleButton(3) => self.buttons ^= BTN_X,
+            Action::ToggleButton(_) => return vec![],
+            Action::Move(dx, dy) => {
+                self.left_stick.0 = push_axis(self.left_stick.0, dx as i32 * STICK_STEP);
+                /* the pad reports up as positive Y */
+                self.left_stick.1 = push_axis(self.left_stick.1, -(dy as i32) * STICK_STEP);
+            },
+            Action::Wheel(delta) => {
+                self.right_trigger = (self.right_trigger as i32 + delta as i32 * TRIGGER_STEP).clamp(0, 255) as u8;
+            },
+        }
+
+        vec![self.report()]
+    }
+
+    fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
+            return;
+        }
+        /* unnumbered reports are prefixed with a zero report-id */
+        let packet = match data.split_first() {
+            Some((&0, packet)) => packet,
+            _ => return,
+        };
+
+        match packet {
+            [0x00, 0x08, _, large, small, ..] => (self.rumble)(*large, *small),
+            [0x01, 0x03, pattern, ..] => eprintln!("LED pattern {} requested", pattern),
+            _ => eprintln!("Unknown output packet {:?}", packet),
+        }
+    }
+
+    fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+        if rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
+            return vec![];
+        }
+
+        match data {
+            [0, 0x00, 0x08, _, large, small, ..] => vec![("rumble_large", *large as i64), ("rumble_small", *small as i64)],
+            [0, 0x01, 0x03, pattern, ..] => vec![("led_pattern", *pattern as i64)],
+            _ => vec![],
+        }
+    }
+}
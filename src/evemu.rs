@@ -0,0 +1,679 @@
+/*
+ * evemu descriptions
+ * Dumps an evdev device in the format of evemu-describe, so the devices the
+ * kernel makes of a descriptor can be recreated with evemu-device or used as
+ * test assets, e.g. by libinput's test suite, without uhid:
+ *
+ *   N: <name>
+ *   I: <bus> <vendor> <product> <version>
+ *   P: <input properties, 8 bytes per line>
+ *   B: <event type> <event code bits, 8 bytes per line>
+ *   A: <axis> <minimum> <maximum> <fuzz> <flat> <resolution>
+ *
+ * Lines starting with # describe the device for humans and are ignored when
+ * the description is read.
+ *
+ * evemu-record adds the events after the description, one per line as
+ *
+ *   E: <seconds>.<microseconds> <type> <code> <value>
+ *
+ * Such recordings are replayed by making up a report descriptor hid-input
+ * turns into the same capabilities, within what HID can express: keyboard
+ * keys, up to 16 mouse buttons and the first nine axes, X to Wheel, either
+ * relative or absolute. Each SYN_REPORT becomes the reports of what changed
+ * in that frame. Scan codes, LEDs and key repeat come from the kernel, and
+ * axis resolution, fuzz and flat as well as the input properties are not
+ * reproduced. Recordings of anything else, like touchpads with their
+ * multitouch slots, are refused.
+ *
+ * The same translation works on live devices, see src/merge.rs, where what
+ * cannot be reproduced is left out instead.
+ */
+
+use hid_report::bits;
+use hid_report::descriptor::{Builder, APPLICATION, CONSTANT, PHYSICAL, RELATIVE, VARIABLE};
+use hid_report::usage::{button, generic_desktop, keyboard};
+use libc;
+use recording::Capture;
+use remap::{Remap, Target};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+use uhid::{Identity, BUS_USB};
+
+const EV_SYN: usize = 0x00;
+const EV_KEY: usize = 0x01;
+const EV_REL: usize = 0x02;
+const EV_ABS: usize = 0x03;
+const EV_MSC: usize = 0x04;
+const EV_LED: usize = 0x11;
+const EV_SND: usize = 0x12;
+const EV_REP: usize = 0x14;
+const EV_CNT: usize = 0x20;
+const INPUT_PROP_CNT: usize = 0x20;
+
+/* Number of codes of every event type, from <linux/input-event-codes.h> */
+const CODES: [usize; EV_CNT] = [
+    0x10,  /* EV_SYN */
+    0x300, /* EV_KEY */
+    0x10,  /* EV_REL */
+    0x40,  /* EV_ABS */
+    0x08,  /* EV_MSC */
+    0x11,  /* EV_SW */
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0x10,  /* EV_LED */
+    0x08,  /* EV_SND */
+    0,
+    0x02,  /* EV_REP */
+    0x80,  /* EV_FF */
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+const TYPE_NAMES: [(usize, &str); 11] = [
+    (0x00, "EV_SYN"), (0x01, "EV_KEY"), (0x02, "EV_REL"), (0x03, "EV_ABS"), (0x04, "EV_MSC"), (0x05, "EV_SW"),
+    (0x11, "EV_LED"), (0x12, "EV_SND"), (0x14, "EV_REP"), (0x15, "EV_FF"), (0x16, "EV_PWR"),
+];
+
+#[repr(C)]
+#[derive(Default)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct InputAbsinfo {
+    value: i32,
+    minimum: i32,
+    maximum: i32,
+    fuzz: i32,
+    flat: i32,
+    resolution: i32,
+}
+
+/* _IOC(_IOC_READ, 'E', nr, size) */
+fn eviocg(nr: usize, size: usize) -> libc::c_ulong {
+    ((2 << 30) | (size << 16) | ((b'E' as usize) << 8) | nr) as libc::c_ulong
+}
+
+fn ioctl<T>(file: &File, request: libc::c_ulong, data: *mut T) -> io::Result<()> {
+    if unsafe { libc::ioctl(file.as_raw_fd(), request as _, data) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn bits(file: &File, nr: usize, count: usize) -> io::Result<Vec<u8>> {
+    /* whole lines of 8 bytes, like evemu writes them */
+    let mut mask = vec![0u8; count.div_ceil(8).div_ceil(8) * 8];
+    ioctl(file, eviocg(nr, mask.len()), mask.as_mut_ptr())?;
+    Ok(mask)
+}
+
+fn has(mask: &[u8], bit: usize) -> bool {
+    mask.get(bit / 8).is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+fn hex_lines<W: Write>(output: &mut W, prefix: &str, mask: &[u8]) -> io::Result<()> {
+    for line in mask.chunks(8) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{:02x}", byte)).collect();
+        writeln!(output, "{}{}", prefix, bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+fn kernel_release() -> String {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return String::from("unknown");
+    }
+    unsafe { CStr::from_ptr(name.release.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+/* Writes the description of the event node, e.g. /dev/input/event5 */
+pub fn describe<W: Write>(node: &Path, mut output: W) -> io::Result<()> {
+    let file = File::open(node)?;
+
+    let mut name = [0u8; 256];
+    ioctl(&file, eviocg(0x06, name.len()), name.as_mut_ptr())?;
+    let name = CStr::from_bytes_until_nul(&name).map_err(|_| io::Error::other("Device name not terminated"))?;
+    let name = name.to_string_lossy();
+
+    let mut id = InputId::default();
+    ioctl(&file, eviocg(0x02, std::mem::size_of::<InputId>()), &mut id)?;
+
+    let properties = bits(&file, 0x09, INPUT_PROP_CNT)?;
+    let types = bits(&file, 0x20, EV_CNT)?;
+    let mut masks = Vec::new();
+    for (event_type, &count) in CODES.iter().enumerate() {
+        masks.push(match count {
+            0 => vec![],
+            /* EV_SYN asks for the event types */
+            _ if event_type == 0 => types.clone(),
+            _ if has(&types, event_type) => bits(&file, 0x20 + event_type, count)?,
+            _ => vec![0u8; count.div_ceil(8).div_ceil(8) * 8],
+        });
+    }
+
+    writeln!(output, "# EVEMU 1.3")?;
+    writeln!(output, "# Kernel: {}", kernel_release())?;
+    writeln!(output, "# Input device name: \"{}\"", name)?;
+    writeln!(output, "# Input device ID: bus {:#04x} vendor {:#04x} product {:#04x} version {:#04x}",
+             id.bustype, id.vendor, id.product, id.version)?;
+    writeln!(output, "# Supported events:")?;
+    for &(event_type, type_name) in TYPE_NAMES.iter().filter(|&&(event_type, _)| has(&types, event_type)) {
+        writeln!(output, "#   Event type {} ({})", event_type, type_name)?;
+        let codes = masks.get(event_type).map_or(0, |mask| mask.len() * 8);
+        for code in (0..codes).filter(|&code| has(&masks[event_type], code)) {
+            writeln!(output, "#     Event code {}", code)?;
+        }
+    }
+
+    writeln!(output, "N: {}", name)?;
+    writeln!(output, "I: {:04x} {:04x} {:04x} {:04x}", id.bustype, id.vendor, id.product, id.version)?;
+    hex_lines(&mut output, "P: ", &properties)?;
+    for (event_type, mask) in masks.iter().enumerate().filter(|(_, mask)| !mask.is_empty()) {
+        hex_lines(&mut output, &format!("B: {:02x} ", event_type), mask)?;
+    }
+    for axis in (0..CODES[EV_ABS]).filter(|&axis| has(&masks[EV_ABS], axis)) {
+        let mut info = InputAbsinfo::default();
+        ioctl(&file, eviocg(0x40 + axis, std::mem::size_of::<InputAbsinfo>()), &mut info)?;
+        writeln!(output, "A: {:02x} {} {} {} {} {}", axis, info.minimum, info.maximum, info.fuzz, info.flat, info.resolution)?;
+    }
+    output.flush()
+}
+
+const SYN_REPORT: u16 = 0x00;
+const BTN_MOUSE: u16 = 0x110;
+const MAX_BUTTONS: u16 = 16;
+/* X, Y, Z, Rx, Ry, Rz, Slider, Dial and Wheel, codes of both kinds of axes */
+const MAX_AXIS: u16 = 0x08;
+/* High resolution wheels are added by hid-input along with the wheels */
+const REL_WHEEL_HI_RES: u16 = 0x0b;
+const REL_HWHEEL_HI_RES: u16 = 0x0c;
+const KEY_UNKNOWN: u8 = 240;
+const MAX_DESCRIPTOR_LEN: usize = 4096;
+
+const REPORT_KEYBOARD: u8 = 1;
+const REPORT_POINTER: u8 = 2;
+
+/* evdev key codes of the keyboard page usages, as mapped by hid-input */
+const HID_KEYBOARD: [u8; 256] = [
+      0,   0,   0,   0,  30,  48,  46,  32,  18,  33,  34,  35,  23,  36,  37,  38,
+     50,  49,  24,  25,  16,  19,  31,  20,  22,  47,  17,  45,  21,  44,   2,   3,
+      4,   5,   6,   7,   8,   9,  10,  11,  28,   1,  14,  15,  57,  12,  13,  26,
+     27,  43,  43,  39,  40,  41,  51,  52,  53,  58,  59,  60,  61,  62,  63,  64,
+     65,  66,  67,  68,  87,  88,  99,  70, 119, 110, 102, 104, 111, 107, 109, 106,
+    105, 108, 103,  69,  98,  55,  74,  78,  96,  79,  80,  81,  75,  76,  77,  71,
+     72,  73,  82,  83,  86, 127, 116, 117, 183, 184, 185, 186, 187, 188, 189, 190,
+    191, 192, 193, 194, 134, 138, 130, 132, 128, 129, 131, 137, 133, 135, 136, 113,
+    115, 114, 240, 240, 240, 121, 240,  89,  93, 124,  92,  94,  95, 240, 240, 240,
+    122, 123,  90,  91,  85, 240, 240, 240, 240, 240, 240, 240, 111, 240, 240, 240,
+    240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240,
+    240, 240, 240, 240, 240, 240, 179, 180, 240, 240, 240, 240, 240, 240, 240, 240,
+    240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240, 240,
+    240, 240, 240, 240, 240, 240, 240, 240, 111, 240, 240, 240, 240, 240, 240, 240,
+     29,  42,  56, 125,  97,  54, 100, 126, 164, 166, 165, 163, 161, 115, 114, 113,
+    150, 158, 159, 128, 136, 177, 178, 176, 142, 152, 173, 140, 240, 240, 240, 240,
+];
+
+fn keyboard_usage(code: u16) -> Option<u32> {
+    HID_KEYBOARD.iter()
+        .position(|&key| key != 0 && key != KEY_UNKNOWN && key as u16 == code)
+        .map(|usage| usage as u32)
+}
+
+/* The evdev code of a key by the name of its usage with dashes for spaces,
+ * e.g. "scroll-lock" or "left-control", in any case */
+pub fn key_code(name: &str) -> Option<u16> {
+    keyboard::NAMES.iter()
+        .find(|&&(_, usage_name)| usage_name.replace(' ', "-").eq_ignore_ascii_case(name))
+        .map(|&(usage, _)| HID_KEYBOARD[usage as usize] as u16)
+        .filter(|&code| code != 0 && code != KEY_UNKNOWN as u16)
+}
+
+/* The event codes of every type a device sends and the ranges of its
+ * absolute axes, as described or read from the device */
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    masks: HashMap<usize, Vec<u8>>,
+    ranges: HashMap<u16, (i32, i32)>,
+}
+
+impl Capabilities {
+    /* Asks an opened event node */
+    pub fn read(file: &File) -> io::Result<Capabilities> {
+        let mut capabilities = Capabilities::default();
+        let types = bits(file, 0x20, EV_CNT)?;
+        for (event_type, &count) in CODES.iter().enumerate().skip(1) {
+            if count > 0 && has(&types, event_type) {
+                capabilities.masks.insert(event_type, bits(file, 0x20 + event_type, count)?);
+            }
+        }
+        let axes = capabilities.masks.get(&EV_ABS).cloned().unwrap_or_default();
+        for axis in (0..CODES[EV_ABS]).filter(|&axis| has(&axes, axis)) {
+            let mut info = InputAbsinfo::default();
+            ioctl(file, eviocg(0x40 + axis, std::mem::size_of::<InputAbsinfo>()), &mut info)?;
+            capabilities.ranges.insert(axis as u16, (info.minimum, info.maximum));
+        }
+        Ok(capabilities)
+    }
+
+    /* Adds a code of a type without a range, like a key */
+    pub fn add(&mut self, event_type: usize, code: u16) {
+        let mask = self.masks.entry(event_type).or_default();
+        let byte = code as usize / 8;
+        if mask.len() <= byte {
+            /* whole lines of 8 bytes, like read ones */
+            mask.resize((byte + 1).div_ceil(8) * 8, 0);
+        }
+        mask[byte] |= 1 << (code % 8);
+    }
+
+    /* Adds what another device sends; an axis both have must have the same
+     * range, there being only one field for it */
+    pub fn merge(&mut self, other: &Capabilities) -> io::Result<()> {
+        for (&code, &range) in other.ranges.iter() {
+            match self.ranges.insert(code, range) {
+                Some(previous) if previous != range => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              format!("Absolute axis {:#x} ranges {}..{} and {}..{}", code, previous.0, previous.1, range.0, range.1)));
+                },
+                _ => (),
+            }
+        }
+        for (&event_type, mask) in other.masks.iter() {
+            let merged = self.masks.entry(event_type).or_default();
+            if merged.len() < mask.len() {
+                merged.resize(mask.len(), 0);
+            }
+            for (merged, byte) in merged.iter_mut().zip(mask) {
+                *merged |= byte;
+            }
+        }
+        Ok(())
+    }
+}
+
+/* What the reports made up for a recording hold */
+struct Layout {
+    /* key codes, one bit each in the keyboard report */
+    keys: Vec<u16>,
+    buttons: u16,
+    /* 16 bit fields after the buttons, then the 32 bit absolute ones */
+    relative: Vec<u16>,
+    absolute: Vec<(u16, i32, i32)>,
+    /* Keys, axes and event types left out */
+    skipped: usize,
+}
+
+impl Layout {
+    /* Unless strict, what cannot be reproduced is left out rather than refused,
+     * counted in `skipped` */
+    fn new(capabilities: &Capabilities, strict: bool) -> io::Result<Layout> {
+        let (masks, ranges) = (&capabilities.masks, &capabilities.ranges);
+        let mut skipped = 0;
+        let mut refuse = |message: String| {
+            if strict {
+                return Err(unsupported(message));
+            }
+            skipped += 1;
+            Ok(())
+        };
+        let codes = |event_type: usize| {
+            let mask = masks.get(&event_type).map_or(&[][..], |mask| &mask[..]);
+            (0..mask.len() * 8).filter(move |&code| has(mask, code)).map(|code| code as u16)
+        };
+
+        for (&event_type, mask) in masks.iter() {
+            match event_type {
+                EV_SYN | EV_KEY | EV_REL | EV_ABS | EV_MSC | EV_LED | EV_SND | EV_REP => (),
+                _ if mask.iter().all(|&byte| byte == 0) => (),
+                _ => refuse(format!("Events of type {:#x} cannot be reproduced", event_type))?,
+            }
+        }
+        let (mut keys, mut buttons, mut relative, mut absolute) = (Vec::new(), 0, Vec::new(), Vec::new());
+        for code in codes(EV_KEY) {
+            if keyboard_usage(code).is_some() {
+                keys.push(code);
+            } else if (BTN_MOUSE..BTN_MOUSE + MAX_BUTTONS).contains(&code) {
+                buttons = code - BTN_MOUSE + 1;
+            } else {
+                refuse(format!("Key {:#x} cannot be reproduced", code))?;
+            }
+        }
+        for code in codes(EV_REL) {
+            match code {
+                REL_WHEEL_HI_RES | REL_HWHEEL_HI_RES => (),
+                _ if code <= MAX_AXIS => relative.push(code),
+                _ => refuse(format!("Relative axis {:#x} cannot be reproduced", code))?,
+            }
+        }
+        for code in codes(EV_ABS) {
+            match ranges.get(&code) {
+                Some(&(minimum, maximum)) if code <= MAX_AXIS => absolute.push((code, minimum, maximum)),
+                Some(_) => refuse(format!("Absolute axis {:#x} cannot be reproduced", code))?,
+                None => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("No range for absolute axis {:#x}", code))),
+            }
+        }
+        let layout = Layout { keys, buttons, relative, absolute, skipped };
+        if layout.keys.is_empty() && !layout.has_pointer() {
+            return Err(unsupported(String::from("Nothing the device sends can be reproduced")));
+        }
+        Ok(layout)
+    }
+
+    fn has_pointer(&self) -> bool {
+        self.buttons > 0 || !self.relative.is_empty() || !self.absolute.is_empty()
+    }
+
+    /* Bits of the buttons, padded to whole bytes */
+    fn button_bits(&self) -> usize {
+        (self.buttons as usize).div_ceil(8) * 8
+    }
+
+    fn keyboard_len(&self) -> usize {
+        1 + self.keys.len().div_ceil(8)
+    }
+
+    fn pointer_len(&self) -> usize {
+        1 + self.button_bits() / 8 + 2 * self.relative.len() + 4 * self.absolute.len()
+    }
+
+    fn descriptor(&self) -> Vec<u8> {
+        let mut buffer = [0u8; MAX_DESCRIPTOR_LEN];
+        let mut builder = Builder::new(&mut buffer);
+        let padding = |bits: usize| ((8 - bits % 8) % 8) as u32;
+
+        if !self.keys.is_empty() {
+            builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::KEYBOARD).collection(APPLICATION)
+                .report_id(REPORT_KEYBOARD)
+                .usage_page(keyboard::PAGE);
+            for &code in self.keys.iter() {
+                builder.usage(keyboard_usage(code).unwrap());
+            }
+            builder.logical(0, 1).report(1, self.keys.len() as u32).input(VARIABLE);
+            if padding(self.keys.len()) > 0 {
+                builder.report(1, padding(self.keys.len())).input(CONSTANT);
+            }
+            builder.end_collection();
+        }
+
+        if self.has_pointer() {
+            builder.usage_page(generic_desktop::PAGE).usage(generic_desktop::MOUSE).collection(APPLICATION)
+                .report_id(REPORT_POINTER)
+                .usage(generic_desktop::POINTER).collection(PHYSICAL);
+            if self.buttons > 0 {
+                builder.usage_page(button::PAGE).usage_range(button::PRIMARY, self.buttons as u32)
+                    .logical(0, 1).report(1, self.buttons as u32).input(VARIABLE);
+                if padding(self.buttons as usize) > 0 {
+                    builder.report(1, padding(self.buttons as usize)).input(CONSTANT);
+                }
+            }
+            builder.usage_page(generic_desktop::PAGE);
+            if !self.relative.is_empty() {
+                /* the axes codes count from X in the order of the usages */
+                for &code in self.relative.iter() {
+                    builder.usage(generic_desktop::X + code as u32);
+                }
+                builder.logical(-0x7fff, 0x7fff).report(16, self.relative.len() as u32).input(VARIABLE | RELATIVE);
+            }
+            for &(code, minimum, maximum) in self.absolute.iter() {
+                builder.usage(generic_desktop::X + code as u32).logical(minimum, maximum).report(32, 1).input(VARIABLE);
+            }
+            builder.end_collection().end_collection();
+        }
+        /* at most 2 bytes each for the 256 keys */
+        let length = builder.finish().unwrap();
+        buffer[..length].to_vec()
+    }
+}
+
+fn unsupported(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+fn parse_time(number: usize, time: Option<&str>) -> io::Result<Duration> {
+    let (seconds, micros) = time.and_then(|time| time.split_once('.')).ok_or_else(|| invalid(number, "Invalid time"))?;
+    match (seconds.parse::<u64>(), micros.parse::<u32>()) {
+        (Ok(seconds), Ok(micros)) if micros < 1_000_000 => Ok(Duration::new(seconds, micros * 1000)),
+        _ => Err(invalid(number, "Invalid time")),
+    }
+}
+
+/* The current state of the reports, sent at the end of every frame */
+struct Frame {
+    keyboard: Vec<u8>,
+    pointer: Vec<u8>,
+    /* motion summed up over the frame */
+    motion: Vec<i32>,
+    keyboard_changed: bool,
+    pointer_changed: bool,
+}
+
+impl Frame {
+    fn new(layout: &Layout) -> Frame {
+        let mut keyboard = vec![0u8; layout.keyboard_len()];
+        keyboard[0] = REPORT_KEYBOARD;
+        let mut pointer = vec![0u8; layout.pointer_len()];
+        pointer[0] = REPORT_POINTER;
+        let mut frame = Frame { keyboard, pointer, motion: vec![0; layout.relative.len()], keyboard_changed: false, pointer_changed: false };
+        /* absolute axes start within their range */
+        for index in 0..layout.absolute.len() {
+            let (_, minimum, maximum) = layout.absolute[index];
+            frame.absolute(layout, index, 0i32.clamp(minimum, maximum.max(minimum)));
+        }
+        frame
+    }
+
+    fn absolute(&mut self, layout: &Layout, index: usize, value: i32) {
+        let offset = layout.button_bits() + 16 * layout.relative.len() + 32 * index;
+        bits::write(&mut self.pointer[1..], offset, 32, value as u32);
+        self.pointer_changed = true;
+    }
+
+    fn event(&mut self, layout: &Layout, event_type: usize, code: u16, value: i32) {
+        match event_type {
+            /* autorepeat is up to the kernel */
+            EV_KEY if value == 2 => (),
+            EV_KEY if (BTN_MOUSE..BTN_MOUSE + layout.buttons).contains(&code) => {
+                bits::write(&mut self.pointer[1..], (code - BTN_MOUSE) as usize, 1, (value != 0) as u32);
+                self.pointer_changed = true;
+            },
+            EV_KEY => if let Some(index) = layout.keys.iter().position(|&key| key == code) {
+                bits::write(&mut self.keyboard[1..], index, 1, (value != 0) as u32);
+                self.keyboard_changed = true;
+            },
+            EV_REL => if let Some(index) = layout.relative.iter().position(|&axis| axis == code) {
+                self.motion[index] += value;
+                self.pointer_changed = true;
+            },
+            EV_ABS => if let Some(index) = layout.absolute.iter().position(|&(axis, _, _)| axis == code) {
+                self.absolute(layout, index, value);
+            },
+            _ => (),
+        }
+    }
+
+    fn reports(&mut self, layout: &Layout) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        if self.keyboard_changed {
+            reports.push(self.keyboard.clone());
+        }
+        if self.pointer_changed {
+            for (index, motion) in self.motion.iter_mut().enumerate() {
+                let offset = layout.button_bits() + 16 * index;
+                bits::write(&mut self.pointer[1..], offset, 16, (*motion).clamp(-0x7fff, 0x7fff) as u32);
+                *motion = 0;
+            }
+            reports.push(self.pointer.clone());
+        }
+        self.keyboard_changed = false;
+        self.pointer_changed = false;
+        reports
+    }
+}
+
+/*
+ * Turns the events of a device into the reports of the device made up for
+ * it, a keyboard report 0x01 and a pointer report 0x02, frame by frame,
+ * remapping them on the way if asked to, see src/remap.rs
+ */
+pub struct Translator {
+    layout: Layout,
+    frame: Frame,
+    remap: Remap,
+    /* fractions of scaled relative motion carried over, by axis */
+    remainders: HashMap<u16, f64>,
+}
+
+impl Translator {
+    /* Unless strict, keys and axes that cannot be reproduced are left out
+     * instead of refusing the device */
+    pub fn new(capabilities: &Capabilities, strict: bool) -> io::Result<Translator> {
+        let layout = Layout::new(capabilities, strict)?;
+        let frame = Frame::new(&layout);
+        Ok(Translator { layout, frame, remap: Remap::default(), remainders: HashMap::new() })
+    }
+
+    /* Remaps the events from now on; the capabilities the translator was
+     * made for have to include what they are mapped to, see Remap::extend() */
+    pub fn set_remap(&mut self, remap: Remap) {
+        self.remap = remap;
+        self.remainders.clear();
+    }
+
+    pub fn descriptor(&self) -> Vec<u8> {
+        self.layout.descriptor()
+    }
+
+    /* Keys, axes and event types left out */
+    pub fn skipped(&self) -> usize {
+        self.layout.skipped
+    }
+
+    /* The reports of the frame a SYN_REPORT ends, or of a macro a key
+     * pressed taps, none for other events */
+    pub fn event(&mut self, event_type: usize, code: u16, value: i32) -> Vec<Vec<u8>> {
+        if event_type == EV_SYN && code == SYN_REPORT {
+            return self.frame.reports(&self.layout);
+        }
+        let (layout, frame) = (&self.layout, &mut self.frame);
+        match event_type {
+            EV_KEY => match self.remap.keys.get(&code) {
+                Some(&Target::Key(target)) => frame.event(layout, event_type, target, value),
+                /* each key pressed and released in a frame of its own,
+                 * after what the frame so far holds */
+                Some(Target::Macro(targets)) if value == 1 => {
+                    let mut reports = Vec::new();
+                    for &target in targets {
+                        for pressed in [1, 0] {
+                            frame.event(layout, EV_KEY, target, pressed);
+                            reports.extend(frame.reports(layout));
+                        }
+                    }
+                    return reports;
+                },
+                Some(Target::Macro(_)) => (),
+                None => frame.event(layout, event_type, code, value),
+            },
+            EV_REL => match self.remap.axes.get(&code) {
+                Some(axis) => {
+                    let remainder = self.remainders.entry(code).or_insert(0.0);
+                    let scaled = value as f64 * if axis.invert { -axis.scale } else { axis.scale } + *remainder;
+                    *remainder = scaled.fract();
+                    frame.event(layout, event_type, code, scaled.trunc() as i32);
+                },
+                None => frame.event(layout, event_type, code, value),
+            },
+            EV_ABS => match (self.remap.axes.get(&code), layout.absolute.iter().find(|&&(axis, _, _)| axis == code)) {
+                (Some(axis), Some(&(_, minimum, maximum))) if axis.invert => {
+                    let inverted = minimum as i64 + maximum as i64 - value as i64;
+                    frame.event(layout, event_type, code, inverted.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+                },
+                _ => frame.event(layout, event_type, code, value),
+            },
+            _ => frame.event(layout, event_type, code, value),
+        }
+        vec![]
+    }
+}
+
+/* Reads an evemu recording, making up a device reproducing it */
+pub fn parse<R: BufRead>(input: R) -> io::Result<Capture> {
+    let mut name = String::new();
+    let mut identity = Identity { bus: BUS_USB as u16, vendor: 0, product: 0, version: 0, country: 0 };
+    let mut capabilities = Capabilities::default();
+    let mut events = Vec::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        /* events have a comment naming them at the end */
+        let line = line.split('#').next().unwrap_or_default();
+        let (tag, rest) = match line.split_once(':') {
+            Some((tag, rest)) => (tag.trim(), rest.trim()),
+            None => continue,
+        };
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let hex = |field: &str| u32::from_str_radix(field, 16).map_err(|_| invalid(number, "Invalid number"));
+
+        match tag {
+            "N" => name = rest.to_string(),
+            "I" if fields.len() == 4 => {
+                identity.bus = hex(fields[0])? as u16;
+                identity.vendor = hex(fields[1])?;
+                identity.product = hex(fields[2])?;
+                identity.version = hex(fields[3])?;
+            },
+            "I" => return Err(invalid(number, "Expected bus, vendor, product and version")),
+            "B" if !fields.is_empty() => {
+                let event_type = hex(fields[0])? as usize;
+                let bytes = fields[1..].iter().map(|&byte| hex(byte).map(|byte| byte as u8)).collect::<io::Result<Vec<u8>>>()?;
+                capabilities.masks.entry(event_type).or_default().extend(bytes);
+            },
+            "A" if fields.len() >= 3 => {
+                let code = hex(fields[0])? as u16;
+                let minimum = fields[1].parse::<i32>().map_err(|_| invalid(number, "Invalid minimum"))?;
+                let maximum = fields[2].parse::<i32>().map_err(|_| invalid(number, "Invalid maximum"))?;
+                capabilities.ranges.insert(code, (minimum, maximum));
+            },
+            "E" if fields.len() == 4 => {
+                let time = parse_time(number, Some(fields[0]))?;
+                let event_type = hex(fields[1])? as usize;
+                let code = hex(fields[2])? as u16;
+                let value = fields[3].parse::<i32>().map_err(|_| invalid(number, "Invalid value"))?;
+                events.push((time, event_type, code, value));
+            },
+            "B" | "A" | "E" => return Err(invalid(number, "Missing fields")),
+            /* properties, switches and LEDs have nothing to reproduce */
+            _ => (),
+        }
+    }
+
+    if capabilities.masks.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No device description in the recording"));
+    }
+    let mut translator = Translator::new(&capabilities, true)?;
+    let start = events.first().map_or(Duration::from_secs(0), |&(time, _, _, _)| time);
+    let mut reports = Vec::new();
+    for (time, event_type, code, value) in events {
+        for report in translator.event(event_type, code, value) {
+            reports.push((time.saturating_sub(start), report));
+        }
+    }
+
+    Ok(Capture { name, descriptor: translator.descriptor(), identity, events: reports })
+}
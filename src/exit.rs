@@ -0,0 +1,194 @@
+/*
+ * Exit codes
+ * What the binary exits with, so scripts wrapping it can branch on why it
+ * failed rather than parse the message, a single line on stderr:
+ *     0  success
+ *     1  any other failure
+ *     2  invalid arguments, or arguments that cannot be combined
+ *     3  permission denied, e.g. on the uhid-cdev
+ *     4  uhid missing: no uhid-cdev, the uhid module is not loaded
+ *     5  the kernel rejected the report descriptor
+ *     6  protocol error: a peer, like the Barrier server or a serve client,
+ *        or the reports forwarded on stdin did not follow the protocol
+ *   130  interrupted by SIGINT or SIGTERM, after destroying the device
+ *
+ * Interrupts are caught once catch_interrupts() is called: system calls
+ * blocking until then fail with EINTR and interrupted() tells them from
+ * others, so the device is destroyed on the way out like on any other.
+ *
+ * With --output json the line is a JSON object instead, for harnesses to
+ * read rather than scrape, with the code by name and value and what is known
+ * of where it went wrong, like the report-id or the offset in the descriptor:
+ *
+ *   {"error":{"code":"descriptor_rejected","status":5,"message":"...","context":{"offset":12}}}
+ *
+ * Errors deep down tell where with located(), the place travelling inside
+ * the io::Error up to the failure.
+ */
+
+use libc;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    Failure,
+    Usage,
+    PermissionDenied,
+    UhidMissing,
+    DescriptorRejected,
+    Protocol,
+    Interrupted,
+}
+
+impl Code {
+    pub fn value(self) -> i32 {
+        match self {
+            Code::Failure => 1,
+            Code::Usage => 2,
+            Code::PermissionDenied => 3,
+            Code::UhidMissing => 4,
+            Code::DescriptorRejected => 5,
+            Code::Protocol => 6,
+            Code::Interrupted => 128 + libc::SIGINT,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Code::Failure => "failure",
+            Code::Usage => "usage",
+            Code::PermissionDenied => "permission_denied",
+            Code::UhidMissing => "uhid_missing",
+            Code::DescriptorRejected => "descriptor_rejected",
+            Code::Protocol => "protocol",
+            Code::Interrupted => "interrupted",
+        }
+    }
+
+    /* The code of a failure caused by the error, as far as its kind tells */
+    pub fn of(err: &io::Error) -> Code {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => Code::PermissionDenied,
+            io::ErrorKind::Interrupted if interrupted() => Code::Interrupted,
+            _ => Code::Failure,
+        }
+    }
+}
+
+/* An error at a place in a report or descriptor, e.g. ("offset", 12) */
+#[derive(Debug)]
+pub struct Located {
+    message: String,
+    context: Vec<(&'static str, u64)>,
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for Located {}
+
+/* An io::Error of the kind, remembering where for the failure it causes */
+pub fn located(kind: io::ErrorKind, message: String, context: Vec<(&'static str, u64)>) -> io::Error {
+    io::Error::new(kind, Located { message, context })
+}
+
+/* Why the binary gives up, with the line to tell */
+#[derive(Debug)]
+pub struct Failure {
+    pub code: Code,
+    pub message: String,
+    pub context: Vec<(&'static str, u64)>,
+}
+
+impl Failure {
+    pub fn new(code: Code, message: String) -> Failure {
+        Failure { code, message, context: vec![] }
+    }
+
+    /* Caused by the error, the code following from it */
+    pub fn io(err: &io::Error, message: String) -> Failure {
+        Failure::new(Code::of(err), message).within(err)
+    }
+
+    /* Knowing where the error went wrong, if it was located */
+    pub fn within(mut self, err: &io::Error) -> Failure {
+        if let Some(located) = err.get_ref().and_then(|err| err.downcast_ref::<Located>()) {
+            self.context.extend_from_slice(&located.context);
+        }
+        self
+    }
+
+    /* The failure as one line of JSON */
+    pub fn json(&self) -> String {
+        let message = self.message.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+            .replace('\r', "\\r").replace('\t', "\\t");
+        let mut line = format!("{{\"error\":{{\"code\":\"{}\",\"status\":{},\"message\":\"{}\",\"context\":{{",
+                               self.code.name(), self.code.value(), message);
+        for (index, &(name, value)) in self.context.iter().enumerate() {
+            let _ = write!(line, "{}\"{}\":{}", if index > 0 { "," } else { "" }, name, value);
+        }
+        line.push_str("}}}");
+        line
+    }
+}
+
+impl From<io::Error> for Failure {
+    fn from(err: io::Error) -> Failure {
+        Failure::io(&err, err.to_string())
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/* Whether failures are told as JSON, as set by --output */
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::SeqCst);
+}
+
+/* Tells the failure on stderr, in the format set */
+pub fn report(failure: &Failure) {
+    if JSON.load(Ordering::SeqCst) {
+        eprintln!("{}", failure.json());
+    } else {
+        eprintln!("{}", failure);
+    }
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/* Catches SIGINT and SIGTERM from now on, without restarting the system
+ * calls they interrupt. Only the first is caught, a second one kills as
+ * usual in case the way out hangs */
+pub fn catch_interrupts() -> io::Result<()> {
+    for &signal in &[libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/* Whether SIGINT or SIGTERM arrived */
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
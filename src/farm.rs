@@ -0,0 +1,201 @@
+/*
+ * Device farm
+ * Creates many devices of one preset and drives them all at once, for load
+ * testing compositors and games against many input devices, e.g.
+ *
+ *   uhid-example farm --count 64 --type mouse --rate 100
+ *
+ * The devices are spread over --parallel threads, each device with a
+ * uhid-cdev of its own, and named <name>-<number>. Every device sends the
+ * same reports in a loop, a small square moved around, --rate times per
+ * second. The events of the kernel are read and dropped as they come, so
+ * its queue of them never fills up.
+ *
+ * The throughput of all devices together is printed every second, and in
+ * total once SIGINT or SIGTERM destroys them.
+ */
+
+use devices::{Action, Device};
+use exit;
+use libc;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use uhid::{self, uhid_event_type, Identity};
+
+pub const DEFAULT_RATE: u32 = 100;
+
+/* Counts each side of the square moved */
+const SIDE: i8 = 10;
+/* How long a device gets to be started */
+const START_TIMEOUT: Duration = Duration::from_secs(1);
+/* How often the throughput is printed */
+const READOUT_INTERVAL: Duration = Duration::from_secs(1);
+/* How long the main thread sleeps between looking for interrupts */
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Farm {
+    pub count: u64,
+    pub parallel: u64,
+    /* reports per second, of each device */
+    pub rate: u32,
+    pub name: String,
+    pub descriptor: Vec<u8>,
+    pub identity: Identity,
+    /* what every device sends over and over */
+    pub reports: Vec<Vec<u8>>,
+}
+
+#[derive(Default)]
+struct Counters {
+    created: AtomicU64,
+    sent: AtomicU64,
+    bytes: AtomicU64,
+    write_errors: AtomicU64,
+}
+
+#[derive(Default)]
+pub struct Summary {
+    pub created: u64,
+    pub sent: u64,
+    pub bytes: u64,
+    pub write_errors: u64,
+    pub errors: Vec<String>,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    pub fn reports_per_second(&self) -> f64 {
+        self.sent as f64 / self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+/* The reports moving the device around the square: the pointer of a mouse,
+ * the arrow keys of a keyboard and so on; none for devices that do not move */
+pub fn square(device: &mut dyn Device) -> Vec<Vec<u8>> {
+    [(SIDE, 0), (0, SIDE), (-SIDE, 0), (0, -SIDE)].iter()
+        .flat_map(|&(dx, dy)| device.action(Action::Move(dx, dy)))
+        .collect()
+}
+
+/* Drops the events queued for the device */
+fn drain(file: &mut File) -> io::Result<()> {
+    loop {
+        match uhid::read_event(file) {
+            Ok(_) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/* Creates the devices with the index modulo parallel and drives them until
+ * told to stop, returning the errors creating them */
+fn worker(path: &Path, farm: &Farm, index: u64, counters: &Counters, stop: &AtomicBool) -> io::Result<Vec<String>> {
+    let mut errors = Vec::new();
+    let mut devices = Vec::new();
+    for number in (index..farm.count).step_by(farm.parallel as usize) {
+        let name = format!("{}-{}", farm.name, number);
+        let mut file = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC | libc::O_NONBLOCK).open(path)?;
+        let created2 = match uhid::create_any(&mut file, &name, &farm.descriptor, &farm.identity) {
+            Ok(created2) => created2,
+            Err(err) => {
+                errors.push(format!("{}: {}", name, err));
+                continue;
+            },
+        };
+        if !uhid::wait_for(&mut file, uhid_event_type::UHID_START, START_TIMEOUT)? {
+            errors.push(format!("{}: never started", name));
+        }
+        counters.created.fetch_add(1, Ordering::SeqCst);
+        devices.push((name, file, created2));
+    }
+
+    let interval = Duration::from_secs(1) / farm.rate;
+    let mut next = Instant::now();
+    let mut reports = farm.reports.iter().cycle();
+    while !stop.load(Ordering::SeqCst) {
+        /* all devices send the same report at a time */
+        let report = match reports.next() {
+            Some(report) => report,
+            None => break,
+        };
+        for &mut (_, ref mut file, created2) in devices.iter_mut() {
+            match uhid::send_input(file, report, created2) {
+                Ok(()) => {
+                    counters.sent.fetch_add(1, Ordering::Relaxed);
+                    counters.bytes.fetch_add(report.len() as u64, Ordering::Relaxed);
+                },
+                Err(_) => {
+                    counters.write_errors.fetch_add(1, Ordering::Relaxed);
+                },
+            }
+            drain(file)?;
+        }
+        next += interval;
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            /* behind, catch up rather than burst */
+            next = now;
+        }
+    }
+
+    for &mut (ref name, ref mut file, _) in devices.iter_mut() {
+        if let Err(err) = uhid::destroy(file) {
+            errors.push(format!("{}: {}", name, err));
+        }
+    }
+    Ok(errors)
+}
+
+/* Drives the farm until interrupted, printing the throughput as it goes */
+pub fn run(path: &Path, farm: Farm) -> io::Result<Summary> {
+    exit::catch_interrupts()?;
+    let farm = Arc::new(farm);
+    let counters = Arc::new(Counters::default());
+    let stop = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+
+    let workers: Vec<_> = (0..farm.parallel).map(|index| {
+        let (path, farm, counters, stop) = (path.to_path_buf(), farm.clone(), counters.clone(), stop.clone());
+        thread::spawn(move || worker(&path, &farm, index, &counters, &stop))
+    }).collect();
+
+    let mut since = Instant::now();
+    let mut last = (0, 0);
+    /* workers only finish on their own when failing */
+    while !exit::interrupted() && !workers.iter().any(|worker| worker.is_finished()) {
+        thread::sleep(CHECK_INTERVAL);
+        let now = Instant::now();
+        if now.duration_since(since) < READOUT_INTERVAL {
+            continue;
+        }
+        let seconds = now.duration_since(since).as_secs_f64();
+        let (sent, bytes) = (counters.sent.load(Ordering::Relaxed), counters.bytes.load(Ordering::Relaxed));
+        eprintln!("{} devices, {:.0} reports/s, {:.0} bytes/s, {} write errors",
+                  counters.created.load(Ordering::SeqCst), (sent - last.0) as f64 / seconds, (bytes - last.1) as f64 / seconds,
+                  counters.write_errors.load(Ordering::Relaxed));
+        last = (sent, bytes);
+        since = now;
+    }
+    stop.store(true, Ordering::SeqCst);
+
+    let mut summary = Summary { elapsed: start.elapsed(), ..Summary::default() };
+    for (index, worker) in workers.into_iter().enumerate() {
+        let result = worker.join().map_err(|_| io::Error::other("Worker panicked"))?;
+        let errors = result.map_err(|err| io::Error::new(err.kind(), format!("Worker {}: {}", index, err)))?;
+        summary.errors.extend(errors);
+    }
+    summary.created = counters.created.load(Ordering::SeqCst);
+    summary.sent = counters.sent.load(Ordering::SeqCst);
+    summary.bytes = counters.bytes.load(Ordering::SeqCst);
+    summary.write_errors = counters.write_errors.load(Ordering::SeqCst);
+    Ok(summary)
+}
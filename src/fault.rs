@@ -0,0 +1,73 @@
+/*
+ * Fault injection
+ * Drops or duplicates replayed or forwarded input reports at random, like a
+ * flaky wireless link losing a packet or resending one it did not see
+ * acknowledged, to harden what consumes the reports against both. Set with
+ * --fault and a comma separated list of probabilities per report:
+ *   drop=0.01,dup=0.001
+ * Either can be left out. A report dropped is not duplicated.
+ */
+
+use devices::path::Rng;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Faults {
+    pub drop: f32,
+    pub dup: f32,
+}
+
+impl Faults {
+    pub fn parse(spec: &str) -> Option<Faults> {
+        let mut faults = Faults::default();
+        for field in spec.split(',') {
+            let mut parts = field.splitn(2, '=');
+            let name = parts.next()?;
+            let probability: f32 = parts.next()?.parse().ok()?;
+            if !(0.0..=1.0).contains(&probability) {
+                return None;
+            }
+            match name {
+                "drop" => faults.drop = probability,
+                "dup" => faults.dup = probability,
+                _ => return None,
+            }
+        }
+        Some(faults)
+    }
+}
+
+/* How many reports were hit so far */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub dropped: u64,
+    pub duplicated: u64,
+}
+
+pub struct Injector {
+    faults: Faults,
+    rng: Rng,
+    stats: Stats,
+}
+
+impl Injector {
+    pub fn new(faults: Faults, seed: u64) -> Injector {
+        Injector { faults, rng: Rng::new(seed), stats: Stats::default() }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /* The reports to send in place of this one: none, it or it twice */
+    pub fn apply(&mut self, report: Vec<u8>) -> Vec<Vec<u8>> {
+        if self.rng.range(0.0, 1.0) < self.faults.drop {
+            self.stats.dropped += 1;
+            return vec![];
+        }
+        if self.rng.range(0.0, 1.0) < self.faults.dup {
+            self.stats.duplicated += 1;
+            return vec![report.clone(), report];
+        }
+        vec![report]
+    }
+}
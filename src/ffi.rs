@@ -0,0 +1,275 @@
+/*
+ * C API
+ * Exposes the uhid wrapper to C and C++ through the cdylib, declared in
+ * include/uhid_example.h, so existing harnesses built around uhid-example.c
+ * can use it instead of speaking the uhid protocol themselves.
+ *
+ * Functions return 0 on success and a negative errno value on failure, like
+ * the kernel does. Only the creation of a device, which returns a pointer,
+ * reports failures through errno instead. Devices and events passed in have
+ * to be valid pointers, as the header documents.
+ */
+
+#![allow(clippy::missing_safety_doc)]
+
+use libc;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::slice;
+use std::time::{Duration, Instant};
+use pending::Pending;
+use uhid;
+use uhid::{uhid_event_type, Identity};
+
+const DEFAULT_PATH: &str = "/dev/uhid";
+
+pub const UHID_DEVICE_DATA_MAX: usize = 4096;
+
+/* Opaque to C */
+pub struct UhidDevice {
+    file: File,
+    /* whether it was created with UHID_CREATE2, see uhid::create_any() */
+    created2: bool,
+    /* GET_REPORT requests handed out and not answered yet */
+    pending: Pending,
+}
+
+/* Mirrors struct uhid_device_event in the header */
+#[repr(C)]
+pub struct UhidDeviceEvent {
+    pub type_: u32,
+    pub id: u32,
+    pub rnum: u8,
+    pub rtype: u8,
+    pub size: u16,
+    pub data: [u8; UHID_DEVICE_DATA_MAX],
+}
+
+/* Mirrors struct uhid_device_report_stats in the header */
+#[repr(C)]
+pub struct UhidDeviceReportStats {
+    pub answered: u64,
+    pub timed_out: u64,
+    pub late: u64,
+}
+
+fn errno(err: &io::Error) -> c_int {
+    err.raw_os_error().unwrap_or(libc::EIO)
+}
+
+/* Bionic names the errno accessor differently from glibc and musl */
+#[cfg(target_os = "android")]
+unsafe fn set_errno(value: c_int) {
+    *libc::__errno() = value;
+}
+
+#[cfg(not(target_os = "android"))]
+unsafe fn set_errno(value: c_int) {
+    *libc::__errno_location() = value;
+}
+
+fn to_result(result: io::Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(err) => -errno(&err),
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(data, size)
+    }
+}
+
+fn create(path: &str, name: &str, rdesc: &[u8], identity: &Identity) -> io::Result<UhidDevice> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let created2 = uhid::create_any(&mut file, name, rdesc, identity)?;
+    Ok(UhidDevice { file, created2, pending: Pending::default() })
+}
+
+/* Opens path, /dev/uhid when NULL, and creates the device; NULL and errno
+ * set on failure */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_create(path: *const c_char, name: *const c_char,
+                                            rdesc: *const u8, rdesc_size: usize,
+                                            bus: u16, vendor: u32, product: u32,
+                                            version: u32, country: u32) -> *mut UhidDevice {
+    let path = if path.is_null() { Ok(DEFAULT_PATH) } else { CStr::from_ptr(path).to_str() };
+    let name = if name.is_null() { Ok(uhid::DEFAULT_NAME) } else { CStr::from_ptr(name).to_str() };
+    let (path, name) = match (path, name) {
+        (Ok(path), Ok(name)) => (path, name),
+        _ => {
+            set_errno(libc::EINVAL);
+            return ptr::null_mut();
+        }
+    };
+
+    let identity = Identity { bus, vendor, product, version, country };
+    match create(path, name, bytes(rdesc, rdesc_size), &identity) {
+        Ok(device) => Box::into_raw(Box::new(device)),
+        Err(err) => {
+            set_errno(if err.kind() == io::ErrorKind::InvalidInput { libc::EINVAL } else { errno(&err) });
+            ptr::null_mut()
+        }
+    }
+}
+
+/* Destroys the device and frees it */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_destroy(device: *mut UhidDevice) {
+    if device.is_null() {
+        return;
+    }
+    let mut device = Box::from_raw(device);
+    let _ = uhid::destroy(&mut device.file);
+}
+
+/* For integrating into an existing poll loop */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_fd(device: *const UhidDevice) -> c_int {
+    (*device).file.as_raw_fd()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_send_input(device: *mut UhidDevice, data: *const u8, size: usize) -> c_int {
+    let device = &mut *device;
+    let result = uhid::send_input(&mut device.file, bytes(data, size), device.created2);
+    match result {
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidInput => -libc::EINVAL,
+        result => to_result(result),
+    }
+}
+
+/* Answers the GET_REPORT requests that were not answered in time */
+fn expire(device: &mut UhidDevice) -> io::Result<()> {
+    for id in device.pending.expire(Instant::now()) {
+        uhid::get_report_reply(&mut device.file, id, Err(io::Error::from_raw_os_error(libc::ETIMEDOUT)))?;
+    }
+    Ok(())
+}
+
+/* Milliseconds to poll for until `until`, rounded up not to wake early */
+fn poll_timeout(until: Instant) -> c_int {
+    let micros = until.saturating_duration_since(Instant::now()).as_micros();
+    micros.div_ceil(1000).min(c_int::MAX as u128) as c_int
+}
+
+/*
+ * Waits up to timeout_ms, or forever when negative, for the next event from
+ * the kernel. Returns 1 with the event filled in, 0 on timeout. GET_REPORT
+ * requests not answered in time are answered with ETIMEDOUT meanwhile.
+ */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_poll_event(device: *mut UhidDevice, timeout_ms: c_int,
+                                                event: *mut UhidDeviceEvent) -> c_int {
+    let device = &mut *device;
+    let end = if timeout_ms < 0 { None } else { Some(Instant::now() + Duration::from_millis(timeout_ms as u64)) };
+    loop {
+        if let Err(err) = expire(device) {
+            return -errno(&err);
+        }
+        /* wake up for the next request timing out too */
+        let wake = match (end, device.pending.deadline()) {
+            (Some(end), Some(deadline)) => Some(end.min(deadline)),
+            (end, deadline) => end.or(deadline),
+        };
+        let mut fds = libc::pollfd { fd: device.file.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match libc::poll(&mut fds, 1, wake.map_or(-1, poll_timeout)) {
+            -1 => return -errno(&io::Error::last_os_error()),
+            0 if end.is_some_and(|end| end <= Instant::now()) => return 0,
+            0 => continue,
+            _ => break,
+        }
+    }
+
+    let ev = match uhid::read_event(&mut device.file) {
+        Ok(ev) => ev,
+        Err(err) => return -errno(&err),
+    };
+
+    let event = &mut *event;
+    event.type_ = ev.type_;
+    event.id = 0;
+    event.rnum = 0;
+    event.rtype = 0;
+    event.size = 0;
+
+    match uhid::event_type(ev.type_) {
+        Some(uhid_event_type::UHID_OUTPUT) => {
+            let output = ev.u.output.as_ref();
+            let size = (output.size as usize).min(UHID_DEVICE_DATA_MAX);
+            event.rtype = output.rtype;
+            event.size = size as u16;
+            event.data[..size].copy_from_slice(&output.data[..size]);
+        },
+        Some(uhid_event_type::UHID_GET_REPORT) => {
+            let req = ev.u.get_report.as_ref();
+            device.pending.add(req.id, Instant::now());
+            event.id = req.id;
+            event.rnum = req.rnum;
+            event.rtype = req.rtype;
+        },
+        Some(uhid_event_type::UHID_SET_REPORT) => {
+            let req = ev.u.set_report.as_ref();
+            let size = (req.size as usize).min(UHID_DEVICE_DATA_MAX);
+            event.id = req.id;
+            event.rnum = req.rnum;
+            event.rtype = req.rtype;
+            event.size = size as u16;
+            event.data[..size].copy_from_slice(&req.data[..size]);
+        },
+        _ => (),
+    }
+    1
+}
+
+/* Answers a GET_REPORT event; err is a positive errno value or 0.
+ * -ETIMEDOUT if the request was answered with a timeout already. */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_get_report_reply(device: *mut UhidDevice, id: u32, err: u16,
+                                                      data: *const u8, size: usize) -> c_int {
+    if !(*device).pending.answer(id) {
+        return -libc::ETIMEDOUT;
+    }
+    let result = if err == 0 {
+        Ok(bytes(data, size).to_vec())
+    } else {
+        Err(io::Error::from_raw_os_error(err as i32))
+    };
+    to_result(uhid::get_report_reply(&mut (*device).file, id, result))
+}
+
+/* Answers a SET_REPORT event; err is a positive errno value or 0 */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_set_report_reply(device: *mut UhidDevice, id: u32, err: u16) -> c_int {
+    let result = if err == 0 { Ok(()) } else { Err(io::Error::from_raw_os_error(err as i32)) };
+    to_result(uhid::set_report_reply(&mut (*device).file, id, result))
+}
+
+/* How long GET_REPORT requests wait for a reply, 1000ms unless set */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_set_report_timeout(device: *mut UhidDevice, timeout_ms: c_int) -> c_int {
+    if timeout_ms < 0 {
+        return -libc::EINVAL;
+    }
+    (*device).pending.set_timeout(Duration::from_millis(timeout_ms as u64));
+    0
+}
+
+/* How the GET_REPORT requests went so far */
+#[no_mangle]
+pub unsafe extern "C" fn uhid_device_report_stats(device: *const UhidDevice, stats: *mut UhidDeviceReportStats) -> c_int {
+    let counted = (*device).pending.stats();
+    *stats = UhidDeviceReportStats {
+        answered: counted.answered,
+        timed_out: counted.timed_out,
+        late: counted.late,
+    };
+    0
+}
@@ -0,0 +1,128 @@
+/*
+ * Framing
+ * Length-prefixed frames over a byte stream such as stdin and stdout, for
+ * --forward and stdio-serve: every frame is its length as two bytes, least
+ * significant first, followed by that many bytes. Empty frames and ones
+ * longer than MAX_FRAME_LEN are invalid; there is no finding the next frame
+ * after one, so they end the stream.
+ */
+
+use std::io;
+use std::io::{Read, Write};
+use uhid::UHID_DATA_MAX;
+
+/* The longest report uhid takes */
+pub const MAX_FRAME_LEN: usize = UHID_DATA_MAX as usize;
+
+/* Collects what was read and splits the complete frames off */
+#[derive(Default)]
+pub struct Deframer {
+    buffer: Vec<u8>,
+}
+
+impl Deframer {
+    /* Reads what a non-blocking input has; false once it is closed */
+    pub fn fill<R: Read>(&mut self, mut input: R) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            match input.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(true),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /* The next complete frame, None until it has been read */
+    pub fn next_frame(&mut self) -> io::Result<Option<Vec<u8>>> {
+        if self.buffer.len() < 2 {
+            return Ok(None);
+        }
+        let length = u16::from_le_bytes([self.buffer[0], self.buffer[1]]) as usize;
+        if length == 0 || length > MAX_FRAME_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid frame length {}", length)));
+        }
+        if self.buffer.len() < 2 + length {
+            return Ok(None);
+        }
+        Ok(Some(self.buffer.drain(..2 + length).skip(2).collect()))
+    }
+
+    /* Bytes of an incomplete frame */
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+pub fn write_frame<W: Write>(mut output: W, payload: &[u8]) -> io::Result<()> {
+    if payload.is_empty() || payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid frame length {}", payload.len())));
+    }
+    output.write_all(&(payload.len() as u16).to_le_bytes())?;
+    output.write_all(payload)?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Hands out its chunks one read at a time, then would block */
+    struct Chunks(Vec<Vec<u8>>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            let chunk = self.0.remove(0);
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    #[test]
+    fn splits_frames_read_in_pieces() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, &[1, 2, 3]).unwrap();
+        write_frame(&mut stream, &[4]).unwrap();
+        assert_eq!(stream, vec![3, 0, 1, 2, 3, 1, 0, 4]);
+
+        let mut deframer = Deframer::default();
+        assert!(deframer.fill(Chunks(vec![stream[..1].to_vec()])).unwrap());
+        assert_eq!(deframer.next_frame().unwrap(), None);
+        assert!(deframer.fill(Chunks(vec![stream[1..4].to_vec()])).unwrap());
+        assert_eq!(deframer.next_frame().unwrap(), None);
+        assert_eq!(deframer.pending(), 4);
+
+        assert!(!deframer.fill(&stream[4..]).unwrap());
+        assert_eq!(deframer.next_frame().unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(deframer.next_frame().unwrap(), Some(vec![4]));
+        assert_eq!(deframer.next_frame().unwrap(), None);
+        assert_eq!(deframer.pending(), 0);
+    }
+
+    #[test]
+    fn takes_frames_up_to_the_longest_report() {
+        let payload = vec![0xaa; MAX_FRAME_LEN];
+        let mut stream = Vec::new();
+        write_frame(&mut stream, &payload).unwrap();
+
+        let mut deframer = Deframer::default();
+        deframer.fill(&stream[..]).unwrap();
+        assert_eq!(deframer.next_frame().unwrap(), Some(payload));
+    }
+
+    #[test]
+    fn refuses_empty_and_overlong_frames() {
+        for length in [0, MAX_FRAME_LEN + 1] {
+            let mut deframer = Deframer::default();
+            deframer.fill(&(length as u16).to_le_bytes()[..]).unwrap();
+            assert_eq!(deframer.next_frame().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+            let err = write_frame(Vec::new(), &vec![0; length]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,211 @@
+/*
+ * Descriptor fuzzing
+ * Feeds the kernel's HID parser mutated report descriptors: a device is
+ * created with each, given a moment to be started, and destroyed again,
+ * while the kernel log is watched for what the parser had to say. A device
+ * that is never started had its descriptor rejected; an oops, warning or
+ * sanitizer report means something worse.
+ *
+ * Mutations are made from a seed, so a run can be repeated exactly. They
+ * flip bits, overwrite bytes, truncate the descriptor, and insert items at
+ * item boundaries: collections nested deeper than any real device does,
+ * end collections without a start, and pushes of the global state without
+ * a pop.
+ *
+ * Reading /dev/kmsg needs root, or kernel.dmesg_restrict=0.
+ */
+
+use libc;
+use hid_report::descriptor;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::fs::OpenOptionsExt;
+use std::time::Duration;
+use uhid::{self, uhid_event_type, Identity};
+
+/* Longest descriptor the kernel accepts, HID_MAX_DESCRIPTOR_SIZE */
+pub const MAX_DESCRIPTOR_LEN: usize = 4096;
+
+/* How long a device gets to be started before it counts as rejected */
+pub const START_TIMEOUT: Duration = Duration::from_millis(500);
+
+const KMSG: &str = "/dev/kmsg";
+
+/* Kernel messages that mean more than a rejected descriptor */
+const CRASH_MARKERS: &[&str] = &["Oops", "BUG:", "WARNING:", "KASAN", "UBSAN", "general protection fault", "Call Trace"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mutation {
+    BitFlip { offset: usize, bit: u8 },
+    Byte { offset: usize, value: u8 },
+    Truncate(usize),
+    Nest { offset: usize, depth: usize },
+    Unbalanced { offset: usize, count: usize },
+    Push { offset: usize, count: usize },
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Mutation::BitFlip { offset, bit } => write!(f, "flipped bit {} of byte {}", bit, offset),
+            Mutation::Byte { offset, value } => write!(f, "set byte {} to {:02x}", offset, value),
+            Mutation::Truncate(length) => write!(f, "truncated to {} bytes", length),
+            Mutation::Nest { offset, depth } => write!(f, "nested {} collections at {}", depth, offset),
+            Mutation::Unbalanced { offset, count } => write!(f, "inserted {} end collections at {}", count, offset),
+            Mutation::Push { offset, count } => write!(f, "inserted {} pushes at {}", count, offset),
+        }
+    }
+}
+
+/* xorshift64*, good enough for picking mutations and repeatable */
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        /* xorshift gets stuck at zero */
+        Rng { state: seed ^ 0x9e37_79b9_7f4a_7c15 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /* A number below bound, or 0 if it is 0 */
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound.max(1) as u64) as usize
+    }
+}
+
+/* Makes mutations from a seed */
+pub struct Mutator {
+    rng: Rng,
+}
+
+impl Mutator {
+    pub fn new(seed: u64) -> Mutator {
+        Mutator { rng: Rng::new(seed) }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.rng.below(bound)
+    }
+
+    /* Offsets items start at, plus the end */
+    fn boundary(&mut self, data: &[u8]) -> usize {
+        let mut offsets: Vec<usize> = descriptor::items(data).filter_map(|item| item.ok()).map(|(offset, _)| offset).collect();
+        offsets.push(data.len());
+        offsets[self.below(offsets.len())]
+    }
+
+    /* Returns a mutated copy of a descriptor and what was done to it */
+    pub fn mutate(&mut self, data: &[u8]) -> (Mutation, Vec<u8>) {
+        let mut mutated = data.to_vec();
+        /* the insertions only make sense with some room left */
+        let room = MAX_DESCRIPTOR_LEN.saturating_sub(data.len()) / 3;
+        let kinds = if room > 0 { 6 } else { 3 };
+
+        let mutation = match (self.below(kinds), data.len()) {
+            (_, 0) | (2, 1) => Mutation::Nest { offset: 0, depth: 1 + self.below(room) },
+            (0, length) => Mutation::BitFlip { offset: self.below(length), bit: self.below(8) as u8 },
+            (1, length) => Mutation::Byte { offset: self.below(length), value: self.next() as u8 },
+            (2, length) => Mutation::Truncate(1 + self.below(length - 1)),
+            (3, _) => Mutation::Nest { offset: self.boundary(data), depth: 1 + self.below(room) },
+            (4, _) => Mutation::Unbalanced { offset: self.boundary(data), count: 1 + self.below(room.min(16)) },
+            _ => Mutation::Push { offset: self.boundary(data), count: 1 + self.below(room.min(16)) },
+        };
+
+        match mutation {
+            Mutation::BitFlip { offset, bit } => mutated[offset] ^= 1 << bit,
+            Mutation::Byte { offset, value } => mutated[offset] = value,
+            Mutation::Truncate(length) => mutated.truncate(length),
+            Mutation::Nest { offset, depth } => {
+                /* COLLECTION (Physical) ... END_COLLECTION */
+                let mut items = [0xa1u8, 0x00].repeat(depth);
+                items.extend(vec![0xc0; depth]);
+                mutated.splice(offset..offset, items);
+            },
+            Mutation::Unbalanced { offset, count } => {
+                mutated.splice(offset..offset, vec![0xc0; count]);
+            },
+            Mutation::Push { offset, count } => {
+                mutated.splice(offset..offset, vec![0xa4; count]);
+            },
+        }
+        mutated.truncate(MAX_DESCRIPTOR_LEN);
+        (mutation, mutated)
+    }
+}
+
+pub fn is_crash(message: &str) -> bool {
+    CRASH_MARKERS.iter().any(|marker| message.contains(marker))
+}
+
+/* New messages of the kernel log, from when it was opened on */
+pub struct KernelLog {
+    file: File,
+}
+
+impl KernelLog {
+    pub fn open() -> io::Result<KernelLog> {
+        let mut file = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(KMSG)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(KernelLog { file })
+    }
+
+    /* The messages logged since the last call, without their prefix */
+    pub fn read(&mut self) -> io::Result<Vec<String>> {
+        let mut messages = Vec::new();
+        /* every read returns one record, longer ones are cut to fit */
+        let mut record = [0u8; 8192];
+        loop {
+            match self.file.read(&mut record) {
+                Ok(0) => return Ok(messages),
+                Ok(length) => {
+                    let record = String::from_utf8_lossy(&record[..length]);
+                    /* "<priority>,<sequence>,<time>,<flags>;<message>" and
+                     * continuation lines with the fields of the message */
+                    let message = record.lines().next().unwrap_or_default();
+                    messages.extend(message.split_once(';').map(|(_, message)| message.to_string()));
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(messages),
+                /* records were overwritten before they were read */
+                Err(ref err) if err.raw_os_error() == Some(libc::EPIPE) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+pub struct Outcome {
+    pub started: bool,
+    pub messages: Vec<String>,
+}
+
+impl Outcome {
+    pub fn crashed(&self) -> bool {
+        self.messages.iter().any(|message| is_crash(message))
+    }
+}
+
+/* Creates a device with the descriptor on the non-blocking uhid-cdev, waits
+ * for it to be started and destroys it, collecting what the kernel logged */
+pub fn probe(file: &mut File, log: &mut KernelLog, name: &str, data: &[u8], identity: &Identity) -> io::Result<Outcome> {
+    log.read()?;
+    uhid::create(file, name, data, identity)?;
+    let started = uhid::wait_for(file, uhid_event_type::UHID_START, START_TIMEOUT);
+    uhid::destroy(file)?;
+    /* the destroy races with a late start, drain it for the next device */
+    while uhid::wait_for(file, uhid_event_type::UHID_STOP, Duration::from_millis(0))? {}
+    Ok(Outcome { started: started?, messages: log.read()? })
+}
@@ -0,0 +1,109 @@
+/*
+ * Safety guard
+ * Keeps the automation modes from hammering the session of the user running
+ * them: a cap on the input reports sent per second, reports over it being
+ * dropped, an emergency stop blocking every report until it is lifted, a
+ * pause doing the same while leaving macros and the like running, and asking
+ * on the terminal before typing a long text.
+ *
+ * The cap counts the reports of the last second, so a burst up to it goes
+ * through at once and anything beyond waits for the oldest to age out.
+ * Reports letting go of keys or buttons go through over the cap, so none is
+ * left held; the emergency stop lets go of everything before blocking.
+ */
+
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+
+/* Texts this long are only typed once confirmed */
+pub const CONFIRM_CHARACTERS: usize = 200;
+
+#[derive(Default)]
+pub struct Guard {
+    max_rate: Option<u32>,
+    /* When the reports of the last second were sent */
+    sent: VecDeque<Instant>,
+    /* Whether reports are being dropped over the cap, to warn once */
+    limited: bool,
+    stopped: bool,
+    paused: bool,
+    confirm: bool,
+}
+
+impl Guard {
+    pub fn new(max_rate: Option<u32>, confirm: bool) -> Guard {
+        Guard { max_rate, confirm, ..Guard::default() }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /* Blocks every report, or lifts the block again */
+    pub fn toggle_stop(&mut self) {
+        self.stopped = !self.stopped;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /* Whether a report may be sent now, counting it if so; one releasing
+     * what is held is over the cap too */
+    pub fn allow(&mut self, now: Instant, releasing: bool) -> bool {
+        if self.stopped || self.paused {
+            return false;
+        }
+        let max_rate = match self.max_rate {
+            Some(max_rate) => max_rate as usize,
+            None => return true,
+        };
+
+        while self.sent.front().is_some_and(|&sent| now.saturating_duration_since(sent) >= Duration::from_secs(1)) {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= max_rate {
+            if releasing {
+                self.sent.push_back(now);
+                return true;
+            }
+            if !self.limited {
+                self.limited = true;
+                eprintln!("Over {} reports/s, dropping input", max_rate);
+            }
+            return false;
+        }
+        if self.limited {
+            self.limited = false;
+            eprintln!("Below {} reports/s again", max_rate);
+        }
+        self.sent.push_back(now);
+        true
+    }
+
+    /*
+     * Asks on the terminal whether to type a text of that many characters, if
+     * confirming was asked for and it is long. It is read from /dev/tty, so
+     * this works with stdin carrying commands too; without a terminal the
+     * answer is no.
+     */
+    pub fn confirm_typing(&self, characters: usize) -> io::Result<bool> {
+        if !self.confirm || characters < CONFIRM_CHARACTERS {
+            return Ok(true);
+        }
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        write!(tty, "Type {} characters? [y/N] ", characters)?;
+        /* the terminal is non-canonical, the key is the answer */
+        let mut answer = [0; 1];
+        tty.read_exact(&mut answer)?;
+        writeln!(tty)?;
+        Ok(answer[0] == b'y' || answer[0] == b'Y')
+    }
+}
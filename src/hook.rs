@@ -0,0 +1,105 @@
+/*
+ * Output hooks
+ * Runs a command for every output or feature report the host sends the
+ * device, set with --on-output, so physical actuators or test assertions can react to
+ * what the host asks of the device: a rumble motor, a lightbar, an LED. The
+ * command runs through sh -c and gets the report as a line of JSON on stdin:
+ *
+ *   {"type":"output","report":"02 02","fields":{"Num Lock":0,"Caps Lock":1,"Scroll Lock":0},"values":{...}}
+ *
+ * "report" holds the bytes in hex, including the report-id if numbered,
+ * "fields" its fields named by the report descriptor, see src/decode.rs, and
+ * "values" what the device makes of them: the LEDs of keyboards, the rumble
+ * and lightbar of the dualshock4, the rumble and LED pattern of the xbox360.
+ * Either is empty for reports the descriptor or the device does not decode.
+ *
+ * Commands run one at a time and in order, on a thread of their own so a
+ * slow one does not hold up the device. Reports arriving while QUEUE_SIZE
+ * others are still waiting for it are skipped.
+ */
+
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::Write;
+use std::process::{self, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use uhid::uhid_report_type;
+
+/* Reports waiting for the command to finish */
+const QUEUE_SIZE: usize = 64;
+
+fn type_name(rtype: u8) -> &'static str {
+    match rtype {
+        rtype if rtype == uhid_report_type::UHID_FEATURE_REPORT as u8 => "feature",
+        rtype if rtype == uhid_report_type::UHID_OUTPUT_REPORT as u8 => "output",
+        rtype if rtype == uhid_report_type::UHID_INPUT_REPORT as u8 => "input",
+        _ => "unknown",
+    }
+}
+
+/* The report and its decoded values as one line of JSON */
+pub fn json(rtype: u8, data: &[u8], fields: &[(String, i32)], values: &[(&str, i64)]) -> String {
+    let report: Vec<String> = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let mut line = format!("{{\"type\":\"{}\",\"report\":\"{}\",\"fields\":{{", type_name(rtype), report.join(" "));
+    for (index, &(ref name, value)) in fields.iter().enumerate() {
+        let _ = write!(line, "{}\"{}\":{}", if index > 0 { "," } else { "" }, name, value);
+    }
+    line.push_str("},\"values\":{");
+    for (index, &(name, value)) in values.iter().enumerate() {
+        let _ = write!(line, "{}\"{}\":{}", if index > 0 { "," } else { "" }, name, value);
+    }
+    line.push_str("}}\n");
+    line
+}
+
+/* Runs the command with the line on stdin and waits for it */
+fn run(command: &str, line: &str) -> io::Result<()> {
+    let mut child = process::Command::new("sh").arg("-c").arg(command).stdin(Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        /* a command not reading its input is fine */
+        match stdin.write_all(line.as_bytes()) {
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => (),
+            result => result?,
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        eprintln!("Output hook {}", status);
+    }
+    Ok(())
+}
+
+pub struct OutputHook {
+    sender: SyncSender<String>,
+    skipped: u64,
+}
+
+impl OutputHook {
+    pub fn new(command: String) -> OutputHook {
+        let (sender, receiver) = mpsc::sync_channel::<String>(QUEUE_SIZE);
+        thread::spawn(move || {
+            for line in receiver {
+                if let Err(err) = run(&command, &line) {
+                    eprintln!("Cannot run the output hook: {}", err);
+                }
+            }
+        });
+        OutputHook { sender, skipped: 0 }
+    }
+
+    /* Queues the command for a report; data includes the report-id if numbered */
+    pub fn report(&mut self, rtype: u8, data: &[u8], fields: &[(String, i32)], values: &[(&str, i64)]) {
+        match self.sender.try_send(json(rtype, data, fields, values)) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => {
+                self.skipped += 1;
+                /* every time the count doubles, not to flood the terminal */
+                if self.skipped.is_power_of_two() {
+                    eprintln!("Output hook busy, skipped {} reports", self.skipped);
+                }
+            },
+            Err(TrySendError::Disconnected(_)) => (),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+/*
+ * Terminal keys
+ * Decodes what a terminal in non-canonical mode sends for a key press into
+ * the key and its modifiers: plain characters as they are, Ctrl with a
+ * letter as the control character, Alt as an escape before the key, and the
+ * cursor, editing and function keys as the escape sequences of xterm and
+ * compatible terminals, e.g. "\x1b[1;5A" for Ctrl+Up.
+ *
+ * Keys are named like "k", "ctrl-k", "alt-x", "shift-up" or "f5", and a chord
+ * of keys pressed one after another by their names separated by spaces, e.g.
+ * "g c". Shift with a character is the shifted character itself, "K" rather
+ * than "shift-k".
+ */
+
+use std::fmt;
+
+const ESCAPE: u8 = 0x1b;
+
+/* Longest escape sequence taken for one, anything longer is dropped */
+const MAX_SEQUENCE_LEN: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Key {
+    Char(u8),
+    Tab,
+    Enter,
+    Escape,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+}
+
+const KEY_NAMES: &[(&str, Key)] = &[
+    ("space", Key::Char(b' ')),
+    ("tab", Key::Tab),
+    ("enter", Key::Enter),
+    ("escape", Key::Escape),
+    ("backspace", Key::Backspace),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("insert", Key::Insert),
+    ("delete", Key::Delete),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPress {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+}
+
+impl KeyPress {
+    pub fn plain(key: Key) -> KeyPress {
+        KeyPress { key, ctrl: false, alt: false, shift: false }
+    }
+
+    /* The character of a key pressed without modifiers */
+    pub fn char(&self) -> Option<u8> {
+        match self.key {
+            Key::Char(c) if !self.ctrl && !self.alt && !self.shift => Some(c),
+            _ => None,
+        }
+    }
+
+    /* Parses a key name like "ctrl-k" */
+    pub fn parse(name: &str) -> Option<KeyPress> {
+        let mut press = KeyPress::plain(Key::Escape);
+        let mut rest = name;
+        loop {
+            let (modifier, after) = match rest.split_once('-') {
+                Some((modifier, after)) if !after.is_empty() => (modifier, after),
+                _ => break,
+            };
+            match modifier {
+                "ctrl" => press.ctrl = true,
+                "alt" => press.alt = true,
+                "shift" => press.shift = true,
+                _ => return None,
+            }
+            rest = after;
+        }
+
+        press.key = match KEY_NAMES.iter().find(|&&(key_name, _)| key_name == rest) {
+            Some(&(_, key)) => key,
+            None => match rest.as_bytes() {
+                [c] if c.is_ascii_graphic() => Key::Char(if press.ctrl { c.to_ascii_lowercase() } else { *c }),
+                [b'f', ..] => Key::F(rest[1..].parse().ok().filter(|&number| (1..=12).contains(&number))?),
+                _ => return None,
+            },
+        };
+        /* shifted characters are characters of their own */
+        if press.shift && matches!(press.key, Key::Char(_)) {
+            return None;
+        }
+        Some(press)
+    }
+}
+
+impl fmt::Display for KeyPress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &(set, modifier) in &[(self.ctrl, "ctrl-"), (self.alt, "alt-"), (self.shift, "shift-")] {
+            if set {
+                f.write_str(modifier)?;
+            }
+        }
+        match (self.key, KEY_NAMES.iter().find(|&&(_, key)| key == self.key)) {
+            (_, Some(&(name, _))) => f.write_str(name),
+            (Key::Char(c), None) => write!(f, "{}", c as char),
+            (Key::F(number), None) => write!(f, "f{}", number),
+            _ => unreachable!(),
+        }
+    }
+}
+
+/* Parses a chord, key names separated by spaces */
+pub fn parse_chord(names: &str) -> Option<Vec<KeyPress>> {
+    let chord: Option<Vec<KeyPress>> = names.split_whitespace().map(KeyPress::parse).collect();
+    chord.filter(|chord| !chord.is_empty())
+}
+
+pub fn chord_name(chord: &[KeyPress]) -> String {
+    chord.iter().map(|press| press.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+/* A single byte, as sent for the key alone */
+fn decode_byte(byte: u8) -> KeyPress {
+    match byte {
+        b'\t' => KeyPress::plain(Key::Tab),
+        b'\r' | b'\n' => KeyPress::plain(Key::Enter),
+        ESCAPE => KeyPress::plain(Key::Escape),
+        0x08 | 0x7f => KeyPress::plain(Key::Backspace),
+        0x00 => KeyPress { ctrl: true, ..KeyPress::plain(Key::Char(b' ')) },
+        0x01..=0x1a => KeyPress { ctrl: true, ..KeyPress::plain(Key::Char(b'a' + byte - 1)) },
+        _ => KeyPress::plain(Key::Char(byte)),
+    }
+}
+
+/* The key of a CSI or SS3 sequence with its parameters and final byte */
+fn decode_sequence(parameters: &[u8], last: u8) -> Option<KeyPress> {
+    let parameters = std::str::from_utf8(parameters).ok()?;
+    let mut numbers = parameters.split(';').map(|number| number.parse::<u8>().unwrap_or(1));
+    let first = numbers.next().unwrap_or(1);
+    /* xterm sends the modifiers as one more than a bit mask */
+    let modifiers = numbers.next().unwrap_or(1).saturating_sub(1);
+
+    let key = match last {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        b'P'..=b'S' => Key::F(last - b'P' + 1),
+        b'Z' => return Some(KeyPress { shift: true, ..KeyPress::plain(Key::Tab) }),
+        b'~' => match first {
+            1 | 7 => Key::Home,
+            2 => Key::Insert,
+            3 => Key::Delete,
+            4 | 8 => Key::End,
+            5 => Key::PageUp,
+            6 => Key::PageDown,
+            11..=15 => Key::F(first - 10),
+            17..=21 => Key::F(first - 11),
+            23 | 24 => Key::F(first - 12),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(KeyPress {
+        key,
+        shift: modifiers & 1 != 0,
+        alt: modifiers & 2 != 0,
+        ctrl: modifiers & 4 != 0,
+    })
+}
+
+/* Collects what was read from the terminal and splits the key presses off */
+#[derive(Default)]
+pub struct Decoder {
+    buffer: Vec<u8>,
+}
+
+impl Decoder {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /*
+     * The next complete key press, None until it has been read. Terminals
+     * write a key's sequence at once, so an escape that nothing followed in
+     * the same read is the Escape key itself.
+     */
+    pub fn next_press(&mut self) -> Option<KeyPress> {
+        loop {
+            let (press, length) = match self.buffer.as_slice() {
+                [] => return None,
+                &[ESCAPE] => (Some(KeyPress::plain(Key::Escape)), 1),
+                &[ESCAPE, introducer, ref rest @ ..] if introducer == b'[' || introducer == b'O' => {
+                    match rest.iter().position(|&byte| (0x40..=0x7e).contains(&byte)) {
+                        Some(end) => (decode_sequence(&rest[..end], rest[end]), end + 3),
+                        None if self.buffer.len() < MAX_SEQUENCE_LEN => return None,
+                        None => (None, self.buffer.len()),
+                    }
+                },
+                &[ESCAPE, byte, ..] => (Some(KeyPress { alt: true, ..decode_byte(byte) }), 2),
+                &[byte, ..] => (Some(decode_byte(byte)), 1),
+            };
+            self.buffer.drain(..length);
+            /* sequences of keys not known here are skipped */
+            if press.is_some() {
+                return press;
+            }
+        }
+    }
+}
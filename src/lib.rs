@@ -0,0 +1,71 @@
+/*
+ * UHID Example
+ * The virtual devices are kept in this library so other programs can drive
+ * the devices with their own input, e.g. to script pen strokes, instead of
+ * going through the interactive keyboard mapping of the example binary.
+ *
+ * The workspace is layered: uhid-sys has the raw bindings, uhid the thin
+ * wrapper around /dev/uhid, re-exported here, and this crate the devices and
+ * the binary. Depend on just the layer needed. hid-report has the no_std
+ * report encoding the devices share with firmware.
+ */
+
+pub extern crate hid_report;
+extern crate libc;
+pub extern crate uhid;
+#[cfg(feature = "scripting")]
+extern crate rhai;
+#[cfg(feature = "repl")]
+extern crate rustyline;
+#[cfg(feature = "tls")]
+extern crate rustls;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "logind")]
+extern crate zbus;
+
+pub mod auth;
+pub mod autoclick;
+pub mod backend;
+pub mod barrier;
+pub mod changes;
+pub mod completions;
+pub mod corpus;
+pub mod decode;
+pub mod delay;
+pub mod devices;
+pub mod evemu;
+pub mod exit;
+pub mod farm;
+pub mod fault;
+pub mod ffi;
+pub mod framing;
+pub mod fuzz;
+pub mod guard;
+pub mod hook;
+pub mod keys;
+pub mod limit;
+pub mod logind;
+pub mod macros;
+pub mod merge;
+pub mod metrics;
+pub mod nodes;
+pub mod pcap;
+pub mod pending;
+pub mod plugin;
+pub mod probe;
+pub mod recording;
+pub mod remap;
+pub mod repl;
+pub mod sandbox;
+pub mod schema;
+pub mod schedule;
+pub mod script;
+pub mod session;
+pub mod split;
+pub mod stress;
+pub mod systemd;
+pub mod timing;
+pub mod tls;
+pub mod vsock;
+pub mod watchdog;
@@ -0,0 +1,140 @@
+/*
+ * Client limits
+ * Rate limits each connection to the control socket and of vsock-serve on
+ * its own, so one misbehaving or malicious controller cannot flood the
+ * session, while --max-rate caps the reports of all of them together. With
+ * --client-limit <rate>,<burst> a connection gets a bucket of burst commands,
+ * keys for the control socket, refilled at rate per second:
+ *
+ *   a command takes one out of the bucket and runs
+ *   with the bucket empty it is refused, answered with an error by serve
+ *   once as many commands were refused in a row as the burst, the client is
+ *   disconnected as abusive
+ *
+ * e.g. --client-limit 20,100 lets a client type 100 keys at once and 20 a
+ * second after that.
+ */
+
+use std::time::Instant;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limit {
+    /* commands per second */
+    pub rate: u32,
+    pub burst: u32,
+}
+
+impl Limit {
+    /* The limit of <rate>,<burst>, the burst being the rate without it */
+    pub fn parse(value: &str) -> Option<Limit> {
+        let mut parts = value.splitn(2, ',');
+        let rate = parts.next()?.trim().parse().ok().filter(|&rate| rate > 0)?;
+        let burst = match parts.next() {
+            Some(burst) => burst.trim().parse().ok().filter(|&burst| burst > 0)?,
+            None => rate,
+        };
+        Some(Limit { rate, burst })
+    }
+
+    /* A full bucket for a new connection */
+    pub fn bucket(self, now: Instant) -> Bucket {
+        Bucket { limit: self, tokens: self.burst as f64, last: now, refused: 0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Allow,
+    Refuse,
+    /* refused too often, close the connection */
+    Disconnect,
+}
+
+pub struct Bucket {
+    limit: Limit,
+    tokens: f64,
+    last: Instant,
+    /* commands refused since the last allowed */
+    refused: u32,
+}
+
+impl Bucket {
+    /* Whether a command may run now, taking it out of the bucket if so */
+    pub fn take(&mut self, now: Instant) -> Verdict {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate as f64).min(self.limit.burst as f64);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.refused = 0;
+            Verdict::Allow
+        } else {
+            self.refused += 1;
+            if self.refused >= self.limit.burst { Verdict::Disconnect } else { Verdict::Refuse }
+        }
+    }
+
+    /* Whether the command just refused was the first in a row, to log once */
+    pub fn started_refusing(&self) -> bool {
+        self.refused == 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn parses_limits() {
+        assert_eq!(Limit::parse("20,100"), Some(Limit { rate: 20, burst: 100 }));
+        assert_eq!(Limit::parse(" 5 "), Some(Limit { rate: 5, burst: 5 }));
+        for value in ["0", "0,10", "10,0", "", "10,", "ten", "10,20,30", "-1,5"].iter() {
+            assert_eq!(Limit::parse(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = Limit { rate: 10, burst: 3 }.bucket(start);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(start), Verdict::Allow);
+        }
+        assert_eq!(bucket.take(start), Verdict::Refuse);
+        assert!(bucket.started_refusing());
+
+        /* a token every 100 ms, never more than the burst */
+        assert_eq!(bucket.take(start + Duration::from_millis(100)), Verdict::Allow);
+        assert_eq!(bucket.take(start + Duration::from_millis(150)), Verdict::Refuse);
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(later), Verdict::Allow);
+        }
+        assert_eq!(bucket.take(later), Verdict::Refuse);
+    }
+
+    #[test]
+    fn disconnects_after_a_burst_of_refusals() {
+        let start = Instant::now();
+        let mut bucket = Limit { rate: 1, burst: 3 }.bucket(start);
+        for _ in 0..3 {
+            bucket.take(start);
+        }
+        assert_eq!(bucket.take(start), Verdict::Refuse);
+        assert_eq!(bucket.take(start), Verdict::Refuse);
+        assert!(!bucket.started_refusing());
+        assert_eq!(bucket.take(start), Verdict::Disconnect);
+
+        /* a command allowed in between starts the count over */
+        let mut bucket = Limit { rate: 1, burst: 3 }.bucket(start);
+        for _ in 0..5 {
+            bucket.take(start);
+        }
+        assert_eq!(bucket.take(start + Duration::from_secs(1)), Verdict::Allow);
+        assert_eq!(bucket.take(start + Duration::from_secs(1)), Verdict::Refuse);
+        assert_eq!(bucket.take(start + Duration::from_secs(1)), Verdict::Refuse);
+        assert_eq!(bucket.take(start + Duration::from_secs(1)), Verdict::Disconnect);
+    }
+}
@@ -0,0 +1,90 @@
+/*
+ * logind
+ * Follows the lock state of a login session over D-Bus, so automation
+ * injecting keystrokes into a desktop can hold off while the screen is
+ * locked instead of typing into the lock screen's password field.
+ *
+ * The session is the one given by its id, like $XDG_SESSION_ID, or else the
+ * session active on seat0 when watching starts, which is the desktop shown
+ * on the local display. The lock state is the LockedHint the desktop's
+ * screen locker reports to logind, kept up to date by a thread listening for
+ * its changes.
+ *
+ * Needs the logind feature.
+ */
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "logind")]
+mod watcher {
+    use std::io;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::OwnedObjectPath;
+
+    const LOGIND: &str = "org.freedesktop.login1";
+    const MANAGER_PATH: &str = "/org/freedesktop/login1";
+    const SEAT0_PATH: &str = "/org/freedesktop/login1/seat/seat0";
+
+    fn error(err: zbus::Error) -> io::Error {
+        io::Error::other(format!("logind: {}", err))
+    }
+
+    fn session_path(connection: &Connection, session: Option<&str>) -> zbus::Result<OwnedObjectPath> {
+        match session {
+            Some(id) => {
+                let manager = Proxy::new(connection, LOGIND, MANAGER_PATH, "org.freedesktop.login1.Manager")?;
+                manager.call("GetSession", &(id,))
+            },
+            None => {
+                let seat = Proxy::new(connection, LOGIND, SEAT0_PATH, "org.freedesktop.login1.Seat")?;
+                let (_, path): (String, OwnedObjectPath) = seat.get_property("ActiveSession")?;
+                Ok(path)
+            },
+        }
+    }
+
+    pub fn watch(session: Option<&str>, locked: Arc<AtomicBool>) -> io::Result<()> {
+        let connection = Connection::system().map_err(error)?;
+        let path = session_path(&connection, session).map_err(error)?;
+        let proxy = Proxy::new(&connection, LOGIND, path, "org.freedesktop.login1.Session").map_err(error)?;
+        locked.store(proxy.get_property("LockedHint").map_err(error)?, Ordering::SeqCst);
+
+        thread::spawn(move || {
+            for change in proxy.receive_property_changed::<bool>("LockedHint") {
+                if let Ok(hint) = change.get() {
+                    locked.store(hint, Ordering::SeqCst);
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/* Lock state of the watched session */
+pub struct SessionLock {
+    locked: Arc<AtomicBool>,
+}
+
+impl SessionLock {
+    /* Starts following the session with the given id, or the active one */
+    #[cfg(feature = "logind")]
+    pub fn watch(session: Option<&str>) -> io::Result<SessionLock> {
+        let locked = Arc::new(AtomicBool::new(false));
+        watcher::watch(session, locked.clone())?;
+        Ok(SessionLock { locked })
+    }
+
+    #[cfg(not(feature = "logind"))]
+    pub fn watch(_session: Option<&str>) -> io::Result<SessionLock> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Built without logind support, enable the logind feature"))
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,195 @@
+/*
+ * Macros
+ * Named sequences of input reports with the delays between them, recorded
+ * from a session or written by hand, and played back on demand, optionally
+ * several times in a row. They are kept in a text file:
+ *
+ *   # comment
+ *   macro <name> [<loops>]
+ *   wait <milliseconds>
+ *   report <report bytes>
+ *   end
+ *
+ * Report bytes are hex and include the report-id if numbered; they are sent
+ * as they are, so a macro only makes sense for the device it was made for.
+ * A wait delays the next report. By default the file is macros in the
+ * uhid-example directory of $XDG_CONFIG_HOME, or ~/.config.
+ */
+
+use std::collections::VecDeque;
+use std::env;
+use std::io;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug)]
+pub struct Step {
+    /* Pause before the report, after the previous one */
+    pub delay: Duration,
+    pub report: Vec<u8>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Macro {
+    pub name: String,
+    pub loops: u32,
+    pub steps: Vec<Step>,
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("uhid-example").join("macros"))
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+pub fn parse<R: BufRead>(input: R) -> io::Result<Vec<Macro>> {
+    let mut macros = Vec::new();
+    let mut current: Option<Macro> = None;
+    let mut delay = Duration::from_millis(0);
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+
+        match (keyword, current.as_mut()) {
+            ("macro", None) => {
+                let name = fields.next().ok_or_else(|| invalid(number, "Missing macro name"))?;
+                let loops = match fields.next() {
+                    Some(loops) => loops.parse::<u32>().ok().filter(|&loops| loops > 0)
+                        .ok_or_else(|| invalid(number, "Invalid loop count"))?,
+                    None => 1,
+                };
+                current = Some(Macro { name: name.to_string(), loops, steps: Vec::new() });
+                delay = Duration::from_millis(0);
+            },
+            ("wait", Some(_)) => {
+                let ms = fields.next().and_then(|ms| ms.parse::<u64>().ok())
+                    .ok_or_else(|| invalid(number, "Invalid wait"))?;
+                delay += Duration::from_millis(ms);
+            },
+            ("report", Some(current)) => {
+                let report = fields.map(|byte| u8::from_str_radix(byte, 16))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| invalid(number, "Invalid byte"))?;
+                if report.is_empty() {
+                    return Err(invalid(number, "Empty report"));
+                }
+                current.steps.push(Step { delay, report });
+                delay = Duration::from_millis(0);
+            },
+            ("end", Some(_)) => macros.extend(current.take()),
+            ("macro", Some(_)) => return Err(invalid(number, "Macro not ended")),
+            (_, None) => return Err(invalid(number, "Expected a macro")),
+            _ => return Err(invalid(number, "Unknown statement")),
+        }
+    }
+
+    if current.is_some() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Last macro not ended"));
+    }
+    Ok(macros)
+}
+
+pub fn write<W: Write>(mut output: W, macros: &[Macro]) -> io::Result<()> {
+    for (index, macro_) in macros.iter().enumerate() {
+        if index > 0 {
+            writeln!(output)?;
+        }
+        writeln!(output, "macro {} {}", macro_.name, macro_.loops)?;
+        for step in macro_.steps.iter() {
+            if step.delay > Duration::from_millis(0) {
+                writeln!(output, "wait {}", step.delay.as_millis())?;
+            }
+            let bytes: Vec<String> = step.report.iter().map(|byte| format!("{:02x}", byte)).collect();
+            writeln!(output, "report {}", bytes.join(" "))?;
+        }
+        writeln!(output, "end")?;
+    }
+    output.flush()
+}
+
+/* Records the reports sent, with the delays between them */
+pub struct MacroRecorder {
+    last: Option<Instant>,
+    steps: Vec<Step>,
+}
+
+impl Default for MacroRecorder {
+    fn default() -> MacroRecorder {
+        MacroRecorder::new()
+    }
+}
+
+impl MacroRecorder {
+    pub fn new() -> MacroRecorder {
+        MacroRecorder { last: None, steps: Vec::new() }
+    }
+
+    pub fn input(&mut self, report: &[u8]) {
+        let now = Instant::now();
+        /* the wait before the first report is when recording started */
+        let delay = self.last.map_or(Duration::from_millis(0), |last| now - last);
+        self.last = Some(now);
+        self.steps.push(Step { delay, report: report.to_vec() });
+    }
+
+    pub fn finish(self, name: &str) -> Macro {
+        Macro { name: name.to_string(), loops: 1, steps: self.steps }
+    }
+}
+
+/* Plays macros back, the next report being due at deadline() */
+#[derive(Default)]
+pub struct Player {
+    queue: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl Player {
+    /* Starts playing the macro now, after anything still playing */
+    pub fn play(&mut self, macro_: &Macro, now: Instant) {
+        let mut due = self.queue.back().map_or(now, |&(due, _)| due.max(now));
+        for _ in 0..macro_.loops {
+            for step in macro_.steps.iter() {
+                due += step.delay;
+                self.queue.push_back((due, step.report.clone()));
+            }
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.queue.clear();
+    }
+
+    /* Reports still to be played */
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.queue.front().map(|&(due, _)| due)
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut reports = Vec::new();
+        while self.queue.front().is_some_and(|&(due, _)| due <= now) {
+            reports.extend(self.queue.pop_front().map(|(_, report)| report));
+        }
+        reports
+    }
+}
@@ -13,25 +13,361 @@
 /*
  * UHID Example
  * This example emulates a basic 3 buttons mouse with wheel over UHID. Run this
- * program as root and then use the following keys to control the mouse:
- *   q: Quit the application
- *   1: Toggle left button (down, up, ...)
- *   2: Toggle right button
- *   3: Toggle middle button
- *   a: Move mouse left
- *   d: Move mouse right
- *   w: Move mouse up
- *   s: Move mouse down
- *   r: Move wheel up
- *   f: Move wheel down
+ * program as root and then control the mouse with the keys it lists when it
+ * starts, and again on ?: 1, 2 and 3 toggle the left, right and middle
+ * button, a, d, w and s or the arrow keys move the mouse, r and f or Page Up
+ * and Page Down the wheel, and q quits. The
+ * list is generated from KEYS below along with the macros bound with --bind.
+ *
+ * The keys move by 20 counts, pixels on the Barrier screen; --step sets
+ * another amount, + and - change it by 5 while running and the step command
+ * of --schedule and --fixture sets it. A number typed before a key that
+ * moves, scrolls or clicks repeats it, like a count in vim: 15d moves right
+ * 15 times, 3 Page Down scrolls three detents. Digits wait a second for the
+ * key they count, so 1..5 alone toggle their button after that.
+ *
+ * A detent scrolls the wheel by 1 count, --wheel-step sets another amount.
+ * Scrolling more than a report holds, like "wheel 10" with a step of 30, is
+ * sent as consecutive reports of at most 127 counts each, or --wheel-max to
+ * mimic a wheel reporting less at once, rather than one saturated value.
  *
  * Additionally to 3 button mouse, 3 keyboard LEDs are also supported (LED_NUML,
  * LED_CAPSL and LED_SCROLLL). The device doesn't generate any related keyboard
  * events, though. You need to manually write the EV_LED/LED_XY/1 activation
  * input event to the evdev device to see it being sent to this device.
  *
+ * Once created, the descriptor the kernel registered is read back from
+ * sysfs and compared with the one sent; if they differ, e.g. because it was
+ * cut short, the device is destroyed again rather than used. Once it is
+ * started its hidraw and event nodes are printed, to open without guessing;
+ * src/nodes.rs finds them for other programs.
+ *
+ * Instead of the mouse, one of the device presets can be emulated by passing
+ * --preset <name>; see src/devices for the available presets and how they map
+ * the keys above.
+ *
+ * With keyboard presets, "type <text>" types the given text as soon as the
+ * device is opened and exits afterwards; "type --file <path>" and "type -"
+ * stream a file or stdin instead. --layout selects the host keyboard layout
+ * the text is mapped with, built in or loaded from a layout file, see
+ * src/devices/layout.rs, --key-delay the pause between keys and --rate
+ * limits the keys typed per second. Accented letters are typed through the
+ * dead keys of the layout; --substitute types those it cannot produce at
+ * all without their accent instead of refusing the text. --unicode types
+ * any character the layout lacks as Ctrl+Shift+U, its code point and Enter,
+ * for applications using IBus or GTK. While typing, space pauses and resumes
+ * and q stops; when typing stdin, use Ctrl-Z and fg instead.
+ *
+ * --repeat <delay-ms>,<keys/s> makes the keyboard preset repeat held keys
+ * itself, the way some keyboard firmware does. The keyboard preset follows
+ * the LED state set by the host and types text accordingly; --sync-leds makes
+ * it start out with the host's current lock state.
+ *
+ * --preset multi-report sends vendor defined reports of several ids and
+ * sizes interleaved, to test report-ID demuxing; --interleave sets their
+ * order, see src/devices/multi_report.rs.
+ * --preset large-report has 256 byte input, output and feature reports, to
+ * check reports past 64 bytes make it through in one piece, see
+ * src/devices/large_report.rs.
+ *
+ * --template <name>[:<variable>=<value>,...] renders the descriptor of a
+ * family of devices from a template instead of taking a preset, e.g.
+ * "--template rel-mouse:buttons=8,resolution=16,minimum=-32767,maximum=32767"
+ * for a mouse with 8 buttons and 16 bit axes. The library has mice, 6KRO
+ * and NKRO keyboards, a gamepad, touchscreen, pen tablet, consumer controls
+ * and raw vendor reports; templates list prints them and templates show
+ * <name> the variables of one and the descriptor it renders. See
+ * src/devices/template.rs.
+ *
+ * --dpi sets the simulated resolution of the mouse, which 4 cycles through at
+ * runtime; --dpi-report additionally exposes it as a vendor feature report.
+ * --humanize spreads mouse moves over short paths with jitter, speed
+ * variation and overshoot, like a hand would produce. --absolute adds an
+ * absolute pointer to the mouse, 5 switches between it and the relative one.
+ * --hi-res-wheel gives the mouse a wheel reporting eighths of a detent once
+ * the kernel enables it; "scroll <pixels> <duration>" in a schedule, on the
+ * control socket or with --fixture then scrolls smoothly with it, e.g.
+ * "scroll 480 300ms", see src/devices/mouse.rs.
+ *
+ * --plugin loads device types from a shared library, see src/plugin.rs, and
+ * makes them available to --preset. It can be given several times and needs
+ * the plugins feature.
+ *
+ * --script runs a Rhai script driving the device, see src/script.rs. It needs
+ * the scripting feature.
+ *
+ * --record writes the input reports sent to a file in the hid-recorder format,
+ * which hid-replay from hid-tools can play back.
+ *
+ * replay <file> reproduces a device from a recording in that format, e.g. a
+ * capture of a real device made with hid-recorder: it is created with the
+ * recorded descriptor and identity, and once opened the recorded reports are
+ * sent with their original timing. --format pcap replays a usbmon capture
+ * from Wireshark or tcpdump instead, see src/pcap.rs, of the interface
+ * chosen with --interface or else the first HID one. --format evemu replays
+ * an evemu-record recording through a device made up to have the recorded
+ * capabilities, see src/evemu.rs for what can be reproduced.
+ *
+ * --speed plays a recording faster or slower, e.g. 2 or 0.5, and --from and
+ * --to replay just the part between two times of the recording, like 1.5s
+ * and 3s, starting right away with the first event from then. With
+ * --single-step nothing is sent by itself: n sends the next event, so the
+ * effect of each report can be watched.
+ *
+ * --latency holds back replayed or forwarded reports by a fixed time, like
+ * 20ms, and --jitter by a random extra time on top, drawn as chosen with
+ * --jitter-distribution, uniform by default, and following from --seed. This
+ * tests input smoothing and prediction under a bad link. See src/delay.rs.
+ * --fault drop=0.01,dup=0.001 drops or duplicates them at random instead,
+ * with the given probabilities, like a flaky wireless link; see
+ * src/fault.rs.
+ *
+ * --session-log <file> logs the commands received, from the terminal, a
+ * control socket, the fixture or the schedule, with their times. rerun <file>
+ * sends the commands of such a log again once the device is opened, through
+ * the same translation into reports, and exits after the last. See
+ * src/session.rs.
+ *
+ * --sandbox restricts the process with a seccomp filter once everything is
+ * opened, so it can only keep using the uhid-cdev, stdin and the other files
+ * it already has open. See src/sandbox.rs.
+ *
+ * --pause-on-lock drops the input reports while the desktop session is
+ * locked, rather than typing into the lock screen; the session is the one
+ * active on seat0 unless given with --session. It needs the logind feature,
+ * see src/logind.rs.
+ *
+ * Started by systemd with socket activation, the keys are read from
+ * connections to the passed socket instead of the terminal, q closing the
+ * connection. With Type=notify, readiness is reported once the kernel
+ * started the device. See src/systemd.rs.
+ *
+ * --token-file <file> reads a token that connections to the control socket
+ * and vsock-serve have to send first, UHID_EXAMPLE_TOKEN giving it without a
+ * file; a control socket reachable over TCP needs one. Every connection is
+ * audited on stderr with its peer and what it sent. See src/auth.rs.
+ *
+ * --tls-cert <file> and --tls-key <file> encrypt the connections to the
+ * control socket with TLS, and --tls-client-ca <file> has clients show a
+ * certificate of its CAs, which a control socket on TCP can do with instead
+ * of a token. It needs the tls feature, see src/tls.rs.
+ *
+ * --client-limit <rate>[,<burst>] rate limits every connection on its own,
+ * refusing commands over it and disconnecting clients that keep sending
+ * them; see src/limit.rs.
+ *
+ * --metrics <address> serves /metrics over HTTP for Prometheus on the given
+ * address, like 127.0.0.1:9464, counting reports, kernel events and errors
+ * of long running devices; see src/metrics.rs.
+ *
+ * --stats <interval> prints the reports, bytes and kernel events per second
+ * every interval, e.g. 1s, along with the bytes written, the output reports
+ * received and the reports dropped or coalesced so far.
+ *
+ * --timing timestamps every report written and keeps histograms of the
+ * intervals between them and their jitter, printed along with --stats and
+ * when the device is destroyed. --timing-readback also reads the reports
+ * back from the event nodes, for the time until evdev delivers them; see
+ * src/timing.rs.
+ *
+ * --on-output <command> runs the command for every output or feature report
+ * the host sends, with the report, its fields named by the descriptor and the
+ * values the device decodes from it, like rumble or LEDs, as JSON on stdin,
+ * e.g. to drive a physical actuator or check what a game asks of a gamepad.
+ * See src/hook.rs. The same fields are logged with the UHID_OUTPUT and
+ * UHID_SET_REPORT events.
+ *
+ * --max-rate caps the input reports sent per second, whatever sends them,
+ * dropping the reports over it but those letting go of keys or buttons, so a
+ * runaway script or schedule cannot flood the session. Escape is an emergency
+ * stop: it stops macros, typing and the autoclicker, lets go of everything
+ * held and blocks all input until pressed again. p pauses sending
+ * reports without stopping anything, the device staying up, and pressing it
+ * again resumes; the pause and resume commands of --fixture, the serve modes
+ * and --schedule do the same. --confirm asks on the terminal before typing a
+ * text of 200 characters or more, given with type, type --file or a type
+ * command; text streamed from stdin is not asked about. See src/guard.rs.
+ *
+ * --only-changes leaves out an input report the same as the last one sent
+ * with its report-id, for all of them or the report-ids given, like 1,3, so
+ * state ticked out on a timer only reaches the kernel when it changes. Give
+ * the ids of state reports if the device has relative axes, the same move
+ * twice being two moves. See src/changes.rs.
+ *
+ * --trace-raw hex-dumps every uhid_event written to and read from the kernel
+ * to stderr, with its type and length, to debug what the kernel made of a
+ * descriptor or report.
+ *
+ * Macros are sequences of reports with delays kept in a file, see
+ * src/macros.rs for the format and default location; --macros reads another
+ * one. --bind <keys>=<macro> plays a macro when the keys are pressed, x
+ * stops playing: a single key, a key with modifiers like ctrl-k, alt-x or
+ * shift-up, or a chord of keys pressed one after another like "g c"; see
+ * src/keys.rs for the names. Control sockets only send single plain keys,
+ * the terminal all of them. m starts recording the
+ * reports sent, and pressing it again saves them to the file as the macro
+ * named with --record-macro, "recorded" by default.
+ *
+ * --schedule runs tasks from a file at given times of day or repeatedly, like
+ * typing a text or clicking; see src/schedule.rs for the format.
+ *
+ * autoclick makes c start and stop clicking a button, the left one unless
+ * given with --button, --cps times per second holding it down for --hold
+ * each time, 20ms by default.
+ *
+ * hotplug destroys the device and creates it again every --interval, 5s by
+ * default, to test how desktops, games and udev rules cope with devices
+ * coming and going; everything else keeps running in between. With
+ * --cycle-ids the product id is one higher each time, so it shows up as
+ * another device.
+ *
+ * --watchdog <interval> creates the device again once it is wedged: writes
+ * failing for the interval, or the kernel not answering its creation within
+ * it, e.g. across a reload of the uhid module. Failed writes no longer end
+ * the program then. It cannot be combined with --sandbox, which keeps the
+ * uhid-cdev from being opened again. See src/watchdog.rs.
+ *
+ * --fixture is meant for test suites of libinput, compositors and the like:
+ * once the device is started its event nodes are printed to stdout, one
+ * DEVNAME=/dev/input/eventN line each, then its hidraw node as
+ * HIDRAW=/dev/hidrawN, followed by READY=1. Commands like
+ * the ones of --schedule are then read from stdin one per line, e.g.
+ * "move 10 0" or "report 01 00 05 00 00", and closing stdin destroys the
+ * device.
+ *
+ * repl shows a prompt instead of the keys, with line editing, completion
+ * and history, taking the same commands as the fixture, e.g. "move 10 0",
+ * "click left" or "macro login"; help lists them and quit or Ctrl-D destroys
+ * the device. It needs the repl feature, see src/repl.rs.
+ *
+ * --wait-for-node <timeout> waits, up to the timeout, until the event nodes
+ * of the device exist and can be opened, i.e. udev is done with them, and
+ * prints their paths to stdout before anything else happens; systemd is only
+ * notified of the service being ready then too. Scripts can read the line and
+ * open the node right away without racing udev. The fixture prints them in
+ * its own format instead.
+ *
+ * --forward makes stdin carry raw input reports instead, each preceded by its
+ * length as a 16 bit little endian number, e.g. piped from another program or
+ * over ssh; see src/framing.rs. They are sent to the kernel as they are, and closing stdin destroys the device.
+ *
+ * stdio-serve takes the same commands framed that way on stdin, answering
+ * each with a frame on stdout, so "ssh host uhid-example stdio-serve" gives
+ * remote control over an encrypted channel without setting up any network
+ * access. See Serve below for the answers, the events it notifies of, and
+ * list and select for addressing the devices of a split one.
+ *
+ * vsock-serve <port> and serial-serve <port-device> serve them to the host of
+ * a virtual machine instead, so test tooling on the host can drive input in
+ * the guest at the HID level rather than through QEMU's emulated devices:
+ * the guest runs e.g. "uhid-example --preset keyboard vsock-serve 5000" as a
+ * service, and the host connects to port 5000 of the guest's vsock context
+ * id, one connection at a time; see src/vsock.rs. serial-serve uses a
+ * virtio-serial port like /dev/virtio-ports/org.example.uhid, added with a
+ * <channel> in libvirt, and keeps it open when the host end disconnects.
+ *
+ * barrier <server> makes this a client screen of a Barrier or Synergy server,
+ * port 24800 unless given as <server>:<port>: a keyboard and absolute
+ * pointer move in as the screen, and the server's mouse and keyboard control
+ * it once the pointer crosses over, without a display server on this end.
+ * --screen sets the size of the screen in pixels, 1920x1080 by default, and
+ * --screen-name its name in the server's configuration, the hostname by
+ * default. Losing the connection destroys the device. See src/barrier.rs.
+ *
+ * merge <event-node>,<event-node> reads several evdev devices, e.g. a keypad
+ * and a mouse, and sends their input as one composite keyboard and pointer,
+ * to present a single device to a VM or a game; see src/merge.rs. A source
+ * going away destroys the device. --grab <key> grabs the sources, so their
+ * events reach the session only through the merged device; the key, named
+ * like scroll-lock or left-control, lets go of them and grabs them again.
+ * --remap <file> swaps buttons, inverts and scales axes and maps keys to
+ * others or to macros on the way, as a TOML table; see src/remap.rs.
+ *
+ * --evemu <file> writes what the kernel made of the device as evemu-describe
+ * would, see src/evemu.rs, and destroys it again, to turn a preset into a
+ * device description for evemu-device or libinput's tests. A device with
+ * several event nodes gets a file for each, the further ones with .1, .2 and
+ * so on appended.
+ *
+ * fuzz-rdesc creates and destroys devices with mutations of the preset's
+ * descriptor over and over, --iterations times or until interrupted, to find
+ * descriptors the kernel's HID parser rejects or worse, crashes on; see
+ * src/fuzz.rs. Mutations follow from --seed, printed at the start, and the
+ * descriptors that were rejected or crashed the kernel are written to the
+ * --findings directory as recordings that replay reproduces. It stops at the
+ * first crash, as the kernel cannot be trusted after one. With --corpus the
+ * descriptors in the files of that directory are mutated in turn instead.
+ *
+ * stress-lifecycle creates and destroys devices of the preset --iterations
+ * times, 1000 by default, spread over --parallel threads with a uhid-cdev
+ * each, and checks each was started and its uevents came and went; see
+ * src/stress.rs.
+ *
+ * farm creates --count devices of the preset, given with --type or
+ * --preset, and moves them all around at once --rate times per second, 100
+ * by default, over --parallel threads, printing the reports per second of
+ * all of them, until SIGINT or SIGTERM. For load testing compositors and
+ * games against many input devices; see src/farm.rs.
+ *
+ * capabilities prints what the running kernel's uhid supports, CREATE2,
+ * INPUT2, GET_REPORT and SET_REPORT, and the dev_flags it reports, found out
+ * with a device created for the purpose; see src/probe.rs. Devices are
+ * created with CREATE2 and sent INPUT2 wherever the kernel has them, falling
+ * back to the legacy requests on its own otherwise, so no flag is needed
+ * for old kernels.
+ *
+ * corpus <dir> writes --count valid descriptors of varied shape to the
+ * directory, 100 by default, as raw bytes in one file each, to seed
+ * fuzz-rdesc or other fuzzers. They follow from --seed like the mutations.
+ * See src/corpus.rs.
+ *
+ * completions <bash|zsh|fish> prints a completion script for the shell,
+ * made from the usage, e.g. to save to
+ * /usr/share/bash-completion/completions/uhid-example; see
+ * src/completions.rs. schema prints the JSON schema of the commands of the
+ * fixture, the REPL and the serve modes for tools driving them; see
+ * src/schema.rs.
+ *
+ * pattern <shape> keeps the pointer moving along a circle, spiral, lissajous
+ * figure or square, e.g. to check the smoothness of pointer motion: --radius
+ * sets its size in counts and --period the time per round, like 2s or 500ms.
+ * See src/devices/pattern.rs.
+ *
+ * --backend mock runs the device without uhid, keeping the reports in memory
+ * instead, as if it was started and opened right away; see src/backend.rs.
+ * uhid is the default. --dry-run, short for --backend dry-run, prints every
+ * report to stdout instead, in hex and decoded with the descriptor, so
+ * descriptors and scripts can be worked on without root or CONFIG_UHID.
+ *
+ * --split registers one device per top-level collection of the descriptor
+ * instead of a single one, like the interfaces of a composite USB device, so
+ * each function gets its own hidraw and event nodes and driver. Input reports
+ * go to the device declaring their report-id. See src/split.rs.
+ *
+ * --name, --bus, --vid, --pid, --device-version and --country override the
+ * values the preset identifies itself with, e.g. to trigger driver matching
+ * or quirks.
+ *
  * If uhid is not available as /dev/uhid, then you can pass a different path as
  * first argument.
+ *
+ * Failures end with a single line on stderr and an exit code telling their
+ * cause, like 3 for permission denied on the uhid-cdev or 130 once SIGINT or
+ * SIGTERM destroyed the device. See src/exit.rs. --output json writes it as
+ * a JSON object instead, with the code and where it went wrong, like the
+ * report-id or the offset in the descriptor, for CI harnesses to read.
+ *
+ * Android kernels have uhid as /dev/uhid too, so input can be injected into a
+ * phone or emulator over adb. Build with the NDK, pointing bindgen at its
+ * sysroot for <linux/uhid.h>:
+ *   ANDROID_NDK_SYSROOT=$NDK/toolchains/llvm/prebuilt/linux-x86_64/sysroot \
+ *     cargo build --target aarch64-linux-android
+ *   adb push target/aarch64-linux-android/debug/uhid-example /data/local/tmp
+ *   adb root
+ *   adb shell /data/local/tmp/uhid-example keyboard
+ * On user builds without adb root, run it through su on a rooted device. The
+ * interactive keys need a terminal, so use adb shell -t.
+ *
  * If <linux/uhid.h> is not installed in /usr, then compile this with:
  *   gcc -o ./uhid_test -Wall -I./include ./samples/uhid/uhid-example.c
  * And ignore the warning about kernel headers. However, it is recommended to
@@ -42,477 +378,3204 @@ extern crate libc;
 extern crate mio;
 extern crate nix;
 extern crate termios;
+extern crate uhid_example;
 
+use uhid_example::devices::replay::Replay;
+use uhid_example::recording::{self, Recorder};
+use uhid_example::repl::Repl;
+use uhid_example::auth::{self, Auth};
+use uhid_example::autoclick::{self, Autoclicker};
+use uhid_example::logind::SessionLock;
+use uhid_example::macros::{self, Macro, MacroRecorder, Player, Step};
+use uhid_example::metrics::{self, Metrics, Readout};
+use uhid_example::backend::{self, Backend, DryRun, Mock, Uhid, BACKEND_NAMES};
+use uhid_example::barrier::{self, Barrier, Client};
+use uhid_example::changes::{Changes, Only};
+use uhid_example::completions::{self, Shell, SHELL_NAMES};
+use uhid_example::decode::{Kind, Reports};
+use uhid_example::delay::{Delay, Distribution, DISTRIBUTION_NAMES};
+use uhid_example::exit::{self, Code, Failure};
+use uhid_example::fault::{Faults, Injector};
+use uhid_example::framing::{self, Deframer};
+use uhid_example::guard::Guard;
+use uhid_example::hook::OutputHook;
+use uhid_example::merge::Merge;
+use uhid_example::remap::Remap;
+use uhid_example::nodes::Nodes;
+use uhid_example::keys::{self, Decoder, Key, KeyPress};
+use uhid_example::limit::{Bucket, Limit, Verdict};
+use uhid_example::schedule::{self, Command, Schedule};
+use uhid_example::session::{Rerun, SessionLog};
+use uhid_example::split::Split;
+use uhid_example::systemd::{self, Notifier};
+use uhid_example::timing::{self, Timing};
+use uhid_example::tls::Acceptor;
+use uhid_example::watchdog::Watchdog;
+use uhid_example::{corpus, devices, evemu, farm, fuzz, nodes, pcap, plugin, probe, sandbox, schema, script, stress, uhid, vsock};
+
+use devices::keyboard::{self, Repeat, Typist};
+use devices::layout::{self, Layout};
+use devices::multi_report::Interleave;
+use devices::pattern::{self, Pattern, Shape};
+use devices::screen::Screen;
+use devices::template::{self, Template};
+use devices::{Action, Device};
 use mio::{Events, Poll, PollOpt, Ready, Token};
 use mio::unix::EventedFd;
 use nix::fcntl;
-use nix::unistd;
+use std::collections::HashMap;
 use std::env;
-use std::ffi::CString;
-use std::fs::File;
+use std::fmt::Write as FmtWrite;
+use std::fs;
+use std::fs::{File, OpenOptions};
 use std::io;
-use std::io::{Read, Write};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::mem;
-use std::os::unix::io::FromRawFd;
-use std::path::PathBuf;
 use std::process;
-use std::slice;
+use std::ptr;
+use std::thread;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use termios::*;
+use uhid::{uhid_event_type, Event, Identity};
 
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+const DEFAULT_PATH: &str = "/dev/uhid";
+const DEFAULT_PRESET: &str = "mouse";
+const DEFAULT_SCREEN_SIZE: (u16, u16) = (1920, 1080);
 
-/*
- * HID Report Desciptor
- * We emulate a basic 3 button mouse with wheel and 3 keyboard LEDs. This is
- * the report-descriptor as the kernel will parse it:
- *
- * INPUT(1)[INPUT]
- *   Field(0)
- *     Physical(GenericDesktop.Pointer)
- *     Application(GenericDesktop.Mouse)
- *     Usage(3)
- *       Button.0001
- *       Button.0002
- *       Button.0003
- *     Logical Minimum(0)
- *     Logical Maximum(1)
- *     Report Size(1)
- *     Report Count(3)
- *     Report Offset(0)
- *     Flags( Variable Absolute )
- *   Field(1)
- *     Physical(GenericDesktop.Pointer)
- *     Application(GenericDesktop.Mouse)
- *     Usage(3)
- *       GenericDesktop.X
- *       GenericDesktop.Y
- *       GenericDesktop.Wheel
- *     Logical Minimum(-128)
- *     Logical Maximum(127)
- *     Report Size(8)
- *     Report Count(3)
- *     Report Offset(8)
- *     Flags( Variable Relative )
- * OUTPUT(2)[OUTPUT]
- *   Field(0)
- *     Application(GenericDesktop.Keyboard)
- *     Usage(3)
- *       LED.NumLock
- *       LED.CapsLock
- *       LED.ScrollLock
- *     Logical Minimum(0)
- *     Logical Maximum(1)
- *     Report Size(1)
- *     Report Count(3)
- *     Report Offset(0)
- *     Flags( Variable Absolute )
- *
- * This is the mapping that we expect:
- *   Button.0001 ---> Key.LeftBtn
- *   Button.0002 ---> Key.RightBtn
- *   Button.0003 ---> Key.MiddleBtn
- *   GenericDesktop.X ---> Relative.X
- *   GenericDesktop.Y ---> Relative.Y
- *   GenericDesktop.Wheel ---> Relative.Wheel
- *   LED.NumLock ---> LED.NumLock
- *   LED.CapsLock ---> LED.CapsLock
- *   LED.ScrollLock ---> LED.ScrollLock
- *
- * This information can be verified by reading /sys/kernel/debug/hid/<dev>/rdesc
- * This file should print the same information as showed above.
- */
+#[cfg(not(target_os = "android"))]
+const PERMISSION_HINT: &str = "Run as root, or give your user access to the uhid-cdev, e.g. with a udev rule adding it to a group";
+/* Only the shell of a rooted device or of an adb root session may use it */
+#[cfg(target_os = "android")]
+const PERMISSION_HINT: &str = "Run as root, e.g. after adb root or through su";
 
-const RDESC: [u8; 85] = [
-    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
-    0x09, 0x02,	/* USAGE (Mouse) */
-    0xa1, 0x01,	/* COLLECTION (Application) */
-    0x09, 0x01,		/* USAGE (Pointer) */
-    0xa1, 0x00,		/* COLLECTION (Physical) */
-    0x85, 0x01,			/* REPORT_ID (1) */
-    0x05, 0x09,			/* USAGE_PAGE (Button) */
-    0x19, 0x01,			/* USAGE_MINIMUM (Button 1) */
-    0x29, 0x03,			/* USAGE_MAXIMUM (Button 3) */
-    0x15, 0x00,			/* LOGICAL_MINIMUM (0) */
-    0x25, 0x01,			/* LOGICAL_MAXIMUM (1) */
-    0x95, 0x03,			/* REPORT_COUNT (3) */
-    0x75, 0x01,			/* REPORT_SIZE (1) */
-    0x81, 0x02,			/* INPUT (Data,Var,Abs) */
-    0x95, 0x01,			/* REPORT_COUNT (1) */
-    0x75, 0x05,			/* REPORT_SIZE (5) */
-    0x81, 0x01,			/* INPUT (Cnst,Var,Abs) */
-    0x05, 0x01,			/* USAGE_PAGE (Generic Desktop) */
-    0x09, 0x30,			/* USAGE (X) */
-    0x09, 0x31,			/* USAGE (Y) */
-    0x09, 0x38,			/* USAGE (WHEEL) */
-    0x15, 0x81,			/* LOGICAL_MINIMUM (-127) */
-    0x25, 0x7f,			/* LOGICAL_MAXIMUM (127) */
-    0x75, 0x08,			/* REPORT_SIZE (8) */
-    0x95, 0x03,			/* REPORT_COUNT (3) */
-    0x81, 0x06,			/* INPUT (Data,Var,Rel) */
-    0xc0,			/* END_COLLECTION */
-    0xc0,		/* END_COLLECTION */
-    0x05, 0x01,	/* USAGE_PAGE (Generic Desktop) */
-    0x09, 0x06,	/* USAGE (Keyboard) */
-    0xa1, 0x01,	/* COLLECTION (Application) */
-    0x85, 0x02,		/* REPORT_ID (2) */
-    0x05, 0x08,		/* USAGE_PAGE (Led) */
-    0x19, 0x01,		/* USAGE_MINIMUM (1) */
-    0x29, 0x03,		/* USAGE_MAXIMUM (3) */
-    0x15, 0x00,		/* LOGICAL_MINIMUM (0) */
-    0x25, 0x01,		/* LOGICAL_MAXIMUM (1) */
-    0x95, 0x03,		/* REPORT_COUNT (3) */
-    0x75, 0x01,		/* REPORT_SIZE (1) */
-    0x91, 0x02,		/* Output (Data,Var,Abs) */
-    0x95, 0x01,		/* REPORT_COUNT (1) */
-    0x75, 0x05,		/* REPORT_SIZE (5) */
-    0x91, 0x01,		/* Output (Cnst,Var,Abs) */
-    0xc0,		/* END_COLLECTION */
-];
+/* Formats of the recordings that can be replayed */
+enum Format {
+    HidRecorder,
+    Pcap,
+    Evemu,
+}
 
-const DEFAULT_PATH: &str = "/dev/uhid";
+/* Where text to type comes from */
+enum Text {
+    Inline(String),
+    File(PathBuf),
+    Stdin,
+}
 
+/* What a control key does */
 #[derive(Clone, Copy)]
-struct DeviceState {
-    btn1_down: bool,
-    btn2_down: bool,
-    btn3_down: bool,
+enum Binding {
+    Device(Action),
+    /* moves by the movement step in a direction */
+    Move(i8, i8),
+    /* changes the movement step */
+    Step(i8),
+    Quit,
+    Help,
+    RecordMacro,
+    StopMacro,
+    Autoclick,
+    Stop,
+    Pause,
+    NextEvent,
 }
 
-impl Default for DeviceState {
-    fn default() -> DeviceState {
-        DeviceState {
-            btn1_down: false,
-            btn2_down: false,
-            btn3_down: false,
-        }
+/* The control keys of the terminal and control sockets, listed in this order
+ * by ?. Macros cannot be bound to them, nor to space, which pauses typing. */
+const KEYS: &[(Key, Binding, &str)] = &[
+    (Key::Char(b'1'), Binding::Device(Action::ToggleButton(1)), "Toggle button 1, left"),
+    (Key::Char(b'2'), Binding::Device(Action::ToggleButton(2)), "Toggle button 2, right"),
+    (Key::Char(b'3'), Binding::Device(Action::ToggleButton(3)), "Toggle button 3, middle"),
+    (Key::Char(b'4'), Binding::Device(Action::ToggleButton(4)), "Toggle button 4, if the preset has it"),
+    (Key::Char(b'5'), Binding::Device(Action::ToggleButton(5)), "Toggle button 5, if the preset has it"),
+    (Key::Char(b'a'), Binding::Move(-1, 0), "Move left"),
+    (Key::Char(b'd'), Binding::Move(1, 0), "Move right"),
+    (Key::Char(b'w'), Binding::Move(0, -1), "Move up"),
+    (Key::Char(b's'), Binding::Move(0, 1), "Move down"),
+    (Key::Char(b'r'), Binding::Device(Action::Wheel(1)), "Wheel up"),
+    (Key::Char(b'f'), Binding::Device(Action::Wheel(-1)), "Wheel down"),
+    (Key::Left, Binding::Move(-1, 0), "Move left"),
+    (Key::Right, Binding::Move(1, 0), "Move right"),
+    (Key::Up, Binding::Move(0, -1), "Move up"),
+    (Key::Down, Binding::Move(0, 1), "Move down"),
+    (Key::PageUp, Binding::Device(Action::Wheel(1)), "Wheel up"),
+    (Key::PageDown, Binding::Device(Action::Wheel(-1)), "Wheel down"),
+    (Key::Char(b'+'), Binding::Step(STEP_CHANGE), "Increase the movement step"),
+    (Key::Char(b'-'), Binding::Step(-STEP_CHANGE), "Decrease the movement step"),
+    (Key::Char(b'm'), Binding::RecordMacro, "Start recording a macro, or stop and save it"),
+    (Key::Char(b'x'), Binding::StopMacro, "Stop playing macros"),
+    (Key::Char(b'c'), Binding::Autoclick, "Start or stop the autoclicker"),
+    (Key::Char(b'n'), Binding::NextEvent, "Send the next event of the recording"),
+    (Key::Char(b'p'), Binding::Pause, "Pause or resume sending reports"),
+    (Key::Escape, Binding::Stop, "Emergency stop, blocking all input until pressed again"),
+    (Key::Char(b'?'), Binding::Help, "Show these keys"),
+    (Key::Char(b'q'), Binding::Quit, "Quit"),
+];
+const DEFAULT_MACRO_NAME: &str = "recorded";
+/* Counts moved per key press unless given with --step, and what + and -
+ * change it by */
+const DEFAULT_STEP: i8 = 20;
+const STEP_CHANGE: i8 = 5;
+/* How long digits wait for a key to count before they are keys themselves */
+const COUNT_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_COUNT: u32 = 999;
+const NODE_TIMEOUT: Duration = Duration::from_secs(2);
+const DEFAULT_HOTPLUG_INTERVAL: Duration = Duration::from_secs(5);
+
+/* Highest country code defined by the HID specification (Turkish-F) */
+const MAX_COUNTRY: u32 = 35;
+
+const STDIN: Token = Token(0);
+const UHID_DEVICE: Token = Token(1);
+const CONTROL: Token = Token(2);
+const METRICS: Token = Token(3);
+const BARRIER: Token = Token(4);
+const CHANNEL: Token = Token(5);
+const VSOCK: Token = Token(6);
+const REPL: Token = Token(7);
+/* The sources of merge, after the control clients, whose tokens stay below */
+const SOURCES: usize = 1 << 16;
+
+/* Whether a client may run a command now, auditing it getting limited */
+fn admit(bucket: Option<&mut Bucket>, peer: &str) -> Verdict {
+    let bucket = match bucket {
+        Some(bucket) => bucket,
+        None => return Verdict::Allow,
+    };
+    let verdict = bucket.take(Instant::now());
+    if verdict == Verdict::Refuse && bucket.started_refusing() {
+        auth::audit(peer, format_args!("over the rate limit, refusing commands"));
     }
+    verdict
+}
+
+/*
+ * Connections to the control socket passed in by systemd, each sending the
+ * same keys as the terminal. q closes the connection instead of quitting.
+ * With a token, see src/auth.rs, a connection first sends it on a line;
+ * with TLS, see src/tls.rs, everything is read through the session.
+ */
+struct Control {
+    listener: RawFd,
+    auth: Option<Auth>,
+    tls: Option<Acceptor>,
+    limit: Option<Limit>,
+    clients: HashMap<Token, ControlClient>,
+    /* tokens of closed clients, taken again before new ones */
+    free: Vec<Token>,
+    next: usize,
+    accepted: u64,
+}
+
+struct ControlClient {
+    fd: RawFd,
+    stream: Box<dyn Read>,
+    peer: String,
+    /* the line of the token so far, until it is complete */
+    token: Option<Vec<u8>>,
+    bucket: Option<Bucket>,
+    keys: u64,
 }
 
-impl DeviceState {
-    fn toggle_btn1(&mut self) {
-        self.btn1_down = !self.btn1_down;
+impl Control {
+    fn new(listener: RawFd, auth: Option<Auth>, tls: Option<Acceptor>, limit: Option<Limit>) -> Control {
+        /* accepted until it would block, which systemd's sockets do not
+         * unless NonBlocking=true */
+        unsafe { libc::fcntl(listener, libc::F_SETFL, libc::fcntl(listener, libc::F_GETFL) | libc::O_NONBLOCK) };
+        Control {
+            listener,
+            auth,
+            tls,
+            limit,
+            clients: HashMap::new(),
+            free: vec![],
+            next: REPL.0 + 1,
+            accepted: 0,
+        }
+    }
+
+    /* A token for a new client, None once all below SOURCES are taken */
+    fn token(&mut self) -> Option<Token> {
+        self.free.pop().or_else(|| {
+            if self.next >= SOURCES {
+                return None;
+            }
+            self.next += 1;
+            Some(Token(self.next - 1))
+        })
+    }
+
+    fn accept(&mut self, poll: &Poll) -> io::Result<()> {
+        loop {
+            let fd = unsafe { libc::accept4(self.listener, ptr::null_mut(), ptr::null_mut(), libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC) };
+            if fd < 0 {
+                let err = io::Error::last_os_error();
+                return if err.kind() == io::ErrorKind::WouldBlock { Ok(()) } else { Err(err) };
+            }
+
+            self.accepted += 1;
+            let peer = format!("control connection {} from {}", self.accepted, auth::peer(fd));
+            let socket = unsafe { File::from_raw_fd(fd) };
+            let token = match self.token() {
+                Some(token) => token,
+                None => {
+                    auth::audit(&peer, format_args!("refused, too many connections"));
+                    continue;
+                },
+            };
+            let stream = match self.tls.as_ref() {
+                Some(tls) => tls.accept(socket),
+                None => Ok(Box::new(socket) as Box<dyn Read>),
+            };
+            let registered = stream.and_then(|stream| {
+                poll.register(&EventedFd(&fd), token, Ready::readable(), PollOpt::edge()).map(|()| stream)
+            });
+            let stream = match registered {
+                Ok(stream) => stream,
+                Err(err) => {
+                    self.free.push(token);
+                    return Err(err);
+                },
+            };
+            auth::audit(&peer, format_args!("accepted{}", if self.tls.is_some() { " over TLS" } else { "" }));
+            let client = ControlClient {
+                fd,
+                stream,
+                peer,
+                token: self.auth.as_ref().map(|_| vec![]),
+                bucket: self.limit.map(|limit| limit.bucket(Instant::now())),
+                keys: 0,
+            };
+            self.clients.insert(token, client);
+        }
     }
-    fn toggle_btn2(&mut self) {
-        self.btn2_down = !self.btn2_down;
+
+    /* Handles what a client sent, until it would block */
+    fn read(&mut self, token: Token, poll: &Poll, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device) -> io::Result<()> {
+        let mut buffer = [0; 64];
+        loop {
+            let read = match self.clients.get_mut(&token) {
+                Some(client) => client.stream.read(&mut buffer),
+                None => return Ok(()),
+            };
+            let size = match read {
+                Ok(size) => size,
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                /* e.g. a TLS handshake failing */
+                Err(err) => return self.close(token, poll, &format!("closed, {}", err)),
+            };
+            if size == 0 {
+                return self.close(token, poll, "closed");
+            }
+
+            let mut keys = &buffer[..size];
+            let authenticated = match (self.clients.get_mut(&token), self.auth.as_ref()) {
+                (Some(&mut ControlClient { token: Some(ref mut line), .. }), Some(auth)) => {
+                    let end = keys.iter().position(|&character| character == b'\n');
+                    line.extend_from_slice(&keys[..end.unwrap_or(keys.len())]);
+                    match end {
+                        Some(end) => {
+                            let offered = if line.last() == Some(&b'\r') { &line[..line.len() - 1] } else { &line[..] };
+                            keys = &keys[end + 1..];
+                            Some(auth.check(offered))
+                        },
+                        None if line.len() > auth::MAX_TOKEN => Some(false),
+                        None => continue,
+                    }
+                },
+                _ => None,
+            };
+            match authenticated {
+                Some(true) => {
+                    let client = self.clients.get_mut(&token).unwrap();
+                    client.token = None;
+                    auth::audit(&client.peer, format_args!("authenticated"));
+                },
+                Some(false) => return self.close(token, poll, "refused, wrong token"),
+                None => (),
+            }
+
+            for &character in keys.iter().filter(|character| !character.is_ascii_whitespace()) {
+                if character == b'q' {
+                    return self.close(token, poll, "closed with q");
+                }
+                let verdict = match self.clients.get_mut(&token) {
+                    Some(client) => {
+                        let verdict = admit(client.bucket.as_mut(), &client.peer);
+                        if verdict == Verdict::Allow {
+                            client.keys += 1;
+                        }
+                        verdict
+                    },
+                    None => return Ok(()),
+                };
+                match verdict {
+                    Verdict::Allow => typed(backend, output, device, character)?,
+                    Verdict::Refuse => (),
+                    Verdict::Disconnect => return self.close(token, poll, "disconnected over the rate limit"),
+                }
+            }
+        }
     }
-    fn toggle_btn3(&mut self) {
-        self.btn3_down = !self.btn3_down;
+
+    fn close(&mut self, token: Token, poll: &Poll, why: &str) -> io::Result<()> {
+        if let Some(client) = self.clients.remove(&token) {
+            auth::audit(&client.peer, format_args!("{} after {} keys", why, client.keys));
+            poll.deregister(&EventedFd(&client.fd))?;
+            self.free.push(token);
+        }
+        Ok(())
     }
 }
 
+/* The fields of a report the host sent, named by the descriptor, to log */
+fn describe_fields(reports: &Reports, rtype: u8, data: &[u8]) -> String {
+    let fields = match Kind::from_rtype(rtype) {
+        Some(kind) => reports.describe(kind, data),
+        None => String::new(),
+    };
+    if fields.is_empty() { fields } else { format!(": {}", fields) }
+}
 
-#[derive(Clone, Copy)]
-struct InputEvent {
-    btn1_down: bool,
-    btn2_down: bool,
-    btn3_down: bool,
-    abs_hor: i8,
-    abs_ver: i8,
-    wheel: i8,
-}
-
-impl InputEvent {
-    fn from_state(state: &DeviceState) -> InputEvent {
-        InputEvent {
-            btn1_down: state.btn1_down,
-            btn2_down: state.btn2_down,
-            btn3_down: state.btn2_down,
-            abs_hor: 0,
-            abs_ver: 0,
-            wheel: 0,
-        }
-    }
-}
-
-fn uhid_write(file: &mut File, uhid_event: &uhid_event) -> io::Result<()> {
-    let uhid_event_slice: &[u8];
-    let uhid_event_size = mem::size_of::<uhid_event>();
-    unsafe {
-        uhid_event_slice = slice::from_raw_parts(
-            uhid_event as *const _ as *const u8,
-            uhid_event_size
-        );
-    }
-    match file.write(uhid_event_slice) {
-        Ok(bytes_written) =>
-            if bytes_written != uhid_event_size {
-                Err(io::Error::new(io::ErrorKind::Interrupted, format!("Wrong size written to uhid: {} != {}", bytes_written, uhid_event_size)))
-            } else {
-                Ok(())
-            },
-        Err(err) => Err(io::Error::new(err.kind(), format!("Cannot write to uhid: {}", err)))
+/* The same as values, for --on-output */
+fn field_values(reports: &Reports, rtype: u8, data: &[u8]) -> Vec<(String, i32)> {
+    match Kind::from_rtype(rtype) {
+        Some(kind) => reports.values(kind, data),
+        None => vec![],
     }
 }
 
-fn create(file: &mut File) -> io::Result<()> {
-    let mut rdesc = RDESC;
-    let mut ev: uhid_event = unsafe { mem::zeroed() };
+/* Handles the next event for the device, None if there is none; reports
+ * are the parsed descriptor of the device */
+fn handle_event(backend: &mut dyn Backend, device: &mut dyn Device, reports: &Reports, metrics: &mut Metrics) -> io::Result<Option<Event>> {
+    let event = match backend.read_event()? {
+        Some(event) => event,
+        None => return Ok(None),
+    };
+    metrics.event(&event);
+
+    match event {
+        Event::Start => eprintln!("UHID_START from uhid-dev"),
+        Event::Stop => eprintln!("UHID_STOP from uhid-dev"),
+        Event::Open => eprintln!("UHID_OPEN from uhid-dev"),
+        Event::Close => eprintln!("UHID_CLOSE from uhid-dev"),
+        Event::Output { rtype, ref data } => {
+            eprintln!("UHID_OUTPUT from uhid-dev{}", describe_fields(reports, rtype, data));
+            device.handle_output(rtype, data);
+        },
+        Event::GetReport { id, rnum, rtype } => {
+            eprintln!("UHID_GET_REPORT from uhid-dev");
+            let result = device.get_report(rnum, rtype);
+            backend.get_report_reply(id, result)?;
+        },
+        Event::SetReport { id, rnum, rtype, ref data } => {
+            eprintln!("UHID_SET_REPORT from uhid-dev{}", describe_fields(reports, rtype, data));
+            let result = device.set_report(rnum, rtype, data);
+            backend.set_report_reply(id, result)?;
+        },
+        Event::Other(type_) if type_ == uhid_event_type::__UHID_LEGACY_OUTPUT_EV as u32 => eprintln!("UHID_OUTPUT_EV from uhid-dev"),
+        Event::Other(type_) => eprintln!("Invalid event from uhid-dev: {}", type_),
+    };
+
+    Ok(Some(event))
+}
+
+/* Macros from the macro backend, played with the keys bound to them */
+struct Macros {
+    path: Option<PathBuf>,
+    macros: Vec<Macro>,
+    bindings: Vec<(Vec<KeyPress>, String)>,
+    /* keys of a chord pressed so far */
+    chord: Vec<KeyPress>,
+    /* name the next recording is saved as */
+    record_name: String,
+    recording: Option<MacroRecorder>,
+    player: Player,
+}
+
+impl Macros {
+    fn play(&mut self, name: &str) {
+        if let Some(macro_) = self.macros.iter().find(|macro_| macro_.name == name) {
+            eprintln!("Playing macro {}", name);
+            self.player.play(macro_, Instant::now());
+        }
+    }
 
-    ev.type_ = uhid_event_type::__UHID_LEGACY_CREATE as u32;
+    /* Starts recording, or stops and saves the recording to the macro file */
+    fn toggle_recording(&mut self) -> io::Result<()> {
+        let recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => {
+                eprintln!("Recording macro {}, press m again to stop", self.record_name);
+                self.recording = Some(MacroRecorder::new());
+                return Ok(());
+            }
+        };
 
-    unsafe {
-        let create = ev.u.create.as_mut();
-        create.name.copy_from_slice(
-            &[CString::new("test-uhid-device").unwrap().as_bytes_with_nul(), &[0u8; 111]].concat());
-        create.rd_data = &mut rdesc[0] as *mut u8;
-        create.rd_size = rdesc.len() as u16;
-        create.bus = BUS_USB as u16;
-        create.vendor = 0x15d9;
-        create.product = 0x0a37;
-        create.version = 0;
-        create.country = 0;
+        let recorded = recording.finish(&self.record_name);
+        self.macros.retain(|macro_| macro_.name != recorded.name);
+        self.macros.push(recorded);
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "No macro file to save to")),
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        macros::write(File::create(path)?, &self.macros)?;
+        eprintln!("Saved macro {} to {}", self.record_name, path.display());
+        Ok(())
     }
+}
 
-    uhid_write(file, &ev)
+/* What happens to input reports besides being sent */
+struct Output {
+    recorder: Option<Recorder<File>>,
+    /* reports are dropped while the session is locked */
+    lock: Option<SessionLock>,
+    paused: bool,
+    macros: Macros,
+    autoclicker: Option<Autoclicker>,
+    metrics: Metrics,
+    session_log: Option<SessionLog<File>>,
+    /* counts moved per key press */
+    step: i8,
+    /* counts scrolled per detent, and at most per report */
+    wheel_step: i8,
+    wheel_max: i8,
+    count: Count,
+    guard: Guard,
+    stepper: Option<Stepper>,
+    /* replayed and forwarded reports are held back by --latency and --jitter */
+    delay: Option<Delay>,
+    /* and dropped or duplicated by --fault */
+    injector: Option<Injector>,
+    timing: Option<Timing>,
+    watchdog: Option<Watchdog>,
+    /* unchanged reports are left out with --only-changes */
+    changes: Option<Changes>,
+    /* the descriptor of the device, parsed once to name the fields of the
+     * reports the host sends */
+    reports: Reports,
+    /* the last input report sent, by report-id, for what it holds */
+    last: HashMap<u8, Vec<u8>>,
 }
 
-fn destroy(file: &mut File) -> io::Result<()>
-{
-    let mut ev: uhid_event = unsafe { mem::zeroed() };
+/* The events of a recording replayed with --single-step, sent one per n */
+struct Stepper {
+    events: Vec<(Duration, Vec<u8>)>,
+    next: usize,
+}
 
-    ev.type_ = uhid_event_type::UHID_DESTROY as u32;
+impl Stepper {
+    fn new(events: Vec<(Duration, Vec<u8>)>) -> Stepper {
+        Stepper { events, next: 0 }
+    }
+
+    fn next_event(&mut self) -> Option<&(Duration, Vec<u8>)> {
+        let event = self.events.get(self.next)?;
+        self.next += 1;
+        Some(event)
+    }
+}
 
-    uhid_write(file, &ev)
+/* Unplugs the device and plugs it back in every interval with hotplug */
+struct Hotplug {
+    interval: Duration,
+    next: Instant,
+    cycle_ids: bool,
+    cycles: u32,
 }
 
-/* This parses raw output reports sent by the kernel to the device. A normal
- * uhid program shouldn't do this but instead just forward the raw report.
- * However, for ducomentational purposes, we try to detect LED events here and
- * print debug messages for it. */
-fn handle_output(ev: &uhid_event) {
-    unsafe {
-        let ev_output = ev.u.output.as_ref();
+impl Hotplug {
+    fn new(interval: Duration, cycle_ids: bool) -> Hotplug {
+        Hotplug { interval, next: Instant::now() + interval, cycle_ids, cycles: 0 }
+    }
+
+    fn deadline(&self) -> Instant {
+        self.next
+    }
 
-        /* LED messages are adverised via OUTPUT reports; ignore the rest */
-        if ev_output.rtype != uhid_report_type::UHID_OUTPUT_REPORT as u8 {
-            return;
+    /* The identity to plug the device back in with once it is due; the
+     * product id counts up each time with --cycle-ids */
+    fn tick(&mut self, now: Instant, identity: Identity) -> Option<Identity> {
+        if now < self.next {
+            return None;
         }
-        /* LED reports have length 2 bytes */
-        if ev_output.size != 2 {
-            return;
+        self.next = now + self.interval;
+        self.cycles += 1;
+        let mut identity = identity;
+        if self.cycle_ids {
+            identity.product = (identity.product + self.cycles) & 0xffff;
         }
-        /* first byte is report-id which is 0x02 for LEDs in our rdesc */
-        if ev_output.data[0] != 0x2 {
-            return;
+        Some(identity)
+    }
+}
+
+/*
+ * A count typed before a key, vim-style: 15d moves right 15 times and 3r
+ * scrolls up three detents. The digits 1..5 are keys of their own, so digits
+ * are held until the key they count, and taken as keys if it is one that
+ * doesn't repeat or none follows within COUNT_TIMEOUT.
+ */
+#[derive(Default)]
+struct Count {
+    digits: Vec<u8>,
+    deadline: Option<Instant>,
+}
+
+impl Count {
+    fn is_pending(&self) -> bool {
+        !self.digits.is_empty()
+    }
+
+    fn push(&mut self, digit: u8, now: Instant) {
+        self.digits.push(digit);
+        self.deadline = Some(now + COUNT_TIMEOUT);
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn take(&mut self) -> Vec<u8> {
+        self.deadline = None;
+        mem::take(&mut self.digits)
+    }
+
+    /* The digits nothing followed in time */
+    fn expire(&mut self, now: Instant) -> Vec<u8> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => self.take(),
+            _ => vec![],
         }
+    }
+}
 
-        /* print flags payload */
-        eprintln!("LED output report received with flags {:x}", ev_output.data[1]);
+/* How often the count typed before it repeats a key, the digits being keys
+ * if the binding doesn't repeat */
+fn counted(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, binding: Option<Binding>) -> io::Result<u32>
+{
+    let digits = output.count.take();
+    match binding {
+        Some(Binding::Device(_)) | Some(Binding::Move(..)) | Some(Binding::Step(_)) if !digits.is_empty() => {
+            let count = digits.iter().fold(0, |count: u32, &digit| count.saturating_mul(10).saturating_add((digit - b'0') as u32));
+            Ok(count.min(MAX_COUNT))
+        },
+        _ => {
+            for digit in digits {
+                log(output, &Command::Key(digit))?;
+                key(backend, output, device, digit)?;
+            }
+            Ok(1)
+        }
     }
 }
 
-fn handle_event(file: &mut File) -> io::Result<()> {
-    let mut ev: uhid_event = unsafe { mem::zeroed() };
-    let uhid_event_size = mem::size_of::<uhid_event>();
+/* Logs a command received to the session log */
+fn log(output: &mut Output, command: &Command) -> io::Result<()> {
+    match output.session_log.as_mut() {
+        Some(session_log) => session_log.command(command),
+        None => Ok(()),
+    }
+}
 
-    unsafe {
-        let uhid_event_slice = slice::from_raw_parts_mut(
-            &mut ev as *mut _ as *mut u8,
-            uhid_event_size
-        );
-        file.read_exact(uhid_event_slice).unwrap();
+/* Sends an input report, recording it if the session is recorded */
+fn send(backend: &mut dyn Backend, output: &mut Output, report: &[u8]) -> io::Result<()> {
+    let locked = output.lock.as_ref().is_some_and(|lock| lock.is_locked());
+    if locked != output.paused {
+        output.paused = locked;
+        eprintln!("{}", if locked { "Session locked, input paused" } else { "Session unlocked, input resumed" });
+    }
+    if locked {
+        output.metrics.report_dropped();
+        return Ok(());
+    }
+    if output.changes.as_ref().is_some_and(|changes| changes.is_unchanged(report)) {
+        output.metrics.report_unchanged();
+        return Ok(());
+    }
+    let report_id = if output.reports.is_numbered() { report.first().cloned().unwrap_or(0) } else { 0 };
+    let releasing = output.last.get(&report_id).is_some_and(|last| output.reports.releases(last, report));
+    if !output.guard.allow(Instant::now(), releasing) {
+        output.metrics.report_dropped();
+        return Ok(());
     }
 
-    match from_u32_to_maybe_uhid_event_type(ev.type_).unwrap() {
-        uhid_event_type::UHID_START => eprintln!("UHID_START from uhid-dev"),
-        uhid_event_type::UHID_STOP => eprintln!("UHID_STOP from uhid-dev"),
-        uhid_event_type::UHID_OPEN => eprintln!("UHID_OPEN from uhid-dev"),
-        uhid_event_type::UHID_CLOSE => eprintln!("UHID_CLOSE from uhid-dev"),
-        uhid_event_type::UHID_OUTPUT => {
-            eprintln!("UHID_OUTPUT from uhid-dev");
-            handle_output(&ev);
+    if let Some(recorder) = output.recorder.as_mut() {
+        recorder.input(report)?;
+    }
+    if let Some(recording) = output.macros.recording.as_mut() {
+        recording.input(report);
+    }
+    if let Some(timing) = output.timing.as_mut() {
+        timing.write(timing::monotonic());
+    }
+    match backend.input(report) {
+        Ok(()) => {
+            output.metrics.report_sent(report);
+            output.last.insert(report_id, report.to_vec());
+            if let Some(changes) = output.changes.as_mut() {
+                changes.sent(report);
+            }
         },
-        uhid_event_type::__UHID_LEGACY_OUTPUT_EV => eprintln!("UHID_OUTPUT_EV from uhid-dev"),
-        _ => eprintln!("Invalid event from uhid-dev: {}", ev.type_),
+        /* lost, for the watchdog to recover from if writes keep failing */
+        Err(_) if output.watchdog.is_some() => output.metrics.write_error(),
+        Err(err) => {
+            output.metrics.write_error();
+            return Err(err);
+        }
+    }
+    match output.timing.as_mut() {
+        Some(timing) => timing.read_back(),
+        None => Ok(()),
+    }
+}
+
+/* Sends a replayed or forwarded input report, through the faults injected
+ * and once it is due if delayed */
+fn relay(backend: &mut dyn Backend, output: &mut Output, report: Vec<u8>) -> io::Result<()> {
+    let reports = match output.injector.as_mut() {
+        Some(injector) => injector.apply(report),
+        None => vec![report],
     };
+    if reports.is_empty() {
+        output.metrics.report_dropped();
+    }
+    for report in reports {
+        match output.delay.as_mut() {
+            Some(delay) => delay.push(report, Instant::now()),
+            None => send(backend, output, &report)?,
+        }
+    }
+    Ok(())
+}
 
+/* Makes stdin non-blocking, for the frames read until nothing is left */
+fn nonblocking_stdin() -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(libc::STDIN_FILENO, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
     Ok(())
 }
 
-fn from_u32_to_maybe_uhid_event_type(value: u32) -> Option<uhid_event_type> {
-    if value == uhid_event_type::__UHID_LEGACY_CREATE as u32 {
-        Some(uhid_event_type::__UHID_LEGACY_CREATE)
-    } else if value == uhid_event_type::UHID_DESTROY as u32 {
-        Some(uhid_event_type::UHID_DESTROY)
-    } else if value == uhid_event_type::UHID_START as u32 {
-        Some(uhid_event_type::UHID_START)
-    } else if value == uhid_event_type::UHID_STOP as u32 {
-        Some(uhid_event_type::UHID_STOP)
-    } else if value == uhid_event_type::UHID_OPEN as u32 {
-        Some(uhid_event_type::UHID_OPEN)
-    } else if value == uhid_event_type::UHID_CLOSE as u32 {
-        Some(uhid_event_type::UHID_CLOSE)
-    } else if value == uhid_event_type::UHID_OUTPUT as u32 {
-        Some(uhid_event_type::UHID_OUTPUT)
-    } else if value == uhid_event_type::__UHID_LEGACY_OUTPUT_EV as u32 {
-        Some(uhid_event_type::__UHID_LEGACY_OUTPUT_EV)
-    } else if value == uhid_event_type::__UHID_LEGACY_INPUT as u32 {
-        Some(uhid_event_type::__UHID_LEGACY_INPUT)
-    } else if value == uhid_event_type::UHID_GET_REPORT as u32 {
-        Some(uhid_event_type::UHID_GET_REPORT)
-    } else if value == uhid_event_type::UHID_GET_REPORT_REPLY as u32 {
-        Some(uhid_event_type::UHID_GET_REPORT_REPLY)
-    } else if value == uhid_event_type::UHID_CREATE2 as u32 {
-        Some(uhid_event_type::UHID_CREATE2)
-    } else if value == uhid_event_type::UHID_INPUT2 as u32 {
-        Some(uhid_event_type::UHID_INPUT2)
-    } else if value == uhid_event_type::UHID_SET_REPORT as u32 {
-        Some(uhid_event_type::UHID_SET_REPORT)
-    } else if value == uhid_event_type::UHID_SET_REPORT_REPLY as u32 {
-        Some(uhid_event_type::UHID_SET_REPORT_REPLY)
-    } else {
-        None
+/* Answers the scrapers waiting on the metrics listener */
+fn serve_metrics(listener: &TcpListener, output: &Output) -> io::Result<()> {
+    loop {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        /* accepted sockets are blocking, unlike the listener; a scraper sends
+         * its request right away, don't wait on others. Both work within the
+         * sandbox, which allows only this socket option */
+        stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let text = output.metrics.render(output.macros.player.len());
+        if let Err(err) = metrics::respond(&mut stream, &text) {
+            eprintln!("Cannot answer metrics request: {}", err);
+        }
     }
 }
 
-fn send_event(file: &mut File, input: &InputEvent) -> io::Result<()> {
-    let mut ev: uhid_event = unsafe { mem::zeroed() };
+fn keyboard(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, decoder: &mut Decoder) -> io::Result<()>
+{
+    /* a blocking read returns what is there, read on until nothing is left */
+    let mut buffer = [0; 64];
+    loop {
+        let size = io::stdin().read(&mut buffer)?;
+        decoder.push(&buffer[..size]);
+        let mut pending: libc::c_int = 0;
+        if size == 0 || unsafe { libc::ioctl(libc::STDIN_FILENO, libc::FIONREAD, &mut pending) } < 0 || pending == 0 {
+            break;
+        }
+    }
+    while let Some(press) = decoder.next_press() {
+        pressed(backend, output, device, press)?;
+    }
+    Ok(())
+}
+
+/* Handles a key pressed on the terminal, a control key or part of a chord */
+fn pressed(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, press: KeyPress) -> io::Result<()>
+{
+    output.macros.chord.push(press);
+    let chord = &output.macros.chord;
+    if let Some((_, name)) = output.macros.bindings.iter().find(|(keys, _)| keys == chord) {
+        let name = name.clone();
+        output.macros.chord.clear();
+        counted(backend, output, device, None)?;
+        log(output, &Command::Macro(name.clone()))?;
+        output.macros.play(&name);
+        return Ok(());
+    }
+    if output.macros.bindings.iter().any(|(keys, _)| keys.starts_with(chord)) {
+        return Ok(());
+    }
+
+    let chord = mem::take(&mut output.macros.chord);
+    match press.char() {
+        _ if chord.len() > 1 => eprintln!("No binding for {}", keys::chord_name(&chord)),
+        Some(character) => return typed(backend, output, device, character),
+        None => match KEYS.iter().find(|binding| KeyPress::plain(binding.0) == press) {
+            Some(&(_, binding, _)) => {
+                for _ in 0..counted(backend, output, device, Some(binding))? {
+                    /* keys without a character are logged as what they do */
+                    match binding {
+                        Binding::Move(dx, dy) => log(output, &Command::Move(dx * output.step, dy * output.step))?,
+                        Binding::Device(Action::Wheel(delta)) => log(output, &Command::Wheel(delta.into()))?,
+                        _ => (),
+                    }
+                    act(backend, output, device, binding)?;
+                }
+                return Ok(());
+            },
+            None => eprintln!("Invalid input: {}", press),
+        },
+    }
+    Ok(())
+}
 
-    ev.type_ = uhid_event_type::__UHID_LEGACY_INPUT as u32;
+/* Handles a character typed on the terminal or a control socket, counted
+ * and logged */
+fn typed(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, character: u8) -> io::Result<()>
+{
+    let bound = output.macros.bindings.iter().any(|(keys, _)| keys.len() == 1 && keys[0].char() == Some(character));
+    if character.is_ascii_digit() && !bound && (character != b'0' || output.count.is_pending()) {
+        output.count.push(character, Instant::now());
+        return Ok(());
+    }
 
-    unsafe {
-        let uhid_input = ev.u.input.as_mut();
-        uhid_input.size = 5;
-        uhid_input.data[0] = 0x1;
-        if input.btn1_down {
-            uhid_input.data[1] |= 0x1;
-        }
-        if input.btn2_down {
-            uhid_input.data[1] |= 0x2;
+    let binding = KEYS.iter().find(|binding| binding.0 == Key::Char(character)).map(|&(_, binding, _)| binding);
+    for _ in 0..counted(backend, output, device, binding)? {
+        /* q ends the session rather than being part of it */
+        if character.is_ascii_graphic() && character != b'q' {
+            log(output, &Command::Key(character))?;
         }
-        if input.btn3_down {
-            uhid_input.data[1] |= 0x4;
-        }
-        uhid_input.data[2] = input.abs_hor as u8;
-        uhid_input.data[3] = input.abs_ver as u8;
-        uhid_input.data[4] = input.wheel as u8;
+        key(backend, output, device, character)?;
     }
-
-    uhid_write(file, &ev)
+    Ok(())
 }
 
-fn keyboard(file: &mut File, state: &mut DeviceState) -> io::Result<()>
+/* Handles a control key, from the terminal or a control socket */
+fn key(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, character: u8) -> io::Result<()>
 {
-    let mut character: [u8; 1] = Default::default();
-    io::stdin().read(&mut character)?;
+    if let Some((_, name)) = output.macros.bindings.iter().find(|(keys, _)| keys.len() == 1 && keys[0].char() == Some(character)) {
+        let name = name.clone();
+        output.macros.play(&name);
+        return Ok(());
+    }
 
-    let input_event = match character[0] {
-        b'1' => {
-            state.toggle_btn1();
-            InputEvent::from_state(state)
-        },
-        b'2' => {
-            state.toggle_btn2();
-            InputEvent::from_state(state)
+    match KEYS.iter().find(|binding| binding.0 == Key::Char(character)) {
+        Some(&(_, binding, _)) => act(backend, output, device, binding),
+        None => {
+            eprintln!("Invalid input: {}", character as char);
+            Ok(())
+        }
+    }
+}
+
+/* Does what a control key is bound to */
+fn act(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, binding: Binding) -> io::Result<()>
+{
+    let action = match binding {
+        Binding::Device(Action::Wheel(detents)) => return wheel(backend, output, device, detents.into()),
+        Binding::Device(action) => action,
+        Binding::Move(dx, dy) => Action::Move(dx * output.step, dy * output.step),
+        Binding::Step(change) => {
+            set_step(output, output.step.saturating_add(change));
+            return Ok(());
         },
-        b'3' => {
-            state.toggle_btn3();
-            InputEvent::from_state(state)
+        Binding::Quit => {
+            return Err(io::Error::other("Cancelled"));
         },
-        b'a' => {
-            let mut input = InputEvent::from_state(state);
-            input.abs_hor = -20;
-            input
+        Binding::Help => {
+            print!("{}", help(output));
+            return Ok(());
         },
-        b'd' => {
-            let mut input = InputEvent::from_state(state);
-            input.abs_hor = 20;
-            input
+        Binding::RecordMacro => {
+            if let Err(err) = output.macros.toggle_recording() {
+                eprintln!("Cannot save macro: {}", err);
+            }
+            return Ok(());
         },
-        b'w' => {
-            let mut input = InputEvent::from_state(state);
-            input.abs_ver = -20;
-            input
+        Binding::StopMacro => {
+            output.macros.player.stop();
+            return Ok(());
         },
-        b's' => {
-            let mut input = InputEvent::from_state(state);
-            input.abs_ver = 20;
-            input
+        Binding::Stop => {
+            if !output.guard.is_stopped() {
+                output.macros.player.stop();
+                output.count.take();
+                let release = match output.autoclicker.as_mut() {
+                    Some(autoclicker) if autoclicker.is_running() => autoclicker.toggle(Instant::now()),
+                    _ => vec![],
+                };
+                for report in release.into_iter().flat_map(|action| device.action(action)) {
+                    send(backend, output, &report)?;
+                }
+                /* let go of whatever is still held before blocking */
+                let held: Vec<Vec<u8>> = output.last.values().filter_map(|last| {
+                    let released = output.reports.released(last);
+                    Some(released).filter(|released| output.reports.releases(last, released))
+                }).collect();
+                for report in held {
+                    send(backend, output, &report)?;
+                }
+            }
+            output.guard.toggle_stop();
+            eprintln!("{}", if output.guard.is_stopped() { "Emergency stop, input blocked until Escape is pressed again" } else { "Input resumed" });
+            return Ok(());
         },
-        b'r' => {
-            let mut input = InputEvent::from_state(state);
-            input.wheel = 1;
-            input
+        Binding::Pause => {
+            let paused = !output.guard.is_paused();
+            set_paused(output, paused);
+            return Ok(());
         },
-        b'f' => {
-            let mut input = InputEvent::from_state(state);
-            input.wheel = -1;
-            input
+        Binding::NextEvent => {
+            let stepped = output.stepper.as_mut().map(|stepper| (stepper.next_event().cloned(), stepper.next, stepper.events.len()));
+            match stepped {
+                Some((Some((time, report)), number, total)) => {
+                    eprintln!("Event {} of {} at {}.{:06}s", number, total, time.as_secs(), time.subsec_micros());
+                    send(backend, output, &report)?;
+                },
+                Some((None, _, _)) => eprintln!("End of the recording"),
+                None => (),
+            }
+            return Ok(());
         },
-        b'q' => {
-            return Err(io::Error::new(io::ErrorKind::Other, "Cancelled"));
+        Binding::Autoclick => {
+            if let Some(autoclicker) = output.autoclicker.as_mut() {
+                let release = autoclicker.toggle(Instant::now());
+                eprintln!("Autoclicker {}", if autoclicker.is_running() { "started" } else { "stopped" });
+                for report in release.into_iter().flat_map(|action| device.action(action)) {
+                    send(backend, output, &report)?;
+                }
+            }
+            return Ok(());
         },
-        c => {
-            eprintln!("Invalid input: {}", c as char);
-            return Ok(())
-        }
     };
 
-    send_event(file, &input_event)?;
+    for report in device.action(action) {
+        send(backend, output, &report)?;
+    }
 
     Ok(())
 }
 
-fn main() {
-    let mut device_state = Default::default();
-
-    match Termios::from_fd(libc::STDIN_FILENO) {
-        Err(_) => eprintln!("Cannot get tty state"),
-        Ok(mut state) => {
-            state.c_lflag &= !ICANON;
-            state.c_cc[VMIN] = 1;
-            match tcsetattr(libc::STDIN_FILENO, TCSANOW, &state) {
-                Err(_) => eprintln!("Cannot set tty state"),
-                Ok(_) => ()
-            }
+/* Scrolls by detents of the wheel step, as many reports as that takes */
+fn wheel(backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, detents: i16) -> io::Result<()> {
+    for delta in devices::wheel_deltas(detents as i32 * output.wheel_step as i32, output.wheel_max) {
+        for report in device.action(Action::Wheel(delta)) {
+            send(backend, output, &report)?;
         }
     }
+    Ok(())
+}
 
-    let path = match env::args().nth(1) {
-        Some(arg) => {
-            if arg == "-h" || arg == "--help" {
-                eprintln!("Usage: {} [{}]", env::args().nth(0).unwrap(), DEFAULT_PATH);
-                return;
-            } else {
-                PathBuf::from(arg)
-            }
+/* Sets the movement step, clamped to 1..=127 */
+fn set_step(output: &mut Output, step: i8) {
+    output.step = step.max(1);
+    eprintln!("Movement step {}", output.step);
+}
+
+/* The control keys in effect, one per line */
+fn help(output: &Output) -> String {
+    let mut lines = format!("Keys (movement step {}):\n", output.step);
+    for &(key, binding, description) in KEYS {
+        if matches!(binding, Binding::Autoclick) && output.autoclicker.is_none() || matches!(binding, Binding::NextEvent) && output.stepper.is_none() {
+            continue;
         }
-        None => PathBuf::from(DEFAULT_PATH)
-    };
+        lines.push_str(&format!("  {:<9} {}\n", KeyPress::plain(key).to_string(), description));
+    }
+    for (keys, name) in output.macros.bindings.iter() {
+        lines.push_str(&format!("  {:<9} Play macro {}\n", keys::chord_name(keys), name));
+    }
+    lines.push_str(&format!("  {:<9} Repeat a move, wheel or button key, e.g. 15d\n", "<n><key>"));
+    lines
+}
 
-    eprintln!("Open uhid-cdev {}", path.to_str().unwrap());
-    let fd = fcntl::open(&path, fcntl::O_RDWR | fcntl::O_CLOEXEC | fcntl::O_NONBLOCK, nix::sys::stat::S_IRUSR | nix::sys::stat::S_IWUSR | nix::sys::stat::S_IRGRP | nix::sys::stat::S_IWGRP).map_err(|err| format!("Cannot open uhid-cdev {}: {}", path.to_str().unwrap(), err)).unwrap();
-    let mut file = unsafe { File::from_raw_fd(fd) };
+fn set_paused(output: &mut Output, paused: bool) {
+    output.guard.set_paused(paused);
+    eprintln!("{}", if paused { "Input paused, press p or send resume to resume" } else { "Input resumed" });
+}
 
-    eprintln!("Create uhid device");
-    create(&mut file).unwrap();
+/* Whether typing that many characters was confirmed, if --confirm asks */
+fn confirmed(guard: &Guard, characters: usize) -> bool {
+    match guard.confirm_typing(characters) {
+        Ok(true) => true,
+        Ok(false) => {
+            eprintln!("Not typing {} characters", characters);
+            false
+        },
+        Err(err) => {
+            eprintln!("Cannot confirm typing {} characters: {}", characters, err);
+            false
+        }
+    }
+}
 
-    const STDIN: Token = Token(0);
-    const UHID_DEVICE: Token = Token(1);
+/* Runs a scheduled or fixture command; typed text plays like a macro, so it
+ * doesn't block */
+fn run(command: Command, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, layout: &Layout, key_delay: Duration) -> io::Result<()> {
+    match command {
+        Command::Type(text) => {
+            if !confirmed(&output.guard, text.chars().count()) {
+                return Ok(());
+            }
+            let strokes = layout.strokes_with_leds(&text, device.leds().unwrap_or(0))?;
+            let mut steps = Vec::new();
+            for (index, stroke) in strokes.iter().enumerate() {
+                for (number, report) in stroke.reports().into_iter().enumerate() {
+                    let delay = if index > 0 && number == 0 { key_delay } else { Duration::from_millis(0) };
+                    steps.push(Step { delay, report });
+                }
+            }
+            output.macros.player.play(&Macro { name: String::new(), loops: 1, steps }, Instant::now());
+        },
+        Command::Click(button) => {
+            let mut reports = device.action(Action::ToggleButton(button));
+            reports.extend(device.action(Action::ToggleButton(button)));
+            for report in reports {
+                send(backend, output, &report)?;
+            }
+        },
+        Command::Key(character) => key(backend, output, device, character)?,
+        Command::Macro(name) => output.macros.play(&name),
+        Command::Move(dx, dy) => for report in device.action(Action::Move(dx, dy)) {
+            send(backend, output, &report)?;
+        },
+        Command::Wheel(detents) => wheel(backend, output, device, detents)?,
+        Command::Scroll(pixels, duration) => match device.scroll(pixels, duration) {
+            Some(reports) => {
+                let steps = reports.into_iter().map(|(delay, report)| Step { delay, report }).collect();
+                output.macros.player.play(&Macro { name: String::new(), loops: 1, steps }, Instant::now());
+            },
+            None => eprintln!("Cannot scroll smoothly, the device has no hi-res wheel"),
+        },
+        Command::Step(step) => set_step(output, step),
+        Command::Pause => set_paused(output, true),
+        Command::Resume => set_paused(output, false),
+        Command::Report(report) => send(backend, output, &report)?,
+    }
+    Ok(())
+}
 
-    let poll = Poll::new().unwrap();
+/* Runs a command typed in the fixture or the REPL, telling what is wrong */
+fn run_line(line: &str, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, layout: &Layout, key_delay: Duration) {
+    match Command::parse(line) {
+        Some(command) => if let Err(err) = log(output, &command).and_then(|_| run(command, backend, output, device, layout, key_delay)) {
+            eprintln!("{}: {}", line.trim(), err);
+        },
+        None => eprintln!("Invalid command: {}", line.trim()),
+    }
+}
 
-    poll.register(&EventedFd(&libc::STDIN_FILENO), STDIN,
-                  Ready::readable(), PollOpt::edge()).unwrap();
-    poll.register(&EventedFd(&fd), UHID_DEVICE, Ready::readable(),
-                  PollOpt::edge()).unwrap();
+/* The commands of the REPL */
+fn command_help() -> String {
+    let mut lines = String::from("Commands:\n");
+    for &(usage, description) in schedule::COMMANDS {
+        lines.push_str(&format!("  {:<27} {}\n", usage, description));
+    }
+    lines.push_str(&format!("  {:<27} {}\n", "help", "list the commands"));
+    lines.push_str(&format!("  {:<27} {}\n", "quit", "destroy the device"));
+    lines
+}
 
-    let mut events = Events::with_capacity(1);
+/*
+ * Commands read line by line from stdin in fixture mode, see src/schedule.rs
+ * for them. stdin is non-blocking then, so partial lines wait for the rest.
+ */
+#[derive(Default)]
+struct Fixture {
+    buffer: Vec<u8>,
+}
 
-    println!("Press 'q' to quit...");
-    loop {
-        poll.poll(&mut events, None).map_err(|err| eprintln!("Cannot poll for fds: {}", err)).unwrap();
+impl Fixture {
+    /* Runs the complete lines read; false once stdin is closed */
+    fn read(&mut self, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, layout: &Layout, key_delay: Duration) -> io::Result<bool> {
+        let mut chunk = [0; 4096];
+        loop {
+            match io::stdin().read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err),
+            }
+        }
 
-        for event in events.iter() {
-            match event.token() {
-                STDIN => keyboard(&mut file, &mut device_state).unwrap(),
-                UHID_DEVICE => handle_event(&mut file).unwrap(),
-                _ => unreachable!(),
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
             }
+            run_line(&line, backend, output, device, layout, key_delay);
         }
+        Ok(true)
     }
+}
 
-    // TODO: Unreachable, should instead cleanly exit when q is pressed
-    println!("Destroy uhid device");
-    destroy(&mut file).unwrap();
+/*
+ * Raw input reports read from stdin in forward mode, one per frame, see
+ * src/framing.rs. They are sent as they are, stdin
+ * being non-blocking so partial reports wait for the rest.
+ */
+#[derive(Default)]
+struct Forward {
+    frames: Deframer,
+}
+
+impl Forward {
+    /* Sends the complete reports read; false once stdin is closed */
+    fn read(&mut self, backend: &mut dyn Backend, output: &mut Output) -> io::Result<bool> {
+        let open = self.frames.fill(io::stdin())?;
+        while let Some(report) = self.frames.next_frame()? {
+            relay(backend, output, report)?;
+        }
+        if !open && self.frames.pending() > 0 {
+            eprintln!("Dropping {} bytes of an incomplete report", self.frames.pending());
+        }
+        Ok(open)
+    }
+}
+
+/* The bytes in hex, separated by spaces like the report command takes them */
+fn hex(data: &[u8]) -> String {
+    let bytes: Vec<String> = data.iter().map(|byte| format!("{:02x}", byte)).collect();
+    bytes.join(" ")
+}
+
+/*
+ * Commands read by stdio-serve from stdin, one per frame, answered on stdout
+ * with a frame each: "ok", or "error " and what went wrong, each followed by
+ * the device the command went to. The commands are those of the fixture, see
+ * src/schedule.rs, and:
+ *
+ *   list              answers "ok" and the device, then a line per device
+ *                     created, its number and name
+ *   select <device>   sends the reports of the commands after it to the
+ *                     device numbered so, or by report-id again with any
+ *
+ * There is more than one device when it is split, numbered in the order of
+ * its top-level collections; otherwise there is device 0. Devices are any
+ * until selected, the reports going to the one declaring their report-id.
+ *
+ * A "ready" frame is written once the device is started, and a frame for
+ * every event of the kernel after that, "event", the device and its type,
+ * with the report in hex for output and set_report, e.g. "event 1 output 02
+ * 01". vsock-serve and serial-serve do the same over a vsock connection or
+ * virtio-serial port. vsock connections are audited, and with a token they
+ * first send "auth <token>", see src/auth.rs.
+ */
+struct Serve {
+    frames: Deframer,
+    input: Box<dyn Read>,
+    output: Box<dyn Write>,
+    selected: Option<usize>,
+    /* the connection as audited, none for local channels */
+    peer: Option<String>,
+    /* the token still to be sent */
+    auth: Option<Auth>,
+    bucket: Option<Bucket>,
+    commands: u64,
+}
+
+impl Serve {
+    fn stdio() -> Serve {
+        Serve::with(Box::new(io::stdin()), Box::new(io::stdout()))
+    }
+
+    fn with(input: Box<dyn Read>, output: Box<dyn Write>) -> Serve {
+        Serve { frames: Deframer::default(), input, output, selected: None, peer: None, auth: None, bucket: None, commands: 0 }
+    }
+
+    /* Reads and answers on the same non-blocking file */
+    fn new(channel: File) -> io::Result<Serve> {
+        let output = channel.try_clone()?;
+        Ok(Serve::with(Box::new(channel), Box::new(output)))
+    }
+
+    /* Audits the connection, requiring the token first if there is one and
+     * limiting its commands if asked to */
+    fn audited(mut self, peer: String, auth: Option<Auth>, limit: Option<Limit>) -> Serve {
+        auth::audit(&peer, format_args!("accepted"));
+        self.peer = Some(peer);
+        self.auth = auth;
+        self.bucket = limit.map(|limit| limit.bucket(Instant::now()));
+        self
+    }
+
+    /* Audits the connection going away */
+    fn closed(&self) {
+        if let Some(peer) = self.peer.as_ref() {
+            auth::audit(peer, format_args!("closed after {} commands", self.commands));
+        }
+    }
+
+    /* Whether the frame is the token wanted, answering it; the commands
+     * before are refused */
+    fn authenticate(&mut self, line: &str) -> io::Result<bool> {
+        let (auth, peer) = match (self.auth.as_ref(), self.peer.as_ref()) {
+            (Some(auth), Some(peer)) => (auth, peer),
+            _ => return Ok(true),
+        };
+        if line.strip_prefix("auth ").is_some_and(|token| auth.check(token.trim().as_bytes())) {
+            auth::audit(peer, format_args!("authenticated"));
+            self.auth = None;
+            framing::write_frame(&mut self.output, b"ok")?;
+            Ok(true)
+        } else {
+            auth::audit(peer, format_args!("refused, {}", if line.starts_with("auth ") { "wrong token" } else { "not authenticated" }));
+            framing::write_frame(&mut self.output, b"error Not authenticated")?;
+            Ok(false)
+        }
+    }
+
+    /* The device selected, as answered */
+    fn addressed(&self) -> String {
+        self.selected.map_or(String::from("any"), |device| device.to_string())
+    }
+
+    /* Tells the client of an event from the device numbered so */
+    fn notify(&mut self, device: usize, event: &Event) -> io::Result<()> {
+        let mut frame = format!("event {} ", device);
+        match *event {
+            Event::Start => frame.push_str("start"),
+            Event::Stop => frame.push_str("stop"),
+            Event::Open => frame.push_str("open"),
+            Event::Close => frame.push_str("close"),
+            Event::Output { ref data, .. } => frame.push_str(&format!("output {}", hex(data))),
+            Event::GetReport { rnum, .. } => frame.push_str(&format!("get_report {:02x}", rnum)),
+            Event::SetReport { ref data, .. } => frame.push_str(&format!("set_report {}", hex(data))),
+            Event::Other(type_) => frame.push_str(&format!("other {}", type_)),
+        }
+        framing::write_frame(&mut self.output, frame.trim_end().as_bytes())
+    }
+
+    /* Answers list and select, the lines to add to the answer; None for the
+     * other commands */
+    fn address(&mut self, line: &str, name: &str, backend: &dyn Backend) -> Option<io::Result<String>> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            ["list"] => {
+                let mut lines = String::new();
+                for index in 0..backend.devices() {
+                    let _ = write!(lines, "\n{} {}", index, name);
+                }
+                return Some(Ok(lines));
+            },
+            ["select", "any"] => Ok(None),
+            ["select", selected] => match selected.parse() {
+                Ok(selected) if selected < backend.devices() => Ok(Some(selected)),
+                Ok(selected) => Err(backend::no_device(selected)),
+                Err(_) => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid device")),
+            },
+            _ => return None,
+        };
+        Some(result.map(|selected| {
+            self.selected = selected;
+            String::new()
+        }))
+    }
+
+    fn ready(&mut self) -> io::Result<()> {
+        framing::write_frame(&mut self.output, b"ready")
+    }
+
+    /* Drops an incomplete command, e.g. of a host that went away */
+    fn reset(&mut self) {
+        self.frames = Deframer::default();
+    }
+
+    /* Runs the complete commands read; false once the input is closed */
+    fn read(&mut self, name: &str, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, layout: &Layout, key_delay: Duration) -> io::Result<bool> {
+        let open = self.frames.fill(&mut self.input)?;
+        while let Some(frame) = self.frames.next_frame()? {
+            let line = String::from_utf8_lossy(&frame);
+            if self.auth.is_some() {
+                if !self.authenticate(&line)? {
+                    return Ok(false);
+                }
+                continue;
+            }
+            match admit(self.bucket.as_mut(), self.peer.as_deref().unwrap_or_default()) {
+                Verdict::Allow => (),
+                Verdict::Refuse => {
+                    let answer = format!("error {} Rate limited", self.addressed());
+                    framing::write_frame(&mut self.output, answer.as_bytes())?;
+                    continue;
+                },
+                Verdict::Disconnect => {
+                    let answer = format!("error {} Rate limited, disconnecting", self.addressed());
+                    framing::write_frame(&mut self.output, answer.as_bytes())?;
+                    if let Some(peer) = self.peer.as_ref() {
+                        auth::audit(peer, format_args!("disconnected over the rate limit"));
+                    }
+                    return Ok(false);
+                },
+            }
+            self.commands += 1;
+            if let Some(peer) = self.peer.as_ref() {
+                auth::audit(peer, format_args!("{}", line));
+            }
+            let result = match self.address(&line, name, backend) {
+                Some(result) => result,
+                None => match Command::parse(&line) {
+                    Some(command) => log(output, &command).and_then(|_| {
+                        /* the selection only holds for the commands served */
+                        backend.select(self.selected)?;
+                        let result = run(command, backend, output, device, layout, key_delay);
+                        backend.select(None)?;
+                        result
+                    }).map(|()| String::new()),
+                    None => Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid command")),
+                },
+            };
+            let answer = match result {
+                Ok(lines) => format!("ok {}{}", self.addressed(), lines),
+                Err(err) => format!("error {} {}", self.addressed(), err),
+            };
+            framing::write_frame(&mut self.output, answer.as_bytes())?;
+        }
+        Ok(open)
+    }
+}
+
+/* The options and commands taking a value, the argument after them */
+const VALUE_OPTIONS: &[&str] = &[
+    "--preset", "--type", "--evemu", "--seed", "--iterations", "--findings", "--corpus", "corpus", "--count",
+    "--parallel", "--metrics", "--stats", "--session-log", "--backend", "rerun", "--layout", "--key-delay",
+    "--rate", "--repeat", "--dpi", "--plugin", "--script", "--record", "--name", "--bus", "--vid", "--pid",
+    "--device-version", "--country", "type", "replay", "--interface", "--speed", "--from", "--to",
+    "--session", "pattern", "--radius", "--period", "--macros", "--bind", "--record-macro", "--schedule",
+    "--button", "--cps", "--hold", "--interleave", "--interval", "--wait-for-node", "--step", "--max-rate",
+    "--only-changes", "barrier", "merge", "--grab", "--remap", "--screen", "--screen-name", "vsock-serve",
+    "serial-serve", "--latency", "--jitter", "--jitter-distribution", "--fault", "--wheel-step",
+    "--wheel-max", "--on-output", "--output", "--watchdog", "--token-file", "--tls-cert", "--tls-key",
+    "--tls-client-ca", "--client-limit", "--template", "templates", "completions",
+];
+
+/* The usage, also read by the shell completions */
+fn usage_text() -> String {
+    let mut text = String::new();
+    let _ = writeln!(text, "Usage: {} [options] [{}] [type <text>|type --file <path>|type -]", env::args().next().unwrap_or_else(|| "uhid-example".into()), DEFAULT_PATH);
+    let _ = writeln!(text, "Options:");
+    let _ = writeln!(text, "  --preset <{}>", devices::PRESETS.join("|"));
+    let _ = writeln!(text, "  --layout <{}|<file>>, --substitute, --unicode", layout_names().join("|"));
+    let _ = writeln!(text, "  --key-delay <ms>");
+    let _ = writeln!(text, "  --rate <keys/s>");
+    let _ = writeln!(text, "  --repeat <delay-ms>,<keys/s>");
+    let _ = writeln!(text, "  --sync-leds");
+    let _ = writeln!(text, "  --dpi <dpi>");
+    let _ = writeln!(text, "  --interleave <round-robin|burst|random|<ids>>");
+    let _ = writeln!(text, "  --template <{}>[:<variable>=<value>,...]", template::TEMPLATES.join("|"));
+    let _ = writeln!(text, "  --dpi-report");
+    let _ = writeln!(text, "  --humanize");
+    let _ = writeln!(text, "  --absolute");
+    let _ = writeln!(text, "  --hi-res-wheel");
+    let _ = writeln!(text, "  --plugin <library>");
+    let _ = writeln!(text, "  --script <file>");
+    let _ = writeln!(text, "  --record <file>");
+    let _ = writeln!(text, "  replay [--format hid-recorder|pcap|evemu] <file> [--speed <factor>] [--from <time>] [--to <time>] [--single-step]");
+    let _ = writeln!(text, "  --session-log <file>, rerun <file>");
+    let _ = writeln!(text, "  --interface <number>");
+    let _ = writeln!(text, "  pattern <{}> [--radius <counts>] [--period <duration>]", pattern_names().join("|"));
+    let _ = writeln!(text, "  --schedule <file>");
+    let _ = writeln!(text, "  --fixture");
+    let _ = writeln!(text, "  repl");
+    let _ = writeln!(text, "  --wait-for-node <timeout>");
+    let _ = writeln!(text, "  --forward");
+    let _ = writeln!(text, "  --latency <duration>, --jitter <duration> [--jitter-distribution <{}>]", DISTRIBUTION_NAMES.join("|"));
+    let _ = writeln!(text, "  --fault drop=<probability>,dup=<probability>");
+    let _ = writeln!(text, "  stdio-serve, vsock-serve <port>, serial-serve <port-device>");
+    let _ = writeln!(text, "  --token-file <file>");
+    let _ = writeln!(text, "  --tls-cert <file>, --tls-key <file>, --tls-client-ca <file>");
+    let _ = writeln!(text, "  --client-limit <rate>[,<burst>]");
+    let _ = writeln!(text, "  barrier <server>[:<port>] [--screen <width>x<height>] [--screen-name <name>]");
+    let _ = writeln!(text, "  merge <event-node>[,<event-node>...] [--grab <toggle-key>] [--remap <file>]");
+    let _ = writeln!(text, "  --evemu <file>");
+    let _ = writeln!(text, "  fuzz-rdesc [--iterations <n>] [--seed <n>] [--findings <dir>] [--corpus <dir>]");
+    let _ = writeln!(text, "  corpus <dir> [--count <n>] [--seed <n>]");
+    let _ = writeln!(text, "  stress-lifecycle [--iterations <n>] [--parallel <threads>]");
+    let _ = writeln!(text, "  capabilities");
+    let _ = writeln!(text, "  farm [--count <devices>] [--type <{}>] [--rate <reports/s>] [--parallel <threads>]", devices::PRESETS.join("|"));
+    let _ = writeln!(text, "  autoclick [--button left|right|middle|<n>] [--cps <clicks/s>] [--hold <duration>]");
+    let _ = writeln!(text, "  hotplug [--interval <duration>] [--cycle-ids]");
+    let _ = writeln!(text, "  --watchdog <interval>");
+    let _ = writeln!(text, "  --backend <{}>, --dry-run", BACKEND_NAMES.join("|"));
+    let _ = writeln!(text, "  --split");
+    let _ = writeln!(text, "  --name <name>");
+    let _ = writeln!(text, "  --bus <{}>", bus_names().join("|"));
+    let _ = writeln!(text, "  --vid <id>, --pid <id>, --device-version <bcd>, --country <code>");
+    let _ = writeln!(text, "  --sandbox");
+    let _ = writeln!(text, "  --max-rate <reports/s>, --confirm");
+    let _ = writeln!(text, "  --only-changes <all|<report-ids>>");
+    let _ = writeln!(text, "  --macros <file>, --bind <keys>=<macro>, --record-macro <name>");
+    let _ = writeln!(text, "  --pause-on-lock [--session <id>]");
+    let _ = writeln!(text, "  --metrics <address>:<port>");
+    let _ = writeln!(text, "  --stats <interval>");
+    let _ = writeln!(text, "  --timing, --timing-readback");
+    let _ = writeln!(text, "  --on-output <command>");
+    let _ = writeln!(text, "  --step <counts>");
+    let _ = writeln!(text, "  --wheel-step <counts>, --wheel-max <counts>");
+    let _ = writeln!(text, "  --trace-raw");
+    let _ = writeln!(text, "  --output <text|json>");
+    let _ = writeln!(text, "  templates <list|show> [<template>]");
+    let _ = writeln!(text, "  completions <{}>, schema", SHELL_NAMES.join("|"));
+    text
+}
+
+fn usage() {
+    eprint!("{}", usage_text());
+}
+
+/* An argument missing its value or with one not understood */
+fn invalid(arg: &str) -> Failure {
+    Failure::new(Code::Usage, format!("Invalid or missing value for {}, see --help", arg))
+}
+
+/* Parses a decimal or 0x prefixed hexadecimal number */
+fn parse_number(value: &str) -> Option<u32> {
+    if value.starts_with("0x") || value.starts_with("0X") {
+        u32::from_str_radix(&value[2..], 16).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+/* Parses the typematic setting given as "<delay in ms>,<repeats per second>" */
+fn parse_repeat(value: &str) -> Option<Repeat> {
+    let mut parts = value.splitn(2, ',');
+    let delay = parts.next()?.parse().ok()?;
+    let rate: u32 = parts.next()?.parse().ok()?;
+    if rate == 0 {
+        return None;
+    }
+
+    Some(Repeat {
+        delay: Duration::from_millis(delay),
+        interval: Duration::from_secs(1) / rate,
+    })
+}
+
+/* Parses the screen size given as "<width>x<height>" in pixels */
+fn parse_size(value: &str) -> Option<(u16, u16)> {
+    let (width, height) = value.split_once('x')?;
+    match (width.parse().ok()?, height.parse().ok()?) {
+        (0, _) | (_, 0) => None,
+        size => Some(size),
+    }
+}
+
+/* The screen name Barrier servers see unless configured otherwise */
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| String::from("uhid-example"))
+}
+
+fn bus_names() -> Vec<&'static str> {
+    uhid::BUS_NAMES.iter().map(|bus| bus.0).collect()
+}
+
+/* Parses <keys>=<macro>, the keys not starting with one used otherwise */
+fn parse_binding(value: &str) -> Option<(Vec<KeyPress>, String)> {
+    let (keys, name) = value.split_once('=')?;
+    let keys = keys::parse_chord(keys)?;
+    match keys[0].char() {
+        Some(b' ') => None,
+        _ if KEYS.iter().any(|binding| KeyPress::plain(binding.0) == keys[0]) => None,
+        _ if name.is_empty() => None,
+        _ => Some((keys, name.to_string())),
+    }
+}
+
+fn pattern_names() -> Vec<&'static str> {
+    pattern::SHAPES.iter().map(|shape| shape.0).collect()
+}
+
+fn layout_names() -> Vec<&'static str> {
+    layout::LAYOUTS.iter().map(|layout| layout.name.as_ref()).collect()
+}
+
+/* The nodes appear shortly after UHID_START, give them a moment. hidraw
+ * connects last, devices without an input device have only that */
+fn wait_for_nodes(name: &str, identity: &uhid::Identity) -> io::Result<Nodes> {
+    let deadline = Instant::now() + NODE_TIMEOUT;
+    loop {
+        let nodes = nodes::device_nodes(name, identity)?;
+        if nodes.hidraw.is_some() || Instant::now() >= deadline {
+            return Ok(nodes);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/* Waits until every event node of the device can be opened, which is only
+ * once udev created it and applied its rules, not when the kernel did */
+fn wait_for_openable(name: &str, identity: &uhid::Identity, timeout: Duration) -> io::Result<Vec<PathBuf>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let nodes = nodes::device_nodes(name, identity)?.events;
+        let error = match nodes.iter().map(|node| OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(node)).find_map(Result::err) {
+            None if !nodes.is_empty() => return Ok(nodes),
+            None => io::Error::new(io::ErrorKind::NotFound, "No event node found"),
+            Some(err) => err,
+        };
+        if Instant::now() >= deadline {
+            return Err(error);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/* Prints the nodes of the device once it is started, to open it by */
+fn print_nodes(name: &str, identity: &uhid::Identity) {
+    match wait_for_nodes(name, identity) {
+        Ok(ref nodes) if nodes.paths().is_empty() => eprintln!("No device nodes found"),
+        Ok(nodes) => {
+            let paths: Vec<String> = nodes.paths().iter().map(|path| path.display().to_string()).collect();
+            eprintln!("Device nodes: {}", paths.join(" "));
+        },
+        Err(err) => eprintln!("Cannot find the device nodes: {}", err),
+    }
+}
+
+/* Checks the kernel registered the descriptor sent, once the HID device
+ * shows up, which it does before UHID_START */
+fn verify_descriptor(name: &str, identity: &uhid::Identity, descriptor: &[u8]) -> io::Result<()> {
+    let deadline = Instant::now() + NODE_TIMEOUT;
+    loop {
+        if let Some(device) = nodes::hid_device(name, identity)? {
+            return nodes::check_descriptor(&device, descriptor);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "HID device not found in sysfs"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/* Whether the device can be used, failing on a descriptor registered
+ * differently and only warning if it cannot be checked */
+fn check_registered(name: &str, identity: &uhid::Identity, descriptor: &[u8]) -> Result<(), Failure> {
+    match verify_descriptor(name, identity, descriptor) {
+        Err(ref err) if err.kind() == io::ErrorKind::InvalidData => Err(Failure::new(Code::DescriptorRejected, err.to_string()).within(err)),
+        Err(err) => {
+            eprintln!("Cannot verify the descriptor: {}", err);
+            Ok(())
+        },
+        Ok(()) => Ok(()),
+    }
+}
+
+/* Describes every event node, the first to the path and the others to
+ * <path>.1, <path>.2 and so on */
+fn describe_nodes(nodes: &[PathBuf], path: &Path) -> io::Result<()> {
+    if nodes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No event node found"));
+    }
+    for (index, node) in nodes.iter().enumerate() {
+        let mut target = path.as_os_str().to_owned();
+        if index > 0 {
+            target.push(format!(".{}", index));
+        }
+        eprintln!("Describe {} to {}", node.display(), PathBuf::from(&target).display());
+        evemu::describe(node, io::BufWriter::new(File::create(&target)?))?;
+    }
+    Ok(())
+}
+
+/* Settings of fuzz-rdesc */
+struct Fuzzing {
+    seed: Option<u64>,
+    corpus: Option<PathBuf>,
+    iterations: Option<u64>,
+    findings: Option<PathBuf>,
+}
+
+/* Writes a descriptor the kernel did not like as a recording replay reads */
+fn write_finding(path: &Path, name: &str, data: &[u8], identity: &uhid::Identity, mutation: fuzz::Mutation, outcome: &fuzz::Outcome) -> io::Result<()> {
+    let mut output = File::create(path)?;
+    writeln!(output, "# {}", mutation)?;
+    for message in outcome.messages.iter() {
+        writeln!(output, "# kernel: {}", message)?;
+    }
+    Recorder::new(output, name, data, identity).map(|_| ())
+}
+
+fn report_stress(summary: &stress::Summary) {
+    eprintln!("Created {} devices in {:.1}s", summary.created, summary.elapsed.as_secs_f64());
+    let problems: [(&str, &[String]); 5] = [
+        ("Errors", &summary.errors),
+        ("Never started", &summary.not_started),
+        ("No add uevent", &summary.missing_add),
+        ("No remove uevent", &summary.missing_remove),
+        ("Left behind", &summary.left),
+    ];
+    for (problem, names) in problems.iter().filter(|(_, names)| !names.is_empty()) {
+        eprintln!("{} ({}):", problem, names.len());
+        for name in names.iter() {
+            eprintln!("  {}", name);
+        }
+    }
+    if summary.uevents_lost {
+        eprintln!("Uevents were lost, the uevent check is incomplete");
+    }
+    eprintln!("{}", if summary.passed() { "Passed" } else { "Failed" });
+}
+
+fn report_capabilities(capabilities: &probe::Capabilities) {
+    let supported = |supported: bool| if supported { "supported" } else { "not supported" };
+    let requested = |requested: Option<bool>| requested.map_or("unknown, hidraw not accessible", supported);
+    println!("UHID_CREATE2     {}", supported(capabilities.create2));
+    println!("UHID_INPUT2      {}", supported(capabilities.input2));
+    println!("UHID_GET_REPORT  {}", requested(capabilities.get_report));
+    println!("UHID_SET_REPORT  {}", requested(capabilities.set_report));
+    println!("dev_flags        {:#x} {}", capabilities.dev_flags, capabilities.dev_flag_names().join(" "));
+}
+
+/* The throughput of the farm, failing if any device could not be created,
+ * started or destroyed */
+fn report_farm(summary: &farm::Summary) -> Result<(), Failure> {
+    eprintln!("Sent {} reports, {} bytes, to {} devices in {:.1}s, {:.0} reports/s; {} write errors",
+              summary.sent, summary.bytes, summary.created, summary.elapsed.as_secs_f64(), summary.reports_per_second(), summary.write_errors);
+    for error in summary.errors.iter() {
+        eprintln!("  {}", error);
+    }
+    match summary.errors.len() {
+        0 => Ok(()),
+        errors => Err(Failure::new(Code::Failure, format!("{} devices failed", errors))),
+    }
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+}
+
+/* The files of a corpus directory, in the order of their names */
+fn read_corpus(directory: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut paths = fs::read_dir(directory)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    let descriptors = paths.iter().filter(|path| path.is_file()).map(fs::read).collect::<io::Result<Vec<Vec<u8>>>>()?;
+    if descriptors.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No descriptors in the corpus"));
+    }
+    Ok(descriptors)
+}
+
+fn write_corpus(directory: &Path, seed: u64, count: usize) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+    for (index, descriptor) in corpus::generate(seed, count).iter().enumerate() {
+        fs::write(directory.join(format!("{}-{:04}.bin", seed, index)), descriptor)?;
+    }
+    Ok(())
+}
+
+fn fuzz_rdesc(file: &mut File, name: &str, descriptor: &[u8], identity: &uhid::Identity, fuzzing: &Fuzzing) -> io::Result<()> {
+    let descriptors = match fuzzing.corpus.as_ref() {
+        Some(directory) => read_corpus(directory).map_err(|err| io::Error::new(err.kind(), format!("Cannot read corpus {}: {}", directory.display(), err)))?,
+        None => vec![descriptor.to_vec()],
+    };
+    let mut log = fuzz::KernelLog::open().map_err(|err| io::Error::new(err.kind(), format!("Cannot read the kernel log: {}", err)))?;
+    let seed = fuzzing.seed.unwrap_or_else(time_seed);
+    eprintln!("Fuzzing with seed {}", seed);
+
+    let mut mutator = fuzz::Mutator::new(seed);
+    let (mut rejected, mut iteration) = (0, 0);
+    while fuzzing.iterations.is_none_or(|iterations| iteration < iterations) {
+        let (mutation, data) = mutator.mutate(&descriptors[iteration as usize % descriptors.len()]);
+        let outcome = fuzz::probe(file, &mut log, name, &data, identity)?;
+        if !outcome.started || outcome.crashed() {
+            rejected += 1;
+            eprintln!("{}: {}, {}", iteration, mutation, if outcome.crashed() { "crashed the kernel" } else { "rejected" });
+            for message in outcome.messages.iter() {
+                eprintln!("  {}", message);
+            }
+            if let Some(findings) = fuzzing.findings.as_ref() {
+                write_finding(&findings.join(format!("{}-{}.hid", seed, iteration)), name, &data, identity, mutation, &outcome)?;
+            }
+            if outcome.crashed() {
+                break;
+            }
+        }
+        iteration += 1;
+    }
+    eprintln!("{} of {} descriptors were rejected", rejected, iteration);
+    Ok(())
+}
+
+/* Tells systemd the device is up once the kernel started it */
+fn started(notifier: &mut Option<Notifier>, event: &Event) -> io::Result<()> {
+    match notifier.as_mut() {
+        Some(notifier) if *event == Event::Start => notifier.ready(),
+        _ => Ok(()),
+    }
+}
+
+/* Handles uhid events until the given one arrived. Keys sent before anyone
+ * listens on the input device are lost, so typing waits for UHID_OPEN */
+fn wait_for(poll: &Poll, backend: &mut dyn Backend, device: &mut dyn Device, reports: &Reports, metrics: &mut Metrics, notifier: &mut Option<Notifier>, wanted: Event) -> io::Result<()> {
+    let mut events = Events::with_capacity(1);
+
+    loop {
+        /* the fd is edge triggered, take what is pending before polling */
+        while let Some(event) = handle_event(backend, device, reports, metrics)? {
+            started(notifier, &event)?;
+            if event == wanted {
+                return Ok(());
+            }
+        }
+        poll.poll(&mut events, None)?;
+    }
+}
+
+/*
+ * Handles pending uhid events and control keys between two typed keys. Space
+ * pauses and resumes typing, q stops it; while paused this blocks.
+ */
+fn flow_control(poll: &Poll, backend: &mut dyn Backend, device: &mut dyn Device, reports: &Reports, metrics: &mut Metrics, typist: &mut Typist) -> io::Result<()> {
+    let mut events = Events::with_capacity(4);
+    let mut paused = false;
+
+    loop {
+        let timeout = if paused { None } else { Some(Duration::from_millis(0)) };
+        poll.poll(&mut events, timeout)?;
+
+        for event in events.iter() {
+            match event.token() {
+                STDIN => {
+                    let mut character: [u8; 1] = Default::default();
+                    io::stdin().read_exact(&mut character)?;
+                    match character[0] {
+                        b' ' => {
+                            paused = !paused;
+                            eprintln!("{}", if paused { "Paused, press space to resume" } else { "Resumed" });
+                        },
+                        /* Escape is the emergency stop, here it stops like q */
+                        b'q' | 0x1b => return Err(io::Error::other("Cancelled")),
+                        _ => {},
+                    }
+                },
+                UHID_DEVICE => while handle_event(backend, device, reports, metrics)?.is_some() {},
+                _ => unreachable!(),
+            }
+        }
+
+        if !paused {
+            return Ok(());
+        }
+        typist.reset();
+    }
+}
+
+/* Types text read from `text` line by line, then returns */
+fn type_text(poll: &Poll, backend: &mut dyn Backend, output: &mut Output, device: &mut dyn Device, layout: &Layout, text: &mut dyn BufRead, typist: &mut Typist) -> io::Result<()> {
+    let mut line = String::new();
+    let mut number = 0;
+    while text.read_line(&mut line)? > 0 {
+        number += 1;
+        let strokes = layout.strokes_with_leds(&line, device.leds().unwrap_or(0))
+            .map_err(|err| exit::located(err.kind(), format!("Line {}: {}", number, err), vec![("line", number)]))?;
+        for stroke in strokes.iter() {
+            flow_control(poll, backend, device, &output.reports, &mut output.metrics, typist)?;
+            typist.type_stroke(stroke, |report| send(backend, output, report))?;
+        }
+        line.clear();
+    }
+
+    Ok(())
+}
+
+fn try_main() -> Result<(), Failure> {
+    /* known ahead of the other arguments, so failures parsing them follow it */
+    exit::set_json(env::args().skip_while(|arg| arg != "--output").nth(1).as_deref() == Some("json"));
+
+    match Termios::from_fd(libc::STDIN_FILENO) {
+        Err(_) => eprintln!("Cannot get tty state"),
+        Ok(mut state) => {
+            state.c_lflag &= !ICANON;
+            state.c_cc[VMIN] = 1;
+            if tcsetattr(libc::STDIN_FILENO, TCSANOW, &state).is_err() {
+                eprintln!("Cannot set tty state");
+            }
+        }
+    }
+
+    let mut path = PathBuf::from(DEFAULT_PATH);
+    let mut preset = String::from(DEFAULT_PRESET);
+    let mut layout_name = String::from(layout::DEFAULT_LAYOUT);
+    let mut substitute = false;
+    let mut unicode = false;
+    let mut key_delay = keyboard::DEFAULT_KEY_DELAY_MS;
+    let mut rate = None;
+    let mut options = devices::Options::default();
+    let mut text = None;
+    let mut plugins = Vec::new();
+    let mut script = None;
+    let mut record = None;
+    let mut replay = None;
+    let mut session_log_path = None;
+    let mut backend_name = String::from("uhid");
+    let mut rerun_path = None;
+    let mut interface = None;
+    let mut sandbox = false;
+    let mut max_rate = None;
+    let mut only_changes = None;
+    let mut confirm = false;
+    let mut pause_on_lock = false;
+    let mut splitting = false;
+    let mut session = None;
+    let mut pattern = None;
+    let mut macros_path = None;
+    let mut schedule_path = None;
+    let mut autoclick = false;
+    let mut hotplug = false;
+    let mut hotplug_interval = DEFAULT_HOTPLUG_INTERVAL;
+    let mut watchdog_interval = None;
+    let mut token_file = None;
+    let mut tls_cert = None;
+    let mut tls_key = None;
+    let mut tls_client_ca = None;
+    let mut client_limit = None;
+    let mut wait_for_node = None;
+    let mut cycle_ids = false;
+    let mut speed = None;
+    let mut from = None;
+    let mut to = None;
+    let mut single_step = false;
+    let mut fixture = None;
+    let mut repl = false;
+    let mut forward = None;
+    let mut serve = None;
+    let mut vsock_port = None;
+    let mut serial_path = None;
+    let mut barrier_server = None;
+    let mut merge_sources = None;
+    let mut grab_key = None;
+    let mut remap_path = None;
+    let mut screen_size = DEFAULT_SCREEN_SIZE;
+    let mut screen_name = None;
+    let mut evemu = None;
+    let mut fuzzing = false;
+    let mut stressing = false;
+    let mut farming = false;
+    let mut probing = false;
+    let mut parallel = 1;
+    let mut metrics_address = None;
+    let mut stats_interval = None;
+    let mut timing = None;
+    let mut readback = false;
+    let mut on_output = None;
+    let mut seed = None;
+    let mut iterations = None;
+    let mut findings = None;
+    let mut corpus_path = None;
+    let mut corpus_out = None;
+    let mut completions = None;
+    let mut schema = false;
+    /* templates list, or templates show with the template */
+    let mut templates: Option<Option<template::Kind>> = None;
+    let mut count = 100;
+    let mut button = 1;
+    let mut cps = autoclick::DEFAULT_CPS;
+    let mut hold = autoclick::DEFAULT_HOLD;
+    let mut latency = None;
+    let mut jitter = None;
+    let mut distribution = Distribution::Uniform;
+    let mut faults = None;
+    let mut bindings = Vec::new();
+    let mut record_name = String::from(DEFAULT_MACRO_NAME);
+    let mut step = DEFAULT_STEP;
+    let mut wheel_step = 1;
+    let mut wheel_max = i8::MAX;
+    let mut radius = pattern::DEFAULT_RADIUS;
+    let mut period = pattern::DEFAULT_PERIOD;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            usage();
+            return Ok(());
+        } else if arg == "--version" {
+            println!("uhid-example {}", env!("CARGO_PKG_VERSION"));
+            return Ok(());
+        } else if arg == "--sync-leds" {
+            options.sync_leds = true;
+        } else if arg == "--dpi-report" {
+            options.dpi_report = true;
+        } else if arg == "--humanize" {
+            options.humanize = true;
+        } else if arg == "--absolute" {
+            options.absolute = true;
+        } else if arg == "--hi-res-wheel" {
+            options.hi_res_wheel = true;
+        } else if arg == "--trace-raw" {
+            uhid::set_trace(true);
+        } else if arg == "--sandbox" {
+            sandbox = true;
+        } else if arg == "--confirm" {
+            confirm = true;
+        } else if arg == "--single-step" {
+            single_step = true;
+        } else if arg == "--dry-run" {
+            backend_name = String::from("dry-run");
+        } else if arg == "--timing" {
+            timing = Some(Timing::default());
+        } else if arg == "--timing-readback" {
+            timing = Some(Timing::default());
+            readback = true;
+        } else if arg == "--substitute" {
+            substitute = true;
+        } else if arg == "--unicode" {
+            unicode = true;
+        } else if arg == "--split" {
+            splitting = true;
+        } else if arg == "--pause-on-lock" {
+            pause_on_lock = true;
+        } else if arg == "autoclick" {
+            autoclick = true;
+        } else if arg == "hotplug" {
+            hotplug = true;
+        } else if arg == "--cycle-ids" {
+            cycle_ids = true;
+        } else if arg == "stress-lifecycle" {
+            stressing = true;
+        } else if arg == "farm" {
+            farming = true;
+        } else if arg == "capabilities" {
+            probing = true;
+        } else if arg == "fuzz-rdesc" {
+            fuzzing = true;
+        } else if arg == "schema" {
+            schema = true;
+        } else if arg == "repl" {
+            repl = true;
+        } else if arg == "--fixture" {
+            fixture = Some(Fixture::default());
+        } else if arg == "--forward" {
+            forward = Some(Forward::default());
+        } else if arg == "stdio-serve" {
+            serve = Some(Serve::stdio());
+        } else if VALUE_OPTIONS.contains(&arg.as_str()) {
+            let value = match args.next() {
+                Some(value) => value,
+                None => {
+                    return Err(invalid(&arg));
+                }
+            };
+            match arg.as_str() {
+                "--preset" | "--type" => preset = value,
+                "--layout" => layout_name = value,
+                "--key-delay" => match value.parse() {
+                    Ok(ms) => key_delay = ms,
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--rate" => match value.parse() {
+                    Ok(keys) => rate = Some(keys),
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--plugin" => plugins.push(PathBuf::from(value)),
+                "--script" => script = Some(PathBuf::from(value)),
+                "--record" => record = Some(PathBuf::from(value)),
+                "--session-log" => session_log_path = Some(PathBuf::from(value)),
+                "--backend" if BACKEND_NAMES.contains(&value.as_str()) => backend_name = value,
+                "--backend" => {
+                    return Err(Failure::new(Code::Usage, format!("Unknown backend {}, expected one of: {}", value, BACKEND_NAMES.join(", "))));
+                },
+                "rerun" => rerun_path = Some(PathBuf::from(value)),
+                "--evemu" => evemu = Some(PathBuf::from(value)),
+                "--findings" => findings = Some(PathBuf::from(value)),
+                "--metrics" => metrics_address = Some(value),
+                "--stats" => match schedule::parse_duration(&value) {
+                    Some(interval) if interval > Duration::from_secs(0) => stats_interval = Some(interval),
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--max-rate" => match value.parse() {
+                    Ok(reports) if reports > 0 => max_rate = Some(reports),
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--only-changes" => match Only::from_name(&value) {
+                    Some(only) => only_changes = Some(only),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--step" => match value.parse() {
+                    Ok(counts) if counts > 0 => step = counts,
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--wheel-step" | "--wheel-max" => match value.parse() {
+                    Ok(counts) if counts > 0 && arg == "--wheel-step" => wheel_step = counts,
+                    Ok(counts) if counts > 0 => wheel_max = counts,
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--corpus" => corpus_path = Some(PathBuf::from(value)),
+                "corpus" => corpus_out = Some(PathBuf::from(value)),
+                "templates" => match (value.as_str(), args.next()) {
+                    ("list", _) => templates = Some(None),
+                    ("show", Some(name)) => match template::Kind::from_name(&name) {
+                        Some(kind) => templates = Some(Some(kind)),
+                        None => {
+                            return Err(Failure::new(Code::Usage, format!("Unknown template {}, expected one of: {}", name, template::TEMPLATES.join(", "))));
+                        }
+                    },
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "completions" => match Shell::from_name(&value) {
+                    Some(shell) => completions = Some(shell),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--parallel" => match value.parse() {
+                    Ok(threads) if threads > 0 => parallel = threads,
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--count" => match value.parse() {
+                    Ok(number) => count = number,
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--seed" | "--iterations" => match value.parse() {
+                    Ok(number) if arg == "--seed" => seed = Some(number),
+                    Ok(number) => iterations = Some(number),
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--session" => session = Some(value),
+                "--macros" => macros_path = Some(PathBuf::from(value)),
+                "--schedule" => schedule_path = Some(PathBuf::from(value)),
+                "--button" => match devices::button_from_name(&value) {
+                    Some(number) => button = number,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--cps" => match value.parse() {
+                    Ok(clicks) if clicks > 0 => cps = clicks,
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--hold" => match schedule::parse_duration(&value) {
+                    Some(duration) => hold = duration,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--latency" | "--jitter" => match schedule::parse_duration(&value) {
+                    Some(duration) if arg == "--latency" => latency = Some(duration),
+                    Some(duration) => jitter = Some(duration),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--fault" => match Faults::parse(&value) {
+                    Some(parsed) => faults = Some(parsed),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--on-output" => on_output = Some(value),
+                "--output" => match value.as_str() {
+                    "text" => exit::set_json(false),
+                    "json" => exit::set_json(true),
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--jitter-distribution" => match Distribution::from_name(&value) {
+                    Some(chosen) => distribution = chosen,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--watchdog" => match schedule::parse_duration(&value) {
+                    Some(interval) => watchdog_interval = Some(interval),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--token-file" => token_file = Some(PathBuf::from(value)),
+                "--tls-cert" => tls_cert = Some(PathBuf::from(value)),
+                "--tls-key" => tls_key = Some(PathBuf::from(value)),
+                "--tls-client-ca" => tls_client_ca = Some(PathBuf::from(value)),
+                "--client-limit" => match Limit::parse(&value) {
+                    Some(limit) => client_limit = Some(limit),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--interval" => match schedule::parse_duration(&value) {
+                    Some(interval) => hotplug_interval = interval,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--wait-for-node" => match schedule::parse_duration(&value) {
+                    Some(timeout) => wait_for_node = Some(timeout),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--record-macro" => record_name = value,
+                "barrier" => barrier_server = Some(barrier::with_port(&value)),
+                "merge" => merge_sources = Some(value.split(',').map(PathBuf::from).collect::<Vec<_>>()),
+                "--remap" => remap_path = Some(PathBuf::from(value)),
+                "--grab" => match evemu::key_code(&value) {
+                    Some(code) => grab_key = Some(code),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--screen" => match parse_size(&value) {
+                    Some(size) => screen_size = size,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--screen-name" => screen_name = Some(value),
+                "vsock-serve" => match value.parse() {
+                    Ok(port) => vsock_port = Some(port),
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "serial-serve" => serial_path = Some(PathBuf::from(value)),
+                "--bind" => match parse_binding(&value) {
+                    Some(binding) => bindings.push(binding),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--name" => match uhid::check_name(&value) {
+                    Ok(()) => options.name = Some(value),
+                    Err(err) => {
+                        return Err(Failure::new(Code::Usage, err.to_string()));
+                    }
+                },
+                "--bus" => match uhid::bus_from_name(&value) {
+                    Some(bus) => options.bus = Some(bus),
+                    None => {
+                        return Err(Failure::new(Code::Usage, format!("Unknown bus {}, expected one of: {}", value, bus_names().join(", "))));
+                    }
+                },
+                "--vid" | "--pid" | "--device-version" | "--country" => {
+                    /* USB ids and bcdDevice are 16 bit, HID country codes go up to 35 */
+                    let max = if arg == "--country" { MAX_COUNTRY } else { u16::MAX as u32 };
+                    let number = match parse_number(&value) {
+                        Some(number) if number <= max => number,
+                        _ => {
+                            return Err(Failure::new(Code::Usage, format!("Invalid value {} for {}, expected 0 to {:#x}", value, arg, max)));
+                        }
+                    };
+                    match arg.as_str() {
+                        "--vid" => options.vendor = Some(number),
+                        "--pid" => options.product = Some(number),
+                        "--device-version" => options.version = Some(number),
+                        _ => options.country = Some(number),
+                    }
+                },
+                "--dpi" => match value.parse() {
+                    Ok(dpi) if dpi > 0 => options.dpi = Some(dpi),
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--repeat" => match parse_repeat(&value) {
+                    Some(repeat) => options.repeat = Some(repeat),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "replay" if value == "--format" => match (args.next(), args.next()) {
+                    (Some(format), Some(file)) => match format.as_str() {
+                        "hid-recorder" => replay = Some((Format::HidRecorder, PathBuf::from(file))),
+                        "pcap" => replay = Some((Format::Pcap, PathBuf::from(file))),
+                        "evemu" => replay = Some((Format::Evemu, PathBuf::from(file))),
+                        _ => {
+                            return Err(Failure::new(Code::Usage, format!("Unknown recording format {}, expected one of: hid-recorder, pcap, evemu", format)));
+                        }
+                    },
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "replay" => replay = Some((Format::HidRecorder, PathBuf::from(value))),
+                "--speed" => match value.parse::<f64>() {
+                    Ok(factor) if factor.is_finite() && factor > 0.0 => speed = Some(factor),
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--from" | "--to" => match schedule::parse_duration(&value) {
+                    Some(time) if arg == "--from" => from = Some(time),
+                    Some(time) => to = Some(time),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--interface" => match value.parse() {
+                    Ok(number) => interface = Some(number),
+                    Err(_) => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--interleave" => match Interleave::from_name(&value) {
+                    Ok(interleave) => options.interleave = Some(interleave),
+                    Err(err) => {
+                        return Err(Failure::new(Code::Usage, err.to_string()));
+                    }
+                },
+                "--template" => match Template::from_spec(&value) {
+                    Ok(template) => options.template = Some(template),
+                    Err(err) => {
+                        return Err(Failure::new(Code::Usage, err.to_string()));
+                    }
+                },
+                "pattern" => match Shape::from_name(&value) {
+                    Ok(shape) => pattern = Some(shape),
+                    Err(err) => {
+                        return Err(Failure::new(Code::Usage, err.to_string()));
+                    }
+                },
+                "--radius" => match value.parse() {
+                    Ok(counts) if counts > 0 => radius = counts,
+                    _ => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                "--period" => match schedule::parse_duration(&value) {
+                    Some(duration) => period = duration,
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                _ if value == "-" => text = Some(Text::Stdin),
+                _ if value == "--file" => match args.next() {
+                    Some(file) => text = Some(Text::File(PathBuf::from(file))),
+                    None => {
+                        return Err(invalid(&arg));
+                    }
+                },
+                /* type <text> */
+                _ => text = Some(Text::Inline(value)),
+            }
+        } else {
+            path = PathBuf::from(arg);
+        }
+    }
+
+    if vsock_port.is_some() && serial_path.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("Only one of vsock-serve and serial-serve can be used")));
+    }
+    let auth = match Auth::load(token_file.as_deref()) {
+        Ok(auth) => auth,
+        Err(err) => return Err(Failure::io(&err, format!("Cannot read the token: {}", err))),
+    };
+    let tls = match (tls_cert, tls_key) {
+        (Some(cert), Some(key)) => match Acceptor::load(&cert, &key, tls_client_ca.as_deref()) {
+            Ok(tls) => Some(tls),
+            Err(err) => return Err(Failure::io(&err, format!("Cannot set up TLS: {}", err))),
+        },
+        (None, None) if tls_client_ca.is_none() => None,
+        _ => return Err(Failure::new(Code::Usage, String::from("--tls-cert and --tls-key are needed for TLS"))),
+    };
+
+    if backend_name != "uhid" && (fuzzing || stressing || farming || probing) {
+        return Err(Failure::new(Code::Usage, String::from("fuzz-rdesc, stress-lifecycle, farm and capabilities need the uhid backend")));
+    }
+    if backend_name != "uhid" && wait_for_node.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("--wait-for-node needs the uhid backend")));
+    }
+    if splitting && wait_for_node.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("--wait-for-node cannot be used with --split")));
+    }
+    if readback && (backend_name != "uhid" || splitting) {
+        return Err(Failure::new(Code::Usage, String::from("--timing-readback needs the uhid backend, without --split")));
+    }
+    if sandbox && on_output.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("--on-output runs a command, which --sandbox does not allow")));
+    }
+    if sandbox && repl {
+        return Err(Failure::new(Code::Usage, String::from("repl sets up the terminal and saves its history, which --sandbox does not allow")));
+    }
+    if sandbox && watchdog_interval.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("--watchdog opens the uhid-cdev again, which --sandbox does not allow")));
+    }
+
+    if let Some(shell) = completions {
+        let program = env::args().next().map(PathBuf::from)
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| String::from("uhid-example"));
+        print!("{}", completions::script(shell, &program, &completions::words(&usage_text())));
+        return Ok(());
+    }
+    if schema {
+        print!("{}", schema::render());
+        return Ok(());
+    }
+
+    match templates {
+        Some(Some(kind)) => {
+            print!("{}", template::describe(kind));
+            return Ok(());
+        },
+        Some(None) => {
+            for &kind in template::KINDS {
+                println!("{:<12} {}", kind.name(), kind.about());
+            }
+            return Ok(());
+        },
+        None => (),
+    }
+
+    if let Some(directory) = corpus_out {
+        let seed = seed.unwrap_or_else(time_seed);
+        eprintln!("Writing {} descriptors with seed {} to {}", count, seed, directory.display());
+        return write_corpus(&directory, seed, count)
+            .map_err(|err| Failure::io(&err, format!("Cannot write corpus {}: {}", directory.display(), err)));
+    }
+
+    let mut registry = devices::Registry::new();
+    for library in plugins.iter() {
+        if let Err(err) = plugin::load(library, &mut registry) {
+            return Err(Failure::io(&err, format!("Cannot load plugin {}: {}", library.display(), err)));
+        }
+    }
+
+    let mut capture = match replay {
+        Some((format, replay)) => match File::open(&replay).and_then(|file| match format {
+            Format::HidRecorder => recording::parse(BufReader::new(file)),
+            Format::Pcap => pcap::parse(file, interface),
+            Format::Evemu => evemu::parse(BufReader::new(file)),
+        }) {
+            Ok(capture) => Some(capture),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot read recording {}: {}", replay.display(), err)));
+            }
+        },
+        None => None,
+    };
+
+    if (speed.is_some() || from.is_some() || to.is_some() || single_step) && capture.is_none() {
+        return Err(Failure::new(Code::Usage, String::from("--speed, --from, --to and --single-step only apply to replay")));
+    }
+    let mut stepper = None;
+    if let Some(capture) = capture.as_mut() {
+        capture.trim(from.unwrap_or_default(), to);
+        if capture.events.is_empty() && (from.is_some() || to.is_some()) {
+            return Err(Failure::new(Code::Failure, String::from("No recorded events between --from and --to")));
+        }
+        capture.scale(speed.unwrap_or(1.0)).map_err(|err| Failure::new(Code::Usage, format!("Invalid --speed: {}", err)))?;
+        if single_step {
+            stepper = Some(Stepper::new(mem::take(&mut capture.events)));
+        }
+    }
+
+    /* replays keep the recorded name unless overridden */
+    let name = match (&options.name, &capture) {
+        (None, Some(capture)) if uhid::check_name(&capture.name).is_ok() => capture.name.clone(),
+        _ => options.name().to_string(),
+    };
+    let replaying = capture.is_some();
+
+    if replaying && barrier_server.is_some() {
+        return Err(Failure::new(Code::Usage, String::from("Cannot replay a recording as a Barrier client")));
+    }
+    if merge_sources.is_some() && (replaying || barrier_server.is_some()) {
+        return Err(Failure::new(Code::Usage, String::from("merge cannot be combined with replay or barrier")));
+    }
+    if (grab_key.is_some() || remap_path.is_some()) && merge_sources.is_none() {
+        return Err(Failure::new(Code::Usage, String::from("--grab and --remap need merge")));
+    }
+    let remap = match remap_path {
+        Some(path) => match Remap::load(&path) {
+            Ok(remap) => remap,
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot load the remap table {}: {}", path.display(), err)));
+            }
+        },
+        None => Remap::default(),
+    };
+    if (latency.is_some() || jitter.is_some() || faults.is_some()) && !replaying && forward.is_none() {
+        return Err(Failure::new(Code::Usage, String::from("--latency, --jitter and --fault need replay or --forward")));
+    }
+    let relay_seed = seed.unwrap_or_else(time_seed);
+    if jitter.is_some() || faults.is_some() {
+        eprintln!("Relaying reports with seed {}", relay_seed);
+    }
+    let delay = if latency.is_some() || jitter.is_some() {
+        Some(Delay::new(latency.unwrap_or_default(), jitter.unwrap_or_default(), distribution, relay_seed))
+    } else {
+        None
+    };
+    /* a generator of its own, drops not following the jitter drawn */
+    let injector = faults.map(|faults| Injector::new(faults, relay_seed.rotate_left(32)));
+    let screen = barrier_server.as_ref().map(|_| Screen::new(screen_size.0, screen_size.1));
+    let mut merge = match merge_sources {
+        Some(paths) => match Merge::open(&paths, remap) {
+            Ok(mut merge) => {
+                if merge.skipped() > 0 {
+                    eprintln!("Leaving out {} keys, axes and event types of the sources that cannot be merged", merge.skipped());
+                }
+                if let Some(key) = grab_key {
+                    if let Err(err) = merge.grab(key) {
+                        return Err(Failure::io(&err, format!("Cannot grab the devices: {}", err)));
+                    }
+                }
+                Some(merge)
+            },
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot merge the devices: {}", err)));
+            }
+        },
+        None => None,
+    };
+
+    let mut device: Box<dyn Device> = match capture {
+        Some(capture) => Box::new(Replay::new(capture)),
+        None if merge.is_some() => Box::new(merge.as_ref().unwrap().device()),
+        None if screen.is_some() => Box::new(screen.clone().unwrap()),
+        None if options.template.is_some() => Box::new(options.template.as_ref().unwrap().device()),
+        None => match registry.create(&preset, &options) {
+            Some(device) => device,
+            None => {
+                return Err(Failure::new(Code::Usage, format!("Unknown preset {}, expected one of: {}", preset, registry.names().join(", "))));
+            }
+        },
+    };
+
+    if let Some(shape) = pattern {
+        device = Box::new(Pattern::new(device, shape, radius, period));
+    }
+
+    if let Some(script_path) = script {
+        device = match script::load(&script_path, device) {
+            Ok(device) => device,
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot load script {}: {}", script_path.display(), err)));
+            }
+        };
+    }
+
+    let mut layout = match layout::layout(&layout_name) {
+        Some(layout) => layout.clone(),
+        None if Path::new(&layout_name).is_file() => match Layout::load(Path::new(&layout_name)) {
+            Ok(layout) => layout,
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot load layout {}: {}", layout_name, err)));
+            }
+        },
+        None => {
+            return Err(Failure::new(Code::Usage, format!("Unknown layout {}, expected one of: {}, or a layout file", layout_name, layout_names().join(", "))));
+        }
+    };
+    layout.set_substitute(substitute);
+    layout.set_unicode(unicode);
+    /* kept for the whole run, like the built-in ones */
+    let layout: &'static Layout = Box::leak(Box::new(layout));
+
+    /* the terminal controls typing unless it is what is being typed */
+    let stdin_is_text = matches!(text, Some(Text::Stdin));
+    let stdin_modes = [stdin_is_text, fixture.is_some(), forward.is_some(), serve.is_some(), repl];
+    if stdin_modes.iter().filter(|&&mode| mode).count() > 1 {
+        return Err(Failure::new(Code::Usage, String::from("Only one of type -, --fixture, --forward, stdio-serve and repl can use stdin")));
+    }
+    let guard = Guard::new(max_rate, confirm);
+    let mut text: Option<Box<dyn BufRead>> = match text {
+        Some(_) if !device.is_keyboard() => {
+            return Err(Failure::new(Code::Usage, format!("Cannot type with preset {}, it has no keyboard", preset)));
+        },
+        Some(Text::Inline(text)) => {
+            if let Err(err) = layout.strokes(&text) {
+                return Err(Failure::new(Code::Usage, err.to_string()));
+            }
+            if !confirmed(&guard, text.chars().count()) {
+                return Ok(());
+            }
+            Some(Box::new(io::Cursor::new(text.into_bytes())))
+        },
+        Some(Text::File(path)) => match File::open(&path).and_then(|file| file.metadata().map(|metadata| (file, metadata.len()))) {
+            /* the size in bytes stands in for the characters */
+            Ok((_, size)) if !confirmed(&guard, size as usize) => return Ok(()),
+            Ok((file, _)) => Some(Box::new(BufReader::new(file))),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot open {}: {}", path.display(), err)));
+            }
+        },
+        Some(Text::Stdin) => Some(Box::new(BufReader::new(io::stdin()))),
+        None => None,
+    };
+    let macros_given = macros_path.is_some();
+    let macros_path = macros_path.or_else(macros::default_path);
+    let macros = match macros_path.as_ref().map(|path| File::open(path).and_then(|file| macros::parse(BufReader::new(file)))) {
+        Some(Ok(macros)) => macros,
+        /* the default file only exists once something was recorded */
+        Some(Err(ref err)) if err.kind() == io::ErrorKind::NotFound && !macros_given => vec![],
+        Some(Err(err)) => {
+            return Err(Failure::io(&err, format!("Cannot read macros from {}: {}", macros_path.unwrap().display(), err)));
+        },
+        None => vec![],
+    };
+    if let Some((_, name)) = bindings.iter().find(|&(_, name)| !macros.iter().any(|macro_| &macro_.name == name)) {
+        return Err(Failure::new(Code::Usage, format!("Unknown macro {}", name)));
+    }
+    /* a chord would never get past a binding of its first keys */
+    for (index, (keys, _)) in bindings.iter().enumerate() {
+        if let Some((other, _)) = bindings.iter().skip(index + 1).find(|(other, _)| other.starts_with(keys) || keys.starts_with(other)) {
+            return Err(Failure::new(Code::Usage, format!("Conflicting bindings {} and {}", keys::chord_name(keys), keys::chord_name(other))));
+        }
+    }
+    let mut schedule = match schedule_path {
+        Some(path) => match File::open(&path).and_then(|file| Schedule::parse(BufReader::new(file), Instant::now())) {
+            Ok(schedule) => schedule,
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot read schedule {}: {}", path.display(), err)));
+            }
+        },
+        None => Schedule::default(),
+    };
+    let mut rerun = match rerun_path {
+        Some(path) => match File::open(&path).and_then(|file| Rerun::parse(BufReader::new(file))) {
+            Ok(rerun) => Some(rerun),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot read session log {}: {}", path.display(), err)));
+            }
+        },
+        None => None,
+    };
+    let scheduled = schedule.tasks().iter().map(|task| ("schedule", &task.command));
+    for (source, command) in scheduled.chain(rerun.iter().flat_map(Rerun::commands).map(|command| ("session log", command))) {
+        match *command {
+            Command::Macro(ref name) if !macros.iter().any(|macro_| &macro_.name == name) => {
+                return Err(Failure::new(Code::Usage, format!("Unknown macro {} in the {}", name, source)));
+            },
+            Command::Type(_) if !device.is_keyboard() => {
+                return Err(Failure::new(Code::Usage, format!("Cannot type with preset {}, it has no keyboard", preset)));
+            },
+            Command::Type(ref text) => if let Err(err) = layout.strokes(text) {
+                return Err(Failure::io(&err, format!("{} in the {}", err, source)));
+            },
+            _ => (),
+        }
+    }
+
+    let mut barrier = match (barrier_server, screen) {
+        (Some(server), Some(screen)) => {
+            let screen_name = screen_name.unwrap_or_else(hostname);
+            eprintln!("Connect to Barrier server {} as {}", server, screen_name);
+            match Client::connect(&server, &screen_name, screen.size()) {
+                Ok(client) => Some(Barrier::new(client, screen, layout)),
+                Err(err) => {
+                    return Err(Failure::io(&err, format!("Cannot connect to Barrier server {}: {}", server, err)));
+                }
+            }
+        },
+        _ => None,
+    };
+
+    let autoclicker = if autoclick {
+        match Autoclicker::new(button, cps, hold) {
+            Some(autoclicker) => Some(autoclicker),
+            None => {
+                return Err(Failure::new(Code::Usage, format!("Cannot hold the button for {:?} at {} clicks per second", hold, cps)));
+            }
+        }
+    } else {
+        None
+    };
+
+    let macros = Macros {
+        path: macros_path,
+        macros,
+        bindings,
+        chord: vec![],
+        record_name,
+        recording: None,
+        player: Player::default(),
+    };
+
+    let lock = if pause_on_lock {
+        match SessionLock::watch(session.as_deref()) {
+            Ok(lock) => Some(lock),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot watch the session lock state: {}", err)));
+            }
+        }
+    } else {
+        None
+    };
+
+    if probing {
+        let capabilities = probe::probe(&path).map_err(|err| Failure::io(&err, format!("Cannot probe the kernel: {}", err)))?;
+        report_capabilities(&capabilities);
+        return Ok(());
+    }
+
+    if stressing {
+        let stress = stress::Stress {
+            iterations: iterations.unwrap_or(stress::DEFAULT_ITERATIONS),
+            parallel,
+            name: name.clone(),
+            descriptor: device.descriptor().to_vec(),
+            identity: options.identity(device.identity()),
+        };
+        eprintln!("Creating and destroying {} devices on {} threads", stress.iterations, parallel);
+        let summary = stress::run(&path, stress).map_err(|err| Failure::io(&err, format!("Cannot run the stress test: {}", err)))?;
+        report_stress(&summary);
+        return Ok(());
+    }
+
+    if farming {
+        let farm = farm::Farm {
+            count: count as u64,
+            parallel,
+            rate: rate.unwrap_or(farm::DEFAULT_RATE),
+            name: name.clone(),
+            descriptor: device.descriptor().to_vec(),
+            identity: options.identity(device.identity()),
+            reports: farm::square(device.as_mut()),
+        };
+        if farm.reports.is_empty() || farm.rate == 0 {
+            return Err(Failure::new(Code::Usage, format!("Cannot drive a farm of preset {} at {} reports per second", preset, farm.rate)));
+        }
+        eprintln!("Creating {} devices on {} threads, sending {} reports per second each", farm.count, parallel, farm.rate);
+        let summary = farm::run(&path, farm).map_err(|err| Failure::io(&err, format!("Cannot run the farm: {}", err)))?;
+        return report_farm(&summary);
+    }
+
+    let mut backend: Box<dyn Backend> = if backend_name == "mock" {
+        match Mock::new() {
+            Ok(mock) => Box::new(mock.without_reports()),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot set up the mock backend: {}", err)));
+            }
+        }
+    } else if backend_name == "dry-run" {
+        match DryRun::new() {
+            Ok(dry_run) => Box::new(dry_run),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot set up the dry run: {}", err)));
+            }
+        }
+    } else {
+        eprintln!("Open uhid-cdev {}", path.to_str().unwrap());
+        let fd = match fcntl::open(&path, fcntl::O_RDWR | fcntl::O_CLOEXEC | fcntl::O_NONBLOCK, nix::sys::stat::S_IRUSR | nix::sys::stat::S_IWUSR | nix::sys::stat::S_IRGRP | nix::sys::stat::S_IWGRP) {
+            Ok(fd) => fd,
+            Err(err) => {
+                let message = format!("Cannot open uhid-cdev {}: {}", path.to_str().unwrap(), err);
+                return Err(match err {
+                    nix::Error::Sys(nix::Errno::EACCES) | nix::Error::Sys(nix::Errno::EPERM) =>
+                        Failure::new(Code::PermissionDenied, format!("{}. {}", message, PERMISSION_HINT)),
+                    nix::Error::Sys(nix::Errno::ENOENT) | nix::Error::Sys(nix::Errno::ENODEV) =>
+                        Failure::new(Code::UhidMissing, format!("{}. Is the uhid module loaded?", message)),
+                    _ => Failure::new(Code::Failure, message),
+                });
+            }
+        };
+        let mut file = unsafe { File::from_raw_fd(fd) };
+
+        if fuzzing {
+            let fuzzing = Fuzzing { seed, corpus: corpus_path, iterations, findings };
+            let identity = options.identity(device.identity());
+            return fuzz_rdesc(&mut file, &name, device.descriptor(), &identity, &fuzzing)
+                .map_err(|err| Failure::io(&err, format!("Cannot fuzz: {}", err)));
+        }
+
+        Box::new(Uhid::new(file).reopens(&path))
+    };
+
+    if splitting {
+        let backend_name = backend_name.clone();
+        let path = path.clone();
+        let make = move || -> io::Result<Box<dyn Backend>> {
+            match backend_name.as_str() {
+                "mock" => Ok(Box::new(Mock::new()?.without_reports())),
+                "dry-run" => Ok(Box::new(DryRun::new()?)),
+                _ => {
+                    let file = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC | libc::O_NONBLOCK).open(&path)?;
+                    Ok(Box::new(Uhid::new(file).reopens(&path)))
+                },
+            }
+        };
+        backend = match Split::new(backend, make) {
+            Ok(split) => Box::new(split),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot set up splitting: {}", err)));
+            }
+        };
+    }
+    /* split devices are registered under the same name, so cannot be told apart */
+    let single = backend_name == "uhid" && !splitting;
+
+    let metrics_listener = match metrics_address {
+        Some(address) => match TcpListener::bind(&address).and_then(|listener| listener.set_nonblocking(true).map(|_| listener)) {
+            Ok(listener) => Some(listener),
+            Err(err) => {
+                return Err(Failure::io(&err, format!("Cannot serve metrics on {}: {}", address, err)));
+            }
+        },
+        None => None,
+    };
+
+    let kind = match (replaying, barrier.is_some(), merge.is_some()) {
+        (true, _, _) => name.as_str(),
+        (_, true, _) => "Barrier screen",
+        (_, _, true) => "Merged devices",
+        _ if options.template.is_some() => options.template.as_ref().unwrap().kind.name(),
+        _ => preset.as_str(),
+    };
+    eprintln!("Create uhid device ({})", kind);
+    let identity = options.identity(device.identity());
+    if let Err(err) = backend.create(&name, device.descriptor(), &identity) {
+        return Err(Failure::io(&err, format!("Cannot create the device: {}", err)));
+    }
+    /* from now on Ctrl-C destroys the device on the way out */
+    exit::catch_interrupts()?;
+    if single {
+        if let Err(failure) = check_registered(&name, &identity, device.descriptor()) {
+            backend.destroy()?;
+            return Err(failure);
+        }
+    }
+
+    let recorder = match record {
+        Some(record) => match File::create(&record).and_then(|output| Recorder::new(output, &name, device.descriptor(), &identity)) {
+            Ok(recorder) => Some(recorder),
+            Err(err) => {
+                backend.destroy()?;
+                return Err(Failure::io(&err, format!("Cannot record to {}: {}", record.display(), err)));
+            }
+        },
+        None => None,
+    };
+    let session_log = match session_log_path {
+        Some(path) => match File::create(&path).and_then(SessionLog::new) {
+            Ok(session_log) => Some(session_log),
+            Err(err) => {
+                backend.destroy()?;
+                return Err(Failure::io(&err, format!("Cannot log the session to {}: {}", path.display(), err)));
+            }
+        },
+        None => None,
+    };
+    let metrics = Metrics::new(&name, &identity);
+    let mut output_hook = on_output.map(OutputHook::new);
+    let watchdog = watchdog_interval.map(|interval| Watchdog::new(interval, &metrics));
+    let reports = Reports::parse(device.descriptor());
+    let changes = only_changes.map(|only| Changes::new(only, reports.is_numbered()));
+    let mut output = Output { recorder, lock, paused: false, macros, autoclicker, metrics, session_log, step, wheel_step, wheel_max, count: Count::default(), guard, stepper, delay, injector, timing, watchdog, changes, reports, last: HashMap::new() };
+
+    let mut notifier = match Notifier::from_env() {
+        Ok(notifier) => notifier,
+        Err(err) => {
+            eprintln!("Cannot connect to NOTIFY_SOCKET: {}", err);
+            None
+        }
+    };
+
+    let poll = Poll::new()?;
+
+    /* a socket activated service has no terminal, the socket replaces it */
+    let listener = systemd::listen_fds().first().cloned();
+    if listener.is_none() && tls.is_some() {
+        backend.destroy()?;
+        return Err(Failure::new(Code::Usage, String::from("TLS needs a control socket, passed in by systemd")));
+    }
+    let mut control = listener.map(|listener| Control::new(listener, auth.clone(), tls, client_limit));
+    let verified = control.as_ref().is_some_and(|control| control.tls.as_ref().is_some_and(Acceptor::verifies_clients));
+    if control.as_ref().is_some_and(|control| control.auth.is_none() && !verified && auth::local(control.listener).is_network()) {
+        backend.destroy()?;
+        return Err(Failure::new(Code::Usage, format!("The control socket is reachable over the network, set a token with --token-file or {}, or verify clients with --tls-client-ca", auth::TOKEN_VAR)));
+    }
+    if let Some(control) = control.as_ref() {
+        poll.register(&EventedFd(&control.listener), CONTROL,
+                      Ready::readable(), PollOpt::edge())?;
+    } else if !stdin_is_text && !repl {
+        poll.register(&EventedFd(&libc::STDIN_FILENO), STDIN,
+                      Ready::readable(), PollOpt::edge())?;
+    }
+    poll.register(&EventedFd(&backend.fd()), UHID_DEVICE, Ready::readable(),
+                  PollOpt::edge())?;
+    if let Some(listener) = metrics_listener.as_ref() {
+        poll.register(&EventedFd(&listener.as_raw_fd()), METRICS,
+                      Ready::readable(), PollOpt::edge())?;
+    }
+    if let Some(barrier) = barrier.as_ref() {
+        poll.register(&EventedFd(&barrier.client.as_raw_fd()), BARRIER,
+                      Ready::readable(), PollOpt::edge())?;
+    }
+    if let Some(merge) = merge.as_ref() {
+        for index in 0..merge.len() {
+            poll.register(&EventedFd(&merge.fd(index)), Token(SOURCES + index),
+                          Ready::readable(), PollOpt::edge())?;
+        }
+    }
+    let vsock = match vsock_port.map(vsock::Listener::bind) {
+        Some(Ok(listener)) => {
+            poll.register(&EventedFd(&listener.as_raw_fd()), VSOCK,
+                          Ready::readable(), PollOpt::edge())?;
+            Some(listener)
+        },
+        Some(Err(err)) => {
+            backend.destroy()?;
+            return Err(Failure::io(&err, format!("Cannot listen on vsock port {}: {}", vsock_port.unwrap(), err)));
+        },
+        None => None,
+    };
+    let serial = serial_path.as_ref().map(|path| {
+        let port = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NONBLOCK).open(path)?;
+        let fd = port.as_raw_fd();
+        Serve::new(port).map(|serve| (fd, serve))
+    });
+    let mut channel = match serial {
+        Some(Ok((fd, serve))) => {
+            poll.register(&EventedFd(&fd), CHANNEL,
+                          Ready::readable(), PollOpt::edge())?;
+            Some(serve)
+        },
+        Some(Err(err)) => {
+            backend.destroy()?;
+            return Err(Failure::io(&err, format!("Cannot open virtio-serial port {}: {}", serial_path.unwrap().display(), err)));
+        },
+        None => None,
+    };
+
+    if let Some(evemu) = evemu {
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Start)?;
+        let described = wait_for_nodes(&name, &identity).and_then(|nodes| describe_nodes(&nodes.events, &evemu));
+        eprintln!("Destroy uhid device");
+        backend.destroy()?;
+        return described.map_err(|err| Failure::io(&err, format!("Cannot describe the device: {}", err)));
+    }
+
+    /* ready only once the event nodes can be opened, rather than on UHID_START */
+    let nodes_ready = match wait_for_node {
+        Some(timeout) => {
+            wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut None, Event::Start)?;
+            match wait_for_openable(&name, &identity, timeout) {
+                Ok(nodes) => if fixture.is_none() {
+                    for node in nodes {
+                        println!("{}", node.display());
+                    }
+                },
+                Err(err) => {
+                    backend.destroy()?;
+                    return Err(Failure::io(&err, format!("Cannot open the event node: {}", err)));
+                }
+            }
+            if let Some(notifier) = notifier.as_mut() {
+                notifier.ready()?;
+            }
+            true
+        },
+        None => false,
+    };
+
+    if fixture.is_some() {
+        nonblocking_stdin()?;
+        if !nodes_ready {
+            wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Start)?;
+        }
+        match wait_for_nodes(&name, &identity) {
+            Ok(nodes) => {
+                for node in nodes.events {
+                    println!("DEVNAME={}", node.display());
+                }
+                if let Some(hidraw) = nodes.hidraw {
+                    println!("HIDRAW={}", hidraw.display());
+                }
+            },
+            Err(err) => eprintln!("Cannot find the device nodes: {}", err),
+        }
+        println!("READY=1");
+    }
+
+    /* before the sandbox, which refuses fcntl */
+    if forward.is_some() || serve.is_some() {
+        nonblocking_stdin()?;
+    }
+
+    if sandbox {
+        if let Err(err) = sandbox::restrict() {
+            backend.destroy()?;
+            return Err(Failure::io(&err, format!("Cannot install the sandbox: {}", err)));
+        }
+    }
+
+    if let Some(text) = text.as_mut() {
+        let mut typist = Typist::new(Duration::from_millis(key_delay));
+        if let Some(rate) = rate {
+            typist = typist.rate(rate);
+        }
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Open)?;
+        type_text(&poll, backend.as_mut(), &mut output, device.as_mut(), layout, text.as_mut(), &mut typist)?;
+        eprintln!("Destroy uhid device");
+        backend.destroy()?;
+        return Ok(());
+    }
+
+    if replaying {
+        eprintln!("Waiting for the device to be opened to replay");
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Open)?;
+    }
+    if let Some(rerun) = rerun.as_mut() {
+        eprintln!("Waiting for the device to be opened to rerun the session");
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Open)?;
+        rerun.start(Instant::now());
+    }
+
+    let mut events = Events::with_capacity(1);
+    let mut decoder = Decoder::default();
+    let mut readout = stats_interval.map(|interval| Readout::new(interval, &output.metrics));
+    let mut hotplug = if hotplug { Some(Hotplug::new(hotplug_interval, cycle_ids)) } else { None };
+    /* the identity the device was last created with, for finding its nodes */
+    let mut created = identity;
+
+    if serve.is_some() {
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Start)?;
+        framing::write_frame(io::stdout().lock(), b"ready")?;
+    }
+
+    if let Some(channel) = channel.as_mut() {
+        wait_for(&poll, backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics, &mut notifier, Event::Start)?;
+        /* the host end may not be connected yet */
+        if let Err(err) = channel.ready() {
+            eprintln!("Cannot announce the device on {}: {}", serial_path.as_ref().unwrap().display(), err);
+        }
+    }
+
+    let mut repl = if repl {
+        let names = output.macros.macros.iter().map(|saved| saved.name.clone()).collect();
+        match Repl::start(names) {
+            Ok(repl) => {
+                poll.register(&EventedFd(&repl.fd()), REPL, Ready::readable(), PollOpt::edge())?;
+                eprintln!("Type help for the commands");
+                Some(repl)
+            },
+            Err(err) => {
+                backend.destroy()?;
+                return Err(Failure::io(&err, format!("Cannot start the REPL: {}", err)));
+            }
+        }
+    } else {
+        None
+    };
+
+    if fixture.is_none() && forward.is_none() && serve.is_none() && repl.is_none() {
+        print!("{}", help(&output));
+    }
+    /* why the loop ended early, to exit with once the device is destroyed */
+    let mut failure = None;
+    loop {
+        let deadline = device.deadline().into_iter()
+            .chain(output.macros.player.deadline())
+            .chain(schedule.deadline())
+            .chain(output.autoclicker.as_ref().and_then(Autoclicker::deadline))
+            .chain(hotplug.as_ref().map(Hotplug::deadline))
+            .chain(output.watchdog.as_ref().and_then(Watchdog::deadline))
+            .chain(readout.as_ref().map(Readout::deadline))
+            .chain(rerun.as_ref().and_then(Rerun::deadline))
+            .chain(barrier.as_ref().map(|barrier| barrier.client.deadline()))
+            .chain(output.count.deadline())
+            .chain(output.delay.as_ref().and_then(Delay::deadline))
+            .min();
+        let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        /* poll() would retry once interrupted, never telling */
+        let polled = poll.poll_interruptible(&mut events, timeout);
+        if exit::interrupted() {
+            failure = Some(Failure::new(Code::Interrupted, String::from("Interrupted")));
+            break;
+        }
+        match polled {
+            /* a signal caught by someone else, like the REPL for resizes */
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                failure = Some(Failure::io(&err, format!("Cannot poll for fds: {}", err)));
+                break;
+            },
+            Ok(_) => (),
+        }
+
+        let mut closed = false;
+        for event in events.iter() {
+            match (event.token(), control.as_mut()) {
+                (STDIN, _) => match (fixture.as_mut(), forward.as_mut(), serve.as_mut()) {
+                    (Some(fixture), _, _) => closed = !fixture.read(backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay))?,
+                    (_, Some(forward), _) => closed = match forward.read(backend.as_mut(), &mut output) {
+                        Ok(open) => !open,
+                        Err(err) => {
+                            failure = Some(Failure::new(Code::Protocol, format!("Cannot forward reports: {}", err)).within(&err));
+                            true
+                        }
+                    },
+                    (_, _, Some(serve)) => closed = match serve.read(&name, backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay)) {
+                        Ok(open) => !open,
+                        Err(err) => {
+                            failure = Some(Failure::new(Code::Protocol, format!("Cannot serve commands: {}", err)).within(&err));
+                            true
+                        }
+                    },
+                    (None, None, None) => keyboard(backend.as_mut(), &mut output, device.as_mut(), &mut decoder)?,
+                },
+                (UHID_DEVICE, _) => while let Some(event) = handle_event(backend.as_mut(), device.as_mut(), &output.reports, &mut output.metrics)? {
+                    started(&mut notifier, &event)?;
+                    for serve in serve.iter_mut().chain(channel.iter_mut()) {
+                        if let Err(err) = serve.notify(backend.current(), &event) {
+                            eprintln!("Cannot notify of the event: {}", err);
+                        }
+                    }
+                    if event == Event::Start && single {
+                        print_nodes(&name, &created);
+                    }
+                    if let Some(hook) = output_hook.as_mut() {
+                        match event {
+                            Event::Output { rtype, ref data } | Event::SetReport { rtype, ref data, .. } => {
+                                hook.report(rtype, data, &field_values(&output.reports, rtype, data), &device.decode_output(rtype, data));
+                            },
+                            _ => (),
+                        }
+                    }
+                    if event == Event::Start && readback {
+                        let attached = wait_for_openable(&name, &created, NODE_TIMEOUT)
+                            .and_then(|paths| output.timing.as_mut().unwrap().attach(&paths));
+                        if let Err(err) = attached {
+                            eprintln!("Cannot read back the reports: {}", err);
+                        }
+                    }
+                },
+                (REPL, _) => closed = match repl.as_mut().unwrap().read() {
+                    Ok((lines, open)) => {
+                        let mut quit = false;
+                        for line in lines {
+                            match line.as_str() {
+                                "" => (),
+                                "help" => print!("{}", command_help()),
+                                "quit" | "exit" => quit = true,
+                                line => run_line(line, backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay)),
+                            }
+                        }
+                        quit || !open
+                    },
+                    Err(err) => {
+                        failure = Some(Failure::io(&err, format!("Cannot read the REPL: {}", err)));
+                        true
+                    }
+                },
+                (METRICS, _) => serve_metrics(metrics_listener.as_ref().unwrap(), &output)?,
+                (VSOCK, _) => while let Some(connection) = vsock.as_ref().unwrap().accept()? {
+                    /* one host at a time, the others are turned away */
+                    if channel.is_some() {
+                        auth::audit(&format!("vsock connection from {}", auth::peer(connection.as_raw_fd())), format_args!("refused, already serving one"));
+                        continue;
+                    }
+                    let fd = connection.as_raw_fd();
+                    let peer = format!("vsock connection from {}", auth::peer(fd));
+                    let mut serve = Serve::new(connection)?.audited(peer, auth.clone(), client_limit);
+                    poll.register(&EventedFd(&fd), CHANNEL, Ready::readable(), PollOpt::edge())?;
+                    match serve.ready() {
+                        Ok(()) => channel = Some(serve),
+                        Err(err) => eprintln!("Cannot announce the device on vsock: {}", err),
+                    }
+                },
+                (CHANNEL, _) => {
+                    let result = channel.as_mut().unwrap().read(&name, backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay));
+                    if let Err(ref err) = result {
+                        eprintln!("Cannot serve commands: {}", err);
+                    }
+                    /* keep serving the next host; a serial port stays open */
+                    if !result.unwrap_or(false) {
+                        if vsock.is_some() {
+                            channel.take().unwrap().closed();
+                        } else {
+                            channel.as_mut().unwrap().reset();
+                        }
+                    }
+                },
+                (BARRIER, _) => closed = match barrier.as_mut().unwrap().read() {
+                    Ok(Some(reports)) => {
+                        for report in reports {
+                            send(backend.as_mut(), &mut output, &report)?;
+                        }
+                        false
+                    },
+                    Ok(None) => {
+                        eprintln!("Barrier server closed the connection");
+                        true
+                    },
+                    Err(err) => {
+                        failure = Some(Failure::new(Code::Protocol, format!("Lost the Barrier server: {}", err)));
+                        true
+                    }
+                },
+                (Token(token), _) if token >= SOURCES => {
+                    let merge = match merge.as_mut() {
+                        Some(merge) => merge,
+                        None => continue,
+                    };
+                    let index = token - SOURCES;
+                    closed = match merge.read(index) {
+                        Ok(Some(reports)) => {
+                            for report in reports {
+                                send(backend.as_mut(), &mut output, &report)?;
+                            }
+                            false
+                        },
+                        Ok(None) => {
+                            eprintln!("{} went away", merge.path(index).display());
+                            true
+                        },
+                        Err(err) => {
+                            failure = Some(Failure::io(&err, format!("Cannot read {}: {}", merge.path(index).display(), err)));
+                            true
+                        }
+                    };
+                },
+                (CONTROL, Some(control)) => control.accept(&poll)?,
+                (token, Some(control)) => control.read(token, &poll, backend.as_mut(), &mut output, device.as_mut())?,
+                _ => unreachable!(),
+            }
+        }
+        if closed {
+            break;
+        }
+
+        let now = Instant::now();
+        if barrier.as_ref().is_some_and(|barrier| barrier.client.deadline() <= now) {
+            failure = Some(Failure::new(Code::Protocol, String::from("Barrier server stopped sending keep-alives")));
+            break;
+        }
+        for digit in output.count.expire(now) {
+            log(&mut output, &Command::Key(digit))?;
+            key(backend.as_mut(), &mut output, device.as_mut(), digit)?;
+        }
+        if let Some(replugged) = hotplug.as_mut().and_then(|hotplug| hotplug.tick(now, identity)) {
+            created = replugged;
+            eprintln!("Replugging the device as {:04x}:{:04x}", replugged.vendor, replugged.product);
+            backend.destroy()?;
+            backend.create(&name, device.descriptor(), &replugged)?;
+            if let Some(changes) = output.changes.as_mut() {
+                changes.clear();
+            }
+            if single {
+                if let Err(rejected) = check_registered(&name, &replugged, device.descriptor()) {
+                    failure = Some(rejected);
+                    break;
+                }
+            }
+        }
+        let wedged = match output.watchdog.as_mut() {
+            Some(watchdog) => watchdog.check(now, &output.metrics),
+            None => None,
+        };
+        if let Some(wedged) = wedged {
+            eprintln!("Device wedged, {}; creating it again", wedged);
+            output.metrics.recovered(wedged.name());
+            /* as far as it still goes */
+            if let Err(err) = backend.destroy() {
+                eprintln!("Cannot destroy the wedged device: {}", err);
+            }
+            let fd = backend.fd();
+            let recreated = backend.reopen()
+                .and_then(|()| if backend.fd() != fd {
+                    poll.register(&EventedFd(&backend.fd()), UHID_DEVICE, Ready::readable(), PollOpt::edge())
+                } else {
+                    Ok(())
+                })
+                .and_then(|()| backend.create(&name, device.descriptor(), &created));
+            if let Err(err) = recreated {
+                eprintln!("Cannot create the device again, trying again in a while: {}", err);
+            }
+            if let Some(changes) = output.changes.as_mut() {
+                changes.clear();
+            }
+            if let Some(watchdog) = output.watchdog.as_mut() {
+                watchdog.created(now, &output.metrics);
+            }
+        }
+        let clicks = output.autoclicker.as_mut().map(|autoclicker| autoclicker.tick(now)).unwrap_or_default();
+        for report in clicks.into_iter().flat_map(|action| device.action(action)) {
+            send(backend.as_mut(), &mut output, &report)?;
+        }
+        for command in schedule.tick(now) {
+            log(&mut output, &command)?;
+            run(command, backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay))?;
+        }
+        let rerun_commands = rerun.as_mut().map(|rerun| rerun.tick(now)).unwrap_or_default();
+        for command in rerun_commands {
+            log(&mut output, &command)?;
+            run(command, backend.as_mut(), &mut output, device.as_mut(), layout, Duration::from_millis(key_delay))?;
+        }
+        for report in device.tick(now) {
+            relay(backend.as_mut(), &mut output, report)?;
+        }
+        let delayed = output.delay.as_mut().map(|delay| delay.due(now)).unwrap_or_default();
+        for report in delayed.into_iter().chain(output.macros.player.tick(now)) {
+            send(backend.as_mut(), &mut output, &report)?;
+        }
+        if let Some(line) = readout.as_mut().and_then(|readout| readout.tick(now, &output.metrics)) {
+            eprintln!("{}", line);
+            if let Some(timing) = output.timing.as_ref() {
+                eprint!("{}", timing.render());
+            }
+        }
+        /* typed text plays out after its command */
+        if rerun.as_ref().is_some_and(Rerun::is_done) && output.macros.player.is_empty() {
+            eprintln!("Session rerun");
+            break;
+        }
+    }
+
+    /* the reports forwarded last are still on their way */
+    while let Some(deadline) = output.delay.as_ref().and_then(Delay::deadline) {
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        for report in output.delay.as_mut().unwrap().due(Instant::now()) {
+            send(backend.as_mut(), &mut output, &report)?;
+        }
+    }
+
+    if let Some(timing) = output.timing.as_ref() {
+        eprint!("{}", timing.render());
+    }
+    if let Some(injector) = output.injector.as_ref() {
+        let stats = injector.stats();
+        eprintln!("Dropped {} and duplicated {} reports", stats.dropped, stats.duplicated);
+    }
+
+    // TODO: Only reached once the stdin of a fixture, forward or serve mode is closed, the REPL quit, a session rerun, the Barrier connection lost or on SIGINT and SIGTERM, q should cleanly exit too
+    eprintln!("Destroy uhid device");
+    backend.destroy()?;
+    failure.map_or(Ok(()), Err)
+}
+
+fn main() {
+    if let Err(failure) = try_main() {
+        exit::report(&failure);
+        process::exit(failure.code.value());
+    }
 }
@@ -0,0 +1,213 @@
+/*
+ * Merging devices
+ * Reads the events of several evdev devices, e.g. a keypad and a mouse, and
+ * sends them as the reports of one composite HID device, to present a single
+ * clean device to a VM or a game. The device is made up like the one for an
+ * evemu recording, see src/evemu.rs: a keyboard report 0x01 with the keys of
+ * all sources and a pointer report 0x02 with all their buttons and axes,
+ * whichever source they come from. Keys and axes it cannot express, like
+ * multitouch, are left out. A remap table, see src/remap.rs, can swap
+ * buttons, invert and scale axes and map keys on the way.
+ *
+ * Unless grabbed, the events of the sources keep reaching the session
+ * directly too. With a toggle key the sources are grabbed with EVIOCGRAB,
+ * so only the merged device is seen; pressing the key on any source lets
+ * them go or grabs them again. It takes effect once the key is released, so
+ * the session sees both or neither of its events, and the key itself is
+ * never merged. The grab ends with the merge, or with the process as the
+ * kernel lets go of a closed node.
+ *
+ * When the kernel drops events of a source the rest of that frame is
+ * skipped, and a source going away ends the merge.
+ */
+
+use devices::{Action, Device, Identity};
+use evemu::{Capabilities, Translator};
+use libc;
+use remap::Remap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::slice;
+use uhid::BUS_VIRTUAL;
+
+const EV_SYN: u16 = 0x00;
+const SYN_REPORT: u16 = 0x00;
+const SYN_DROPPED: u16 = 0x03;
+const EV_KEY: u16 = 0x01;
+
+/* _IOW('E', 0x90, int) */
+const EVIOCGRAB: libc::c_ulong = (1 << 30) | (4 << 16) | ((b'E' as libc::c_ulong) << 8) | 0x90;
+
+/* Events read at once */
+const READ_EVENTS: usize = 64;
+
+struct Source {
+    path: PathBuf,
+    file: File,
+    /* events were dropped, skip to the end of the frame */
+    dropped: bool,
+}
+
+pub struct Merge {
+    sources: Vec<Source>,
+    translator: Translator,
+    /* the key letting go of the sources and grabbing them again */
+    toggle: Option<u16>,
+    grabbed: bool,
+}
+
+fn grab(file: &File, grab: bool) -> io::Result<()> {
+    /* the argument is the int itself rather than a pointer to it */
+    if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCGRAB as _, grab as libc::c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Merge {
+    /* Opens the event nodes and makes up the device for all of them and
+     * what the remap table maps to */
+    pub fn open(paths: &[PathBuf], remap: Remap) -> io::Result<Merge> {
+        let mut capabilities = Capabilities::default();
+        let mut sources = Vec::new();
+        for path in paths {
+            let source = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)
+                .and_then(|file| Capabilities::read(&file).and_then(|read| capabilities.merge(&read)).map(|_| file));
+            match source {
+                Ok(file) => sources.push(Source { path: path.clone(), file, dropped: false }),
+                Err(err) => return Err(io::Error::new(err.kind(), format!("{}: {}", path.display(), err))),
+            }
+        }
+        remap.extend(&mut capabilities);
+        let mut translator = Translator::new(&capabilities, false)?;
+        translator.set_remap(remap);
+        Ok(Merge { sources, translator, toggle: None, grabbed: false })
+    }
+
+    /* Grabs the sources, with the evdev code of the key toggling the grab */
+    pub fn grab(&mut self, toggle: u16) -> io::Result<()> {
+        self.toggle = Some(toggle);
+        self.set_grabbed(true)
+    }
+
+    pub fn grabbed(&self) -> bool {
+        self.grabbed
+    }
+
+    /* Grabs all sources or lets go of them; none stays grabbed when one
+     * cannot be, e.g. being grabbed by someone else already */
+    fn set_grabbed(&mut self, grabbed: bool) -> io::Result<()> {
+        for (index, source) in self.sources.iter().enumerate() {
+            if let Err(err) = grab(&source.file, grabbed) {
+                if grabbed {
+                    for source in self.sources[..index].iter() {
+                        let _ = grab(&source.file, false);
+                    }
+                    self.grabbed = false;
+                }
+                return Err(io::Error::new(err.kind(), format!("{}: {}", source.path.display(), err)));
+            }
+        }
+        self.grabbed = grabbed;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+
+    pub fn fd(&self, index: usize) -> RawFd {
+        self.sources[index].file.as_raw_fd()
+    }
+
+    pub fn path(&self, index: usize) -> &Path {
+        &self.sources[index].path
+    }
+
+    /* Keys, axes and event types of the sources left out */
+    pub fn skipped(&self) -> usize {
+        self.translator.skipped()
+    }
+
+    pub fn device(&self) -> Composite {
+        Composite { descriptor: self.translator.descriptor() }
+    }
+
+    /* The reports for what the source sent, read until it would block; None
+     * once the source is gone */
+    pub fn read(&mut self, index: usize) -> io::Result<Option<Vec<Vec<u8>>>> {
+        let mut events: [libc::input_event; READ_EVENTS] = unsafe { mem::zeroed() };
+        let mut reports = Vec::new();
+        let mut toggle = false;
+        let source = &mut self.sources[index];
+        loop {
+            let size = {
+                let buffer = unsafe { slice::from_raw_parts_mut(events.as_mut_ptr() as *mut u8, mem::size_of_val(&events)) };
+                match source.file.read(buffer) {
+                    Ok(0) => return Ok(None),
+                    Ok(size) => size,
+                    Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref err) if err.raw_os_error() == Some(libc::ENODEV) => return Ok(None),
+                    Err(err) => return Err(err),
+                }
+            };
+            for event in events[..size / mem::size_of::<libc::input_event>()].iter() {
+                match (event.type_, event.code) {
+                    (EV_SYN, SYN_DROPPED) => source.dropped = true,
+                    (EV_SYN, SYN_REPORT) if source.dropped => source.dropped = false,
+                    _ if source.dropped => (),
+                    (EV_KEY, code) if self.toggle == Some(code) => toggle ^= event.value == 0,
+                    _ => reports.extend(self.translator.event(event.type_ as usize, event.code, event.value)),
+                }
+            }
+        }
+        if toggle {
+            let grabbed = !self.grabbed;
+            self.set_grabbed(grabbed)?;
+            eprintln!("{}", if grabbed { "Grabbed the sources" } else { "Let go of the sources" });
+        }
+        Ok(Some(reports))
+    }
+}
+
+impl Drop for Merge {
+    fn drop(&mut self) {
+        if self.grabbed {
+            let _ = self.set_grabbed(false);
+        }
+    }
+}
+
+/* The merged device, sending only what the sources do */
+pub struct Composite {
+    descriptor: Vec<u8>,
+}
+
+impl Device for Composite {
+    fn descriptor(&self) -> &[u8] {
+        &self.descriptor
+    }
+
+    fn identity(&self) -> Identity {
+        Identity {
+            bus: BUS_VIRTUAL as u16,
+            vendor: 0,
+            product: 0,
+            version: 0,
+            country: 0,
+        }
+    }
+
+    fn action(&mut self, _action: Action) -> Vec<Vec<u8>> {
+        vec![]
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * Metrics
+ * Counts what a long running device does and serves it in the Prometheus
+ * text format, so deployments of virtual devices can be monitored: reports
+ * and bytes sent, reports dropped while the session was locked, over the
+ * rate cap, stopped or paused, merged into later ones by modes that coalesce
+ * them, left out by --only-changes as unchanged, and failed to write, the
+ * events received from the kernel by type, the reports queued for playback
+ * and whether the device is started and opened, and how often --watchdog had
+ * to create it again, by cause. Every metric is labelled with the device
+ * name; uhid_device_info has its ids as well. Snapshots of the counters give
+ * rates for a live readout.
+ *
+ * The HTTP side is just enough for a scraper: GET /metrics is answered, any
+ * other request gets 404, and the connection is closed after the response.
+ */
+
+use std::collections::BTreeMap;
+use std::fmt::Write as FmtWrite;
+use std::io;
+use std::io::{Read, Write};
+use std::time::{Duration, Instant};
+use uhid::{Event, Identity};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/* Longest request head read */
+const MAX_REQUEST: usize = 4096;
+
+/* The counters at one point in time */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Snapshot {
+    pub reports: u64,
+    pub bytes: u64,
+    pub outputs: u64,
+    pub dropped: u64,
+    pub coalesced: u64,
+    pub write_errors: u64,
+    /* events received from the kernel, of any type */
+    pub events: u64,
+}
+
+pub struct Metrics {
+    labels: String,
+    info: String,
+    reports: u64,
+    bytes: u64,
+    dropped: u64,
+    coalesced: u64,
+    unchanged: u64,
+    write_errors: u64,
+    events: BTreeMap<&'static str, u64>,
+    recoveries: BTreeMap<&'static str, u64>,
+    started: bool,
+    opened: bool,
+}
+
+/* Escapes a label value, quotes and line breaks being the syntax */
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn event_name(event: &Event) -> &'static str {
+    match *event {
+        Event::Start => "start",
+        Event::Stop => "stop",
+        Event::Open => "open",
+        Event::Close => "close",
+        Event::Output { .. } => "output",
+        Event::GetReport { .. } => "get_report",
+        Event::SetReport { .. } => "set_report",
+        Event::Other(_) => "other",
+    }
+}
+
+impl Metrics {
+    pub fn new(name: &str, identity: &Identity) -> Metrics {
+        let labels = format!("device=\"{}\"", escape(name));
+        let info = format!("{},bus=\"{:04x}\",vendor=\"{:04x}\",product=\"{:04x}\",version=\"{:x}\"",
+                           labels, identity.bus, identity.vendor, identity.product, identity.version);
+        Metrics {
+            labels,
+            info,
+            reports: 0,
+            bytes: 0,
+            dropped: 0,
+            coalesced: 0,
+            unchanged: 0,
+            write_errors: 0,
+            events: BTreeMap::new(),
+            recoveries: BTreeMap::new(),
+            started: false,
+            opened: false,
+        }
+    }
+
+    pub fn report_sent(&mut self, report: &[u8]) {
+        self.reports += 1;
+        self.bytes += report.len() as u64;
+    }
+
+    pub fn report_dropped(&mut self) {
+        self.dropped += 1;
+    }
+
+    /* A report was merged into a later one instead of being sent */
+    pub fn report_coalesced(&mut self) {
+        self.coalesced += 1;
+    }
+
+    /* A report was left out, being the same as the last one of its id */
+    pub fn report_unchanged(&mut self) {
+        self.unchanged += 1;
+    }
+
+    pub fn write_error(&mut self) {
+        self.write_errors += 1;
+    }
+
+    /* The watchdog created the device again, for the cause */
+    pub fn recovered(&mut self, cause: &'static str) {
+        *self.recoveries.entry(cause).or_insert(0) += 1;
+    }
+
+    /* Counts an event for the device, following the device state */
+    pub fn event(&mut self, event: &Event) {
+        *self.events.entry(event_name(event)).or_insert(0) += 1;
+        match *event {
+            Event::Start => self.started = true,
+            Event::Stop => {
+                self.started = false;
+                self.opened = false;
+            },
+            Event::Open => self.opened = true,
+            Event::Close => self.opened = false,
+            _ => (),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            reports: self.reports,
+            bytes: self.bytes,
+            outputs: self.events.get("output").copied().unwrap_or(0),
+            dropped: self.dropped,
+            coalesced: self.coalesced,
+            write_errors: self.write_errors,
+            events: self.events.values().sum(),
+        }
+    }
+
+    /* The metrics in the text format, with the reports waiting to be sent */
+    pub fn render(&self, queued: usize) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+            let _ = writeln!(text, "# HELP {} {}\n# TYPE {} {}", name, help, name, kind);
+            for (labels, value) in samples.iter() {
+                let _ = writeln!(text, "{}{{{}}} {}", name, labels, value);
+            }
+        };
+
+        metric("uhid_device_info", "gauge", "Identity of the device.", &[(self.info.clone(), 1)]);
+        metric("uhid_device_started", "gauge", "Whether the kernel started the device.", &[(self.labels.clone(), self.started as u64)]);
+        metric("uhid_device_opened", "gauge", "Whether anyone has the device open.", &[(self.labels.clone(), self.opened as u64)]);
+        metric("uhid_reports_sent_total", "counter", "Input reports sent to the kernel.", &[(self.labels.clone(), self.reports)]);
+        metric("uhid_bytes_written_total", "counter", "Bytes of the input reports sent.", &[(self.labels.clone(), self.bytes)]);
+        metric("uhid_reports_coalesced_total", "counter", "Input reports merged into later ones.", &[(self.labels.clone(), self.coalesced)]);
+        metric("uhid_reports_unchanged_total", "counter", "Input reports left out as the same as the last one sent with their report-id.", &[(self.labels.clone(), self.unchanged)]);
+        metric("uhid_reports_dropped_total", "counter", "Input reports dropped while the session was locked, over the rate cap, stopped or paused.", &[(self.labels.clone(), self.dropped)]);
+        metric("uhid_write_errors_total", "counter", "Failed writes to the uhid-cdev.", &[(self.labels.clone(), self.write_errors)]);
+        let events: Vec<(String, u64)> = self.events.iter()
+            .map(|(&event, &count)| (format!("{},type=\"{}\"", self.labels, event), count))
+            .collect();
+        metric("uhid_events_received_total", "counter", "Events received from the kernel, by type.", &events);
+        let recoveries: Vec<(String, u64)> = self.recoveries.iter()
+            .map(|(&cause, &count)| (format!("{},cause=\"{}\"", self.labels, cause), count))
+            .collect();
+        metric("uhid_watchdog_recoveries_total", "counter", "Times the watchdog created the wedged device again, by cause.", &recoveries);
+        metric("uhid_queue_depth", "gauge", "Reports queued for playback.", &[(self.labels.clone(), queued as u64)]);
+        text
+    }
+}
+
+/* Prints the rates of the counters every interval, for watching a busy
+ * device from the terminal */
+pub struct Readout {
+    interval: Duration,
+    last: Snapshot,
+    since: Instant,
+}
+
+impl Readout {
+    pub fn new(interval: Duration, metrics: &Metrics) -> Readout {
+        Readout { interval, last: metrics.snapshot(), since: Instant::now() }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.since + self.interval
+    }
+
+    /* The line for the interval once it is over */
+    pub fn tick(&mut self, now: Instant, metrics: &Metrics) -> Option<String> {
+        if now < self.deadline() {
+            return None;
+        }
+        let seconds = now.duration_since(self.since).as_secs_f64();
+        let current = metrics.snapshot();
+        let last = self.last;
+        let rate = |count: u64, last: u64| (count - last) as f64 / seconds;
+        self.last = current;
+        self.since = now;
+        Some(format!("{:.1} reports/s, {:.0} bytes/s, {:.1} events/s; {} bytes written, {} output reports, {} dropped, {} coalesced",
+                     rate(current.reports, last.reports), rate(current.bytes, last.bytes), rate(current.events, last.events),
+                     current.bytes, current.outputs, current.dropped, current.coalesced))
+    }
+}
+
+/* Answers one HTTP request on the connection with the rendered metrics */
+pub fn respond<S: Read + Write>(stream: &mut S, metrics: &str) -> io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 512];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        match stream.read(&mut buffer)? {
+            0 => break,
+            size => request.extend_from_slice(&buffer[..size]),
+        }
+    }
+
+    let line = request.split(|&byte| byte == b'\r' || byte == b'\n').next().unwrap_or_default();
+    let mut fields = line.split(|&byte| byte == b' ');
+    let (status, body) = match (fields.next(), fields.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => ("200 OK", metrics),
+        _ => ("404 Not Found", "Not found, try /metrics\n"),
+    };
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, CONTENT_TYPE, body.len(), body)?;
+    stream.flush()
+}
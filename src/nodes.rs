@@ -0,0 +1,151 @@
+/*
+ * Device nodes
+ * Finds the evdev nodes the kernel created for a uhid device, so whoever
+ * drives the device can open /dev/input/eventN without guessing. uhid
+ * devices show up in sysfs below the uhid misc device as
+ * <bus>:<vendor>:<product>.<sequence>, with their input devices and event
+ * nodes below that, and the hidraw node in its hidraw directory. Of several
+ * devices with the same ids and name the most recently created one is taken.
+ *
+ * The nodes are registered once hid-input and hidraw connected the device,
+ * which is right after UHID_START but not necessarily before it is read. The
+ * hidraw node comes last.
+ *
+ * The HID device itself also has the descriptor the kernel registered, as
+ * it was received and before any driver fixed it up, in report_descriptor.
+ */
+
+use exit;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use uhid::Identity;
+
+const SYSFS_UHID: &str = "/sys/devices/virtual/misc/uhid";
+
+/* The device nodes of a HID device */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Nodes {
+    pub hidraw: Option<PathBuf>,
+    /* /dev/input/eventN, in order */
+    pub events: Vec<PathBuf>,
+}
+
+impl Nodes {
+    /* Every node, the hidraw one first */
+    pub fn paths(&self) -> Vec<&Path> {
+        self.hidraw.iter().chain(self.events.iter()).map(PathBuf::as_path).collect()
+    }
+}
+
+fn hid_name(device: &Path) -> Option<String> {
+    let uevent = fs::read_to_string(device.join("uevent")).ok()?;
+    uevent.lines().find_map(|line| line.strip_prefix("HID_NAME=")).map(str::to_string)
+}
+
+/* The sysfs directories of all uhid devices, with their names */
+pub fn hid_devices() -> io::Result<Vec<(PathBuf, String)>> {
+    let mut devices = Vec::new();
+    for entry in fs::read_dir(SYSFS_UHID)? {
+        let path = entry?.path();
+        if let Some(name) = hid_name(&path) {
+            devices.push((path, name));
+        }
+    }
+    Ok(devices)
+}
+
+/* The sysfs directory of the newest HID device with the given name and ids */
+pub fn hid_device(name: &str, identity: &Identity) -> io::Result<Option<PathBuf>> {
+    let prefix = format!("{:04X}:{:04X}:{:04X}.", identity.bus, identity.vendor, identity.product);
+    let mut newest: Option<(u32, PathBuf)> = None;
+
+    for entry in fs::read_dir(SYSFS_UHID)? {
+        let path = entry?.path();
+        let sequence = match path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) if file_name.starts_with(&prefix) => u32::from_str_radix(&file_name[prefix.len()..], 16).ok(),
+            _ => None,
+        };
+        match sequence {
+            Some(sequence) if newest.as_ref().is_none_or(|newest| sequence > newest.0) && hid_name(&path).as_deref() == Some(name) => {
+                newest = Some((sequence, path));
+            },
+            _ => (),
+        }
+    }
+    Ok(newest.map(|(_, path)| path))
+}
+
+/*
+ * Compares the descriptor registered for the HID device with the one sent,
+ * an InvalidData error telling where they differ if they do, e.g. because it
+ * was cut short on the way.
+ */
+pub fn check_descriptor(device: &Path, sent: &[u8]) -> io::Result<()> {
+    let registered = fs::read(device.join("report_descriptor"))?;
+    if registered == sent {
+        return Ok(());
+    }
+    let (message, offset) = match registered.iter().zip(sent).position(|(registered, sent)| registered != sent) {
+        Some(offset) => (format!("Registered descriptor differs from the one sent at byte {}: {:02x} instead of {:02x}",
+                                 offset, registered[offset], sent[offset]), offset),
+        None => (format!("Registered descriptor is {} bytes, {} were sent", registered.len(), sent.len()), registered.len().min(sent.len())),
+    };
+    Err(exit::located(io::ErrorKind::InvalidData, message, vec![("offset", offset as u64)]))
+}
+
+/* /dev/input/eventN of every input device of the HID device, in order */
+pub fn event_nodes(device: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut nodes = Vec::new();
+    let inputs = match fs::read_dir(device.join("input")) {
+        Ok(inputs) => inputs,
+        /* not connected yet, or not an input device at all */
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(nodes),
+        Err(err) => return Err(err),
+    };
+    for input in inputs {
+        for entry in fs::read_dir(input?.path())? {
+            let file_name = entry?.file_name();
+            match file_name.to_str() {
+                Some(node) if node.starts_with("event") => nodes.push(Path::new("/dev/input").join(node)),
+                _ => (),
+            }
+        }
+    }
+    nodes.sort_by_key(|node| node.to_str().and_then(|node| node["/dev/input/event".len()..].parse::<u32>().ok()));
+    Ok(nodes)
+}
+
+/* /dev/hidrawN of the HID device, None if hidraw did not connect it (yet) */
+pub fn hidraw_node(device: &Path) -> io::Result<Option<PathBuf>> {
+    let entries = match fs::read_dir(device.join("hidraw")) {
+        Ok(entries) => entries,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let file_name = entry?.file_name();
+        match file_name.to_str() {
+            Some(node) if node.starts_with("hidraw") => return Ok(Some(Path::new("/dev").join(node))),
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+/* The hidraw and event nodes of the newest uhid device with the given name
+ * and ids */
+pub fn device_nodes(name: &str, identity: &Identity) -> io::Result<Nodes> {
+    match hid_device(name, identity)? {
+        Some(device) => Ok(Nodes { hidraw: hidraw_node(&device)?, events: event_nodes(&device)? }),
+        None => Ok(Nodes::default()),
+    }
+}
+
+/* The event nodes of the newest uhid device with the given name and ids */
+pub fn input_nodes(name: &str, identity: &Identity) -> io::Result<Vec<PathBuf>> {
+    match hid_device(name, identity)? {
+        Some(device) => event_nodes(&device),
+        None => Ok(vec![]),
+    }
+}
@@ -0,0 +1,242 @@
+/*
+ * usbmon captures
+ * Reads the USB traffic of a HID device from a pcap file written by
+ * Wireshark or tcpdump capturing on a usbmon interface, so a device can be
+ * reproduced without the hardware. Both usbmon link types are supported;
+ * pcapng files have to be converted first, e.g. with editcap -F pcap.
+ *
+ * The descriptors come from the enumeration, so the capture has to start
+ * before the device is plugged in: the device descriptor gives the identity,
+ * the configuration descriptor maps the interface to its interrupt IN
+ * endpoint and the report descriptor is the one requested for the
+ * interface. Completed transfers on that endpoint are the input reports,
+ * timed relative to the first one.
+ */
+
+use recording::Capture;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+use uhid::{Identity, BUS_USB};
+
+const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const MAGIC_PCAPNG: u32 = 0x0a0d_0d0a;
+
+const LINKTYPE_USB_LINUX: u32 = 189;
+const LINKTYPE_USB_LINUX_MMAPPED: u32 = 220;
+
+const GLOBAL_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/* Transfer types and URB events in the usbmon header */
+const XFER_INTERRUPT: u8 = 1;
+const XFER_CONTROL: u8 = 2;
+const SUBMIT: u8 = b'S';
+const COMPLETE: u8 = b'C';
+
+const GET_DESCRIPTOR: u8 = 0x06;
+const DESCRIPTOR_DEVICE: u8 = 0x01;
+const DESCRIPTOR_CONFIGURATION: u8 = 0x02;
+const DESCRIPTOR_INTERFACE: u8 = 0x04;
+const DESCRIPTOR_ENDPOINT: u8 = 0x05;
+const DESCRIPTOR_HID: u8 = 0x21;
+const DESCRIPTOR_REPORT: u8 = 0x22;
+
+/* Transfer type in the attributes of an endpoint descriptor */
+const ENDPOINT_INTERRUPT: u8 = 0x03;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct Bytes<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Bytes<'a> {
+    fn u16(&self, at: usize) -> u16 {
+        let bytes = [self.data[at], self.data[at + 1]];
+        if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) }
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(&self.data[at..at + 4]);
+        if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) }
+    }
+
+    fn u64(&self, at: usize) -> u64 {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&self.data[at..at + 8]);
+        if self.big_endian { u64::from_be_bytes(bytes) } else { u64::from_le_bytes(bytes) }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Request {
+    Device,
+    Configuration,
+    Report(u8),
+}
+
+/* What was seen of one device, by bus and address */
+#[derive(Default)]
+struct UsbDevice {
+    device: Vec<u8>,
+    configuration: Vec<u8>,
+    reports: BTreeMap<u8, Vec<u8>>,
+    /* Endpoint, time and data of completed interrupt IN transfers */
+    input: Vec<(u8, Duration, Vec<u8>)>,
+}
+
+impl UsbDevice {
+    /* Interrupt IN endpoint and country code of the interface */
+    fn interface(&self, interface: u8) -> Option<(u8, u8)> {
+        let mut current = None;
+        let mut country = 0;
+        let mut rest = &self.configuration[..];
+
+        while rest.len() >= 2 && rest[0] >= 2 && rest.len() >= rest[0] as usize {
+            let (descriptor, next) = rest.split_at(rest[0] as usize);
+            match descriptor[1] {
+                DESCRIPTOR_INTERFACE if descriptor.len() >= 3 => current = Some(descriptor[2]),
+                DESCRIPTOR_HID if current == Some(interface) && descriptor.len() >= 5 => country = descriptor[4],
+                DESCRIPTOR_ENDPOINT if current == Some(interface) && descriptor.len() >= 4
+                    && descriptor[2] & 0x80 != 0 && descriptor[3] & 0x03 == ENDPOINT_INTERRUPT => {
+                    return Some((descriptor[2], country));
+                },
+                _ => (),
+            }
+            rest = next;
+        }
+        None
+    }
+
+    fn identity(&self, country: u8) -> Identity {
+        let id = |at: usize| if self.device.len() >= at + 2 {
+            u16::from_le_bytes([self.device[at], self.device[at + 1]]) as u32
+        } else {
+            0
+        };
+        Identity {
+            bus: BUS_USB as u16,
+            vendor: id(8),
+            product: id(10),
+            version: id(12),
+            country: country as u32,
+        }
+    }
+}
+
+/*
+ * Reads the capture and picks the given interface, or the first one a
+ * report descriptor was read for, of the first device having it.
+ */
+pub fn parse<R: Read>(mut input: R, interface: Option<u8>) -> io::Result<Capture> {
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.len() < GLOBAL_HEADER_LEN {
+        return Err(invalid("Too short for a pcap file"));
+    }
+
+    let magic = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
+    let (big_endian, nanos) = match magic {
+        MAGIC_MICROS => (false, false),
+        MAGIC_NANOS => (false, true),
+        _ if magic == MAGIC_MICROS.swap_bytes() => (true, false),
+        _ if magic == MAGIC_NANOS.swap_bytes() => (true, true),
+        MAGIC_PCAPNG => return Err(invalid("pcapng is not supported, convert the capture with editcap -F pcap")),
+        _ => return Err(invalid("Not a pcap file")),
+    };
+    let file = Bytes { data: &data, big_endian };
+    let header_len = match file.u32(20) {
+        LINKTYPE_USB_LINUX => 48,
+        LINKTYPE_USB_LINUX_MMAPPED => 64,
+        _ => return Err(invalid("Not a usbmon capture")),
+    };
+
+    let mut devices: BTreeMap<(u16, u8), UsbDevice> = BTreeMap::new();
+    let mut pending: HashMap<u64, Request> = HashMap::new();
+    let mut offset = GLOBAL_HEADER_LEN;
+
+    while offset + RECORD_HEADER_LEN <= data.len() {
+        let seconds = file.u32(offset) as u64;
+        let fraction = file.u32(offset + 4) as u64;
+        let time = Duration::from_secs(seconds) + if nanos { Duration::from_nanos(fraction) } else { Duration::from_micros(fraction) };
+        let length = file.u32(offset + 8) as usize;
+        offset += RECORD_HEADER_LEN;
+        if offset + length > data.len() {
+            return Err(invalid("Truncated packet"));
+        }
+        let packet = Bytes { data: &data[offset..offset + length], big_endian };
+        offset += length;
+        if length < header_len {
+            continue;
+        }
+
+        let id = packet.u64(0);
+        let (event, xfer, endpoint) = (packet.data[8], packet.data[9], packet.data[10]);
+        let address = (packet.u16(12), packet.data[11]);
+        let has_setup = packet.data[14] == 0;
+        let status = packet.u32(28);
+        let setup = &packet.data[40..48];
+        let payload = &packet.data[header_len..];
+
+        if event == SUBMIT && xfer == XFER_CONTROL && has_setup && setup[1] == GET_DESCRIPTOR {
+            let request = match (setup[0], setup[3]) {
+                (0x80, DESCRIPTOR_DEVICE) => Some(Request::Device),
+                (0x80, DESCRIPTOR_CONFIGURATION) => Some(Request::Configuration),
+                (0x81, DESCRIPTOR_REPORT) => Some(Request::Report(setup[4])),
+                _ => None,
+            };
+            if let Some(request) = request {
+                pending.insert(id, request);
+            }
+        } else if event == COMPLETE && status == 0 {
+            let device = devices.entry(address).or_default();
+            match pending.remove(&id) {
+                /* descriptors are often read in parts first, keep the longest */
+                Some(Request::Device) if payload.len() > device.device.len() => device.device = payload.to_vec(),
+                Some(Request::Configuration) if payload.len() > device.configuration.len() => device.configuration = payload.to_vec(),
+                Some(Request::Report(number)) => {
+                    device.reports.insert(number, payload.to_vec());
+                },
+                Some(_) => (),
+                None if xfer == XFER_INTERRUPT && endpoint & 0x80 != 0 && !payload.is_empty() => {
+                    device.input.push((endpoint, time, payload.to_vec()));
+                },
+                None => (),
+            }
+        }
+    }
+
+    for device in devices.values() {
+        let (number, descriptor) = match interface {
+            Some(number) => match device.reports.get(&number) {
+                Some(descriptor) => (number, descriptor),
+                None => continue,
+            },
+            None => match device.reports.iter().next() {
+                Some((&number, descriptor)) => (number, descriptor),
+                None => continue,
+            },
+        };
+        let (endpoint, country) = device.interface(number)
+            .ok_or_else(|| invalid("No interrupt IN endpoint for the interface in the configuration descriptor"))?;
+        let identity = device.identity(country);
+
+        let mut events = device.input.iter().filter(|event| event.0 == endpoint).peekable();
+        let first = events.peek().map(|event| event.1).unwrap_or_default();
+        return Ok(Capture {
+            name: format!("Replay of {:04x}:{:04x}", identity.vendor, identity.product),
+            descriptor: descriptor.clone(),
+            identity,
+            events: events.map(|event| (event.1.checked_sub(first).unwrap_or_default(), event.2.clone())).collect(),
+        });
+    }
+
+    Err(invalid("No HID report descriptor in the capture, start capturing before plugging the device in"))
+}
@@ -0,0 +1,142 @@
+/*
+ * Pending requests
+ * Keeps track of the GET_REPORT requests handed to a program answering them
+ * on its own time, like users of the C API do, so none is left unanswered:
+ * the process reading the report through hidraw blocks until a reply comes,
+ * and the kernel only gives up on its own after 5 seconds. Requests still
+ * pending at their deadline are answered with ETIMEDOUT instead.
+ *
+ * An answer for a request that timed out is refused rather than sent, the
+ * kernel having no use for it. A reader that retries sends a new request
+ * with a new id, which is tracked afresh.
+ */
+
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/* How the requests tracked so far went */
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub answered: u64,
+    pub timed_out: u64,
+    /* Answers to requests that had timed out already */
+    pub late: u64,
+}
+
+pub struct Pending {
+    timeout: Duration,
+    /* Request ids with their deadlines */
+    requests: Vec<(u32, Instant)>,
+    /* Ids of the requests that timed out, to tell late answers from bogus ones */
+    expired: Vec<u32>,
+    stats: Stats,
+}
+
+/* Timed out ids remembered, older ones are forgotten */
+const MAX_EXPIRED: usize = 64;
+
+impl Pending {
+    pub fn new(timeout: Duration) -> Pending {
+        Pending { timeout, requests: Vec::new(), expired: Vec::new(), stats: Stats::default() }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn add(&mut self, id: u32, now: Instant) {
+        self.requests.push((id, now + self.timeout));
+    }
+
+    /* Takes the request off; false if it timed out already and the answer
+     * is not to be sent. Ids never tracked are left to the kernel. */
+    pub fn answer(&mut self, id: u32) -> bool {
+        if let Some(index) = self.requests.iter().position(|&(pending, _)| pending == id) {
+            self.requests.remove(index);
+            self.stats.answered += 1;
+            return true;
+        }
+        if let Some(index) = self.expired.iter().position(|&expired| expired == id) {
+            self.expired.remove(index);
+            self.stats.late += 1;
+            return false;
+        }
+        true
+    }
+
+    /* When the next request times out */
+    pub fn deadline(&self) -> Option<Instant> {
+        self.requests.iter().map(|&(_, deadline)| deadline).min()
+    }
+
+    /* Takes off the requests due by `now`, returning their ids to answer */
+    pub fn expire(&mut self, now: Instant) -> Vec<u32> {
+        let ids: Vec<u32> = self.requests.iter().filter(|&&(_, deadline)| deadline <= now).map(|&(id, _)| id).collect();
+        self.requests.retain(|&(_, deadline)| deadline > now);
+        self.stats.timed_out += ids.len() as u64;
+        self.expired.extend_from_slice(&ids);
+        let forgotten = self.expired.len().saturating_sub(MAX_EXPIRED);
+        self.expired.drain(..forgotten);
+        ids
+    }
+}
+
+impl Default for Pending {
+    fn default() -> Pending {
+        Pending::new(DEFAULT_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_requests_at_their_deadline() {
+        let start = Instant::now();
+        let mut pending = Pending::new(Duration::from_millis(100));
+        pending.add(1, start);
+        pending.add(2, start + Duration::from_millis(50));
+        assert_eq!(pending.deadline(), Some(start + Duration::from_millis(100)));
+
+        assert!(pending.expire(start + Duration::from_millis(99)).is_empty());
+        assert_eq!(pending.expire(start + Duration::from_millis(100)), vec![1]);
+        assert_eq!(pending.deadline(), Some(start + Duration::from_millis(150)));
+        assert!(pending.answer(2));
+        assert_eq!(pending.deadline(), None);
+        assert_eq!(pending.stats(), Stats { answered: 1, timed_out: 1, late: 0 });
+    }
+
+    #[test]
+    fn refuses_late_answers() {
+        let start = Instant::now();
+        let mut pending = Pending::new(Duration::from_millis(100));
+        pending.add(7, start);
+        pending.expire(start + Duration::from_secs(1));
+        assert!(!pending.answer(7));
+        /* once, a second answer being one never tracked */
+        assert!(pending.answer(7));
+        assert!(pending.answer(8));
+        assert_eq!(pending.stats(), Stats { answered: 0, timed_out: 1, late: 1 });
+    }
+
+    #[test]
+    fn forgets_the_oldest_expired() {
+        let start = Instant::now();
+        let mut pending = Pending::new(Duration::from_millis(100));
+        for id in 0..MAX_EXPIRED as u32 + 2 {
+            pending.add(id, start);
+        }
+        assert_eq!(pending.expire(start + Duration::from_secs(1)).len(), MAX_EXPIRED + 2);
+        assert!(pending.answer(0));
+        assert!(pending.answer(1));
+        assert!(!pending.answer(2));
+        assert!(!pending.answer(MAX_EXPIRED as u32 + 1));
+        assert_eq!(pending.stats().late, 2);
+    }
+}
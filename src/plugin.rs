@@ -0,0 +1,70 @@
+/*
+ * Plugins
+ * Device types shipped as shared libraries and loaded at runtime, so they
+ * can be used with the example binary without changing it. A plugin is a
+ * cdylib crate depending on this library that implements Device for its
+ * types and exports the registration function:
+ *
+ *   #[no_mangle]
+ *   pub fn uhid_example_register(registry: &mut Registry) {
+ *       registry.register("my-device", |_| Box::new(MyDevice::default()));
+ *   }
+ *
+ * Rust has no stable ABI for trait objects, the plugin has to be built with
+ * the same compiler and the same version of this library as the binary.
+ * Programs linking this library directly can call Registry::register
+ * themselves instead.
+ *
+ * Loading needs the plugins feature.
+ */
+
+use devices::Registry;
+use std::io;
+use std::path::Path;
+
+pub const REGISTER_SYMBOL: &str = "uhid_example_register";
+
+#[cfg(feature = "plugins")]
+pub fn load(path: &Path, registry: &mut Registry) -> io::Result<()> {
+    use libc;
+    use std::ffi::{CStr, CString};
+    use std::mem;
+    use std::os::unix::ffi::OsStrExt;
+
+    fn dl_error() -> io::Error {
+        let message = unsafe { libc::dlerror() };
+        if message.is_null() {
+            return io::Error::other("Unknown dynamic loader error");
+        }
+        let message = unsafe { CStr::from_ptr(message) };
+        io::Error::other(message.to_string_lossy().into_owned())
+    }
+
+    let path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Plugin path contains a NUL byte"))?;
+    let symbol = CString::new(REGISTER_SYMBOL).unwrap();
+
+    unsafe {
+        let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+        if handle.is_null() {
+            return Err(dl_error());
+        }
+
+        let register = libc::dlsym(handle, symbol.as_ptr());
+        if register.is_null() {
+            let err = dl_error();
+            libc::dlclose(handle);
+            return Err(err);
+        }
+
+        /* never closed, the registered constructors point into the library */
+        let register: fn(&mut Registry) = mem::transmute(register);
+        register(registry);
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn load(_path: &Path, _registry: &mut Registry) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Built without plugin support, enable the plugins feature"))
+}
@@ -0,0 +1,179 @@
+/*
+ * Kernel capabilities
+ * What the running kernel's uhid supports, found out by trying it with a
+ * device of its own rather than going by the kernel version, which vendor
+ * kernels backport features to:
+ *
+ *   UHID_CREATE2       creating with the descriptor in the request (3.15),
+ *                      older kernels rejecting it with EOPNOTSUPP
+ *   UHID_INPUT2        the input reports of CREATE2 devices (3.15), only
+ *                      tried where CREATE2 is known as they came together
+ *   UHID_GET_REPORT    feature reports read through hidraw reaching the
+ *                      device as requests to answer (3.16, UHID_FEATURE before)
+ *   UHID_SET_REPORT    and those written (3.18)
+ *   dev_flags          of UHID_START, which kinds of reports are numbered
+ *                      (3.19, zero before)
+ *
+ * The device declares a numbered report of each kind, so all of dev_flags
+ * are set where they are reported. GET_REPORT and SET_REPORT are asked for
+ * through the hidraw node of the device, so they stay unknown when it does
+ * not show up in time or cannot be opened, e.g. without root.
+ */
+
+use libc;
+use nodes;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use uhid::{self, uhid_dev_flag, uhid_event_type, Identity};
+
+const NAME: &str = "uhid-example-probe";
+
+/* A vendor-defined collection with a byte of input (report-id 1), output (2)
+ * and feature report (3) */
+const DESCRIPTOR: &[u8] = &[
+    0x06, 0x00, 0xff,  /* Usage Page (Vendor Defined 0xFF00) */
+    0x09, 0x01,        /* Usage (0x01) */
+    0xa1, 0x01,        /* Collection (Application) */
+    0x15, 0x00,        /*   Logical Minimum (0) */
+    0x26, 0xff, 0x00,  /*   Logical Maximum (255) */
+    0x75, 0x08,        /*   Report Size (8) */
+    0x95, 0x01,        /*   Report Count (1) */
+    0x85, 0x01,        /*   Report ID (1) */
+    0x09, 0x02,        /*   Usage (0x02) */
+    0x81, 0x02,        /*   Input (Data,Var,Abs) */
+    0x85, 0x02,        /*   Report ID (2) */
+    0x09, 0x03,        /*   Usage (0x03) */
+    0x91, 0x02,        /*   Output (Data,Var,Abs) */
+    0x85, 0x03,        /*   Report ID (3) */
+    0x09, 0x04,        /*   Usage (0x04) */
+    0xb1, 0x02,        /*   Feature (Data,Var,Abs) */
+    0xc0,              /* End Collection */
+];
+const FEATURE_ID: u8 = 3;
+
+/* How long the kernel gets to start the device and to pass on requests */
+const TIMEOUT: Duration = Duration::from_secs(1);
+/* How long the hidraw node gets to show up */
+const NODE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const DEV_FLAG_NAMES: &[(uhid_dev_flag, &str)] = &[
+    (uhid_dev_flag::UHID_DEV_NUMBERED_FEATURE_REPORTS, "numbered-feature-reports"),
+    (uhid_dev_flag::UHID_DEV_NUMBERED_OUTPUT_REPORTS, "numbered-output-reports"),
+    (uhid_dev_flag::UHID_DEV_NUMBERED_INPUT_REPORTS, "numbered-input-reports"),
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Capabilities {
+    pub create2: bool,
+    pub input2: bool,
+    /* None when they could not be asked for through hidraw */
+    pub get_report: Option<bool>,
+    pub set_report: Option<bool>,
+    /* The dev_flags of UHID_START for a device numbering every report */
+    pub dev_flags: u64,
+}
+
+impl Capabilities {
+    /* The names of the flags set in dev_flags */
+    pub fn dev_flag_names(&self) -> Vec<&'static str> {
+        DEV_FLAG_NAMES.iter().filter(|&&(flag, _)| self.dev_flags & flag as u64 != 0).map(|&(_, name)| name).collect()
+    }
+}
+
+/* _IOC(_IOC_READ | _IOC_WRITE, 'H', nr, size) */
+fn hidioc(nr: usize, size: usize) -> libc::c_ulong {
+    ((3 << 30) | (size << 16) | ((b'H' as usize) << 8) | nr) as libc::c_ulong
+}
+
+/* HIDIOCGFEATURE (0x07) or HIDIOCSFEATURE (0x06) of the feature report,
+ * blocking until the device answers */
+fn feature(file: &File, nr: usize) -> io::Result<()> {
+    let mut report = [FEATURE_ID, 0];
+    if unsafe { libc::ioctl(file.as_raw_fd(), hidioc(nr, report.len()) as _, report.as_mut_ptr()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/* The hidraw node of the probe device once it shows up */
+fn hidraw_node(identity: &Identity) -> io::Result<Option<PathBuf>> {
+    let deadline = Instant::now() + NODE_TIMEOUT;
+    loop {
+        if let Some(node) = nodes::device_nodes(NAME, identity)?.hidraw.filter(|node| node.exists()) {
+            return Ok(Some(node));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/* Whether a feature request through hidraw reaches the device as the event,
+ * answering it; None if hidraw could not be asked */
+fn request(file: &mut File, node: &Path, nr: usize, wanted: uhid_event_type) -> io::Result<Option<bool>> {
+    let hidraw = match OpenOptions::new().read(true).write(true).open(node) {
+        Ok(hidraw) => hidraw,
+        Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied || err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    /* the ioctl blocks until answered, so it is made on a thread of its own */
+    let asking = thread::spawn(move || feature(&hidraw, nr));
+    let answered = match uhid::wait_for_event(file, wanted, TIMEOUT)? {
+        Some(event) if wanted == uhid_event_type::UHID_GET_REPORT => {
+            let id = unsafe { event.u.get_report.as_ref().id };
+            uhid::get_report_reply(file, id, Ok(vec![FEATURE_ID, 0]))?;
+            true
+        },
+        Some(event) => {
+            let id = unsafe { event.u.set_report.as_ref().id };
+            uhid::set_report_reply(file, id, Ok(()))?;
+            true
+        },
+        None => false,
+    };
+    /* failing or not, the kernel passed the request on or it did not */
+    let _ = asking.join();
+    Ok(Some(answered))
+}
+
+fn probe_device(file: &mut File, capabilities: &mut Capabilities, identity: &Identity) -> io::Result<()> {
+    let start = match uhid::wait_for_event(file, uhid_event_type::UHID_START, TIMEOUT)? {
+        Some(start) => start,
+        None => return Err(io::Error::new(io::ErrorKind::TimedOut, "The kernel did not start the probe device")),
+    };
+    capabilities.dev_flags = uhid::start_flags(&start);
+
+    capabilities.input2 = capabilities.create2 && match uhid::input2(file, &[1, 0]) {
+        Ok(()) => true,
+        Err(ref err) if uhid::unsupported(err) => false,
+        Err(err) => return Err(err),
+    };
+
+    if let Some(node) = hidraw_node(identity)? {
+        capabilities.get_report = request(file, &node, 0x07, uhid_event_type::UHID_GET_REPORT)?;
+        capabilities.set_report = request(file, &node, 0x06, uhid_event_type::UHID_SET_REPORT)?;
+    }
+    Ok(())
+}
+
+/* Finds out what the kernel behind the uhid-cdev supports, with a device
+ * created and destroyed again for it */
+pub fn probe(path: &Path) -> io::Result<Capabilities> {
+    let mut file = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC | libc::O_NONBLOCK).open(path)?;
+    let identity = Identity { bus: uhid::BUS_VIRTUAL as u16, vendor: 0, product: 0, version: 0, country: 0 };
+    let create2 = uhid::create_any(&mut file, NAME, DESCRIPTOR, &identity)?;
+    let mut capabilities = Capabilities { create2, ..Capabilities::default() };
+
+    let probed = probe_device(&mut file, &mut capabilities, &identity);
+    let destroyed = uhid::destroy(&mut file);
+    probed.and(destroyed).map(|()| capabilities)
+}
@@ -0,0 +1,211 @@
+/*
+ * Recordings
+ * Writes sessions in the text format of hid-recorder from hid-tools, so they
+ * can be replayed with hid-replay and compared with captures of real
+ * devices:
+ *
+ *   R: <descriptor length> <descriptor bytes>
+ *   N: <name>
+ *   I: <bus> <vendor> <product>
+ *   E: <seconds>.<microseconds> <report length> <report bytes>
+ *
+ * Bytes and ids are hex, event times are relative to the start of the
+ * recording. Lines starting with # are comments.
+ *
+ * Recordings in this format, including captures of real devices made with
+ * hid-recorder, can also be read back to reproduce the device.
+ */
+
+use std::io;
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+use uhid::{Identity, BUS_USB};
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" ")
+}
+
+pub struct Recorder<W: Write> {
+    output: W,
+    start: Instant,
+}
+
+impl<W: Write> Recorder<W> {
+    /* Writes the description of the device, the recording starts now */
+    pub fn new(mut output: W, name: &str, descriptor: &[u8], identity: &Identity) -> io::Result<Recorder<W>> {
+        write!(output, "R: {} {}\nN: {}\nI: {:x} {:04x} {:04x}\n",
+               descriptor.len(), hex(descriptor), name, identity.bus, identity.vendor, identity.product)?;
+        output.flush()?;
+        Ok(Recorder { output, start: Instant::now() })
+    }
+
+    /* Records an input report as sent now, with its report-id if numbered */
+    pub fn input(&mut self, report: &[u8]) -> io::Result<()> {
+        let elapsed = self.start.elapsed();
+        /* one write per line, so an interrupted session leaves whole events */
+        let line = format!("E: {:06}.{:06} {} {}\n", elapsed.as_secs(), elapsed.subsec_micros(), report.len(), hex(report));
+        self.output.write_all(line.as_bytes())?;
+        self.output.flush()
+    }
+}
+
+/* A device and its input reports read from a recording */
+pub struct Capture {
+    pub name: String,
+    pub descriptor: Vec<u8>,
+    pub identity: Identity,
+    /* Reports with the time they were sent, relative to the start */
+    pub events: Vec<(Duration, Vec<u8>)>,
+}
+
+impl Capture {
+    /* Keeps the events from `from` up to `to`, timed from `from` */
+    pub fn trim(&mut self, from: Duration, to: Option<Duration>) {
+        self.events.retain(|&(time, _)| time >= from && to.is_none_or(|to| time <= to));
+        for event in self.events.iter_mut() {
+            event.0 -= from;
+        }
+    }
+
+    /* Plays the events faster, or slower for a speed below 1; fails if an
+     * event would be delayed longer than a Duration holds */
+    pub fn scale(&mut self, speed: f64) -> io::Result<()> {
+        for event in self.events.iter_mut() {
+            event.0 = Duration::try_from_secs_f64(event.0.as_secs_f64() / speed)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Events delayed too long at this speed"))?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+/* Parses "<length> <bytes>" checking the length matches */
+fn parse_bytes<'a, I>(number: usize, mut fields: I) -> io::Result<Vec<u8>>
+    where I: Iterator<Item = &'a str>
+{
+    let length = fields.next().and_then(|length| length.parse::<usize>().ok())
+        .ok_or_else(|| invalid(number, "Missing length"))?;
+    let data = fields.map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| invalid(number, "Invalid byte"))?;
+    if data.len() != length {
+        return Err(invalid(number, "Length does not match the data"));
+    }
+    Ok(data)
+}
+
+fn parse_time(number: usize, time: Option<&str>) -> io::Result<Duration> {
+    let time = time.ok_or_else(|| invalid(number, "Missing time"))?;
+    let mut parts = time.splitn(2, '.');
+    let seconds = parts.next().and_then(|seconds| seconds.parse::<u64>().ok());
+    let micros = parts.next().and_then(|micros| micros.parse::<u32>().ok());
+    match (seconds, micros) {
+        (Some(seconds), Some(micros)) if micros < 1_000_000 => Ok(Duration::new(seconds, micros * 1000)),
+        _ => Err(invalid(number, "Invalid time")),
+    }
+}
+
+/*
+ * Reads a hid-recorder capture, e.g. of a real device. Captures of several
+ * devices, separated by D: lines, only have their first device read.
+ */
+pub fn parse<R: BufRead>(input: R) -> io::Result<Capture> {
+    let mut capture = Capture {
+        name: String::new(),
+        descriptor: Vec::new(),
+        identity: Identity { bus: BUS_USB as u16, vendor: 0, product: 0, version: 0, country: 0 },
+        events: Vec::new(),
+    };
+    let mut device = 0;
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        let (tag, rest) = match line.find(':') {
+            Some(colon) if !line.starts_with('#') => (&line[..colon], line[colon + 1..].trim()),
+            _ => continue,
+        };
+        if tag == "D" {
+            device = rest.parse::<u32>().map_err(|_| invalid(number, "Invalid device number"))?;
+            continue;
+        }
+        if device != 0 {
+            continue;
+        }
+
+        let mut fields = rest.split_whitespace();
+        match tag {
+            "R" => capture.descriptor = parse_bytes(number, fields)?,
+            "N" => capture.name = rest.to_string(),
+            "I" => {
+                let ids = fields.map(|id| u32::from_str_radix(id, 16))
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map_err(|_| invalid(number, "Invalid id"))?;
+                if ids.len() != 3 {
+                    return Err(invalid(number, "Expected bus, vendor and product"));
+                }
+                capture.identity.bus = ids[0] as u16;
+                capture.identity.vendor = ids[1];
+                capture.identity.product = ids[2];
+            },
+            "E" => {
+                let time = parse_time(number, fields.next())?;
+                capture.events.push((time, parse_bytes(number, fields)?));
+            },
+            /* physical path and anything newer */
+            _ => (),
+        }
+    }
+
+    if capture.descriptor.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No report descriptor in the recording"));
+    }
+    Ok(capture)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_what_it_records() {
+        let identity = Identity { bus: 0x03, vendor: 0x045e, product: 0x028e, version: 0, country: 0 };
+        let mut output = Vec::new();
+        {
+            let mut recorder = Recorder::new(&mut output, "Test pad", &[0x05, 0x01, 0x09, 0x05], &identity).unwrap();
+            recorder.input(&[0x01, 0xff]).unwrap();
+            recorder.input(&[]).unwrap();
+        }
+
+        let capture = parse(&output[..]).unwrap();
+        assert_eq!(capture.name, "Test pad");
+        assert_eq!(capture.descriptor, vec![0x05, 0x01, 0x09, 0x05]);
+        assert_eq!((capture.identity.bus, capture.identity.vendor, capture.identity.product), (0x03, 0x045e, 0x028e));
+        assert_eq!(capture.events.iter().map(|event| event.1.clone()).collect::<Vec<_>>(), vec![vec![0x01, 0xff], vec![]]);
+        assert!(capture.events[0].0 <= capture.events[1].0);
+    }
+
+    #[test]
+    fn keeps_the_first_device_and_trims_and_scales() {
+        let text = "# comment\nD: 0\nR: 2 05 01\nN: first\nE: 000001.500000 1 01\nE: 000003.000000 1 02\nD: 1\nR: 1 00\nE: 000002.000000 1 03\n";
+        let mut capture = parse(text.as_bytes()).unwrap();
+        assert_eq!(capture.name, "first");
+        assert_eq!(capture.events, vec![(Duration::from_millis(1500), vec![1]), (Duration::from_secs(3), vec![2])]);
+
+        capture.trim(Duration::from_secs(1), Some(Duration::from_secs(2)));
+        capture.scale(2.0).unwrap();
+        assert_eq!(capture.events, vec![(Duration::from_millis(250), vec![1])]);
+        assert_eq!(capture.scale(1e-300).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn refuses_malformed_lines() {
+        for text in ["N: no descriptor\n", "R: 3 05 01\n", "R: 1 zz\n", "R: 1 00\nI: 3 1\n", "R: 1 00\nE: 1.0 2 01\n", "D: x\n"] {
+            let err = parse(text.as_bytes()).map(|_| ()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*
+ * Remapping
+ * A table applied to the events of merged devices, see src/merge.rs, on the
+ * way to the reports of the merged device: swapping buttons, inverting or
+ * scaling axes, and mapping keys to other keys or to macros tapping several
+ * in turn, like keyd or xremap do on the evdev level. It is kept in a TOML
+ * file:
+ *
+ *   # left-handed
+ *   [buttons]
+ *   left = "right"
+ *   right = "left"
+ *
+ *   [axes.wheel]
+ *   invert = true
+ *
+ *   [axes.x]
+ *   scale = 0.5
+ *
+ *   [keys]
+ *   caps-lock = "left-control"
+ *   f12 = ["h", "e", "l", "l", "o"]
+ *
+ * Buttons are left, right, middle or a number from 1. Axes are x, y, z, rx,
+ * ry, rz, slider, dial and wheel, the axes the merged device has, whether
+ * the sources report them relative or absolute. Scaling multiplies relative
+ * motion, the fractions carried over to the next event; absolute axes are
+ * only inverted, within their range. Keys are named by their usage with
+ * dashes for spaces, like scroll-lock, names such as "," quoted. A macro
+ * taps its keys when the key mapped to it is pressed.
+ *
+ * Of TOML, tables with strings, numbers, booleans and arrays of strings on
+ * one line are understood.
+ */
+
+use devices;
+use evemu::{self, Capabilities};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const EV_KEY: usize = 0x01;
+const BTN_MOUSE: u16 = 0x110;
+
+/* The axes by the codes the translation takes them by, see src/evemu.rs */
+const AXIS_NAMES: [&str; 9] = ["x", "y", "z", "rx", "ry", "rz", "slider", "dial", "wheel"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    Key(u16),
+    /* keys tapped in turn */
+    Macro(Vec<u16>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Axis {
+    pub invert: bool,
+    pub scale: f64,
+}
+
+impl Default for Axis {
+    fn default() -> Axis {
+        Axis { invert: false, scale: 1.0 }
+    }
+}
+
+/* What keys, buttons and axes become, by their evdev codes; the default maps
+ * nothing */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Remap {
+    pub keys: HashMap<u16, Target>,
+    pub axes: HashMap<u16, Axis>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    String(String),
+    Number(f64),
+    Boolean(bool),
+    Strings(Vec<String>),
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+/* A quoted string at the start of the text and what follows it */
+fn string(text: &str) -> Option<(String, &str)> {
+    let quote = text.chars().next().filter(|&quote| quote == '"' || quote == '\'')?;
+    let end = text[1..].find(quote)? + 1;
+    let content = &text[1..end];
+    /* escapes are not understood rather than taken literally */
+    if quote == '"' && content.contains('\\') {
+        return None;
+    }
+    Some((content.to_string(), &text[end + 1..]))
+}
+
+/* Whether nothing but a comment is left */
+fn ends(text: &str) -> bool {
+    let text = text.trim_start();
+    text.is_empty() || text.starts_with('#')
+}
+
+fn value(text: &str) -> Option<Value> {
+    if let Some((string, rest)) = string(text) {
+        return Some(Value::String(string)).filter(|_| ends(rest));
+    }
+    if let Some(mut rest) = text.strip_prefix('[') {
+        let mut strings = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some(Value::Strings(strings)).filter(|_| ends(after));
+            }
+            let (string, after) = string(rest)?;
+            strings.push(string);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+    }
+    let text = text.split('#').next().unwrap_or_default().trim();
+    match text {
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        _ => text.replace('_', "").parse().ok().filter(|number: &f64| number.is_finite()).map(Value::Number),
+    }
+}
+
+fn key(number: usize, name: &str) -> io::Result<u16> {
+    evemu::key_code(name).ok_or_else(|| invalid(number, &format!("Unknown key {}", name)))
+}
+
+fn button(number: usize, name: &str) -> io::Result<u16> {
+    devices::button_from_name(name)
+        .filter(|&button| button <= 16)
+        .map(|button| BTN_MOUSE + button as u16 - 1)
+        .ok_or_else(|| invalid(number, &format!("Unknown button {}", name)))
+}
+
+impl Remap {
+    pub fn load(path: &Path) -> io::Result<Remap> {
+        Remap::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Remap> {
+        let mut remap = Remap::default();
+        let mut table = String::new();
+        for (index, line) in text.lines().enumerate() {
+            let number = index + 1;
+            let line = line.trim();
+            if ends(line) {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                let (name, rest) = header.split_once(']').ok_or_else(|| invalid(number, "Expected ] after the table name"))?;
+                if !ends(rest) {
+                    return Err(invalid(number, "Expected the end of the line after the table name"));
+                }
+                table = name.trim().to_string();
+                match table.strip_prefix("axes.") {
+                    Some(axis) => match AXIS_NAMES.iter().position(|&name| name == axis) {
+                        Some(code) => {
+                            remap.axes.entry(code as u16).or_default();
+                        },
+                        None => return Err(invalid(number, &format!("Unknown axis {}", axis))),
+                    },
+                    None if table == "buttons" || table == "keys" => (),
+                    None => return Err(invalid(number, &format!("Unknown table {}", table))),
+                }
+                continue;
+            }
+
+            let (name, rest) = match string(line) {
+                Some((name, rest)) => (name, rest),
+                None => {
+                    let end = line.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(line.len());
+                    (line[..end].to_string(), &line[end..])
+                },
+            };
+            let value = rest.trim_start().strip_prefix('=').and_then(|rest| value(rest.trim()))
+                .ok_or_else(|| invalid(number, "Expected a name, = and a value"))?;
+            match (table.as_str(), value) {
+                ("buttons", Value::String(target)) => {
+                    remap.keys.insert(button(number, &name)?, Target::Key(button(number, &target)?));
+                },
+                ("keys", Value::String(target)) => {
+                    remap.keys.insert(key(number, &name)?, Target::Key(key(number, &target)?));
+                },
+                ("keys", Value::Strings(targets)) if !targets.is_empty() => {
+                    let codes = targets.iter().map(|target| key(number, target)).collect::<io::Result<Vec<u16>>>()?;
+                    remap.keys.insert(key(number, &name)?, Target::Macro(codes));
+                },
+                ("buttons", _) => return Err(invalid(number, "Expected the button to map to")),
+                ("keys", _) => return Err(invalid(number, "Expected the key or the keys to map to")),
+                (axis, value) => {
+                    let axis = axis.strip_prefix("axes.").and_then(|axis| AXIS_NAMES.iter().position(|&name| name == axis))
+                        .ok_or_else(|| invalid(number, "Expected a table before the value"))?;
+                    let axis = remap.axes.entry(axis as u16).or_default();
+                    match (name.as_str(), value) {
+                        ("invert", Value::Boolean(invert)) => axis.invert = invert,
+                        ("scale", Value::Number(scale)) => axis.scale = scale,
+                        _ => return Err(invalid(number, "Expected invert = true or false, or scale = a number")),
+                    }
+                },
+            }
+        }
+        Ok(remap)
+    }
+
+    /* Adds the keys and buttons mapped to, so the merged device has them */
+    pub fn extend(&self, capabilities: &mut Capabilities) {
+        for target in self.keys.values() {
+            match *target {
+                Target::Key(code) => capabilities.add(EV_KEY, code),
+                Target::Macro(ref codes) => {
+                    for &code in codes {
+                        capabilities.add(EV_KEY, code);
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evemu::Translator;
+
+    #[test]
+    fn parses_every_table() {
+        let remap = Remap::parse(concat!(
+            "# left-handed\n",
+            "[buttons]\n",
+            "left = \"right\"\n",
+            "right = 'left' # swapped\n",
+            "\n",
+            "[axes.wheel]\n",
+            "invert = true\n",
+            "[axes.x]\n",
+            "scale = 0.5\n",
+            "[keys]\n",
+            "caps-lock = \"Left-Control\"\n",
+            "\",\" = [\"h\", \",\"]\n",
+        )).unwrap();
+
+        assert_eq!(remap.keys[&0x110], Target::Key(0x111));
+        assert_eq!(remap.keys[&0x111], Target::Key(0x110));
+        assert_eq!(remap.keys[&58], Target::Key(29));
+        assert_eq!(remap.keys[&51], Target::Macro(vec![35, 51]));
+        assert_eq!(remap.axes[&8], Axis { invert: true, scale: 1.0 });
+        assert_eq!(remap.axes[&0], Axis { invert: false, scale: 0.5 });
+    }
+
+    #[test]
+    fn remaps_the_translated_events() {
+        let remap = Remap::parse("[buttons]\nleft = \"right\"\nright = \"left\"\n[axes.x]\ninvert = true\nscale = 0.5\n[keys]\na = [\"b\"]\n").unwrap();
+        let mut capabilities = Capabilities::default();
+        for &code in &[0x110, 0x111, 30] {
+            capabilities.add(EV_KEY, code);
+        }
+        capabilities.add(0x02, 0);
+        remap.extend(&mut capabilities);
+        let mut translator = Translator::new(&capabilities, true).unwrap();
+        translator.set_remap(remap);
+
+        translator.event(EV_KEY, 0x110, 1);
+        translator.event(0x02, 0, 3);
+        assert_eq!(translator.event(0x00, 0, 0), vec![vec![2, 0b10, 0xff, 0xff]]);
+        /* the half left over makes the next event a whole one */
+        translator.event(0x02, 0, 1);
+        assert_eq!(translator.event(0x00, 0, 0), vec![vec![2, 0b10, 0xff, 0xff]]);
+        assert_eq!(translator.event(EV_KEY, 30, 1), vec![vec![1, 0b10], vec![1, 0]]);
+        assert!(translator.event(EV_KEY, 30, 0).is_empty());
+    }
+
+    #[test]
+    fn refuses_what_it_does_not_know() {
+        for text in ["[mouse]\n", "[axes.w]\n", "[keys]\nnokey = \"a\"\n", "[keys]\na = 1\n", "[buttons]\nleft = \"17\"\n",
+                     "[axes.x]\nscale = \"fast\"\n", "[keys]\na = [\"b\"\n", "left = \"right\"\n"] {
+            let err = Remap::parse(text).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", text);
+        }
+    }
+}
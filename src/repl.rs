@@ -0,0 +1,178 @@
+/*
+ * REPL
+ * Reads commands typed at a prompt with line editing, as a friendlier way to
+ * drive devices with more to them than a key per action. The commands are
+ * those of --fixture and --schedule, see src/schedule.rs, e.g.
+ *
+ *   > move 10 0
+ *   > click left
+ *   > macro login
+ *
+ * Tab completes the commands, the buttons and the macros loaded at start, and
+ * the history is kept across runs in $XDG_STATE_HOME/uhid-example/history.
+ * help lists the commands, quit or Ctrl-D destroys the device.
+ *
+ * The prompt runs on a thread of its own, as reading a line blocks, and
+ * passes the lines on through a pipe the main loop polls.
+ *
+ * Needs the repl feature.
+ */
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+#[cfg(feature = "repl")]
+mod prompt {
+    use libc;
+    use rustyline::completion::Completer;
+    use rustyline::error::ReadlineError;
+    use rustyline::highlight::Highlighter;
+    use rustyline::hint::Hinter;
+    use rustyline::validate::Validator;
+    use rustyline::{Context, Editor, Helper};
+    use schedule::COMMANDS;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::io::Write;
+    use std::os::unix::io::FromRawFd;
+    use std::path::PathBuf;
+    use std::thread;
+
+    const PROMPT: &str = "> ";
+    const BUTTONS: &[&str] = &["left", "right", "middle"];
+
+    fn history_path() -> Option<PathBuf> {
+        let state = match env::var_os("XDG_STATE_HOME") {
+            Some(state) => PathBuf::from(state),
+            None => PathBuf::from(env::var_os("HOME")?).join(".local").join("state"),
+        };
+        Some(state.join("uhid-example").join("history"))
+    }
+
+    struct Completion {
+        macros: Vec<String>,
+    }
+
+    impl Completer for Completion {
+        type Candidate = String;
+
+        /* The command, or its argument for click and macro */
+        fn complete(&self, line: &str, pos: usize, _: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+            let line = &line[..pos];
+            let start = line.rfind(' ').map_or(0, |index| index + 1);
+            let word = &line[start..];
+            let candidates: Vec<&str> = match line[..start].split_whitespace().collect::<Vec<_>>().as_slice() {
+                [] => COMMANDS.iter().map(|&(usage, _)| usage.split(' ').next().unwrap())
+                    .chain(["help", "quit"].iter().cloned()).collect(),
+                ["click"] => BUTTONS.to_vec(),
+                ["macro"] => self.macros.iter().map(String::as_str).collect(),
+                _ => vec![],
+            };
+            Ok((start, candidates.into_iter().filter(|candidate| candidate.starts_with(word)).map(String::from).collect()))
+        }
+    }
+
+    impl Hinter for Completion {
+        type Hint = String;
+    }
+
+    impl Highlighter for Completion {}
+
+    impl Validator for Completion {}
+
+    impl Helper for Completion {}
+
+    /* Starts the prompt, returning the end of the pipe the lines come out of */
+    pub fn spawn(macros: Vec<String>) -> io::Result<File> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let (reader, mut lines) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+        let flags = unsafe { libc::fcntl(fds[0], libc::F_GETFL) };
+        unsafe { libc::fcntl(fds[0], libc::F_SETFL, flags | libc::O_NONBLOCK) };
+
+        let mut editor = Editor::<Completion>::new().map_err(|err| io::Error::other(err.to_string()))?;
+        editor.set_helper(Some(Completion { macros }));
+        let history = history_path();
+        if let Some(history) = history.as_ref() {
+            /* there is none the first time */
+            let _ = editor.load_history(history);
+            if let Some(directory) = history.parent() {
+                fs::create_dir_all(directory)?;
+            }
+        }
+
+        thread::spawn(move || {
+            loop {
+                match editor.readline(PROMPT) {
+                    Ok(line) => {
+                        /* saved as it goes, quit ends the process right away */
+                        if editor.add_history_entry(line.as_str()) {
+                            if let Some(Err(err)) = history.as_ref().map(|history| editor.append_history(history)) {
+                                eprintln!("Cannot save the history: {}", err);
+                            }
+                        }
+                        if writeln!(lines, "{}", line).is_err() {
+                            break;
+                        }
+                    },
+                    /* Ctrl-C drops the line typed so far */
+                    Err(ReadlineError::Interrupted) => (),
+                    Err(ReadlineError::Eof) => break,
+                    Err(err) => {
+                        eprintln!("Cannot read a command: {}", err);
+                        break;
+                    },
+                }
+            }
+            /* closing the pipe tells the main loop */
+        });
+        Ok(reader)
+    }
+}
+
+pub struct Repl {
+    lines: File,
+    buffer: Vec<u8>,
+}
+
+impl Repl {
+    /* Shows the prompt, completing the given macro names */
+    #[cfg(feature = "repl")]
+    pub fn start(macros: Vec<String>) -> io::Result<Repl> {
+        Ok(Repl { lines: prompt::spawn(macros)?, buffer: Vec::new() })
+    }
+
+    #[cfg(not(feature = "repl"))]
+    pub fn start(_macros: Vec<String>) -> io::Result<Repl> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Built without REPL support, enable the repl feature"))
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.lines.as_raw_fd()
+    }
+
+    /* The lines typed since, until it would block, and whether the prompt
+     * is still open */
+    pub fn read(&mut self) -> io::Result<(Vec<String>, bool)> {
+        let mut chunk = [0; 4096];
+        let open = loop {
+            match self.lines.read(&mut chunk) {
+                Ok(0) => break false,
+                Ok(size) => self.buffer.extend_from_slice(&chunk[..size]),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break true,
+                Err(err) => return Err(err),
+            }
+        };
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        Ok((lines, open))
+    }
+}
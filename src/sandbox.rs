@@ -0,0 +1,139 @@
+/*
+ * Sandbox
+ * Restricts the process with a seccomp filter once it is set up, so a
+ * program driving devices on behalf of someone else, e.g. over a network
+ * protocol, cannot be turned into more than that if the protocol handling is
+ * compromised. Afterwards the process can only read, write and poll the
+ * file descriptors it already has open, accept connections on sockets it
+ * listens on, sleep, allocate memory and exit: opening files, creating
+ * sockets or running programs fails with EPERM. Of the socket options, only
+ * the receive timeout can be set, for the metrics requests.
+ *
+ * Everything has to be opened before calling restrict(), including the
+ * uhid-cdev, recordings and the text to type, and the flags of the files set,
+ * e.g. stdin made non-blocking. The filter is inherited by threads created
+ * afterwards and cannot be lifted again.
+ *
+ * Only x86_64 and aarch64 are supported, where the system call numbers are
+ * known. System calls made through another ABI, like x32, are refused.
+ */
+
+use std::io;
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+mod seccomp {
+    use libc;
+    use libc::{c_long, sock_filter, sock_fprog};
+    use super::io;
+
+    const SECCOMP_DATA_NR: u32 = 0;
+    const SECCOMP_DATA_ARCH: u32 = 4;
+    /* the low half of the arguments, both architectures being little endian */
+    const SECCOMP_DATA_ARGS: u32 = 16;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    const ALLOWED: &[c_long] = &[
+        /* I/O on open fds */
+        libc::SYS_read,
+        libc::SYS_readv,
+        libc::SYS_write,
+        libc::SYS_writev,
+        libc::SYS_close,
+        libc::SYS_epoll_pwait,
+        libc::SYS_epoll_ctl,
+        libc::SYS_ppoll,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_epoll_wait,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_poll,
+        /* connections on listening sockets passed in, sd_notify, D-Bus */
+        libc::SYS_accept4,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        /* timing */
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_sched_yield,
+        /* memory */
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        /* runtime */
+        libc::SYS_futex,
+        libc::SYS_getrandom,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_sigaltstack,
+        libc::SYS_restart_syscall,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+    ];
+
+    fn statement(code: u32, k: u32) -> sock_filter {
+        sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> sock_filter {
+        sock_filter { code: code as u16, jt, jf, k }
+    }
+
+    fn filter() -> Vec<sock_filter> {
+        let mut program = vec![
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARCH),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, AUDIT_ARCH, 1, 0),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_NR),
+        ];
+        for &number in ALLOWED {
+            program.push(jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, number as u32, 0, 1));
+            program.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        }
+        /* setsockopt(_, SOL_SOCKET, SO_RCVTIMEO, ...) */
+        program.extend_from_slice(&[
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SYS_setsockopt as u32, 0, 5),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARGS + 8),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SOL_SOCKET as u32, 0, 3),
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, SECCOMP_DATA_ARGS + 16),
+            jump(libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K, libc::SO_RCVTIMEO as u32, 0, 1),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+        ]);
+        program.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        program
+    }
+
+    /* Installs the filter for the calling thread and those it creates later */
+    pub fn restrict() -> io::Result<()> {
+        let mut program = filter();
+        let fprog = sock_fprog {
+            len: program.len() as u16,
+            filter: program.as_mut_ptr(),
+        };
+
+        /* needed to install a filter without CAP_SYS_ADMIN, and good practice */
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if unsafe { libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &fprog as *const sock_fprog) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+pub use self::seccomp::restrict;
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn restrict() -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "The sandbox is not supported on this architecture"))
+}
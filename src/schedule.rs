@@ -0,0 +1,286 @@
+/*
+ * Schedule
+ * Recurring input for long running devices, so e.g. a keep-alive click or a
+ * daily text does not need cron plus something talking to the control
+ * socket. A schedule file has one task per line:
+ *
+ *   at 14:00 type "standup\n"
+ *   every 5m click left
+ *   every 90s key d
+ *   every 1h macro copy
+ *
+ * "at" runs daily at the given local time, "every" repeatedly, the first
+ * time one interval after starting. Durations are a number with a unit of
+ * ms, s, m or h. Lines starting with # are comments. The commands are:
+ *
+ *   type "<text>"       type text on keyboards, with \n, \t, \" and \\ escapes
+ *   click <button>      left, right, middle or the button number
+ *   key <key>           press a key of the interactive controls
+ *   macro <name>        play a macro
+ *   move <dx> <dy>      relative pointer movement
+ *   wheel <detents>     scroll, up if positive, in as many reports as it takes
+ *   scroll <pixels> <duration>
+ *                       scroll smoothly, up if positive, with a hi-res wheel
+ *   step <counts>       movement per key press of the interactive controls
+ *   pause, resume       stop sending reports, and start again
+ *   report <bytes>      send a raw input report given in hex
+ */
+
+use devices;
+use libc;
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::mem;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Type(String),
+    Click(u8),
+    Key(u8),
+    Macro(String),
+    Move(i8, i8),
+    Wheel(i16),
+    Scroll(i32, Duration),
+    Step(i8),
+    Pause,
+    Resume,
+    /* Raw input report, including the report-id if numbered */
+    Report(Vec<u8>),
+}
+
+/* The commands with their arguments and what they do, for help texts */
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("type \"<text>\"", "type text on keyboards, with \\n, \\t, \\\" and \\\\ escapes"),
+    ("click <button>", "left, right, middle or the button number"),
+    ("key <key>", "press a key of the interactive controls"),
+    ("macro <name>", "play a macro"),
+    ("move <dx> <dy>", "relative pointer movement"),
+    ("wheel <detents>", "scroll, up if positive, in as many reports as it takes"),
+    ("scroll <pixels> <duration>", "scroll smoothly, up if positive, with a hi-res wheel"),
+    ("step <counts>", "movement per key press of the interactive controls"),
+    ("pause", "stop sending reports"),
+    ("resume", "start sending reports again"),
+    ("report <bytes>", "send a raw input report given in hex"),
+];
+
+#[derive(Clone, Copy, Debug)]
+pub enum When {
+    At { hour: u8, minute: u8 },
+    Every(Duration),
+}
+
+#[derive(Clone, Debug)]
+pub struct Task {
+    pub when: When,
+    pub command: Command,
+    next: Instant,
+}
+
+/* Parses a duration like 500ms, 2s, 1.5s, 5m or 1h; zero is not one */
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (number, unit) = match value.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(index) => value.split_at(index),
+        None => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok().filter(|duration| !duration.is_zero())
+}
+
+/* Seconds since the epoch of the next hour:minute local time after `now` */
+fn next_local(now: libc::time_t, hour: u8, minute: u8) -> libc::time_t {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe { libc::localtime_r(&now, &mut tm) };
+    tm.tm_hour = hour as i32;
+    tm.tm_min = minute as i32;
+    tm.tm_sec = 0;
+    /* let mktime work out whether daylight saving applies */
+    tm.tm_isdst = -1;
+    let mut next = unsafe { libc::mktime(&mut tm) };
+    if next <= now {
+        tm.tm_mday += 1;
+        tm.tm_isdst = -1;
+        next = unsafe { libc::mktime(&mut tm) };
+    }
+    next
+}
+
+impl When {
+    /* Next run after `now`, at-times at least `after` later by the clock */
+    fn next(&self, now: Instant, after: Duration) -> Instant {
+        match *self {
+            When::Every(interval) => now + interval,
+            When::At { hour, minute } => {
+                let wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                let next = next_local((wall + after).as_secs() as libc::time_t, hour, minute);
+                now + (Duration::from_secs(next as u64) - Duration::from_secs(wall.as_secs()))
+                    .saturating_sub(Duration::from_nanos(wall.subsec_nanos() as u64))
+            },
+        }
+    }
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+fn parse_time(value: &str) -> Option<When> {
+    let (hour, minute) = value.split_once(':')?;
+    let hour: u8 = hour.parse().ok()?;
+    let minute: u8 = minute.parse().ok()?;
+    if hour > 23 || minute > 59 {
+        return None;
+    }
+    Some(When::At { hour, minute })
+}
+
+/* Text in double quotes, with \n, \t, \" and \\ escapes */
+fn parse_text(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                c @ ('"' | '\\') => c,
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(text)
+}
+
+impl Command {
+    /* Parses "<command> <arguments>", e.g. click left or move 10 -5 */
+    pub fn parse(line: &str) -> Option<Command> {
+        let line = line.trim();
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        let mut numbers = argument.split_whitespace();
+        match command {
+            "type" => parse_text(argument).map(Command::Type),
+            "click" => devices::button_from_name(argument).map(Command::Click),
+            "key" => match argument.as_bytes() {
+                &[key] => Some(Command::Key(key)),
+                _ => None,
+            },
+            "macro" if !argument.is_empty() => Some(Command::Macro(argument.to_string())),
+            "move" => match (numbers.next()?.parse().ok()?, numbers.next()?.parse().ok()?, numbers.next()) {
+                (dx, dy, None) => Some(Command::Move(dx, dy)),
+                _ => None,
+            },
+            "wheel" => argument.parse().ok().map(Command::Wheel),
+            "scroll" => match (numbers.next()?.parse().ok()?, parse_duration(numbers.next()?)?, numbers.next()) {
+                (pixels, duration, None) => Some(Command::Scroll(pixels, duration)),
+                _ => None,
+            },
+            "step" => argument.parse().ok().filter(|&step| step > 0).map(Command::Step),
+            "pause" if argument.is_empty() => Some(Command::Pause),
+            "resume" if argument.is_empty() => Some(Command::Resume),
+            "report" => numbers.map(|byte| u8::from_str_radix(byte, 16).ok())
+                .collect::<Option<Vec<u8>>>()
+                .filter(|report| !report.is_empty())
+                .map(Command::Report),
+            _ => None,
+        }
+    }
+}
+
+/* Writes the command the way it is parsed */
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Type(ref text) => {
+                let escaped: String = text.chars().map(|c| match c {
+                    '\n' => "\\n".to_string(),
+                    '\t' => "\\t".to_string(),
+                    '"' | '\\' => format!("\\{}", c),
+                    c => c.to_string(),
+                }).collect();
+                write!(f, "type \"{}\"", escaped)
+            },
+            Command::Click(button) => write!(f, "click {}", button),
+            Command::Key(key) => write!(f, "key {}", key as char),
+            Command::Macro(ref name) => write!(f, "macro {}", name),
+            Command::Move(dx, dy) => write!(f, "move {} {}", dx, dy),
+            Command::Wheel(delta) => write!(f, "wheel {}", delta),
+            Command::Scroll(pixels, duration) => write!(f, "scroll {} {}ms", pixels, duration.as_secs_f64() * 1000.0),
+            Command::Step(step) => write!(f, "step {}", step),
+            Command::Pause => write!(f, "pause"),
+            Command::Resume => write!(f, "resume"),
+            Command::Report(ref report) => {
+                write!(f, "report")?;
+                for byte in report.iter() {
+                    write!(f, " {:02x}", byte)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    tasks: Vec<Task>,
+}
+
+impl Schedule {
+    /* Reads a schedule, its tasks counting from `now` */
+    pub fn parse<R: BufRead>(input: R, now: Instant) -> io::Result<Schedule> {
+        let mut tasks = Vec::new();
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, char::is_whitespace);
+            let when = match (fields.next(), fields.next()) {
+                (Some("at"), Some(time)) => parse_time(time).ok_or_else(|| invalid(number, "Invalid time, expected hh:mm"))?,
+                (Some("every"), Some(interval)) => When::Every(parse_duration(interval).ok_or_else(|| invalid(number, "Invalid interval"))?),
+                _ => return Err(invalid(number, "Expected at or every")),
+            };
+            let command = fields.next().ok_or_else(|| invalid(number, "Missing command"))?;
+            let command = Command::parse(command).ok_or_else(|| invalid(number, "Invalid command"))?;
+            tasks.push(Task { when, command, next: when.next(now, Duration::from_secs(0)) });
+        }
+        Ok(Schedule { tasks })
+    }
+
+    pub fn tasks(&self) -> &[Task] {
+        &self.tasks
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.tasks.iter().map(|task| task.next).min()
+    }
+
+    /* Returns the commands due by `now`, scheduling their next run */
+    pub fn tick(&mut self, now: Instant) -> Vec<Command> {
+        let mut due = Vec::new();
+        for task in self.tasks.iter_mut().filter(|task| task.next <= now) {
+            due.push(task.command.clone());
+            task.next = match task.when {
+                /* skip runs missed e.g. while suspended instead of catching up */
+                When::Every(interval) => (task.next + interval).max(now),
+                /* the clock may lag behind the timer a bit, don't run twice */
+                at => at.next(now, Duration::from_secs(60)),
+            };
+        }
+        due
+    }
+}
@@ -0,0 +1,98 @@
+/*
+ * Command schema
+ * The commands of --fixture, --schedule, repl and the serve modes as a JSON
+ * schema, printed by the schema subcommand, so clients of the control
+ * protocol can be generated or checked against it instead of following the
+ * usage by hand.
+ *
+ * A command is written as its name and its arguments separated by spaces,
+ * one per line or, in the serve modes, one per frame answered with "ok" or
+ * "error <message>"; see src/schedule.rs and src/framing.rs. The schema
+ * describes it as an object of the name and the arguments in order:
+ *
+ *   {"command":"move","arguments":[10,0]}   is written as   move 10 0
+ *
+ * list and select are only taken by the serve modes, to address the devices
+ * of a split one.
+ */
+
+use schedule::COMMANDS;
+use std::fmt::Write as FmtWrite;
+
+/* The kind of value an argument takes */
+enum Argument {
+    /* quoted, with \n, \t, \" and \\ escapes */
+    Text,
+    Integer(i64, i64),
+    /* left, right, middle or a number */
+    Button,
+    Character,
+    Name,
+    /* a number with a unit of ms, s, m or h */
+    Duration,
+    /* any number of them, in hex */
+    Bytes,
+    /* a number or any */
+    Device,
+}
+
+/* The commands only the serve modes take, see Serve in src/main.rs */
+const SERVE_COMMANDS: &[(&str, &str)] = &[
+    ("list", "list the devices by number and name"),
+    ("select <device>", "send the reports of the commands after it to the device, or by report-id again with any"),
+];
+
+/* The arguments of each command, by the names its usage gives them */
+const ARGUMENTS: &[(&str, &[(&str, Argument)])] = &[
+    ("type", &[("text", Argument::Text)]),
+    ("click", &[("button", Argument::Button)]),
+    ("key", &[("key", Argument::Character)]),
+    ("macro", &[("name", Argument::Name)]),
+    ("move", &[("dx", Argument::Integer(i8::MIN as i64, i8::MAX as i64)), ("dy", Argument::Integer(i8::MIN as i64, i8::MAX as i64))]),
+    ("wheel", &[("detents", Argument::Integer(i16::MIN as i64, i16::MAX as i64))]),
+    ("scroll", &[("pixels", Argument::Integer(i32::MIN as i64, i32::MAX as i64)), ("duration", Argument::Duration)]),
+    ("step", &[("counts", Argument::Integer(1, i8::MAX as i64))]),
+    ("pause", &[]),
+    ("resume", &[]),
+    ("report", &[("bytes", Argument::Bytes)]),
+    ("list", &[]),
+    ("select", &[("device", Argument::Device)]),
+];
+
+fn argument(title: &str, argument: &Argument) -> String {
+    let schema = match *argument {
+        Argument::Text => String::from("\"type\":\"string\""),
+        Argument::Integer(minimum, maximum) => format!("\"type\":\"integer\",\"minimum\":{},\"maximum\":{}", minimum, maximum),
+        Argument::Button => String::from("\"anyOf\":[{\"enum\":[\"left\",\"right\",\"middle\"]},{\"type\":\"integer\",\"minimum\":1,\"maximum\":255}]"),
+        Argument::Character => String::from("\"type\":\"string\",\"minLength\":1,\"maxLength\":1"),
+        Argument::Name => String::from("\"type\":\"string\",\"minLength\":1"),
+        Argument::Duration => String::from("\"type\":\"string\",\"pattern\":\"^[0-9]+(\\\\.[0-9]+)?(ms|s|m|h)$\""),
+        Argument::Bytes => String::from("\"type\":\"string\",\"pattern\":\"^[0-9a-fA-F]{1,2}$\""),
+        Argument::Device => String::from("\"anyOf\":[{\"const\":\"any\"},{\"type\":\"integer\",\"minimum\":0}]"),
+    };
+    format!("{{\"title\":\"{}\",{}}}", title, schema)
+}
+
+/* The schema of all commands, pretty enough to read */
+pub fn render() -> String {
+    let mut schema = String::from("{\n");
+    schema.push_str("  \"$schema\": \"https://json-schema.org/draft/2020-12/schema\",\n");
+    schema.push_str("  \"title\": \"uhid-example command\",\n");
+    schema.push_str("  \"description\": \"Written as the command and its arguments separated by spaces, e.g. move 10 0\",\n");
+    schema.push_str("  \"oneOf\": [");
+    for (index, &(name, arguments)) in ARGUMENTS.iter().enumerate() {
+        let description = COMMANDS.iter().chain(SERVE_COMMANDS).find(|&&(usage, _)| usage.split(' ').next() == Some(name)).map_or("", |&(_, description)| description);
+        let items = match arguments.last() {
+            /* the bytes of a report are each an argument */
+            Some(&(title, Argument::Bytes)) => format!("\"minItems\":1,\"items\":{}", argument(title, &Argument::Bytes)),
+            _ => {
+                let items: Vec<String> = arguments.iter().map(|&(title, ref kind)| argument(title, kind)).collect();
+                format!("\"prefixItems\":[{}],\"minItems\":{},\"items\":false", items.join(","), items.len())
+            },
+        };
+        let _ = write!(schema, "{}\n    {{\"type\":\"object\",\"description\":\"{}\",\"properties\":{{\"command\":{{\"const\":\"{}\"}},\"arguments\":{{\"type\":\"array\",{}}}}},\"required\":[\"command\",\"arguments\"],\"additionalProperties\":false}}",
+                       if index > 0 { "," } else { "" }, description.replace('\\', "\\\\").replace('"', "\\\""), name, items);
+    }
+    schema.push_str("\n  ]\n}\n");
+    schema
+}
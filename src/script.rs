@@ -0,0 +1,256 @@
+/*
+ * Scripting
+ * Drives a device from a Rhai script, for behaviours that react to the host
+ * or keep state, e.g. auto-responders, without recompiling. The script runs
+ * once when loaded and sees the device as `device`:
+ *
+ *   device.move(dx, dy)     relative pointer movement
+ *   device.click(button)    toggle a button twice, like a click
+ *   device.toggle(button)   toggle a button, e.g. to hold it down
+ *   device.wheel(delta)
+ *   device.input(report)    send a raw input report, an array of bytes
+ *
+ * Callbacks are closures, which see `device` by capturing it:
+ *
+ *   on_output(|report| ...)  output reports sent by the host
+ *   after(ms, || ...)        once, ms milliseconds after being set up
+ *   every(ms, || ...)        repeatedly, every ms milliseconds
+ *
+ * Reports the script generates are sent on the next tick, in order. The
+ * keyboard controls still work alongside the script.
+ *
+ * Needs the scripting feature.
+ */
+
+use devices::Device;
+use std::io;
+use std::path::Path;
+
+#[cfg(feature = "scripting")]
+mod engine {
+    use devices::{Action, Device, Identity};
+    use rhai::{Array, Dynamic, Engine, FnPtr, Scope, AST};
+    use std::cell::RefCell;
+    use std::io;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    struct Timer {
+        due: Instant,
+        period: Option<Duration>,
+        callback: FnPtr,
+    }
+
+    struct State {
+        device: Box<dyn Device>,
+        reports: Vec<Vec<u8>>,
+        output: Vec<FnPtr>,
+        timers: Vec<Timer>,
+    }
+
+    impl State {
+        fn action(&mut self, action: Action) {
+            let reports = self.device.action(action);
+            self.reports.extend(reports);
+        }
+    }
+
+    /* The `device` the script sees; clones share the device */
+    #[derive(Clone)]
+    struct Handle(Rc<RefCell<State>>);
+
+    fn clamp(value: i64) -> i8 {
+        value.clamp(-127, 127) as i8
+    }
+
+    fn to_bytes(report: Array) -> Vec<u8> {
+        report.into_iter().map(|byte| byte.as_int().unwrap_or(0) as u8).collect()
+    }
+
+    fn to_array(data: &[u8]) -> Array {
+        data.iter().map(|&byte| Dynamic::from(byte as i64)).collect()
+    }
+
+    fn script_error<E: ToString>(err: E) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+
+    fn add_timer(state: &Rc<RefCell<State>>, ms: i64, period: bool, callback: FnPtr) {
+        let interval = Duration::from_millis(ms.max(1) as u64);
+        state.borrow_mut().timers.push(Timer {
+            due: Instant::now() + interval,
+            period: if period { Some(interval) } else { None },
+            callback,
+        });
+    }
+
+    pub struct Script {
+        /* copied as the device is shared with the script */
+        descriptor: Vec<u8>,
+        engine: Engine,
+        ast: AST,
+        state: Rc<RefCell<State>>,
+    }
+
+    impl Script {
+        pub fn load(path: &Path, device: Box<dyn Device>) -> io::Result<Script> {
+            let descriptor = device.descriptor().to_vec();
+            let state = Rc::new(RefCell::new(State {
+                device,
+                reports: Vec::new(),
+                output: Vec::new(),
+                timers: Vec::new(),
+            }));
+
+            let mut engine = Engine::new();
+            engine.register_type_with_name::<Handle>("Device")
+                .register_fn("move", |handle: &mut Handle, dx: i64, dy: i64| {
+                    handle.0.borrow_mut().action(Action::Move(clamp(dx), clamp(dy)))
+                })
+                .register_fn("click", |handle: &mut Handle, button: i64| {
+                    let mut state = handle.0.borrow_mut();
+                    state.action(Action::ToggleButton(button as u8));
+                    state.action(Action::ToggleButton(button as u8));
+                })
+                .register_fn("toggle", |handle: &mut Handle, button: i64| {
+                    handle.0.borrow_mut().action(Action::ToggleButton(button as u8))
+                })
+                .register_fn("wheel", |handle: &mut Handle, delta: i64| {
+                    handle.0.borrow_mut().action(Action::Wheel(clamp(delta)))
+                })
+                .register_fn("input", |handle: &mut Handle, report: Array| {
+                    handle.0.borrow_mut().reports.push(to_bytes(report))
+                });
+
+            let shared = state.clone();
+            engine.register_fn("on_output", move |callback: FnPtr| shared.borrow_mut().output.push(callback));
+            let shared = state.clone();
+            engine.register_fn("after", move |ms: i64, callback: FnPtr| add_timer(&shared, ms, false, callback));
+            let shared = state.clone();
+            engine.register_fn("every", move |ms: i64, callback: FnPtr| add_timer(&shared, ms, true, callback));
+
+            let ast = engine.compile_file(path.to_path_buf()).map_err(script_error)?;
+            let mut scope = Scope::new();
+            scope.push_constant("device", Handle(state.clone()));
+            engine.run_ast_with_scope(&mut scope, &ast).map_err(script_error)?;
+
+            Ok(Script { descriptor, engine, ast, state })
+        }
+
+        /* Errors in callbacks are reported but keep the script running */
+        fn call(&self, callback: &FnPtr, args: Vec<Dynamic>) {
+            if let Err(err) = callback.call::<Dynamic>(&self.engine, &self.ast, args) {
+                eprintln!("Script error: {}", err);
+            }
+        }
+
+        fn take_reports(&self) -> Vec<Vec<u8>> {
+            self.state.borrow_mut().reports.drain(..).collect()
+        }
+    }
+
+    impl Device for Script {
+        fn descriptor(&self) -> &[u8] {
+            &self.descriptor
+        }
+
+        fn identity(&self) -> Identity {
+            self.state.borrow().device.identity()
+        }
+
+        fn is_keyboard(&self) -> bool {
+            self.state.borrow().device.is_keyboard()
+        }
+
+        fn leds(&self) -> Option<u8> {
+            self.state.borrow().device.leds()
+        }
+
+        fn action(&mut self, action: Action) -> Vec<Vec<u8>> {
+            self.state.borrow_mut().action(action);
+            self.take_reports()
+        }
+
+        fn scroll(&mut self, pixels: i32, duration: Duration) -> Option<Vec<(Duration, Vec<u8>)>> {
+            self.state.borrow_mut().device.scroll(pixels, duration)
+        }
+
+        fn deadline(&self) -> Option<Instant> {
+            let state = self.state.borrow();
+            if !state.reports.is_empty() {
+                return Some(Instant::now());
+            }
+            state.timers.iter().map(|timer| timer.due)
+                .chain(state.device.deadline())
+                .min()
+        }
+
+        fn tick(&mut self, now: Instant) -> Vec<Vec<u8>> {
+            let due: Vec<FnPtr> = {
+                let mut state = self.state.borrow_mut();
+                let reports = state.device.tick(now);
+                state.reports.extend(reports);
+
+                let mut due = Vec::new();
+                state.timers.retain_mut(|timer| {
+                    if timer.due > now {
+                        return true;
+                    }
+                    due.push(timer.callback.clone());
+                    match timer.period {
+                        Some(period) => {
+                            timer.due = (timer.due + period).max(now);
+                            true
+                        },
+                        None => false,
+                    }
+                });
+                due
+            };
+
+            for callback in due.iter() {
+                self.call(callback, vec![]);
+            }
+            self.take_reports()
+        }
+
+        fn handle_output(&mut self, rtype: u8, data: &[u8]) {
+            let callbacks = {
+                let mut state = self.state.borrow_mut();
+                state.device.handle_output(rtype, data);
+                state.output.clone()
+            };
+
+            for callback in callbacks.iter() {
+                self.call(callback, vec![Dynamic::from(to_array(data))]);
+            }
+        }
+
+        fn decode_output(&self, rtype: u8, data: &[u8]) -> Vec<(&'static str, i64)> {
+            self.state.borrow().device.decode_output(rtype, data)
+        }
+
+        fn get_report(&mut self, rnum: u8, rtype: u8) -> io::Result<Vec<u8>> {
+            self.state.borrow_mut().device.get_report(rnum, rtype)
+        }
+
+        fn set_report(&mut self, rnum: u8, rtype: u8, data: &[u8]) -> io::Result<()> {
+            self.state.borrow_mut().device.set_report(rnum, rtype, data)
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+pub use self::engine::Script;
+
+/* Wraps the device so the script at path drives it */
+#[cfg(feature = "scripting")]
+pub fn load(path: &Path, device: Box<dyn Device>) -> io::Result<Box<dyn Device>> {
+    Ok(Box::new(Script::load(path, device)?))
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn load(_path: &Path, _device: Box<dyn Device>) -> io::Result<Box<dyn Device>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Built without scripting support, enable the scripting feature"))
+}
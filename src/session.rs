@@ -0,0 +1,112 @@
+/*
+ * Session logs
+ * Logs the commands a device receives, keys from the terminal or a control
+ * socket, fixture commands and scheduled tasks alike, with the seconds since
+ * the first one:
+ *
+ *   # uhid-example session, started at 1760601600
+ *   0.000 key d
+ *   0.412 type "hello\n"
+ *   2.050 click 1
+ *
+ * A rerun sends the same commands at the same times, so unlike replaying the
+ * recorded reports it goes through the translation of commands into reports
+ * again. The device is not part of the log, rerun with the options of the
+ * logged session. The commands are those of src/schedule.rs.
+ */
+
+use schedule::Command;
+use std::io;
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub struct SessionLog<W: Write> {
+    output: W,
+    start: Option<Instant>,
+}
+
+impl<W: Write> SessionLog<W> {
+    pub fn new(mut output: W) -> io::Result<SessionLog<W>> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        writeln!(output, "# uhid-example session, started at {}", started.as_secs())?;
+        output.flush()?;
+        Ok(SessionLog { output, start: None })
+    }
+
+    pub fn command(&mut self, command: &Command) -> io::Result<()> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        writeln!(self.output, "{:.3} {}", start.elapsed().as_secs_f64(), command)?;
+        /* a session ending in a crash is the one worth rerunning */
+        self.output.flush()
+    }
+}
+
+fn invalid(number: usize, message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Line {}: {}", number, message))
+}
+
+/* Sends the commands of a session log again */
+pub struct Rerun {
+    commands: Vec<(Duration, Command)>,
+    start: Option<Instant>,
+    next: usize,
+}
+
+impl Rerun {
+    pub fn parse<R: BufRead>(input: R) -> io::Result<Rerun> {
+        let mut commands = Vec::new();
+
+        for (index, line) in input.lines().enumerate() {
+            let line = line?;
+            let number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (time, command) = line.split_once(char::is_whitespace).ok_or_else(|| invalid(number, "Missing command"))?;
+            let time = time.parse().ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or_else(|| invalid(number, "Invalid time"))?;
+            let command = Command::parse(command).ok_or_else(|| invalid(number, "Invalid command"))?;
+            commands.push((time, command));
+        }
+        commands.sort_by_key(|&(time, _)| time);
+        Ok(Rerun { commands, start: None, next: 0 })
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.commands.iter().map(|(_, command)| command)
+    }
+
+    /* Starts sending, the first command right away */
+    pub fn start(&mut self, now: Instant) {
+        self.start = Some(now);
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        let start = self.start?;
+        self.commands.get(self.next).map(|&(time, _)| start + time)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next == self.commands.len()
+    }
+
+    /* Returns the commands due by `now` */
+    pub fn tick(&mut self, now: Instant) -> Vec<Command> {
+        let start = match self.start {
+            Some(start) => start,
+            None => return vec![],
+        };
+        let mut due = Vec::new();
+        while let Some((time, command)) = self.commands.get(self.next) {
+            if start + *time > now {
+                break;
+            }
+            due.push(command.clone());
+            self.next += 1;
+        }
+        due
+    }
+}
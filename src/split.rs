@@ -0,0 +1,246 @@
+/*
+ * Splitting devices
+ * Registers one device per top-level collection of a composite descriptor,
+ * the way a USB device with several interfaces shows up as several HID
+ * devices, so each function gets its own hidraw and event nodes and driver,
+ * e.g. to test how drivers bind to one function of a composite device.
+ *
+ * Global items carry over from one collection to the next, so each part
+ * starts with the global items in effect where its collection starts. Input
+ * reports go to the part declaring their report-id; two parts declaring the
+ * same one, or unnumbered reports in several parts, cannot be told apart
+ * and are refused. Every part has the name and ids of the whole device.
+ * Selecting a part, by its number in the order of the collections, sends
+ * all input reports to it instead, for the select command of the serve
+ * modes, see Serve in src/main.rs.
+ */
+
+use backend::{self, Backend};
+use exit;
+use hid_report::descriptor::{self, ItemType};
+use libc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use uhid::{Event, Identity};
+
+/* One top-level collection, as a descriptor of its own */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Part {
+    pub descriptor: Vec<u8>,
+    /* 0 for unnumbered reports */
+    pub report_ids: Vec<u8>,
+}
+
+fn invalid(message: String, context: Vec<(&'static str, u64)>) -> io::Error {
+    exit::located(io::ErrorKind::InvalidData, message, context)
+}
+
+/* The top-level collections of a descriptor */
+pub fn split(descriptor: &[u8]) -> io::Result<Vec<Part>> {
+    let mut parts: Vec<Part> = Vec::new();
+    /* global items in effect, by tag, as they were in the descriptor */
+    let mut globals: BTreeMap<u8, Vec<u8>> = BTreeMap::new();
+    let mut stack = Vec::new();
+    let mut report_id = 0;
+    let mut depth = 0usize;
+    /* where the part being read starts, and the globals in effect there */
+    let mut start = 0;
+    let mut prefix = Vec::new();
+    let mut report_ids = BTreeSet::new();
+
+    for item in descriptor::items(descriptor) {
+        let (offset, item) = item.map_err(|truncated| invalid(format!("Descriptor ends within the item at byte {}", truncated.offset), vec![("offset", truncated.offset as u64)]))?;
+        let end = offset + 1 + item.size as usize;
+        match (item.item_type, item.tag) {
+            (ItemType::Global, descriptor::PUSH) => stack.push((globals.clone(), report_id)),
+            (ItemType::Global, descriptor::POP) => match stack.pop() {
+                Some((pushed, pushed_id)) => {
+                    globals = pushed;
+                    report_id = pushed_id;
+                },
+                None => return Err(invalid(format!("Pop without push at byte {}", offset), vec![("offset", offset as u64)])),
+            },
+            (ItemType::Global, tag) => {
+                if tag == descriptor::REPORT_ID {
+                    report_id = item.data as u8;
+                }
+                globals.insert(tag, descriptor[offset..end].to_vec());
+            },
+            (ItemType::Main, descriptor::COLLECTION) => depth += 1,
+            (ItemType::Main, descriptor::END_COLLECTION) => {
+                depth = depth.checked_sub(1).ok_or_else(|| invalid(format!("End collection without collection at byte {}", offset), vec![("offset", offset as u64)]))?;
+                if depth == 0 {
+                    let mut part = prefix.clone();
+                    part.extend_from_slice(&descriptor[start..end]);
+                    parts.push(Part { descriptor: part, report_ids: report_ids.iter().cloned().collect() });
+                    report_ids.clear();
+                    start = end;
+                    prefix = globals.values().flatten().cloned().collect();
+                }
+            },
+            (ItemType::Main, _) if depth == 0 => return Err(invalid(format!("Main item outside of a collection at byte {}", offset), vec![("offset", offset as u64)])),
+            (ItemType::Main, _) => {
+                report_ids.insert(report_id);
+            },
+            _ => (),
+        }
+    }
+    if depth > 0 {
+        return Err(invalid(String::from("Descriptor ends within a collection"), vec![("offset", descriptor.len() as u64)]));
+    }
+
+    for (index, part) in parts.iter().enumerate() {
+        for other in parts[index + 1..].iter() {
+            if let Some(id) = part.report_ids.iter().find(|id| other.report_ids.contains(id)) {
+                return Err(invalid(format!("Report id {} is used in several top-level collections", id), vec![("report_id", *id as u64)]));
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/*
+ * A backend creating one device per top-level collection, each with a
+ * backend of its own. Their events are read in turn; replies go to the one
+ * the last event came from, the main loop answering right away.
+ */
+pub struct Split {
+    backends: Vec<Box<dyn Backend>>,
+    make: Box<dyn FnMut() -> io::Result<Box<dyn Backend>>>,
+    /* report ids of the parts created */
+    parts: Vec<Vec<u8>>,
+    /* the last event came from this part */
+    current: usize,
+    /* input reports go to this part, whatever their report-id */
+    selected: Option<usize>,
+    /* readable when any of the backends is, for polling all of them */
+    epoll: File,
+}
+
+impl Split {
+    /* Uses `first` for the first part and makes a backend for every further
+     * one with `make` */
+    pub fn new<F>(first: Box<dyn Backend>, make: F) -> io::Result<Split>
+        where F: FnMut() -> io::Result<Box<dyn Backend>> + 'static
+    {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut split = Split {
+            backends: vec![],
+            make: Box::new(make),
+            parts: vec![],
+            current: 0,
+            selected: None,
+            epoll: unsafe { File::from_raw_fd(fd) },
+        };
+        split.add(first)?;
+        Ok(split)
+    }
+
+    fn add(&mut self, backend: Box<dyn Backend>) -> io::Result<()> {
+        let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: self.backends.len() as u64 };
+        if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, backend.fd(), &mut event) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.backends.push(backend);
+        Ok(())
+    }
+}
+
+impl Backend for Split {
+    fn create(&mut self, name: &str, descriptor: &[u8], identity: &Identity) -> io::Result<()> {
+        let parts = split(descriptor)?;
+        while self.backends.len() < parts.len() {
+            let backend = (self.make)()?;
+            self.add(backend)?;
+        }
+        eprintln!("Splitting into {} devices", parts.len());
+        self.parts.clear();
+        for (backend, part) in self.backends.iter_mut().zip(parts) {
+            backend.create(name, &part.descriptor, identity)?;
+            self.parts.push(part.report_ids);
+        }
+        Ok(())
+    }
+
+    fn destroy(&mut self) -> io::Result<()> {
+        for backend in self.backends.iter_mut().take(self.parts.len()) {
+            backend.destroy()?;
+        }
+        self.parts.clear();
+        self.selected = None;
+        Ok(())
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        for (index, backend) in self.backends.iter_mut().enumerate() {
+            let fd = backend.fd();
+            backend.reopen()?;
+            /* the old fd left the epoll set when it was closed */
+            if backend.fd() != fd {
+                let mut event = libc::epoll_event { events: libc::EPOLLIN as u32, u64: index as u64 };
+                if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, backend.fd(), &mut event) } < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn input(&mut self, report: &[u8]) -> io::Result<()> {
+        if let Some(index) = self.selected {
+            return self.backends[index].input(report);
+        }
+        let numbered = self.parts.iter().flatten().any(|&id| id != 0);
+        let id = if numbered { report.first().cloned().unwrap_or(0) } else { 0 };
+        match self.parts.iter().position(|ids| ids.contains(&id)) {
+            Some(index) => self.backends[index].input(report),
+            None => Err(exit::located(io::ErrorKind::InvalidInput, format!("No device has report id {}", id), vec![("report_id", id as u64)])),
+        }
+    }
+
+    fn read_event(&mut self) -> io::Result<Option<Event>> {
+        for index in 0..self.parts.len() {
+            if let Some(event) = self.backends[index].read_event()? {
+                self.current = index;
+                return Ok(Some(event));
+            }
+        }
+        /* all read, empty the ready list so the next event wakes the poll */
+        let mut ready = [libc::epoll_event { events: 0, u64: 0 }; 8];
+        unsafe { libc::epoll_wait(self.epoll.as_raw_fd(), ready.as_mut_ptr(), ready.len() as libc::c_int, 0) };
+        Ok(None)
+    }
+
+    fn get_report_reply(&mut self, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()> {
+        self.backends[self.current].get_report_reply(id, result)
+    }
+
+    fn set_report_reply(&mut self, id: u32, result: io::Result<()>) -> io::Result<()> {
+        self.backends[self.current].set_report_reply(id, result)
+    }
+
+    fn fd(&self) -> RawFd {
+        self.epoll.as_raw_fd()
+    }
+
+    fn devices(&self) -> usize {
+        self.parts.len()
+    }
+
+    fn select(&mut self, device: Option<usize>) -> io::Result<()> {
+        match device {
+            Some(device) if device >= self.parts.len() => return Err(backend::no_device(device)),
+            _ => self.selected = device,
+        }
+        Ok(())
+    }
+
+    fn current(&self) -> usize {
+        self.current
+    }
+}
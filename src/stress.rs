@@ -0,0 +1,246 @@
+/*
+ * Lifecycle stress
+ * Creates and destroys devices as fast as the kernel allows, from several
+ * threads each with its own uhid-cdev, and checks that every device came and
+ * went: the kernel has to start it and send the add uevent of its HID
+ * device, then the remove uevent once it is destroyed, and none of the
+ * devices may be left in sysfs at the end. Devices get unique names,
+ * <name>-<thread>-<number>, so their uevents can be told apart.
+ *
+ * The uevents are read from the kernel's netlink socket, ahead of udev. A
+ * burst of them needs a large receive buffer, which only root can force. If
+ * it still overflows, the check is reported as incomplete rather than as
+ * missing uevents.
+ */
+
+use libc;
+use nodes;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use uhid::{self, uhid_event_type, Identity};
+
+pub const DEFAULT_ITERATIONS: u64 = 1000;
+
+/* How long a device gets to be started */
+const START_TIMEOUT: Duration = Duration::from_secs(1);
+/* Uevents still arriving after the last device is destroyed */
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+const RECEIVE_BUFFER: libc::c_int = 16 * 1024 * 1024;
+/* Multicast group of the uevents sent by the kernel, not by udev */
+const KERNEL_GROUP: u32 = 1;
+
+pub struct Uevent {
+    pub action: String,
+    pub devpath: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    /* "<action>@<devpath>" followed by KEY=value, separated by NULs */
+    fn parse(data: &[u8]) -> Option<Uevent> {
+        let mut fields = data.split(|&byte| byte == 0).map(String::from_utf8_lossy);
+        let header = fields.next()?;
+        let (action, devpath) = header.split_once('@')?;
+        let properties = fields.filter_map(|field| field.split_once('=').map(|(key, value)| (key.to_string(), value.to_string()))).collect();
+        Some(Uevent { action: action.to_string(), devpath: devpath.to_string(), properties })
+    }
+}
+
+/* Receives the uevents of the kernel */
+pub struct UeventMonitor {
+    socket: File,
+}
+
+impl UeventMonitor {
+    pub fn open() -> io::Result<UeventMonitor> {
+        let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        let size = &RECEIVE_BUFFER as *const libc::c_int as *const libc::c_void;
+        let length = mem::size_of::<libc::c_int>() as libc::socklen_t;
+        /* without the privilege to force it, take what the limit allows */
+        if unsafe { libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUFFORCE, size, length) } < 0 {
+            unsafe { libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_RCVBUF, size, length) };
+        }
+
+        let mut address: libc::sockaddr_nl = unsafe { mem::zeroed() };
+        address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        address.nl_groups = KERNEL_GROUP;
+        let result = unsafe {
+            libc::bind(fd, &address as *const libc::sockaddr_nl as *const libc::sockaddr, mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t)
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(UeventMonitor { socket })
+    }
+
+    /* The next uevent, None if none came in time. Fails with ENOBUFS if
+     * uevents were lost. */
+    pub fn receive(&mut self, timeout: Duration) -> io::Result<Option<Uevent>> {
+        let mut pollfd = libc::pollfd { fd: self.socket.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut pollfd, 1, timeout.as_millis() as libc::c_int) } {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(None),
+            _ => (),
+        }
+        let mut data = [0u8; 8192];
+        let length = self.socket.read(&mut data)?;
+        Ok(Uevent::parse(&data[..length]))
+    }
+}
+
+/* Whether the add and the remove uevent of each device came */
+type Seen = HashMap<String, (bool, bool)>;
+
+pub struct Stress {
+    pub iterations: u64,
+    pub parallel: u64,
+    pub name: String,
+    pub descriptor: Vec<u8>,
+    pub identity: Identity,
+}
+
+#[derive(Default)]
+pub struct Summary {
+    pub created: u64,
+    pub errors: Vec<String>,
+    pub not_started: Vec<String>,
+    pub missing_add: Vec<String>,
+    pub missing_remove: Vec<String>,
+    /* devices still in sysfs at the end */
+    pub left: Vec<String>,
+    pub uevents_lost: bool,
+    pub elapsed: Duration,
+}
+
+impl Summary {
+    pub fn passed(&self) -> bool {
+        self.errors.is_empty() && self.not_started.is_empty() && self.missing_add.is_empty()
+            && self.missing_remove.is_empty() && self.left.is_empty() && !self.uevents_lost
+    }
+}
+
+/* Runs the iterations of one thread, those with the index modulo parallel,
+ * returning the names of the devices created as well */
+fn worker(path: &Path, stress: &Stress, index: u64) -> io::Result<(Summary, Vec<String>)> {
+    let mut file = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC | libc::O_NONBLOCK).open(path)?;
+    let mut summary = Summary::default();
+    let mut created = Vec::new();
+
+    for iteration in (index..stress.iterations).step_by(stress.parallel as usize) {
+        let name = format!("{}-{}-{}", stress.name, index, iteration);
+        if let Err(err) = uhid::create(&mut file, &name, &stress.descriptor, &stress.identity) {
+            summary.errors.push(format!("{}: {}", name, err));
+            continue;
+        }
+        summary.created += 1;
+        created.push(name.clone());
+        if !uhid::wait_for(&mut file, uhid_event_type::UHID_START, START_TIMEOUT)? {
+            summary.not_started.push(name.clone());
+        }
+        if let Err(err) = uhid::destroy(&mut file) {
+            summary.errors.push(format!("{}: {}", name, err));
+        }
+        /* leave the STOP and anything else behind for the next device */
+        while uhid::wait_for(&mut file, uhid_event_type::UHID_STOP, Duration::from_millis(0))? {}
+    }
+    Ok((summary, created))
+}
+
+/* Collects which devices of the run were added and removed until told the
+ * run is done and the uevents settled */
+fn monitor(mut monitor: UeventMonitor, prefix: &str, done: &AtomicBool) -> io::Result<(Seen, bool)> {
+    let mut seen = Seen::new();
+    let mut quiet_since: Option<Instant> = None;
+    loop {
+        let uevent = match monitor.receive(Duration::from_millis(100)) {
+            Ok(uevent) => uevent,
+            Err(ref err) if err.raw_os_error() == Some(libc::ENOBUFS) => return Ok((seen, true)),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        match uevent {
+            Some(uevent) => {
+                quiet_since = None;
+                let name = match (uevent.properties.get("SUBSYSTEM"), uevent.properties.get("HID_NAME")) {
+                    (Some(subsystem), Some(name)) if subsystem == "hid" && name.starts_with(prefix) => name,
+                    _ => continue,
+                };
+                let entry = seen.entry(name.clone()).or_default();
+                match uevent.action.as_str() {
+                    "add" => entry.0 = true,
+                    /* a remove without an add is no add */
+                    "remove" if entry.0 => entry.1 = true,
+                    _ => (),
+                }
+            },
+            None if done.load(Ordering::SeqCst) => {
+                let since = *quiet_since.get_or_insert_with(Instant::now);
+                if since.elapsed() >= SETTLE_TIME {
+                    return Ok((seen, false));
+                }
+            },
+            None => (),
+        }
+    }
+}
+
+pub fn run(path: &Path, stress: Stress) -> io::Result<Summary> {
+    let uevents = UeventMonitor::open().map_err(|err| io::Error::new(err.kind(), format!("Cannot listen for uevents: {}", err)))?;
+    let start = Instant::now();
+    let stress = Arc::new(stress);
+    let prefix = format!("{}-", stress.name);
+    let done = Arc::new(AtomicBool::new(false));
+
+    let watcher = {
+        let (prefix, done) = (prefix.clone(), done.clone());
+        thread::spawn(move || monitor(uevents, &prefix, &done))
+    };
+    let workers: Vec<_> = (0..stress.parallel).map(|index| {
+        let (path, stress) = (path.to_path_buf(), stress.clone());
+        thread::spawn(move || worker(&path, &stress, index))
+    }).collect();
+
+    let mut summary = Summary::default();
+    let mut created = Vec::new();
+    for (index, worker) in workers.into_iter().enumerate() {
+        let result = worker.join().map_err(|_| io::Error::other("Worker panicked"))?;
+        let (result, names) = result.map_err(|err| io::Error::new(err.kind(), format!("Worker {}: {}", index, err)))?;
+        created.extend(names);
+        summary.created += result.created;
+        summary.errors.extend(result.errors);
+        summary.not_started.extend(result.not_started);
+    }
+    summary.elapsed = start.elapsed();
+    done.store(true, Ordering::SeqCst);
+
+    let (seen, lost) = watcher.join().map_err(|_| io::Error::other("Uevent monitor panicked"))??;
+    summary.uevents_lost = lost;
+    for name in created {
+        match seen.get(&name) {
+            Some(&(true, true)) => (),
+            Some(&(true, false)) => summary.missing_remove.push(name),
+            _ => summary.missing_add.push(name),
+        }
+    }
+
+    summary.left = nodes::hid_devices()?.into_iter()
+        .map(|(_, name)| name)
+        .filter(|name| name.starts_with(&prefix))
+        .collect();
+    Ok(summary)
+}
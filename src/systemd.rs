@@ -0,0 +1,92 @@
+/*
+ * systemd
+ * Socket activation and readiness notification, implementing the protocols
+ * of sd_listen_fds(3) and sd_notify(3) without linking libsystemd:
+ *
+ *   LISTEN_PID, LISTEN_FDS  sockets passed from fd 3 on to the process named
+ *   NOTIFY_SOCKET           datagram socket to send state changes like READY=1
+ *
+ * Both are read once and removed from the environment, so programs started
+ * from the device, e.g. by a script, do not inherit them. A unit using them
+ * looks like:
+ *
+ *   [Service]
+ *   Type=notify
+ *   ExecStart=/usr/bin/uhid-example --preset keyboard
+ *
+ * with a matching .socket unit listening on the control socket.
+ */
+
+use libc;
+use std::env;
+use std::io;
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::io::RawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::process;
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/* The sockets systemd passed in, none when not socket activated */
+pub fn listen_fds() -> Vec<RawFd> {
+    let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    let count = env::var("LISTEN_FDS").ok().and_then(|count| count.parse::<RawFd>().ok());
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    match (pid, count) {
+        (Some(pid), Some(count)) if pid == process::id() => {
+            let fds: Vec<RawFd> = (LISTEN_FDS_START..LISTEN_FDS_START + count).collect();
+            for &fd in fds.iter() {
+                unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            }
+            fds
+        },
+        _ => vec![],
+    }
+}
+
+/* Reports state changes to the service manager */
+pub struct Notifier {
+    socket: UnixDatagram,
+    ready: bool,
+}
+
+impl Notifier {
+    /* Connects to NOTIFY_SOCKET, None when not started by systemd */
+    pub fn from_env() -> io::Result<Option<Notifier>> {
+        let path = match env::var("NOTIFY_SOCKET") {
+            Ok(path) => path,
+            Err(_) => return Ok(None),
+        };
+        env::remove_var("NOTIFY_SOCKET");
+
+        let address = if path.starts_with('@') {
+            SocketAddr::from_abstract_name(&path.as_bytes()[1..])?
+        } else {
+            SocketAddr::from_pathname(&path)?
+        };
+        let socket = UnixDatagram::unbound()?;
+        socket.connect_addr(&address)?;
+        Ok(Some(Notifier { socket, ready: false }))
+    }
+
+    /* Sends newline separated assignments, e.g. STATUS=... */
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send(state.as_bytes())?;
+        Ok(())
+    }
+
+    /* Sends READY=1 the first time it is called */
+    pub fn ready(&mut self) -> io::Result<()> {
+        if !self.ready {
+            self.notify("READY=1")?;
+            self.ready = true;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Report timing
+ * Timestamps each input report as it is written and, with readback, as the
+ * evdev nodes of the device deliver it, and keeps histograms of how evenly
+ * that happens, to see what pacing, coalescing or a delayed link does to the
+ * stream of reports rather than guess:
+ *   write interval   time between two reports written
+ *   write jitter     change of that interval from one report to the next
+ *   delivery         time from writing a report to evdev timestamping it
+ *   delivery jitter  change of that time from one delivered report to the next
+ *
+ * Readback opens the event nodes, switching their timestamps to the
+ * monotonic clock the writes are stamped with, and reads them after every
+ * write. uhid hands a report to hid-input within the write, so the frame it
+ * produces is stamped before the next write: a frame is taken to be the
+ * delivery of the last report written before its timestamp. Reports that
+ * change nothing, like a repeated key state, produce no frame and are only
+ * counted as written.
+ */
+
+use libc;
+use std::collections::VecDeque;
+use std::fmt::Write as FmtWrite;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::slice;
+use std::time::Duration;
+
+const EV_SYN: u16 = 0x00;
+const SYN_REPORT: u16 = 0x00;
+
+/* Upper bounds of the buckets in microseconds, the last one open */
+const BOUNDS: &[u64] = &[50, 100, 250, 500, 1000, 2500, 5000, 10000, 25000, 50000, 100000];
+
+/* Widest bar of a histogram printed */
+const BAR_WIDTH: u64 = 40;
+
+/* Writes waiting for their frame, older ones are given up */
+const MAX_PENDING: usize = 256;
+
+/* The current time of the monotonic clock */
+pub fn monotonic() -> Duration {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    /* One per bound and one past the last */
+    counts: Vec<u64>,
+    /* In microseconds */
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn record(&mut self, duration: Duration) {
+        if self.counts.is_empty() {
+            self.counts = vec![0; BOUNDS.len() + 1];
+        }
+        let value = micros(duration);
+        let bucket = BOUNDS.iter().position(|&bound| value <= bound).unwrap_or(BOUNDS.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /* Mean in microseconds */
+    pub fn mean(&self) -> u64 {
+        self.sum.checked_div(self.count()).unwrap_or(0)
+    }
+
+    /* A summary line followed by a bar per bucket in use */
+    pub fn render(&self, title: &str) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "{}: {} samples, mean {}us, max {}us", title, self.count(), self.mean(), self.max);
+        let highest = self.counts.iter().cloned().max().unwrap_or(0);
+        for (bucket, &count) in self.counts.iter().enumerate().filter(|&(_, &count)| count > 0) {
+            let label = match BOUNDS.get(bucket) {
+                Some(bound) => format!("<= {}us", bound),
+                None => format!("> {}us", BOUNDS[BOUNDS.len() - 1]),
+            };
+            let bar = "#".repeat(count.saturating_mul(BAR_WIDTH).div_ceil(highest) as usize);
+            let _ = writeln!(text, "  {:>10} {:>8} {}", label, count, bar);
+        }
+        text
+    }
+}
+
+/* _IOC(_IOC_WRITE, 'E', 0xa0, sizeof(int)) */
+const EVIOCSCLOCKID: libc::c_ulong = (1 << 30) | (4 << 16) | ((b'E' as libc::c_ulong) << 8) | 0xa0;
+
+#[derive(Default)]
+pub struct Timing {
+    last_write: Option<Duration>,
+    last_interval: Option<Duration>,
+    write_intervals: Histogram,
+    write_jitter: Histogram,
+    /* Times of the writes not delivered yet, with readback */
+    pending: VecDeque<Duration>,
+    nodes: Vec<File>,
+    last_delivery: Option<Duration>,
+    delivery: Histogram,
+    delivery_jitter: Histogram,
+}
+
+impl Timing {
+    /* Reads the deliveries back from the event nodes from now on */
+    pub fn attach(&mut self, paths: &[PathBuf]) -> io::Result<()> {
+        self.nodes.clear();
+        self.pending.clear();
+        for path in paths {
+            let file = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)?;
+            let clock: libc::c_int = libc::CLOCK_MONOTONIC;
+            if unsafe { libc::ioctl(file.as_raw_fd(), EVIOCSCLOCKID as _, &clock) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.nodes.push(file);
+        }
+        Ok(())
+    }
+
+    pub fn is_attached(&self) -> bool {
+        !self.nodes.is_empty()
+    }
+
+    /* Stamps a report about to be written */
+    pub fn write(&mut self, now: Duration) {
+        if let Some(last) = self.last_write {
+            let interval = now.saturating_sub(last);
+            self.write_intervals.record(interval);
+            if let Some(previous) = self.last_interval {
+                self.write_jitter.record(interval.abs_diff(previous));
+            }
+            self.last_interval = Some(interval);
+        }
+        self.last_write = Some(now);
+        if self.is_attached() {
+            /* evdev stamps to the microsecond */
+            self.pending.push_back(Duration::from_micros(micros(now)));
+            if self.pending.len() > MAX_PENDING {
+                self.pending.pop_front();
+            }
+        }
+    }
+
+    /* Takes in the frames delivered so far */
+    pub fn read_back(&mut self) -> io::Result<()> {
+        let mut events: [libc::input_event; 64] = unsafe { mem::zeroed() };
+        for index in 0..self.nodes.len() {
+            loop {
+                let size = {
+                    let buffer = unsafe { slice::from_raw_parts_mut(events.as_mut_ptr() as *mut u8, mem::size_of_val(&events)) };
+                    match self.nodes[index].read(buffer) {
+                        Ok(size) => size,
+                        Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                        /* the device went away, until attached again */
+                        Err(ref err) if err.raw_os_error() == Some(libc::ENODEV) => {
+                            self.nodes.clear();
+                            return Ok(());
+                        },
+                        Err(err) => return Err(err),
+                    }
+                };
+                if size == 0 {
+                    break;
+                }
+                for event in events[..size / mem::size_of::<libc::input_event>()].iter() {
+                    if event.type_ == EV_SYN && event.code == SYN_REPORT {
+                        self.delivered(Duration::new(event.time.tv_sec as u64, event.time.tv_usec as u32 * 1000));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn delivered(&mut self, time: Duration) {
+        let mut written = None;
+        while self.pending.front().is_some_and(|&write| write <= time) {
+            written = self.pending.pop_front();
+        }
+        /* a frame of a report written before attaching */
+        let written = match written {
+            Some(written) => written,
+            None => return,
+        };
+        let latency = time - written;
+        self.delivery.record(latency);
+        if let Some(last) = self.last_delivery {
+            self.delivery_jitter.record(latency.abs_diff(last));
+        }
+        self.last_delivery = Some(latency);
+    }
+
+    /* The histograms with samples in them */
+    pub fn render(&self) -> String {
+        [(&self.write_intervals, "Write interval"), (&self.write_jitter, "Write jitter"),
+         (&self.delivery, "Delivery"), (&self.delivery_jitter, "Delivery jitter")].iter()
+            .filter(|(histogram, _)| histogram.count() > 0)
+            .map(|(histogram, title)| histogram.render(title))
+            .collect()
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * TLS
+ * Encrypts connections to the control socket, so keys typed from across a
+ * network, like a KVM over the LAN, do not travel in plain text. The server
+ * certificate chain and its key are PEM files, given with --tls-cert and
+ * --tls-key; with --tls-client-ca the clients need a certificate issued by
+ * one of the CAs of that PEM file too, and a socket on TCP then does without
+ * a token, see src/auth.rs. e.g. to test:
+ *
+ *   openssl s_client -connect 127.0.0.1:7000 -quiet
+ *
+ * The handshake goes on as the connection is read, so a client that never
+ * completes it does not hold up the others.
+ *
+ * Needs the tls feature.
+ */
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+
+#[cfg(feature = "tls")]
+mod server {
+    use rustls::crypto::ring;
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{RootCertStore, ServerConfig};
+    use std::fmt;
+    use std::io;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    fn error<E: fmt::Display>(path: &Path, err: E) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), err))
+    }
+
+    fn certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+        CertificateDer::pem_file_iter(path).map_err(|err| error(path, err))?
+            .collect::<Result<Vec<_>, _>>().map_err(|err| error(path, err))
+    }
+
+    pub fn config(cert: &Path, key: &Path, client_ca: Option<&Path>) -> io::Result<ServerConfig> {
+        let chain = certificates(cert)?;
+        let private = PrivateKeyDer::from_pem_file(key).map_err(|err| error(key, err))?;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?;
+        let builder = match client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for ca in certificates(path)? {
+                    roots.add(ca).map_err(|err| error(path, err))?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                    .build()
+                    .map_err(|err| error(path, err))?;
+                builder.with_client_cert_verifier(verifier)
+            },
+            None => builder.with_no_client_auth(),
+        };
+        builder.with_single_cert(chain, private).map_err(|err| error(cert, err))
+    }
+}
+
+/* Sets up the sessions of accepted connections */
+pub struct Acceptor {
+    #[cfg(feature = "tls")]
+    config: Arc<rustls::ServerConfig>,
+    verifies_clients: bool,
+}
+
+impl Acceptor {
+    /* Reads the certificate chain and key, and the CAs of the clients if
+     * they are to be verified */
+    #[cfg(feature = "tls")]
+    pub fn load(cert: &Path, key: &Path, client_ca: Option<&Path>) -> io::Result<Acceptor> {
+        let config = server::config(cert, key, client_ca)?;
+        Ok(Acceptor { config: Arc::new(config), verifies_clients: client_ca.is_some() })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn load(_cert: &Path, _key: &Path, _client_ca: Option<&Path>) -> io::Result<Acceptor> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Built without TLS support, enable the tls feature"))
+    }
+
+    /* Whether only clients with a certificate of the CAs get through */
+    pub fn verifies_clients(&self) -> bool {
+        self.verifies_clients
+    }
+
+    /* The plain text of the connection, non-blocking as the socket is */
+    #[cfg(feature = "tls")]
+    pub fn accept(&self, socket: File) -> io::Result<Box<dyn Read>> {
+        let session = rustls::ServerConnection::new(self.config.clone()).map_err(io::Error::other)?;
+        Ok(Box::new(rustls::StreamOwned::new(session, socket)))
+    }
+
+    #[cfg(not(feature = "tls"))]
+    pub fn accept(&self, _socket: File) -> io::Result<Box<dyn Read>> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "Built without TLS support, enable the tls feature"))
+    }
+}
@@ -0,0 +1,59 @@
+/*
+ * vsock
+ * Listening on an AF_VSOCK socket, the channel between a virtual machine and
+ * its host that needs no network set up in the guest. The guest listens on a
+ * port of its own context id; on the host, QEMU with a vhost-vsock-pci
+ * device, or libvirt with a <vsock> element, connects to it by the guest's
+ * context id, e.g. with "socat - VSOCK-CONNECT:<cid>:<port>".
+ */
+
+use libc;
+use std::fs::File;
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::ptr;
+
+pub struct Listener {
+    socket: File,
+}
+
+impl Listener {
+    /* Listens on the port for connections from any context, non-blocking */
+    pub fn bind(port: u32) -> io::Result<Listener> {
+        let fd = unsafe { libc::socket(libc::AF_VSOCK, libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = unsafe { File::from_raw_fd(fd) };
+
+        let mut address: libc::sockaddr_vm = unsafe { mem::zeroed() };
+        address.svm_family = libc::AF_VSOCK as libc::sa_family_t;
+        address.svm_cid = libc::VMADDR_CID_ANY;
+        address.svm_port = port;
+        let bound = unsafe {
+            libc::bind(fd, &address as *const libc::sockaddr_vm as *const libc::sockaddr,
+                       mem::size_of::<libc::sockaddr_vm>() as libc::socklen_t)
+        };
+        if bound < 0 || unsafe { libc::listen(fd, 1) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Listener { socket })
+    }
+
+    /* The next pending connection, non-blocking too; None if there is none */
+    pub fn accept(&self) -> io::Result<Option<File>> {
+        let fd = unsafe { libc::accept4(self.socket.as_raw_fd(), ptr::null_mut(), ptr::null_mut(), libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            return if err.kind() == io::ErrorKind::WouldBlock { Ok(None) } else { Err(err) };
+        }
+        Ok(Some(unsafe { File::from_raw_fd(fd) }))
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
@@ -0,0 +1,104 @@
+/*
+ * Watchdog
+ * Notices a device gone wedged and has it created again, so a daemon keeps
+ * working across uhid module reloads and the like instead of needing a
+ * restart: with --watchdog <interval> the device counts as wedged when
+ *
+ *   writes kept failing for the interval, none succeeding in between, e.g.
+ *   because the uhid-cdev went away with the module, or
+ *   no event came from the kernel for the interval after the device was
+ *   created, when it should have been answered with UHID_START right away.
+ *
+ * The main loop then destroys the device as far as it still can, opens the
+ * uhid-cdev again and creates the device anew; failing that, it tries again
+ * once the interval is over. Write failures do not end the main loop while
+ * the watchdog is on, they are counted and the reports lost.
+ *
+ * It goes by the counters of src/metrics.rs, which also count the recoveries
+ * by their cause.
+ */
+
+use metrics::{Metrics, Snapshot};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wedged {
+    Writes,
+    Silence,
+}
+
+impl Wedged {
+    /* The cause as labelled in the metrics */
+    pub fn name(self) -> &'static str {
+        match self {
+            Wedged::Writes => "writes",
+            Wedged::Silence => "silence",
+        }
+    }
+}
+
+impl fmt::Display for Wedged {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Wedged::Writes => write!(f, "writes kept failing"),
+            Wedged::Silence => write!(f, "the kernel did not answer creating it"),
+        }
+    }
+}
+
+pub struct Watchdog {
+    interval: Duration,
+    last: Snapshot,
+    /* when writes started failing, none succeeding since */
+    failing: Option<Instant>,
+    /* when the device was created, until the kernel sends an event, and the
+     * events received by then */
+    waiting: Option<(Instant, u64)>,
+}
+
+impl Watchdog {
+    pub fn new(interval: Duration, metrics: &Metrics) -> Watchdog {
+        let mut watchdog = Watchdog { interval, last: metrics.snapshot(), failing: None, waiting: None };
+        watchdog.created(Instant::now(), metrics);
+        watchdog
+    }
+
+    /* The device was created, or tried to be, and should be answered */
+    pub fn created(&mut self, now: Instant, metrics: &Metrics) {
+        self.last = metrics.snapshot();
+        self.failing = None;
+        self.waiting = Some((now, self.last.events));
+    }
+
+    /* When the device counts as wedged unless something changes */
+    pub fn deadline(&self) -> Option<Instant> {
+        self.failing.into_iter()
+            .chain(self.waiting.map(|(since, _)| since))
+            .min()
+            .map(|since| since + self.interval)
+    }
+
+    /* Whether the device is wedged by now, following the counters since the
+     * last check */
+    pub fn check(&mut self, now: Instant, metrics: &Metrics) -> Option<Wedged> {
+        let current = metrics.snapshot();
+        if current.reports > self.last.reports {
+            self.failing = None;
+        } else if current.write_errors > self.last.write_errors && self.failing.is_none() {
+            self.failing = Some(now);
+        }
+        if self.waiting.is_some_and(|(_, events)| current.events > events) {
+            self.waiting = None;
+        }
+        self.last = current;
+
+        if self.failing.is_some_and(|since| now >= since + self.interval) {
+            Some(Wedged::Writes)
+        } else if self.waiting.is_some_and(|(since, _)| now >= since + self.interval) {
+            Some(Wedged::Silence)
+        } else {
+            None
+        }
+    }
+}
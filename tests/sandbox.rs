@@ -0,0 +1,64 @@
+/*
+ * Runs stdio-serve within the sandbox on the mock backend, checking it still
+ * answers commands while stdin stays open, i.e. stdin was made non-blocking
+ * before the filter was installed.
+ */
+
+#![cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+fn write_frame<W: Write>(mut output: W, payload: &[u8]) {
+    output.write_all(&(payload.len() as u16).to_le_bytes()).unwrap();
+    output.write_all(payload).unwrap();
+    output.flush().unwrap();
+}
+
+fn read_frame<R: Read>(mut input: R) -> Option<String> {
+    let mut length = [0u8; 2];
+    input.read_exact(&mut length).ok()?;
+    let mut payload = vec![0; u16::from_le_bytes(length) as usize];
+    input.read_exact(&mut payload).ok()?;
+    Some(String::from_utf8_lossy(&payload).into_owned())
+}
+
+#[test]
+fn serves_stdio_in_the_sandbox() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_uhid-example"))
+        .args(["--backend", "mock", "--sandbox", "stdio-serve"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+
+    let (frames, received) = mpsc::channel();
+    thread::spawn(move || {
+        while let Some(frame) = read_frame(&mut stdout) {
+            if frames.send(frame).is_err() {
+                break;
+            }
+        }
+    });
+    let next = || received.recv_timeout(Duration::from_secs(5)).expect("no answer within the sandbox");
+
+    assert_eq!(next(), "ready");
+    write_frame(&mut stdin, b"list");
+    let answer = loop {
+        let frame = next();
+        if !frame.starts_with("event ") {
+            break frame;
+        }
+    };
+    assert!(answer.starts_with("ok any\n0 "), "{}", answer);
+
+    /* closing stdin ends it */
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+}
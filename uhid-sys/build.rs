@@ -7,10 +7,23 @@ fn main() {
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
-    let bindings = bindgen::Builder::default()
+    let mut builder = bindgen::Builder::default()
         // The input header we would like to generate
         // bindings for.
-        .header("wrapper.h")
+        .header("wrapper.h");
+
+    // Android has <linux/uhid.h> in the NDK sysroot rather than in /usr,
+    // point clang at it when cross-compiling for bionic.
+    let target = env::var("TARGET").unwrap();
+    if target.contains("android") {
+        builder = builder.clang_arg(format!("--target={}", target));
+        if let Ok(sysroot) = env::var("ANDROID_NDK_SYSROOT") {
+            builder = builder.clang_arg(format!("--sysroot={}", sysroot));
+        }
+    }
+    println!("cargo:rerun-if-env-changed=ANDROID_NDK_SYSROOT");
+
+    let bindings = builder
         // Finish the builder and generate the bindings.
         .generate()
         // Unwrap the Result and panic on failure.
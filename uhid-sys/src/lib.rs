@@ -0,0 +1,8 @@
+/*
+ * Raw bindings to <linux/uhid.h>, generated with bindgen at build time. The
+ * safe wrapper lives in the uhid crate.
+ */
+
+#![allow(dead_code, non_camel_case_types, non_upper_case_globals)]
+
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
@@ -0,0 +1,453 @@
+/*
+ * Raw access to the uhid character device. Everything in here speaks in terms
+ * of `struct uhid_event` as defined by <linux/uhid.h>; higher layers only ever
+ * deal with descriptors and report bytes.
+ */
+
+extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
+extern crate uhid_sys;
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/* The raw types stay available here, so users need only this crate */
+pub use uhid_sys::*;
+
+/* Values passed to the kernel in the create request that identify the device */
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Identity {
+    pub bus: u16,
+    pub vendor: u32,
+    pub product: u32,
+    pub version: u32,
+    pub country: u32,
+}
+
+/* Bus types that can be selected by name */
+pub const BUS_NAMES: &[(&str, u32)] = &[
+    ("usb", BUS_USB),
+    ("bluetooth", BUS_BLUETOOTH),
+    ("i2c", BUS_I2C),
+    ("virtual", BUS_VIRTUAL),
+];
+
+pub fn bus_from_name(name: &str) -> Option<u16> {
+    BUS_NAMES.iter().find(|bus| bus.0 == name).map(|bus| bus.1 as u16)
+}
+
+static TRACE: AtomicBool = AtomicBool::new(false);
+
+/* Hex-dumps every event written to and read from the uhid-cdev to stderr */
+pub fn set_trace(enabled: bool) {
+    TRACE.store(enabled, Ordering::Relaxed);
+}
+
+/* Formats data like hexdump -C: offset, 16 bytes in hex and as ASCII, with
+ * runs of identical lines replaced by a "*" */
+pub fn hexdump(data: &[u8]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&[u8]> = None;
+    let mut elided = false;
+    for (index, line) in data.chunks(16).enumerate() {
+        if previous == Some(line) {
+            if !elided {
+                text.push_str("*\n");
+                elided = true;
+            }
+            continue;
+        }
+        previous = Some(line);
+        elided = false;
+
+        text.push_str(&format!("{:08x} ", index * 16));
+        for (column, byte) in line.iter().enumerate() {
+            text.push_str(if column == 8 { "  " } else { " " });
+            text.push_str(&format!("{:02x}", byte));
+        }
+        let padding = (16 - line.len()) * 3 + if line.len() <= 8 { 1 } else { 0 };
+        text.push_str(&" ".repeat(padding));
+        let ascii: String = line.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+        text.push_str(&format!("  |{}|\n", ascii));
+    }
+    if elided {
+        text.push_str(&format!("{:08x}\n", data.len()));
+    }
+    text
+}
+
+fn trace(direction: &str, bytes: &[u8]) {
+    let type_ = u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let name = match event_type(type_) {
+        Some(event_type) => format!("{:?}", event_type),
+        None => format!("unknown type {}", type_),
+    };
+    eprint!("uhid {} {}, {} bytes\n{}", direction, name, bytes.len(), hexdump(bytes));
+}
+
+fn write_event(file: &mut File, uhid_event: &uhid_event) -> io::Result<()> {
+    let uhid_event_slice: &[u8];
+    let uhid_event_size = mem::size_of::<uhid_event>();
+    unsafe {
+        uhid_event_slice = slice::from_raw_parts(
+            uhid_event as *const _ as *const u8,
+            uhid_event_size
+        );
+    }
+    if TRACE.load(Ordering::Relaxed) {
+        trace("write", uhid_event_slice);
+    }
+    /* the error is passed on as it is, callers tell kernels apart by its errno */
+    let bytes_written = file.write(uhid_event_slice)?;
+    if bytes_written != uhid_event_size {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, format!("Wrong size written to uhid: {} != {}", bytes_written, uhid_event_size)));
+    }
+    Ok(())
+}
+
+pub fn read_event(file: &mut File) -> io::Result<uhid_event> {
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+    let uhid_event_size = mem::size_of::<uhid_event>();
+
+    unsafe {
+        let uhid_event_slice = slice::from_raw_parts_mut(
+            &mut ev as *mut _ as *mut u8,
+            uhid_event_size
+        );
+        file.read_exact(uhid_event_slice)?;
+        if TRACE.load(Ordering::Relaxed) {
+            trace("read", uhid_event_slice);
+        }
+    }
+
+    Ok(ev)
+}
+
+/* Waits for an event of the given type on a non-blocking uhid-cdev, skipping
+ * others; false if it did not come in time */
+pub fn wait_for(file: &mut File, wanted: uhid_event_type, timeout: Duration) -> io::Result<bool> {
+    wait_for_event(file, wanted, timeout).map(|event| event.is_some())
+}
+
+/* The same, returning the event; None if it did not come in time */
+pub fn wait_for_event(file: &mut File, wanted: uhid_event_type, timeout: Duration) -> io::Result<Option<uhid_event>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut pollfd = libc::pollfd { fd: file.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+        match unsafe { libc::poll(&mut pollfd, 1, left.as_millis() as libc::c_int) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            0 => return Ok(None),
+            _ => (),
+        }
+        match read_event(file) {
+            Ok(event) if event_type(event.type_) == Some(wanted) => return Ok(Some(event)),
+            Ok(_) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => (),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/* Longest name fitting the 128 byte field of the create request with its NUL */
+pub const MAX_NAME_LEN: usize = 127;
+
+pub const DEFAULT_NAME: &str = "test-uhid-device";
+
+pub fn check_name(name: &str) -> io::Result<()> {
+    if name.len() > MAX_NAME_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Device name is {} bytes long, at most {} are allowed", name.len(), MAX_NAME_LEN)));
+    }
+    if name.contains('\0') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Device name contains a NUL byte"));
+    }
+    Ok(())
+}
+
+pub fn create(file: &mut File, name: &str, rdesc: &[u8], identity: &Identity) -> io::Result<()> {
+    check_name(name)?;
+
+    let mut rdesc = rdesc.to_vec();
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::__UHID_LEGACY_CREATE as u32;
+
+    unsafe {
+        let create = ev.u.create.as_mut();
+        /* the rest of the zeroed field terminates the name */
+        create.name[..name.len()].copy_from_slice(name.as_bytes());
+        create.rd_data = rdesc.as_mut_ptr();
+        create.rd_size = rdesc.len() as u16;
+        create.bus = identity.bus;
+        create.vendor = identity.vendor;
+        create.product = identity.product;
+        create.version = identity.version;
+        create.country = identity.country;
+    }
+
+    /* the legacy request only points at the descriptor */
+    if TRACE.load(Ordering::Relaxed) {
+        eprint!("uhid descriptor, {} bytes\n{}", rdesc.len(), hexdump(&rdesc));
+    }
+    write_event(file, &ev)
+}
+
+/* Checks a descriptor fits the request of UHID_CREATE2 */
+pub fn check_descriptor(rdesc: &[u8]) -> io::Result<()> {
+    if rdesc.len() > HID_MAX_DESCRIPTOR_SIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Descriptor too large: {} bytes, uhid takes up to {}", rdesc.len(), HID_MAX_DESCRIPTOR_SIZE)));
+    }
+    Ok(())
+}
+
+/* Creates the device with UHID_CREATE2, the descriptor copied into the
+ * request; kernels before 3.15 reject it with EOPNOTSUPP */
+pub fn create2(file: &mut File, name: &str, rdesc: &[u8], identity: &Identity) -> io::Result<()> {
+    check_name(name)?;
+    check_descriptor(rdesc)?;
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::UHID_CREATE2 as u32;
+
+    unsafe {
+        let create = ev.u.create2.as_mut();
+        create.name[..name.len()].copy_from_slice(name.as_bytes());
+        create.rd_data[..rdesc.len()].copy_from_slice(rdesc);
+        create.rd_size = rdesc.len() as u16;
+        create.bus = identity.bus;
+        create.vendor = identity.vendor;
+        create.product = identity.product;
+        create.version = identity.version;
+        create.country = identity.country;
+    }
+
+    write_event(file, &ev)
+}
+
+/*
+ * Creates the device with UHID_CREATE2, falling back to the legacy request on
+ * kernels not knowing it, so one binary works on old and new kernels alike.
+ * Other errors, such as EINVAL for a bad request, are returned rather than
+ * hidden by a second try.
+ * Returns whether CREATE2 was taken: the input of a legacy device has to go
+ * with the legacy request too, see send_input(). The name and descriptor are
+ * checked first, so their errors are not mistaken for an old kernel.
+ */
+pub fn create_any(file: &mut File, name: &str, rdesc: &[u8], identity: &Identity) -> io::Result<bool> {
+    check_name(name)?;
+    check_descriptor(rdesc)?;
+    match create2(file, name, rdesc, identity) {
+        Ok(()) => Ok(true),
+        Err(ref err) if unsupported(err) => create(file, name, rdesc, identity).map(|()| false),
+        Err(err) => Err(err),
+    }
+}
+
+/* Whether writing a request failed for the kernel not knowing its type, as
+ * old kernels answer the requests added after them */
+pub fn unsupported(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EOPNOTSUPP)
+}
+
+/* The dev_flags of a UHID_START event, a set of uhid_dev_flag; always zero
+ * before kernel 3.19 */
+pub fn start_flags(ev: &uhid_event) -> u64 {
+    unsafe { ev.u.start.as_ref().dev_flags }
+}
+
+pub fn destroy(file: &mut File) -> io::Result<()>
+{
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::UHID_DESTROY as u32;
+
+    write_event(file, &ev)
+}
+
+/*
+ * Checks a report fits the data of a request, UHID_DATA_MAX bytes. Reports
+ * too large are an error rather than cut short, which would hand the kernel
+ * a different report than the one meant.
+ */
+pub fn check_report(data: &[u8]) -> io::Result<()> {
+    if data.len() > UHID_DATA_MAX as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                  format!("Report too large: {} bytes, uhid takes up to {}", data.len(), UHID_DATA_MAX)));
+    }
+    Ok(())
+}
+
+/* Sends one input report; data[0] is the report-id for numbered reports */
+pub fn input(file: &mut File, data: &[u8]) -> io::Result<()> {
+    check_report(data)?;
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::__UHID_LEGACY_INPUT as u32;
+
+    unsafe {
+        let uhid_input = ev.u.input.as_mut();
+        uhid_input.size = data.len() as u16;
+        uhid_input.data[..data.len()].copy_from_slice(data);
+    }
+
+    write_event(file, &ev)
+}
+
+/* Sends one input report as UHID_INPUT2, the request newer kernels prefer */
+pub fn input2(file: &mut File, data: &[u8]) -> io::Result<()> {
+    check_report(data)?;
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::UHID_INPUT2 as u32;
+
+    unsafe {
+        let uhid_input = ev.u.input2.as_mut();
+        uhid_input.size = data.len() as u16;
+        uhid_input.data[..data.len()].copy_from_slice(data);
+    }
+
+    write_event(file, &ev)
+}
+
+/* Sends one input report with the request matching how the device was
+ * created, see create_any() */
+pub fn send_input(file: &mut File, data: &[u8], created2: bool) -> io::Result<()> {
+    if created2 {
+        input2(file, data)
+    } else {
+        input(file, data)
+    }
+}
+
+/* Answers a UHID_GET_REPORT request; on error `data` is ignored */
+pub fn get_report_reply(file: &mut File, id: u32, result: io::Result<Vec<u8>>) -> io::Result<()> {
+    /* a report too large fails the request instead of being cut short */
+    let result = result.and_then(|data| check_report(&data).map(|_| data));
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::UHID_GET_REPORT_REPLY as u32;
+
+    unsafe {
+        let reply = ev.u.get_report_reply.as_mut();
+        reply.id = id;
+        match result {
+            Ok(data) => {
+                reply.size = data.len() as u16;
+                reply.data[..data.len()].copy_from_slice(&data);
+            },
+            Err(err) => reply.err = error_code(&err),
+        }
+    }
+
+    write_event(file, &ev)
+}
+
+/* Answers a UHID_SET_REPORT request */
+pub fn set_report_reply(file: &mut File, id: u32, result: io::Result<()>) -> io::Result<()> {
+    let mut ev: uhid_event = unsafe { mem::zeroed() };
+
+    ev.type_ = uhid_event_type::UHID_SET_REPORT_REPLY as u32;
+
+    unsafe {
+        let reply = ev.u.set_report_reply.as_mut();
+        reply.id = id;
+        if let Err(err) = result {
+            reply.err = error_code(&err);
+        }
+    }
+
+    write_event(file, &ev)
+}
+
+/* The kernel expects a positive errno value in replies */
+fn error_code(err: &io::Error) -> u16 {
+    err.raw_os_error().unwrap_or(libc::EIO) as u16
+}
+
+pub fn event_type(value: u32) -> Option<uhid_event_type> {
+    if value == uhid_event_type::__UHID_LEGACY_CREATE as u32 {
+        Some(uhid_event_type::__UHID_LEGACY_CREATE)
+    } else if value == uhid_event_type::UHID_DESTROY as u32 {
+        Some(uhid_event_type::UHID_DESTROY)
+    } else if value == uhid_event_type::UHID_START as u32 {
+        Some(uhid_event_type::UHID_START)
+    } else if value == uhid_event_type::UHID_STOP as u32 {
+        Some(uhid_event_type::UHID_STOP)
+    } else if value == uhid_event_type::UHID_OPEN as u32 {
+        Some(uhid_event_type::UHID_OPEN)
+    } else if value == uhid_event_type::UHID_CLOSE as u32 {
+        Some(uhid_event_type::UHID_CLOSE)
+    } else if value == uhid_event_type::UHID_OUTPUT as u32 {
+        Some(uhid_event_type::UHID_OUTPUT)
+    } else if value == uhid_event_type::__UHID_LEGACY_OUTPUT_EV as u32 {
+        Some(uhid_event_type::__UHID_LEGACY_OUTPUT_EV)
+    } else if value == uhid_event_type::__UHID_LEGACY_INPUT as u32 {
+        Some(uhid_event_type::__UHID_LEGACY_INPUT)
+    } else if value == uhid_event_type::UHID_GET_REPORT as u32 {
+        Some(uhid_event_type::UHID_GET_REPORT)
+    } else if value == uhid_event_type::UHID_GET_REPORT_REPLY as u32 {
+        Some(uhid_event_type::UHID_GET_REPORT_REPLY)
+    } else if value == uhid_event_type::UHID_CREATE2 as u32 {
+        Some(uhid_event_type::UHID_CREATE2)
+    } else if value == uhid_event_type::UHID_INPUT2 as u32 {
+        Some(uhid_event_type::UHID_INPUT2)
+    } else if value == uhid_event_type::UHID_SET_REPORT as u32 {
+        Some(uhid_event_type::UHID_SET_REPORT)
+    } else if value == uhid_event_type::UHID_SET_REPORT_REPLY as u32 {
+        Some(uhid_event_type::UHID_SET_REPORT_REPLY)
+    } else {
+        None
+    }
+}
+
+/* Owned copy of an event sent by the kernel, with only the meaningful data */
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Event {
+    Start,
+    Stop,
+    Open,
+    Close,
+    Output { rtype: u8, data: Vec<u8> },
+    GetReport { id: u32, rnum: u8, rtype: u8 },
+    SetReport { id: u32, rnum: u8, rtype: u8, data: Vec<u8> },
+    /* Events this wrapper does not handle, by type */
+    Other(u32),
+}
+
+impl Event {
+    pub fn from_raw(ev: &uhid_event) -> Event {
+        match event_type(ev.type_) {
+            Some(uhid_event_type::UHID_START) => Event::Start,
+            Some(uhid_event_type::UHID_STOP) => Event::Stop,
+            Some(uhid_event_type::UHID_OPEN) => Event::Open,
+            Some(uhid_event_type::UHID_CLOSE) => Event::Close,
+            Some(uhid_event_type::UHID_OUTPUT) => {
+                let output = unsafe { ev.u.output.as_ref() };
+                Event::Output { rtype: output.rtype, data: output.data[..(output.size as usize).min(output.data.len())].to_vec() }
+            },
+            Some(uhid_event_type::UHID_GET_REPORT) => {
+                let req = unsafe { ev.u.get_report.as_ref() };
+                Event::GetReport { id: req.id, rnum: req.rnum, rtype: req.rtype }
+            },
+            Some(uhid_event_type::UHID_SET_REPORT) => {
+                let req = unsafe { ev.u.set_report.as_ref() };
+                Event::SetReport { id: req.id, rnum: req.rnum, rtype: req.rtype, data: req.data[..(req.size as usize).min(req.data.len())].to_vec() }
+            },
+            _ => Event::Other(ev.type_),
+        }
+    }
+}